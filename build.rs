@@ -0,0 +1,56 @@
+//! Records details of the build for `gorg --version --verbose`
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rustc-env=GORG_GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=GORG_BUILD_SECS={}", build_secs());
+    println!("cargo:rustc-env=GORG_TARGET={}", env("TARGET"));
+    println!("cargo:rustc-env=GORG_PROFILE={}", env("PROFILE"));
+    println!("cargo:rustc-env=GORG_FEATURES={}", features());
+}
+
+fn env(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// Commit the build is from with a `-dirty` suffix for uncommitted changes,
+/// or "unknown" when building outside of a Git repository
+fn git_commit() -> String {
+    Command::new("git")
+        .args(["describe", "--always", "--dirty", "--abbrev=12"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| String::from(commit.trim()))
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Time of the build, or the time set for reproducible builds
+fn build_secs() -> u64 {
+    if let Some(secs) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+    {
+        return secs;
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// Comma-separated Cargo features enabled for the build
+fn features() -> String {
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features.join(",")
+}
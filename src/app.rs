@@ -1,20 +1,419 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
 
+use crate::auth;
+use crate::bench;
+use crate::build_info;
+use crate::checkouts::Checkouts;
 use crate::cli;
 use crate::cli::Cli;
-use crate::config::Config;
-use crate::db::DB;
+use crate::completions;
+use crate::config::{self, AutoRefresh, Config, Layout, Mirror};
+use crate::crypt;
+use crate::daemon;
+use crate::dashboard::{self, Dashboard};
+use crate::db::{self, Change, DB, EntryParts, Finder, Transaction};
+use crate::disk_usage;
+use crate::forge;
+use crate::fuzzy;
 use crate::git_cmd;
-use crate::git_dir;
+use crate::git_dir::{self, ScanCache};
 use crate::git_url;
+use crate::glob;
+use crate::history;
+use crate::jobs;
+use crate::lang;
+use crate::opener;
+use crate::oplog::{self, Action};
+use crate::parallel;
+use crate::progress::Progress;
+use crate::project_config::ProjectConfig;
+use crate::status::{self, StatusCache};
+use crate::template;
+use crate::text;
+use crate::tidy::{self, Finding};
 use crate::tui;
+use crate::watch;
 use anyhow::Result;
 use anyhow::bail;
 use clap::{CommandFactory, Parser, error::ErrorKind};
-use std::io::Write;
+use serde::Serialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGWINCH};
+use signal_hook::iterator::Signals;
+use std::io::{IsTerminal, Write};
 use termion::input::TermRead;
 
+const FIND_DEBOUNCE: Duration = Duration::from_millis(15);
+const TUI_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Time to wait for a background refresh of the index before starting another one
+const BACKGROUND_REFRESH_WAIT: Duration = Duration::from_secs(600);
+const BACKUP_INDEX_FILE: &str = "index";
+const BACKUP_BUNDLES_DIR: &str = "bundles";
+const BUNDLE_EXTENSION: &str = "bundle";
+/// Projects with this tag are hidden in the interactive finder until they're toggled visible
+const ARCHIVED_TAG: &str = "archived";
+
+enum FindMessage {
+    Input(std::io::Result<termion::event::Event>),
+    Matches(u64, FoundMatches),
+    Error(anyhow::Error),
+    Signal(i32),
+}
+
+/// Matches for the latest query limited to the number of items that can be shown
+struct FoundMatches {
+    projects: Vec<String>,
+    /// Descriptions for each of the projects
+    descriptions: Vec<Option<String>>,
+    /// Number of matches before limiting
+    count: usize,
+    /// Number of projects searched
+    total: usize,
+}
+
+impl FoundMatches {
+    /// Collect the matches from the finder, narrowed down to the projects in the given scope
+    fn from_finder<F: Finder>(finder: &F, limit: usize, scope: &FindScope) -> Self {
+        let in_scope = |project: &&str| scope.includes(project);
+        let projects: Vec<String> = finder
+            .matches()
+            .filter(in_scope)
+            .take(limit)
+            .map(String::from)
+            .collect();
+        let descriptions = projects
+            .iter()
+            .map(|project| finder.description(project).map(String::from))
+            .collect();
+        Self {
+            projects,
+            descriptions,
+            count: finder.matches().filter(in_scope).count(),
+            total: finder.total(),
+        }
+    }
+}
+
+/// Projects the matches are narrowed down to in the interactive finder
+#[derive(Clone, Copy)]
+struct FindScope<'a> {
+    header: &'a db::Header,
+    /// Hosts of all projects to cycle through
+    hosts: &'a [String],
+    /// Organizations of all projects including their hosts to cycle through
+    orgs: &'a [String],
+    host: Option<&'a str>,
+    /// Organization including the host, e.g. `github.com/jpallari`
+    org: Option<&'a str>,
+    /// Include the projects tagged as archived
+    show_archived: bool,
+}
+
+impl<'a> FindScope<'a> {
+    fn new(header: &'a db::Header, hosts: &'a [String], orgs: &'a [String]) -> Self {
+        Self {
+            header,
+            hosts,
+            orgs,
+            host: None,
+            org: None,
+            show_archived: false,
+        }
+    }
+
+    fn includes(&self, project: &str) -> bool {
+        let in_org = self.org.is_none_or(|org| {
+            project
+                .strip_prefix(org)
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        in_org
+            && self
+                .host
+                .is_none_or(|host| EntryParts::parse(project).host == host)
+            && (self.show_archived || !self.header.tags(project).any(|tag| tag == ARCHIVED_TAG))
+    }
+
+    /// Narrow down to the next host, or back to all hosts after the last one
+    fn cycle_host(&mut self) {
+        self.host = next_scope(self.hosts, self.host);
+        self.org = None;
+    }
+
+    /// Narrow down to the next organization on the selected host,
+    /// or back to the whole host after the last one
+    fn cycle_org(&mut self) {
+        let host = self.host;
+        let on_host = |org: &&String| host.is_none_or(|host| EntryParts::parse(org).host == host);
+        self.org = next_scope(self.orgs.iter().filter(on_host), self.org);
+    }
+
+    fn toggle_archived(&mut self) {
+        self.show_archived = !self.show_archived;
+    }
+
+    /// Description of the scope shown next to the match count. None for the default scope.
+    fn label(&self) -> Option<String> {
+        let mut label = self.org.or(self.host).map(String::from);
+        if self.show_archived {
+            let label = label.get_or_insert_default();
+            if !label.is_empty() {
+                label.push(' ');
+            }
+            label.push_str("+archived");
+        }
+        label
+    }
+}
+
+/// How the matches are shown in the interactive finder
+#[derive(Clone, Copy)]
+struct MatchesDisplay<'a> {
+    statuses: &'a StatusCache,
+    checkouts: &'a Checkouts<'a>,
+    show_full_path: bool,
+    /// Show the host of each match in its own column
+    show_hosts: bool,
+    /// Show how the score of each match adds up
+    explain: bool,
+}
+
+/// Project selected in the interactive finder
+struct Selection {
+    project: String,
+    /// Query the project was selected with
+    query: String,
+    /// Position of the project in the matches, 0 for the best match
+    rank: usize,
+}
+
+/// Sends queries to the scoring thread. Each query gets a new generation,
+/// so that the scoring of older queries can be cancelled.
+struct QuerySender<'a> {
+    tx: mpsc::Sender<(u64, String, FindScope<'a>)>,
+    latest_generation: &'a AtomicU64,
+    generation: u64,
+}
+
+impl<'a> QuerySender<'a> {
+    fn send(&mut self, query: &str, scope: FindScope<'a>) -> Result<()> {
+        self.generation += 1;
+        self.latest_generation
+            .store(self.generation, Ordering::Relaxed);
+        // The error holds the scope, which borrows data that doesn't outlive the prompt
+        if self
+            .tx
+            .send((self.generation, String::from(query), scope))
+            .is_err()
+        {
+            bail!("Scoring of the matches stopped unexpectedly");
+        }
+        Ok(())
+    }
+}
+
+/// Checked out branch of a project
+#[derive(Serialize)]
+struct ProjectBranch<'a> {
+    project: &'a str,
+    /// None when HEAD is detached
+    branch: Option<String>,
+    /// None when the default branch of the remote is not known
+    default_branch: Option<String>,
+}
+
+impl ProjectBranch<'_> {
+    fn is_on_default_branch(&self) -> bool {
+        match (&self.branch, &self.default_branch) {
+            (Some(branch), Some(default_branch)) => branch == default_branch,
+            (_, None) => true,
+            (None, Some(_)) => false,
+        }
+    }
+}
+
+/// Editor workspace with projects as folders.
+/// VS Code workspaces and Sublime Text projects list their folders the same way.
+#[derive(Serialize)]
+struct Workspace {
+    folders: Vec<WorkspaceFolder>,
+}
+
+#[derive(Serialize)]
+struct WorkspaceFolder {
+    name: String,
+    path: String,
+}
+
+/// Project listed in the JSON format
+#[derive(Serialize)]
+struct ListedProject<'a> {
+    project: String,
+    path: String,
+    /// None when the projects are not sorted by score
+    score: Option<f32>,
+    remote: Option<&'a str>,
+    /// Web page of the remote. None when the project has no remote or it isn't a forge URL.
+    web_url: Option<String>,
+    /// Main checkout of the remote when the project is an additional checkout or a worktree
+    checkout_of: Option<&'a str>,
+    languages: Vec<&'a str>,
+    tags: Vec<&'a str>,
+    status: Option<StatusInfo<'a>>,
+}
+
+/// Project containing the current directory of a shell prompt
+#[derive(Serialize)]
+struct PromptInfo<'a> {
+    project: &'a str,
+    path: String,
+    description: Option<&'a str>,
+    remote: Option<&'a str>,
+    /// None when the status hasn't been read yet
+    status: Option<StatusInfo<'a>>,
+}
+
+/// Cached Git status of a project
+#[derive(Serialize)]
+struct ProjectStatus<'a> {
+    project: &'a str,
+    /// None when the status hasn't been read yet
+    status: Option<StatusInfo<'a>>,
+}
+
+#[derive(Serialize)]
+struct StatusInfo<'a> {
+    /// None when HEAD is detached
+    branch: Option<&'a str>,
+    dirty: bool,
+    /// None when the branch has no upstream branch
+    ahead: Option<u32>,
+    behind: Option<u32>,
+    fetched_at: Option<String>,
+    checked_at: String,
+}
+
+impl<'a> From<&'a status::RepoStatus> for StatusInfo<'a> {
+    fn from(status: &'a status::RepoStatus) -> Self {
+        Self {
+            branch: status.branch.as_deref(),
+            dirty: status.dirty,
+            ahead: status.ahead_behind.map(|(ahead, _)| ahead),
+            behind: status.ahead_behind.map(|(_, behind)| behind),
+            fetched_at: status.fetched_at.map(oplog::format_time),
+            checked_at: oplog::format_time(status.checked_at),
+        }
+    }
+}
+
+/// Project directory to run commands in
+struct RunTarget<'a> {
+    project: &'a str,
+    dir: PathBuf,
+    commands: Vec<Vec<String>>,
+}
+
+/// Outcome of running the command in a project
+#[derive(Serialize)]
+struct RunResult<'a> {
+    project: &'a str,
+    ok: bool,
+    /// Why the command failed or didn't finish
+    error: Option<String>,
+}
+
+/// Changes made to the index by `update-index`
+#[derive(Serialize)]
+struct IndexChanges {
+    added: Vec<String>,
+    removed: Vec<String>,
+    /// Entries whose directories were renamed to a different case
+    renamed: Vec<RenamedEntry>,
+    skipped: Vec<SkippedDir>,
+}
+
+impl IndexChanges {
+    fn new(
+        changes: &[Change],
+        renames: &[(String, String)],
+        errors: &[git_dir::ScanError],
+    ) -> Self {
+        let mut index_changes = Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            renamed: renames
+                .iter()
+                .map(|(from, to)| RenamedEntry {
+                    from: from.clone(),
+                    to: to.clone(),
+                })
+                .collect(),
+            skipped: errors
+                .iter()
+                .map(|err| SkippedDir {
+                    path: err.path.to_string_lossy().into_owned(),
+                    error: err.error.to_string(),
+                })
+                .collect(),
+        };
+        for change in changes {
+            match change {
+                Change::Added(entry) => index_changes.added.push(String::from(*entry)),
+                Change::Removed(entry) => index_changes.removed.push(String::from(*entry)),
+            }
+        }
+        index_changes
+    }
+}
+
+#[derive(Serialize)]
+struct RenamedEntry {
+    from: String,
+    to: String,
+}
+
+/// Directory skipped due to an error when scanning
+#[derive(Serialize)]
+struct SkippedDir {
+    path: String,
+    error: String,
+}
+
+/// Work in a project that hasn't been pushed to any remote
+#[derive(Serialize)]
+struct UnpushedWork<'a> {
+    project: &'a str,
+    /// Commits in local branches that are not in any remote-tracking branch
+    commits: u32,
+    stashes: u32,
+}
+
+/// Result of checking out a branch in a project
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum SwitchOutcome {
+    Switched,
+    Created,
+    AlreadyOnBranch,
+    Dirty,
+    MissingBranch,
+}
+
+impl SwitchOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Switched => "switched",
+            Self::Created => "created from remote",
+            Self::AlreadyOnBranch => "already on branch",
+            Self::Dirty => "skipped: uncommitted changes",
+            Self::MissingBranch => "skipped: branch not found",
+        }
+    }
+}
+
 pub struct App {
     cli: Cli,
     cfg: Config,
@@ -22,38 +421,62 @@ pub struct App {
 
 impl App {
     fn handle_init(&self, args: &cli::InitArgs) -> Result<ExitCode> {
-        let git_cmd = git_cmd::GitCmd::new(self.cfg.git_command.clone());
+        let git_cmd = self.git_cmd();
 
         let repo_url = git_url::from_parts(&args.remote)?;
-        let project_path = git_url::to_path(&repo_url)?;
+        // Git clones from the rewritten URL, and it's also what Git reports as the remote URL
+        let clone_url = git_url::rewrite(&repo_url, &self.url_rewrites(&git_cmd)?);
+        git_url::check_scheme(&clone_url, &self.cfg.allowed_url_schemes)?;
+        let project_path = git_url::to_path(&clone_url, self.cfg.lowercase_paths)?;
         log::debug!(
-            "Git URL = {repo_url}, Git path = {}",
+            "Git URL = {repo_url}, clone URL = {clone_url}, Git path = {}",
             project_path.join("/")
         );
 
-        let project_full_path = self
-            .cfg
-            .projects_path
-            .join(project_path.join(std::path::MAIN_SEPARATOR_STR));
+        let entry = match self.init_path(args, &project_path)? {
+            Some(path) => path,
+            None => project_path.join("/"),
+        };
+        let project_full_path = self.project_path(&entry);
         let git_dir = project_full_path.join(".git");
 
-        if !git_dir.try_exists()? {
+        let mut redirected_url = None;
+        let created = !git_dir.try_exists()?;
+        if created {
             let project_full_path_str = project_full_path.to_string_lossy();
             log::debug!("Directory {project_full_path_str} not found",);
-            if args.no_clone {
-                log::debug!("Git init for {project_full_path_str}");
-                std::fs::create_dir_all(&project_full_path)?;
-                git_cmd.init(&project_full_path)?;
+            let new_dirs = tidy::missing_dirs(&project_full_path, &self.cfg.projects_path);
+            let res = if args.no_clone {
+                self.init_local_repo(&git_cmd, &project_full_path, None, args.template.as_deref())
+                    .map(|_| None)
             } else {
                 log::debug!("Git clone for {} from {}", project_full_path_str, &repo_url);
-                git_cmd.clone_repo(&repo_url, project_full_path.as_os_str())?;
+                // Git would create the parents too, but it doesn't remove them when cloning fails
+                project_full_path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| git_cmd.clone_repo(&repo_url, project_full_path.as_os_str()))
+            };
+            match res {
+                Ok(url) => redirected_url = url,
+                Err(err) => {
+                    // Don't leave empty host and organization directories behind
+                    tidy::remove_new_dirs(&new_dirs);
+                    return Err(err);
+                }
+            }
+            for dir in new_dirs.iter() {
+                if let Ok(dir) = dir.strip_prefix(&self.cfg.projects_path) {
+                    eprintln!("created {}", dir.to_string_lossy());
+                }
             }
         }
 
         let remotes_str = git_cmd.remote_list(&project_full_path)?;
         if remotes_str
             .split('\n')
-            .any(|remote| remote == &self.cfg.git_remote_name)
+            .any(|remote| remote == self.cfg.git_remote_name)
         {
             log::debug!(
                 "Git set remote {}={} for {}",
@@ -80,17 +503,206 @@ impl App {
             )?;
         }
 
+        if !created {
+            self.index_project(&entry, Some(&clone_url))?;
+        } else if args.no_clone {
+            self.index_new_project(&entry, Some(&clone_url))?;
+            self.log_operation(Action::Init, &entry, "");
+        } else {
+            self.index_new_project(&entry, Some(&clone_url))?;
+            self.log_operation(Action::Clone, &entry, &repo_url);
+        }
+        if let Some(new_url) = redirected_url {
+            let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+            self.follow_redirect(&mut db, &entry, &new_url)?;
+            db.commit()?;
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Entry for the directory chosen with `--here` or `--path`. Directories outside of
+    /// the projects directory are stored with their absolute paths.
+    fn init_path(&self, args: &cli::InitArgs, project_path: &[String]) -> Result<Option<String>> {
+        let path = match (&args.path, args.here) {
+            (Some(path), _) => path.clone(),
+            (None, true) => PathBuf::from(project_path.last().expect("Paths have several parts")),
+            (None, false) => return Ok(None),
+        };
+        let mut path =
+            std::path::absolute(path)?
+                .components()
+                .fold(PathBuf::new(), |mut path, component| {
+                    match component {
+                        std::path::Component::ParentDir => _ = path.pop(),
+                        component => path.push(component),
+                    }
+                    path
+                });
+        // Resolve symbolic links in the existing parent directories
+        if let (Some(Ok(parent)), Some(name)) =
+            (path.parent().map(std::fs::canonicalize), path.file_name())
+        {
+            path = parent.join(name);
+        }
+        let projects_path = std::fs::canonicalize(&self.cfg.projects_path)
+            .unwrap_or_else(|_| self.cfg.projects_path.clone());
+        let entry = match path.strip_prefix(&projects_path) {
+            Ok(rel_path) => db::entry_from_path(rel_path),
+            Err(_) => db::out_of_tree_entry(&path),
+        };
+        match entry {
+            Some(entry) => Ok(Some(entry)),
+            None => bail!("Cannot add {} to the index", path.to_string_lossy()),
+        }
+    }
+
+    fn handle_new(&self, args: &cli::NewArgs) -> Result<ExitCode> {
+        let git_cmd = self.git_cmd();
+
+        // Derive the path the same way as for remotes, so that
+        // the project ends up where `init` would place it.
+        let repo_url =
+            git_url::from_parts(&[args.host.clone(), args.org.clone(), args.name.clone()])?;
+        let repo_url = git_url::rewrite(&repo_url, &self.url_rewrites(&git_cmd)?);
+        let project_path = git_url::to_path(&repo_url, self.cfg.lowercase_paths)?;
+        let entry = project_path.join("/");
+        let project_full_path = self.project_path(&entry);
+        if project_full_path.join(".git").try_exists()? {
+            log::error!("Project already exists: {entry}");
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let new_dirs = tidy::missing_dirs(&project_full_path, &self.cfg.projects_path);
+        let res = self.init_local_repo(
+            &git_cmd,
+            &project_full_path,
+            args.git_template.as_deref(),
+            args.template.as_deref(),
+        );
+        if let Err(err) = res {
+            // Don't leave a half-initialized project behind, e.g. when the template failed
+            tidy::remove_new_dirs(&new_dirs);
+            return Err(err);
+        }
+        self.index_new_project(&entry, None)?;
+        self.log_operation(Action::Init, &entry, "");
+        println!("{}", project_full_path.to_string_lossy());
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Create the directory for a local project and initialize a Git repository in it.
+    /// The named template from the configuration is applied after the initialization.
+    fn init_local_repo(
+        &self,
+        git_cmd: &git_cmd::GitCmd,
+        project_full_path: &Path,
+        git_template: Option<&Path>,
+        template_name: Option<&str>,
+    ) -> Result<()> {
+        let template = match template_name {
+            Some(name) => match self.cfg.templates.get(name) {
+                Some(template) => Some(template),
+                None => {
+                    let names: Vec<&str> = self.cfg.templates.keys().map(|k| k.as_str()).collect();
+                    bail!(
+                        "Template not found: {name}. Available templates: {}",
+                        names.join(", ")
+                    );
+                }
+            },
+            None => None,
+        };
+
+        log::debug!("Git init for {}", project_full_path.to_string_lossy());
+        std::fs::create_dir_all(project_full_path)?;
+        git_cmd.init(project_full_path, git_template)?;
+        if let Some(template) = template {
+            template::apply(template, project_full_path)?;
+        }
+        Ok(())
+    }
+
+    /// Add a project to the index along with its remote URL
+    fn index_project(&self, entry: &str, remote_url: Option<&str>) -> Result<()> {
         log::debug!(
             "Saving project to DB {}",
             self.cfg.index_file_path.to_string_lossy()
         );
-        let mut db = DB::load(&self.cfg.index_file_path)?.unwrap_or_default();
-        db.add(&project_path.join("/"))?;
-        db.save(&self.cfg.index_file_path)?;
+        let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+        if db::is_out_of_tree(entry) {
+            // Scans of the projects directory don't find projects outside of it
+            db.pin(entry)?;
+        } else {
+            db.add(entry)?;
+        }
+        if let Some(remote_url) = remote_url {
+            db.set_remote(entry, remote_url)?;
+        }
+        let path = self.project_path(entry);
+        db.set_languages(entry, &lang::detect(&path));
+        db.set_tags(entry, &ProjectConfig::load_or_default(&path).tags);
+        db.commit()
+    }
+
+    /// Add a project created by this command to the index. The project directory is removed
+    /// when the index can't be updated, so that the project can be created again cleanly.
+    fn index_new_project(&self, entry: &str, remote_url: Option<&str>) -> Result<()> {
+        let Err(err) = self.index_project(entry, remote_url) else {
+            return Ok(());
+        };
+        let path = self.project_path(entry);
+        log::debug!(
+            "Removing {} after failing to update the index",
+            path.to_string_lossy()
+        );
+        if let Err(remove_err) = std::fs::remove_dir_all(&path) {
+            log::error!(
+                "Failed to remove {} after failing to update the index: {remove_err}",
+                path.to_string_lossy()
+            );
+        } else {
+            tidy::remove_empty_parents(&path, &self.cfg.projects_path);
+        }
+        Err(err)
+    }
+
+    /// Record an operation in the operations log. Failing to write the log
+    /// doesn't fail the operation, since it has already been done.
+    fn log_operation(&self, action: Action, target: &str, details: &str) {
+        if self.read_only() {
+            return;
+        }
+        let operation = oplog::Operation::new(action, target, details);
+        let log_file_path = config::required_path("log_file_path", &self.cfg.log_file_path);
+        if let Err(err) = log_file_path.and_then(|path| oplog::append(path, &operation)) {
+            log::warn!("Failed to write the operations log: {err}");
+        }
+    }
 
+    fn handle_log(&self, args: &cli::LogArgs) -> Result<ExitCode> {
+        let log_file_path = config::required_path("log_file_path", &self.cfg.log_file_path)?;
+        let operations = oplog::read(log_file_path)?;
+        let limit = args.limit.unwrap_or(operations.len());
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        for operation in operations.iter().rev().take(limit) {
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}",
+                oplog::format_time(operation.time),
+                operation.action.as_str(),
+                operation.target,
+                operation.details
+            )?;
+        }
         Ok(ExitCode::SUCCESS)
     }
 
+    /// Whether changes to the projects and the files of gorg are disallowed
+    fn read_only(&self) -> bool {
+        self.cli.read_only || self.cfg.read_only
+    }
+
     fn load_db_or_fail(&self) -> Result<DB> {
         let Some(db) = DB::load(&self.cfg.index_file_path)? else {
             bail!(
@@ -101,170 +713,2724 @@ impl App {
         Ok(db)
     }
 
+    /// Start a transaction for updating the index. Fails when the index doesn't exist.
+    fn begin_db_or_fail(&self) -> Result<Transaction> {
+        if !self.cfg.index_file_path.try_exists()? {
+            bail!(
+                "DB not found at {}",
+                self.cfg.index_file_path.to_string_lossy()
+            );
+        }
+        Transaction::begin(&self.cfg.index_file_path)
+    }
+
+    /// Warn about or refresh the index when it hasn't been rebuilt in a while
+    fn check_index_age(&self) -> Result<()> {
+        if self.cfg.auto_refresh == AutoRefresh::Off {
+            return Ok(());
+        }
+        let Some(header) = DB::load_header(&self.cfg.index_file_path)? else {
+            return Ok(());
+        };
+        // Indexes without a build time are not known to be out of date
+        let max_age = std::time::Duration::from_secs(self.cfg.auto_refresh_after_secs);
+        if header.age().is_none_or(|age| age <= max_age) {
+            return Ok(());
+        }
+
+        match self.cfg.auto_refresh {
+            AutoRefresh::Warn | AutoRefresh::Background if self.read_only() => {
+                eprintln!("Index is out of date.");
+            }
+            AutoRefresh::Warn => {
+                eprintln!("Index is out of date. Run `gorg update-index` to refresh it.");
+            }
+            AutoRefresh::Background => {
+                // Commands run in a quick succession would otherwise start a refresh each
+                let index_path = &self.cfg.index_file_path;
+                let marker_path = db::refresh_marker_path(index_path);
+                let refreshing = std::fs::metadata(&marker_path)
+                    .and_then(|metadata| metadata.modified())
+                    .is_ok_and(|started| {
+                        started.elapsed().unwrap_or_default() < BACKGROUND_REFRESH_WAIT
+                    });
+                if refreshing || db::is_locked(index_path) {
+                    log::debug!("Index is out of date. It's already being updated.");
+                    return Ok(());
+                }
+                log::debug!("Index is out of date. Updating it in the background.");
+                std::fs::File::create(&marker_path)?.set_modified(SystemTime::now())?;
+                let mut cmd = std::process::Command::new(std::env::current_exe()?);
+                if let Some(config_path) = &self.cli.config {
+                    cmd.arg("--config").arg(config_path);
+                }
+                cmd.arg("update-index")
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .spawn()?;
+            }
+            AutoRefresh::Off => {}
+        }
+        Ok(())
+    }
+
+    /// `insteadOf` URL rewrites from the Git configuration when they are enabled
+    fn url_rewrites(&self, git_cmd: &git_cmd::GitCmd) -> Result<Vec<git_url::UrlRewrite>> {
+        if !self.cfg.git_url_rewrites {
+            return Ok(Vec::new());
+        }
+        git_cmd.url_rewrites()
+    }
+
+    /// Projects that are checkouts of the same remotes, based on the remotes in the index
+    fn checkouts<'a>(&self, header: &'a db::Header) -> Checkouts<'a> {
+        let remotes = header
+            .remotes
+            .iter()
+            .map(|(entry, url)| (entry.as_str(), url.as_str()));
+        Checkouts::new(remotes, |url| {
+            git_url::to_path(url, self.cfg.lowercase_paths)
+                .ok()
+                .map(|path| path.join("/"))
+        })
+    }
+
+    /// Patterns for the directories to leave alone when scanning the projects directory
+    fn scan_exclude(&self) -> Vec<glob::Pattern> {
+        self.cfg
+            .scan_exclude
+            .iter()
+            .map(|pattern| glob::Pattern::new(pattern))
+            .collect()
+    }
+
+    fn git_cmd(&self) -> git_cmd::GitCmd {
+        git_cmd::GitCmd::new(self.cfg.git_command.clone(), self.cli.no_input)
+    }
+
+    fn daemon_client(&self) -> Option<daemon::Client> {
+        daemon::Client::connect(&self.cfg.daemon_socket_path)
+    }
+
     fn write_project_with_path<W: Write>(&self, w: &mut W, project: &str) -> Result<()> {
-        write!(
-            w,
-            "{}{}{project}\n",
-            self.cfg.projects_path.to_string_lossy(),
-            std::path::MAIN_SEPARATOR,
-        )?;
+        writeln!(w, "{}", self.project_path(project).to_string_lossy())?;
         Ok(())
     }
 
     fn handle_list(&self, args: &cli::ListArgs) -> Result<ExitCode> {
-        let db = self.load_db_or_fail()?;
-        let query = String::from(args.query.join(" "));
+        let query = args.query.join(" ");
         log::debug!("List with query: {query}");
+        self.check_index_age()?;
+
+        if args.explain {
+            return self.explain_matches(&query, args.explain_limit, args.full_path);
+        }
 
         let stdout = std::io::stdout().lock();
         let mut w = std::io::BufWriter::new(stdout);
 
-        match (args.full_path, args.prefix_search) {
-            (false, false) => {
-                let matches = db.find_matches(&query);
-                for project in matches {
-                    write_project(&mut w, project)?;
-                }
+        let json = args.format == cli::ListOutput::Format(cli::ListFormat::Json);
+        let template = match &args.format {
+            cli::ListOutput::Template(template) => Some(template),
+            cli::ListOutput::Format(_) => None,
+        };
+        let header = if args.remotes
+            || args.checkouts
+            || args.lang.is_some()
+            || args.tag.is_some()
+            || json
+            || template.is_some()
+        {
+            DB::load_header(&self.cfg.index_file_path)?
+        } else {
+            None
+        };
+        let checkouts = header.as_ref().map(|header| self.checkouts(header));
+        let status_filter = status::StatusFilter {
+            dirty: args.dirty,
+            ahead: args.ahead,
+            behind: args.behind,
+            detached: args.detached,
+            no_upstream: args.no_upstream,
+        };
+        let statuses = if args.status || !status_filter.is_empty() || json {
+            Some(StatusCache::load(&self.cfg.status_file_path)?)
+        } else {
+            None
+        };
+
+        let mut listed = Vec::new();
+        let min_score = args.min_score.unwrap_or(0.);
+        let mut count: usize = 0;
+        let mut workspace = Workspace {
+            folders: Vec::new(),
+        };
+        let mut emit = |project: &str, score: Option<f32>| -> Result<()> {
+            if score.is_some_and(|score| score < min_score) {
+                return Ok(());
+            }
+            if let Some(lang) = &args.lang
+                && !header
+                    .as_ref()
+                    .is_some_and(|header| header.languages(project).any(|l| l == lang))
+            {
+                return Ok(());
+            }
+            if let Some(tag) = &args.tag
+                && !header
+                    .as_ref()
+                    .is_some_and(|header| header.tags(project).any(|t| t == tag))
+            {
+                return Ok(());
+            }
+            // Projects without a cached status are not known to be in any state
+            if !status_filter.is_empty()
+                && !statuses
+                    .as_ref()
+                    .and_then(|statuses| statuses.get(project))
+                    .is_some_and(|status| status_filter.matches(status))
+            {
+                return Ok(());
+            }
+            count += 1;
+            if args.count {
+                return Ok(());
+            }
+            if json {
+                let remote = header.as_ref().and_then(|header| header.remote(project));
+                listed.push(ListedProject {
+                    project: String::from(project),
+                    path: self.project_path(project).to_string_lossy().into_owned(),
+                    score,
+                    remote,
+                    checkout_of: checkouts
+                        .as_ref()
+                        .and_then(|checkouts| checkouts.main_checkout(project)),
+                    web_url: remote.and_then(|url| {
+                        git_url::to_web_url(url, &self.cfg.web_urls)
+                            .inspect_err(|err| log::debug!("No web URL for {project}: {err}"))
+                            .ok()
+                    }),
+                    languages: header
+                        .as_ref()
+                        .map(|header| header.languages(project).collect())
+                        .unwrap_or_default(),
+                    tags: header
+                        .as_ref()
+                        .map(|header| header.tags(project).collect())
+                        .unwrap_or_default(),
+                    status: statuses
+                        .as_ref()
+                        .and_then(|statuses| statuses.get(project))
+                        .map(StatusInfo::from),
+                });
+                return Ok(());
             }
-            (false, true) => {
-                let matches = db.find_by_prefix(&query);
-                for project in matches {
-                    write_project(&mut w, project)?;
+            if let Some(template) = template {
+                let parts = db::EntryParts::parse(project);
+                let remote = header.as_ref().and_then(|header| header.remote(project));
+                let web_url = remote
+                    .and_then(|url| git_url::to_web_url(url, &self.cfg.web_urls).ok())
+                    .unwrap_or_default();
+                let description = header
+                    .as_ref()
+                    .and_then(|header| header.description(project));
+                let line = text::fill_placeholders(
+                    template,
+                    &[
+                        ("project", project),
+                        ("path", &self.project_path(project).to_string_lossy()),
+                        ("host", parts.host),
+                        ("org", parts.org),
+                        ("repo", parts.repo),
+                        ("remote", remote.unwrap_or_default()),
+                        ("web_url", &web_url),
+                        ("description", description.unwrap_or_default()),
+                    ],
+                )?;
+                writeln!(w, "{line}")?;
+                return Ok(());
+            }
+            if args.format != cli::ListOutput::Format(cli::ListFormat::Text) {
+                workspace.folders.push(WorkspaceFolder {
+                    name: String::from(project),
+                    path: self.project_path(project).to_string_lossy().into_owned(),
+                });
+                return Ok(());
+            }
+            if let (true, Some(score)) = (args.scores, score) {
+                write!(w, "{score:.3}\t")?;
+            }
+            if args.remotes || args.status || args.checkouts {
+                if args.full_path {
+                    write!(w, "{}", self.project_path(project).to_string_lossy())?;
+                } else {
+                    write!(w, "{project}")?;
+                }
+                if let (true, Some(header)) = (args.remotes, &header) {
+                    write!(w, "\t{}", header.remote(project).unwrap_or_default())?;
                 }
+                if let (true, Some(statuses)) = (args.status, &statuses) {
+                    let status = statuses.get(project).map(|status| status.summary());
+                    write!(w, "\t{}", status.unwrap_or_default())?;
+                }
+                if let (true, Some(checkouts)) = (args.checkouts, &checkouts) {
+                    let checkout = checkouts.describe(project, &self.project_path(project));
+                    write!(w, "\t{}", checkout.unwrap_or_default())?;
+                }
+                writeln!(w)?;
+                return Ok(());
+            }
+            if args.full_path {
+                self.write_project_with_path(&mut w, project)
+            } else {
+                write_project(&mut w, project)
             }
-            (true, false) => {
-                let matches = db.find_matches(&query);
-                for project in matches {
-                    self.write_project_with_path(&mut w, project)?;
+        };
+
+        let by_score = !args.prefix_search && args.sort == cli::ListSort::Score;
+        if let Some(client) = self.daemon_client() {
+            let mut response = String::new();
+            if by_score {
+                client.request(daemon::Request::Scores, &query, &mut response)?;
+                for line in response.lines() {
+                    let Some((score, project)) = line.split_once('\t') else {
+                        continue;
+                    };
+                    emit(project, Some(score.parse()?))?;
+                }
+            } else {
+                let request = if args.prefix_search {
+                    daemon::Request::Prefix
+                } else {
+                    daemon::Request::List
+                };
+                client.request(request, &query, &mut response)?;
+                for project in response.lines().filter(|line| !line.is_empty()) {
+                    emit(project, None)?;
                 }
             }
-            (true, true) => {
-                let matches = db.find_by_prefix(&query);
-                for project in matches {
-                    self.write_project_with_path(&mut w, project)?;
+        } else {
+            let db = self.load_db_or_fail()?;
+            if by_score {
+                let mut db_view = db
+                    .view()
+                    .with_exact_segment_bonus(self.cfg.exact_segment_bonus);
+                for (project, score) in db_view.find_matches(&query) {
+                    emit(project, Some(*score))?;
+                }
+            } else if args.prefix_search {
+                for project in db.find_by_prefix(&query) {
+                    emit(project, None)?;
+                }
+            } else {
+                for project in db.find_matches(&query) {
+                    emit(project, None)?;
                 }
             }
         }
 
+        if args.count {
+            writeln!(w, "{count}")?;
+        }
+        if json {
+            serde_json::to_writer_pretty(&mut w, &listed)?;
+            writeln!(w)?;
+        } else if let cli::ListOutput::Format(
+            cli::ListFormat::VscodeWorkspace | cli::ListFormat::SublimeProject,
+        ) = args.format
+        {
+            serde_json::to_writer_pretty(&mut w, &workspace)?;
+            writeln!(w)?;
+        }
         Ok(ExitCode::SUCCESS)
     }
 
-    fn project_path(&self, project: &str) -> PathBuf {
-        self.cfg.projects_path.join(project)
+    /// Record the projects as used for listing them in the `recent` sub-command
+    fn mark_used<'a, T: Iterator<Item = &'a str>>(&self, projects: T) {
+        let records: Vec<history::Record> = projects
+            .map(|project| history::Record::new(project, None))
+            .collect();
+        if let Err(err) = self.append_history(&records) {
+            log::warn!("Failed to record project use: {err}");
+        }
     }
 
-    fn handle_run(&self, args: &cli::RunArgs) -> Result<ExitCode> {
-        if args.command.len() == 0 {
-            log::error!("No command specified");
-            return Ok(ExitCode::FAILURE);
+    /// Append project uses to the history log next to the index.
+    /// The log is compacted into the index once it grows large.
+    fn append_history(&self, records: &[history::Record]) -> Result<()> {
+        if self.read_only() || !self.cfg.index_file_path.exists() {
+            return Ok(());
+        }
+        let size = history::append(&db::history_path(&self.cfg.index_file_path), records)?;
+        if size > history::COMPACT_SIZE {
+            log::debug!("Compacting the history log into the index");
+            Transaction::begin(&self.cfg.index_file_path)?.commit()?;
         }
+        Ok(())
+    }
 
+    /// Print the score breakdown of the best matches for the query
+    fn explain_matches(&self, query: &str, limit: usize, full_path: bool) -> Result<ExitCode> {
         let db = self.load_db_or_fail()?;
-        let query = args.query.as_deref().unwrap_or_default();
-
-        if args.dry {
-            for item in db.find_matches(&query) {
-                eprintln!("dry! {item}: {}", args.command.join(" "));
-            }
-            Ok(ExitCode::SUCCESS)
-        } else {
-            let mut success = true;
-            for item in db.find_matches(&query) {
-                if !args.quiet {
-                    eprintln!("{item}: {}", args.command.join(" "));
-                }
-                let dir = self.project_path(item);
-                let program = &args.command[0];
-                let args = &args.command[1..];
-                let status = std::process::Command::new(program)
-                    .args(args)
-                    .current_dir(&dir)
-                    .spawn()?
-                    .wait()?;
-                success &= status.success();
-            }
-            Ok(if success {
-                ExitCode::SUCCESS
+        let mut db_view = db
+            .view()
+            .with_exact_segment_bonus(self.cfg.exact_segment_bonus);
+        let matches: Vec<(&str, f32)> = db_view.find_matches(query).to_vec();
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        for (project, score) in matches.into_iter().take(limit) {
+            if full_path {
+                writeln!(
+                    w,
+                    "{score:.3}\t{}",
+                    self.project_path(project).to_string_lossy()
+                )?;
             } else {
-                ExitCode::FAILURE
-            })
+                writeln!(w, "{score:.3}\t{project}")?;
+            }
+            let explanation = fuzzy::explain(
+                query,
+                project,
+                db_view.description(project),
+                self.cfg.exact_segment_bonus,
+            );
+            for part in explanation.parts.iter() {
+                writeln!(w, "  {part}")?;
+            }
+            let pick_bonus = db_view.pick_bonus(query, project);
+            if pick_bonus > 0. {
+                writeln!(w, "  picked for this query before = {pick_bonus:.3}")?;
+            }
         }
+        w.flush()?;
+        Ok(ExitCode::SUCCESS)
     }
 
-    fn handle_find(&self, args: &cli::FindArgs) -> Result<ExitCode> {
-        let mut query = String::from(args.query.join(" "));
+    /// Record the project selected in the interactive finder as used. When it wasn't the best match,
+    /// it's also recorded as picked for the query, so that it ranks higher for the same query later.
+    fn record_selection(&self, selection: &Selection) {
+        let query = (selection.rank > 0).then_some(selection.query.as_str());
+        let record = history::Record::new(&selection.project, query);
+        if let Err(err) = self.append_history(&[record]) {
+            log::warn!("Failed to record project selection: {err}");
+        }
+    }
 
+    fn handle_recent(&self, args: &cli::RecentArgs) -> Result<ExitCode> {
         let db = self.load_db_or_fail()?;
-        let db_view = db.view();
-        let mut results = Vec::with_capacity(self.cfg.max_find_items);
-        db_view.find_matches(&query, &mut results);
-
-        let print_project = |project: &str| {
+        let mut stdout = std::io::stdout().lock();
+        let projects = db
+            .recent()
+            .map(|(project, _)| project)
+            .filter(|project| self.project_path(project).is_dir())
+            .take(args.number);
+        for project in projects {
             if args.full_path {
-                let path = self.cfg.projects_path.join(project);
-                println!("{}", &path.to_string_lossy());
+                self.write_project_with_path(&mut stdout, project)?;
             } else {
-                println!("{project}");
+                write_project(&mut stdout, project)?;
             }
-        };
-
-        if results.len() == 1 {
-            let project = results[0].0;
-            print_project(project);
-            return Ok(ExitCode::SUCCESS);
         }
+        Ok(ExitCode::SUCCESS)
+    }
 
-        let mut selection = None;
-        {
-            let stderr = std::io::stderr();
-            let stdin = std::io::stdin();
-            let mut ui = tui::PromptUI::new(stderr, &query)?;
-            ui.render(
-                results
-                    .iter()
-                    .take(self.cfg.max_find_items)
-                    .map(|(item, _)| *item),
-            )?;
+    fn handle_last(&self, args: &cli::LastArgs) -> Result<ExitCode> {
+        let db = self.load_db_or_fail()?;
+        let last = db
+            .recent()
+            .map(|(project, _)| project)
+            .find(|project| self.project_path(project).is_dir());
+        let Some(project) = last else {
+            log::error!("No recently used projects found");
+            return Ok(ExitCode::FAILURE);
+        };
+        let mut stdout = std::io::stdout().lock();
+        if args.name {
+            write_project(&mut stdout, project)?;
+        } else {
+            self.write_project_with_path(&mut stdout, project)?;
+        }
+        Ok(ExitCode::SUCCESS)
+    }
 
-            for event in stdin.events() {
-                let ui_event = ui.handle_event(event?);
-                match ui_event {
-                    Some(tui::PromptUIEvent::SelectionDone) => {
-                        let selected_item = ui.selected_item() as usize;
-                        if selected_item < results.len() {
-                            selection = Some(selected_item);
+    fn project_path(&self, project: &str) -> PathBuf {
+        db::entry_path(&self.cfg.projects_path, project)
+    }
+
+    fn handle_config(&self, args: &cli::ConfigArgs) -> Result<ExitCode> {
+        match &args.command {
+            cli::ConfigCommands::Show(args) => {
+                let (cfg, origins) = Config::load_with_origins(self.cli.config.as_deref())?;
+                let origins = args.origin.then_some(&origins);
+                print!("{}", cfg.to_toml(origins)?);
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_auth(&self, args: &cli::AuthArgs) -> Result<ExitCode> {
+        let token_file_path = config::required_path("token_file_path", &self.cfg.token_file_path)?;
+        let store = auth::TokenStore::new(token_file_path.to_path_buf());
+        match &args.command {
+            cli::AuthCommands::Login(args) => {
+                let stdin = std::io::stdin();
+                let token = if stdin.is_terminal() {
+                    let mut stderr = std::io::stderr();
+                    write!(stderr, "Token for {}: ", args.host)?;
+                    stderr.flush()?;
+                    let token = stdin.lock().read_passwd(&mut stderr)?;
+                    writeln!(stderr)?;
+                    token.unwrap_or_default()
+                } else {
+                    let mut token = String::new();
+                    stdin.read_line(&mut token)?;
+                    token
+                };
+                match store.set(&args.host, &token)? {
+                    auth::Storage::Keyring => {
+                        eprintln!("Token for {} stored in the OS keyring", args.host);
+                    }
+                    auth::Storage::File => eprintln!(
+                        "OS keyring not available. Token for {} stored in {}",
+                        args.host,
+                        store.file_path().to_string_lossy()
+                    ),
+                }
+            }
+            cli::AuthCommands::Logout(args) => {
+                if !store.remove(&args.host)? {
+                    log::error!("No token found for {}", args.host);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            cli::AuthCommands::Token(args) => match store.get(&args.host)? {
+                Some(token) => println!("{token}"),
+                None => {
+                    log::error!("No token found for {}", args.host);
+                    return Ok(ExitCode::FAILURE);
+                }
+            },
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_backup(&self, args: &cli::BackupArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+
+        // Git writes the bundles from the project directories
+        let backup_path = std::path::absolute(&args.to)?;
+        let bundles_path = backup_path.join(BACKUP_BUNDLES_DIR);
+        let git_cmd = self.git_cmd();
+        // Returns false when the project has no commits to bundle
+        let results = parallel::map(&projects, |project| -> Result<bool> {
+            let project_path = self.project_path(project);
+            if !git_cmd.has_ref("HEAD", &project_path)? {
+                return Ok(false);
+            }
+            let bundle_path =
+                db::entry_path(&bundles_path, &format!("{project}.{BUNDLE_EXTENSION}"));
+            if let Some(parent) = bundle_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            log::debug!("Bundling {project} to {}", bundle_path.to_string_lossy());
+            git_cmd.bundle_create(&bundle_path, &project_path)?;
+            Ok(true)
+        });
+
+        // Only the backed up projects are included in the index of the backup
+        let mut backup_db = DB::empty();
+        let mut backed_up = 0;
+        let mut failed = 0;
+        for (project, res) in projects.iter().zip(results) {
+            match res {
+                Ok(true) => backed_up += 1,
+                Ok(false) => {
+                    log::warn!("Skipping {project}: no commits");
+                    continue;
+                }
+                Err(err) => {
+                    log::error!("{err}");
+                    failed += 1;
+                    continue;
+                }
+            }
+            backup_db.add(project)?;
+            if let Some(remote_url) = db.remote(project) {
+                backup_db.set_remote(project, remote_url)?;
+            }
+            if let Some(description) = db.description(project) {
+                backup_db.describe(project, description)?;
+            }
+        }
+        backup_db.save(backup_path.join(BACKUP_INDEX_FILE))?;
+
+        eprintln!(
+            "Backed up {backed_up} projects to {}",
+            backup_path.to_string_lossy()
+        );
+        if failed > 0 {
+            log::error!("Failed to back up {failed} projects");
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_bench(&self, args: &cli::BenchArgs) -> Result<ExitCode> {
+        let work_dir = std::env::temp_dir().join(format!("gorg-bench-{}", std::process::id()));
+        std::fs::create_dir_all(&work_dir)?;
+        let res = self.run_bench(args, &work_dir);
+        if let Err(err) = std::fs::remove_dir_all(&work_dir) {
+            log::warn!("Failed to remove {}: {err}", work_dir.to_string_lossy());
+        }
+        let Some(report) = res? else {
+            return Ok(ExitCode::FAILURE);
+        };
+
+        if args.format == cli::OutputFormat::Json {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &report)?;
+            writeln!(stdout)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        println!("Projects:      {}", report.projects);
+        println!("Index load:    {}", bench::format_duration(report.load));
+        println!("Scoring:       {:.0} projects/s", report.scoring_per_sec);
+        println!(
+            "Index build:   {}",
+            bench::format_duration(report.index_build)
+        );
+        println!("Keystroke:     {}", report.keystroke);
+        if let Some(scan) = &report.scan {
+            println!(
+                "Scan:          {:.0} dirs/s ({} repos, {} dirs in {})",
+                scan.dirs_per_sec,
+                scan.repos,
+                scan.dirs,
+                bench::format_duration(scan.elapsed)
+            );
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Run the benchmarks with the temporary files in the given directory.
+    /// Returns None when the given index doesn't exist.
+    fn run_bench(&self, args: &cli::BenchArgs, work_dir: &Path) -> Result<Option<bench::Report>> {
+        let index_path = match &args.db {
+            Some(path) => path.clone(),
+            None => {
+                let path = work_dir.join("index");
+                log::info!("Generating an index of {} projects", args.projects);
+                DB::from_entries(bench::synthetic_entries(args.projects).into_iter())
+                    .save(&path)?;
+                path
+            }
+        };
+
+        let started_at = std::time::Instant::now();
+        let Some(db) = DB::load(&index_path)? else {
+            log::error!("Index not found at {}", index_path.to_string_lossy());
+            return Ok(None);
+        };
+        let load = started_at.elapsed();
+        let projects = db.view().find_matches("").len();
+
+        // Every project is scored against each query without the index narrowing them down
+        let mut view = db
+            .view()
+            .with_exact_segment_bonus(self.cfg.exact_segment_bonus);
+        let started_at = std::time::Instant::now();
+        for query in bench::QUERIES {
+            view.find_matches(query);
+        }
+        let scoring_per_sec =
+            bench::throughput(projects * bench::QUERIES.len(), started_at.elapsed());
+
+        // The interactive finder matches again after each typed character
+        let started_at = std::time::Instant::now();
+        let mut view = db
+            .view()
+            .with_exact_segment_bonus(self.cfg.exact_segment_bonus)
+            .with_index();
+        let index_build = started_at.elapsed();
+        let mut samples = Vec::new();
+        for query in bench::QUERIES {
+            for (i, _) in query.char_indices().skip(1).chain([(query.len(), ' ')]) {
+                let started_at = std::time::Instant::now();
+                view.find_matches(&query[..i]);
+                samples.push(started_at.elapsed());
+            }
+        }
+        let keystroke = bench::Latency::new(samples);
+
+        let scan = if args.scan_projects > 0 {
+            let projects_path = work_dir.join("projects");
+            log::info!("Creating {} projects to scan", args.scan_projects);
+            bench::create_project_tree(
+                &projects_path,
+                &bench::synthetic_entries(args.scan_projects),
+            )?;
+            let started_at = std::time::Instant::now();
+            let mut iter = git_dir::GitDirIterator::new(&projects_path)?;
+            let repos = iter.by_ref().filter(|res| res.is_ok()).count();
+            let elapsed = started_at.elapsed();
+            let dirs = iter.dirs_scanned();
+            Some(bench::ScanReport {
+                repos,
+                dirs,
+                elapsed,
+                dirs_per_sec: bench::throughput(dirs, elapsed),
+            })
+        } else {
+            None
+        };
+
+        Ok(Some(bench::Report {
+            projects,
+            load,
+            scoring_per_sec,
+            index_build,
+            keystroke,
+            scan,
+        }))
+    }
+
+    fn handle_restore(&self, args: &cli::RestoreArgs) -> Result<ExitCode> {
+        let backup_path = std::path::absolute(&args.from)?;
+        let backup_index_path = backup_path.join(BACKUP_INDEX_FILE);
+        let Some(backup_db) = DB::load(&backup_index_path)? else {
+            log::error!(
+                "Backup index not found at {}",
+                backup_index_path.to_string_lossy()
+            );
+            return Ok(ExitCode::FAILURE);
+        };
+
+        let mut skipped = 0;
+        let projects: Vec<&str> = backup_db
+            .entries()
+            .filter(|project| {
+                let exists = self.project_path(project).join(".git").exists();
+                if exists {
+                    log::warn!("Skipping {project}: project already exists");
+                    skipped += 1;
+                }
+                !exists
+            })
+            .collect();
+
+        let bundles_path = backup_path.join(BACKUP_BUNDLES_DIR);
+        let git_cmd = self.git_cmd();
+        let results = parallel::map(&projects, |project| -> Result<()> {
+            // The backup may come from elsewhere, so it's not trusted to stay in the directories
+            git_url::check_path(project)?;
+            if let Some(remote_url) = backup_db.remote(project) {
+                git_url::check_scheme(remote_url, &self.cfg.allowed_url_schemes)?;
+            }
+            let bundle_path =
+                db::entry_path(&bundles_path, &format!("{project}.{BUNDLE_EXTENSION}"));
+            if !bundle_path.is_file() {
+                bail!(
+                    "Bundle for {project} not found at {}",
+                    bundle_path.to_string_lossy()
+                );
+            }
+            let project_path = self.project_path(project);
+            log::debug!("Restoring {project} from {}", bundle_path.to_string_lossy());
+            let remote_name = &self.cfg.git_remote_name;
+            git_cmd.clone_bundle(&bundle_path, &project_path, remote_name)?;
+            // The bundle is not a useful remote after the restore
+            match backup_db.remote(project) {
+                Some(remote_url) => git_cmd.remote_set_url(remote_name, remote_url, &project_path),
+                None => git_cmd.remote_remove(remote_name, &project_path),
+            }
+        });
+
+        let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+        let mut failed = 0;
+        for (project, res) in projects.iter().zip(results) {
+            if let Err(err) = res {
+                log::error!("{err}");
+                failed += 1;
+                continue;
+            }
+            println!("{project}");
+            let bundle_path =
+                db::entry_path(&bundles_path, &format!("{project}.{BUNDLE_EXTENSION}"));
+            self.log_operation(Action::Restore, project, &bundle_path.to_string_lossy());
+            db.add(project)?;
+            if let Some(remote_url) = backup_db.remote(project) {
+                db.set_remote(project, remote_url)?;
+            }
+            if let Some(description) = backup_db.description(project)
+                && db.description(project).is_none()
+            {
+                db.describe(project, description)?;
+            }
+        }
+        db.commit()?;
+
+        eprintln!(
+            "Restored {} projects, skipped {skipped} existing projects",
+            projects.len() - failed
+        );
+        if failed > 0 {
+            log::error!("Failed to restore {failed} projects");
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_branches(&self, args: &cli::BranchesArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+
+        let git_cmd = self.git_cmd();
+        let results = parallel::map(&projects, |project| -> Result<ProjectBranch> {
+            let path = self.project_path(project);
+            Ok(ProjectBranch {
+                project,
+                branch: git_cmd.current_branch(&path)?,
+                default_branch: git_cmd.default_branch(&self.cfg.git_remote_name, &path)?,
+            })
+        });
+        let mut branches = Vec::with_capacity(results.len());
+        for res in results {
+            match res {
+                Ok(branch) => branches.push(branch),
+                Err(err) => log::error!("{err}"),
+            }
+        }
+
+        let stdout = std::io::stdout();
+        let highlight = stdout.is_terminal();
+        let mut w = std::io::BufWriter::new(stdout.lock());
+        if args.json || args.format == cli::OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut w, &branches)?;
+            writeln!(w)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        for branch in branches.iter() {
+            let name = branch.branch.as_deref().unwrap_or("(detached)");
+            if branch.is_on_default_branch() {
+                writeln!(w, "{}\t{name}", branch.project)?;
+                continue;
+            }
+            let default_branch = branch.default_branch.as_deref().unwrap_or_default();
+            if highlight {
+                writeln!(
+                    w,
+                    "{}\t{}{name}{}\t(default: {default_branch})",
+                    branch.project,
+                    termion::color::Fg(termion::color::Yellow),
+                    termion::color::Fg(termion::color::Reset),
+                )?;
+            } else {
+                writeln!(w, "{}\t{name}\t(default: {default_branch})", branch.project)?;
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_push_check(&self, args: &cli::PushCheckArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+
+        let git_cmd = self.git_cmd();
+        let results = parallel::map(&projects, |project| -> Result<UnpushedWork> {
+            let path = self.project_path(project);
+            Ok(UnpushedWork {
+                project,
+                commits: git_cmd.count_unpushed(&path)?,
+                stashes: git_cmd.count_stashes(&path)?,
+            })
+        });
+        let mut failed = false;
+        let mut unpushed = Vec::new();
+        for res in results {
+            match res {
+                Ok(work) if work.commits > 0 || work.stashes > 0 => unpushed.push(work),
+                Ok(_) => {}
+                Err(err) => {
+                    log::error!("{err}");
+                    failed = true;
+                }
+            }
+        }
+
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        if args.json || args.format == cli::OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut w, &unpushed)?;
+            writeln!(w)?;
+        } else {
+            for work in unpushed.iter() {
+                writeln!(
+                    w,
+                    "{}\t{} unpushed commits\t{} stashes",
+                    work.project, work.commits, work.stashes
+                )?;
+            }
+        }
+        Ok(if failed || !unpushed.is_empty() {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        })
+    }
+
+    fn handle_doctor(&self, args: &cli::DoctorArgs) -> Result<ExitCode> {
+        let path = &self.cfg.index_file_path;
+        let Some(problems) = db::verify_file(path)? else {
+            log::error!("DB not found at {}", path.to_string_lossy());
+            return Ok(ExitCode::FAILURE);
+        };
+        if problems.is_empty() {
+            println!("No problems found in {}", path.to_string_lossy());
+            return Ok(ExitCode::SUCCESS);
+        }
+        for problem in problems.iter() {
+            println!("{}:{problem}", path.to_string_lossy());
+        }
+        if !args.fix {
+            eprintln!("Run `gorg doctor --verify-db --fix` to repair the index");
+            return Ok(ExitCode::FAILURE);
+        }
+        db::repair_file(path)?;
+        println!("Repaired the index");
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_du(&self, args: &cli::DuArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db.find_matches(&query).collect();
+
+        let results = parallel::map(&projects, |project| {
+            disk_usage::measure(&self.project_path(project))
+        });
+        let mut usages = Vec::with_capacity(results.len());
+        for (project, res) in projects.iter().zip(results) {
+            match res {
+                Ok(usage) => usages.push((*project, usage)),
+                Err(err) => log::error!("Failed to measure disk usage of {project}: {err}"),
+            }
+        }
+        match args.sort {
+            cli::DuSort::Name => usages.sort_by_key(|(project, _)| *project),
+            cli::DuSort::Size => usages.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.total())),
+        }
+
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        let mut total = disk_usage::DiskUsage::default();
+        for (project, usage) in usages.iter() {
+            let size = disk_usage::format_size(usage.total());
+            if args.git {
+                writeln!(
+                    w,
+                    "{size}\t{}\t{}\t{project}",
+                    disk_usage::format_size(usage.git),
+                    disk_usage::format_size(usage.work_tree),
+                )?;
+            } else {
+                writeln!(w, "{size}\t{project}")?;
+            }
+            total.git += usage.git;
+            total.work_tree += usage.work_tree;
+        }
+        w.flush()?;
+        if args.git {
+            eprintln!(
+                "Total: {} (git: {}, working tree: {})",
+                disk_usage::format_size(total.total()),
+                disk_usage::format_size(total.git),
+                disk_usage::format_size(total.work_tree),
+            );
+        } else {
+            eprintln!("Total: {}", disk_usage::format_size(total.total()));
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_sync_orgs(&self, args: &cli::SyncOrgsArgs) -> Result<ExitCode> {
+        if self.cfg.mirrors.is_empty() {
+            log::error!(
+                "No organizations to mirror. Add them to the configuration using [[mirror]]."
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+        let git_cmd = self.git_cmd();
+        let tokens = auth::TokenStore::new(self.cfg.token_file_path.clone());
+        let client = forge::ForgeClient::new(&tokens);
+        // Dry runs don't lock the index, so that they work on read-only file systems
+        let mut tx = match args.dry {
+            true => None,
+            false => Some(Transaction::begin(&self.cfg.index_file_path)?),
+        };
+        // The transaction gets the DB back when it's committed
+        let mut db = match tx.as_mut() {
+            Some(tx) => std::mem::take(&mut **tx),
+            None => DB::load(&self.cfg.index_file_path)?.unwrap_or_default(),
+        };
+
+        let mut failed = 0;
+        for mirror in self.cfg.mirrors.iter() {
+            if let Err(err) = self.sync_mirror(&git_cmd, &client, &mut db, mirror, args) {
+                log::error!("Failed to mirror {}/{}: {err}", mirror.host, mirror.org);
+                failed += 1;
+            }
+        }
+        if let Some(mut tx) = tx {
+            *tx = db;
+            tx.commit()?;
+        }
+        Ok(if failed > 0 {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        })
+    }
+
+    fn sync_mirror(
+        &self,
+        git_cmd: &git_cmd::GitCmd,
+        client: &forge::ForgeClient,
+        db: &mut DB,
+        mirror: &Mirror,
+        args: &cli::SyncOrgsArgs,
+    ) -> Result<()> {
+        let dry = args.dry;
+        let repos = client.list_repos(mirror)?;
+        log::debug!(
+            "Found {} repositories for {}/{}",
+            repos.len(),
+            mirror.host,
+            mirror.org
+        );
+
+        let rewrites = self.url_rewrites(git_cmd)?;
+        let mut mirrored = Vec::with_capacity(repos.len());
+        let mut skipped = 0;
+        for repo in repos.iter() {
+            let url = repo.url(mirror.protocol);
+            let clone_url = git_url::rewrite(url, &rewrites);
+            let project_path =
+                match git_url::check_scheme(&clone_url, &self.cfg.allowed_url_schemes)
+                    .and_then(|_| git_url::to_path(&clone_url, self.cfg.lowercase_paths))
+                {
+                    Ok(project_path) => project_path,
+                    Err(err) => {
+                        log::error!("Skipping repository: {err}");
+                        skipped += 1;
+                        continue;
+                    }
+                };
+            let entry = project_path.join("/");
+            let project_full_path = self.project_path(&entry);
+
+            if !project_full_path.join(".git").try_exists()? {
+                println!("+ {entry}");
+                if !dry {
+                    if let Err(err) = git_cmd.clone_repo(url, project_full_path.as_os_str()) {
+                        log::error!("{err}");
+                        continue;
+                    }
+                    self.log_operation(Action::Clone, &entry, url);
+                }
+            } else {
+                let remote_name = &self.cfg.git_remote_name;
+                let remote_url = git_cmd.remote_get_url(remote_name, &project_full_path)?;
+                if remote_url.as_deref() != Some(&clone_url) {
+                    println!("~ {entry}");
+                    match (dry, remote_url) {
+                        (true, _) => {}
+                        (false, Some(_)) => {
+                            git_cmd.remote_set_url(remote_name, url, &project_full_path)?
+                        }
+                        (false, None) => {
+                            git_cmd.remote_add(remote_name, url, &project_full_path)?
+                        }
+                    }
+                }
+            }
+            db.add(&entry)?;
+            db.set_remote(&entry, &clone_url)?;
+            db.mark_mirrored(&entry);
+            mirrored.push(entry);
+        }
+
+        // An empty list is more likely a problem with the API access than an empty organization
+        if repos.is_empty() {
+            log::warn!(
+                "No repositories found for {}/{}. Skipping archiving.",
+                mirror.host,
+                mirror.org
+            );
+            return Ok(());
+        }
+        let prefix = git_url::normalize_path_case(
+            &format!("{}/{}/", mirror.host, mirror.org),
+            self.cfg.lowercase_paths,
+        );
+        // Skipped repositories may be among the projects that look removed
+        if skipped > 0 {
+            log::warn!(
+                "Skipped {skipped} repositories of {}/{}. Skipping archiving.",
+                mirror.host,
+                mirror.org
+            );
+            return Ok(());
+        }
+        // Only projects listed in an earlier sync are archived. The listing may leave out
+        // e.g. private repositories, and local projects were never listed.
+        let removed: Vec<String> = db
+            .unlisted_mirrored(&prefix, &mirrored)
+            .into_iter()
+            .filter(|entry| self.project_path(entry).join(".git").is_dir())
+            .collect();
+        if removed.is_empty() {
+            return Ok(());
+        }
+        for entry in removed.iter() {
+            println!("- {entry}");
+        }
+        if dry {
+            return Ok(());
+        }
+        if !args.archive {
+            eprintln!(
+                "Run `gorg sync-orgs --archive` to archive the projects removed from {}/{}",
+                mirror.host, mirror.org
+            );
+            return Ok(());
+        }
+        let question = format!(
+            "Archive {} projects removed from {}/{}?",
+            removed.len(),
+            mirror.host,
+            mirror.org
+        );
+        if !self.confirm(&question)? {
+            return Ok(());
+        }
+        for entry in removed {
+            self.archive_project(db, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Move a project to the archive directory and remove it from the index
+    fn archive_project(&self, db: &mut DB, entry: &str) -> Result<()> {
+        let archive_path = config::required_path("archive_path", &self.cfg.archive_path)?;
+        let archived_path = db::entry_path(archive_path, entry);
+        if archived_path.try_exists()? {
+            bail!(
+                "Cannot archive {entry}: {} already exists",
+                archived_path.to_string_lossy()
+            );
+        }
+        if let Some(parent) = archived_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        log::debug!("Archiving {entry} to {}", archived_path.to_string_lossy());
+        std::fs::rename(self.project_path(entry), &archived_path)?;
+        db.remove(entry);
+        db.unmark_mirrored(entry);
+        self.log_operation(Action::Archive, entry, &archived_path.to_string_lossy());
+        Ok(())
+    }
+
+    fn handle_switch(&self, args: &cli::SwitchArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+
+        let git_cmd = self.git_cmd();
+        let results = parallel::map(&projects, |project| {
+            self.switch_branch(&git_cmd, project, args)
+        });
+
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        let (mut switched, mut skipped, mut failed) = (0, 0, 0);
+        for (project, res) in projects.iter().zip(results) {
+            match res {
+                Ok(outcome) => {
+                    match outcome {
+                        SwitchOutcome::Switched | SwitchOutcome::Created => switched += 1,
+                        SwitchOutcome::Dirty | SwitchOutcome::MissingBranch => skipped += 1,
+                        SwitchOutcome::AlreadyOnBranch => {}
+                    }
+                    writeln!(w, "{project}\t{}", outcome.as_str())?;
+                }
+                Err(err) => {
+                    log::error!("{err}");
+                    failed += 1;
+                }
+            }
+        }
+        w.flush()?;
+
+        let dry = if args.dry { " (dry run)" } else { "" };
+        eprintln!(
+            "Switched {switched} projects to {}, skipped {skipped}, failed {failed}{dry}",
+            args.branch
+        );
+        Ok(if failed > 0 {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        })
+    }
+
+    fn switch_branch(
+        &self,
+        git_cmd: &git_cmd::GitCmd,
+        project: &str,
+        args: &cli::SwitchArgs,
+    ) -> Result<SwitchOutcome> {
+        let path = self.project_path(project);
+        let status = status::read_status(git_cmd, &path)?;
+        if status.branch.as_deref() == Some(args.branch.as_str()) {
+            return Ok(SwitchOutcome::AlreadyOnBranch);
+        }
+        if status.dirty {
+            return Ok(SwitchOutcome::Dirty);
+        }
+
+        if git_cmd.has_ref(&format!("refs/heads/{}", args.branch), &path)? {
+            log::debug!("Checking out {} in {project}", args.branch);
+            if !args.dry {
+                git_cmd.checkout(&args.branch, None, &path)?;
+            }
+            return Ok(SwitchOutcome::Switched);
+        }
+
+        let remote_branch = format!("{}/{}", self.cfg.git_remote_name, args.branch);
+        if !args.create || !git_cmd.has_ref(&format!("refs/remotes/{remote_branch}"), &path)? {
+            return Ok(SwitchOutcome::MissingBranch);
+        }
+        log::debug!("Creating {} from {remote_branch} in {project}", args.branch);
+        if !args.dry {
+            git_cmd.checkout(&args.branch, Some(&remote_branch), &path)?;
+        }
+        Ok(SwitchOutcome::Created)
+    }
+
+    fn handle_status(&self, args: &cli::StatusArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        if args.watch {
+            if !std::io::stdout().is_terminal() {
+                log::error!("Watching the statuses requires a terminal");
+                return Ok(ExitCode::FAILURE);
+            }
+            let mut statuses = StatusCache::load(&self.cfg.status_file_path)?;
+            let interval = Duration::from_secs(args.interval);
+            loop {
+                // Matched again on each pass to pick up the projects indexed in the meantime
+                let db = self.load_db_or_fail()?;
+                let projects: Vec<&str> = db
+                    .find_matches(&query)
+                    .filter(|project| self.project_path(project).join(".git").exists())
+                    .collect();
+                self.refresh_statuses(&db, &projects, &mut statuses)?;
+                let stdout = std::io::stdout().lock();
+                let mut w = std::io::BufWriter::new(stdout);
+                write!(w, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1))?;
+                writeln!(
+                    w,
+                    "Every {}s, refreshed at {}\n",
+                    args.interval,
+                    oplog::format_time(SystemTime::now())
+                )?;
+                write_statuses(&mut w, &projects, &statuses)?;
+                w.flush()?;
+                drop(w);
+                std::thread::sleep(interval);
+            }
+        }
+
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+        let mut statuses = StatusCache::load(&self.cfg.status_file_path)?;
+        if args.refresh {
+            self.refresh_statuses(&db, &projects, &mut statuses)?;
+        }
+
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        if args.format == cli::ReportFormat::Markdown {
+            write_status_table(&mut w, &projects, &statuses)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        if args.format == cli::ReportFormat::Json {
+            let statuses: Vec<ProjectStatus> = projects
+                .iter()
+                .map(|project| ProjectStatus {
+                    project,
+                    status: statuses.get(project).map(StatusInfo::from),
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut w, &statuses)?;
+            writeln!(w)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        write_statuses(&mut w, &projects, &statuses)?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_sync(&self, args: &cli::SyncArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        let db = self.load_db_or_fail()?;
+        let projects: Vec<&str> = db
+            .find_matches(&query)
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+
+        let git_cmd = self.git_cmd();
+        // Prompts from several fetches at a time would get mixed up,
+        // so fetches that need input are retried one at a time afterwards
+        let parallel_git_cmd = git_cmd.without_input();
+        let mut results = parallel::map_limited(
+            &projects,
+            |project| EntryParts::parse(project).host,
+            self.cfg.max_fetches_per_host,
+            |project| {
+                log::debug!("Fetching {project}");
+                parallel_git_cmd.fetch(self.project_path(project))
+            },
+        );
+        if git_cmd.is_interactive() {
+            for (project, res) in projects.iter().zip(results.iter_mut()) {
+                if let Err(err) = res
+                    && git_cmd::needs_input(err)
+                {
+                    eprintln!("Fetching {project} with prompts for credentials");
+                    *res = git_cmd.fetch(self.project_path(project));
+                }
+            }
+        }
+        let mut failed = 0;
+        let mut redirects = Vec::new();
+        for (project, res) in projects.iter().zip(results) {
+            match res {
+                Ok(Some(new_url)) => redirects.push((String::from(*project), new_url)),
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!("{err}");
+                    failed += 1;
+                }
+            }
+        }
+
+        let mut statuses = StatusCache::load(&self.cfg.status_file_path)?;
+        self.refresh_statuses(&db, &projects, &mut statuses)?;
+        eprintln!("Fetched {} projects", projects.len() - failed);
+
+        if !redirects.is_empty() {
+            let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+            for (project, new_url) in redirects.iter() {
+                if let Err(err) = self.follow_redirect(&mut db, project, new_url) {
+                    log::error!("Failed to update the remote of {project}: {err}");
+                }
+            }
+            db.commit()?;
+        }
+        if failed > 0 {
+            log::error!("Failed to fetch {failed} projects");
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Read the Git status of the given projects into the status cache and save it.
+    /// Statuses of projects that are no longer in the index are dropped.
+    fn refresh_statuses(
+        &self,
+        db: &DB,
+        projects: &[&str],
+        statuses: &mut StatusCache,
+    ) -> Result<()> {
+        let git_cmd = self.git_cmd();
+        let projects: Vec<&str> = projects
+            .iter()
+            .copied()
+            .filter(|project| self.project_path(project).join(".git").exists())
+            .collect();
+        status::refresh(&git_cmd, &self.cfg.projects_path, &projects, statuses);
+
+        let entries: Vec<&str> = db.entries().collect();
+        statuses.retain(|entry| entries.binary_search(&entry).is_ok());
+        if self.read_only() {
+            return Ok(());
+        }
+        statuses.save(&self.cfg.status_file_path)
+    }
+
+    fn handle_run(&self, args: &cli::RunArgs) -> Result<ExitCode> {
+        let commands = if let Some(script) = &args.script {
+            // The script is run from the project directories, so it needs an absolute path
+            let Ok(script) = std::fs::canonicalize(script) else {
+                log::error!("Script not found: {}", script.to_string_lossy());
+                return Ok(ExitCode::FAILURE);
+            };
+            let shell = std::env::var("SHELL").unwrap_or_else(|_| String::from("sh"));
+            vec![vec![shell, script.to_string_lossy().into_owned()]]
+        } else if args.commands.is_empty() && args.command.is_empty() {
+            // Each project runs the task from its configuration
+            Vec::new()
+        } else if args.commands.is_empty() {
+            vec![args.command.clone()]
+        } else {
+            let mut commands = Vec::new();
+            for command in args.commands.iter() {
+                commands.push(text::split_words(command)?);
+            }
+            commands
+        };
+        if commands.iter().any(|command| command.is_empty()) {
+            log::error!("No command specified");
+            return Ok(ExitCode::FAILURE);
+        }
+        let commands_str = match commands.is_empty() {
+            true => String::from("(project task)"),
+            false => format_commands(&commands),
+        };
+
+        if let Some(subdir) = &args.subdir
+            && (subdir.is_absolute()
+                || subdir
+                    .components()
+                    .any(|c| c == std::path::Component::ParentDir))
+        {
+            log::error!(
+                "Sub-directory must be inside the projects: {}",
+                subdir.to_string_lossy()
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+
+        self.check_index_age()?;
+        let db = self.load_db_or_fail()?;
+        let query = args.query.as_deref().unwrap_or_default();
+        let projects = db.find_matches(query).filter(|project| {
+            args.lang
+                .as_ref()
+                .is_none_or(|lang| db.languages(project).any(|l| l == lang))
+                && args
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| db.tags(project).any(|t| t == tag))
+        });
+        let targets = self.run_targets(
+            projects,
+            args.subdir.as_deref(),
+            &commands,
+            args.query.is_none(),
+        );
+
+        if args.dry {
+            for target in targets.iter() {
+                eprintln!(
+                    "dry! {}: {}",
+                    target.project,
+                    format_commands(&target.commands)
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        } else {
+            // Running a command in all projects doesn't say much about which ones are in use
+            if args.query.is_some() {
+                self.mark_used(targets.iter().map(|target| target.project));
+            }
+            self.log_operation(Action::Run, query, &commands_str);
+            if let Some(jobs) = args.jobs {
+                return self.run_in_parallel(&targets, args, jobs);
+            }
+            let json = args.format == cli::OutputFormat::Json;
+            let mut failures = Vec::new();
+            for RunTarget {
+                project: item,
+                dir,
+                commands,
+            } in targets.iter()
+            {
+                for command in commands.iter() {
+                    if !args.quiet {
+                        eprintln!("{item}: {}", command.join(" "));
+                    }
+                    let mut process = std::process::Command::new(&command[0]);
+                    process.args(&command[1..]).current_dir(dir);
+                    // Stdout is reserved for the summary
+                    if json {
+                        process.stdout(std::io::stderr());
+                    }
+                    let status = process.spawn()?.wait()?;
+                    if !status.success() {
+                        let error = if commands.len() > 1 {
+                            format!("{}: {status}", command.join(" "))
+                        } else {
+                            status.to_string()
+                        };
+                        failures.push((*item, error));
+                        break;
+                    }
+                }
+            }
+            for (item, error) in failures.iter() {
+                log::error!("{item}: {error}");
+            }
+            if json {
+                let results: Vec<RunResult> = targets
+                    .iter()
+                    .map(|target| {
+                        let error = failures
+                            .iter()
+                            .find(|(failed, _)| *failed == target.project)
+                            .map(|(_, error)| error.clone());
+                        RunResult {
+                            project: target.project,
+                            ok: error.is_none(),
+                            error,
+                        }
+                    })
+                    .collect();
+                print_json(&results)?;
+            }
+            Ok(if failures.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+    }
+
+    /// Directories and commands to run for the given projects. Without commands, the task from
+    /// the project configuration is run. Projects without the sub-directory, projects excluded
+    /// from runs in all projects, and projects without a task are skipped.
+    fn run_targets<'a, T: Iterator<Item = &'a str>>(
+        &self,
+        projects: T,
+        subdir: Option<&Path>,
+        commands: &[Vec<String>],
+        all_projects: bool,
+    ) -> Vec<RunTarget<'a>> {
+        let mut targets = Vec::new();
+        for project in projects {
+            let project_path = self.project_path(project);
+            let dir = match subdir {
+                Some(subdir) => project_path.join(subdir),
+                None => project_path.clone(),
+            };
+            if subdir.is_some() && !dir.is_dir() {
+                eprintln!("skip! {project}: {} not found", dir.to_string_lossy());
+                continue;
+            }
+            let project_cfg = ProjectConfig::load_or_default(&project_path);
+            if all_projects && project_cfg.exclude_from_runs {
+                eprintln!("skip! {project}: excluded from runs in all projects");
+                continue;
+            }
+            let commands = if !commands.is_empty() {
+                commands.to_vec()
+            } else if let Some(task) = &project_cfg.task {
+                match text::split_words(task) {
+                    Ok(task) if !task.is_empty() => vec![task],
+                    Ok(_) => {
+                        log::error!("{project}: task is empty");
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("{project}: {err}");
+                        continue;
+                    }
+                }
+            } else {
+                log::debug!("Skipping {project}: no task");
+                continue;
+            };
+            targets.push(RunTarget {
+                project,
+                dir,
+                commands,
+            });
+        }
+        targets
+    }
+
+    fn run_in_parallel(
+        &self,
+        targets: &[RunTarget],
+        args: &cli::RunArgs,
+        jobs: usize,
+    ) -> Result<ExitCode> {
+        if jobs == 0 {
+            log::error!("Number of jobs must be at least 1");
+            return Ok(ExitCode::FAILURE);
+        }
+        if args.tui && !std::io::stderr().is_terminal() {
+            log::error!("The jobs view requires a terminal");
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let runner = jobs::Runner::new(targets.len());
+        let indices: Vec<usize> = (0..targets.len()).collect();
+        let (tx, rx) = mpsc::channel();
+        let mut jobs_state: Vec<jobs::Job> = targets
+            .iter()
+            .map(|target| jobs::Job::new(String::from(target.project)))
+            .collect();
+
+        let res = std::thread::scope(|scope| {
+            let runner = &runner;
+            scope.spawn(move || {
+                parallel::map_in_threads(&indices, jobs, |index| {
+                    let target = &targets[*index];
+                    runner.run(*index, &target.dir, &target.commands, &tx);
+                });
+            });
+            let res = if args.tui {
+                self.show_jobs(&rx, runner, &mut jobs_state)
+            } else {
+                // Commands run in their own process groups and don't get the signals
+                // from the terminal (e.g. Ctrl-C), so they are cancelled here instead
+                let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+                let signals_handle = signals.handle();
+                scope.spawn(move || {
+                    if signals.forever().next().is_some() {
+                        runner.cancel();
+                    }
+                });
+                let to_stderr = args.format == cli::OutputFormat::Json;
+                let prefix = !args.quiet && !args.no_prefix;
+                let res = self.print_jobs(&rx, &mut jobs_state, prefix, to_stderr);
+                signals_handle.close();
+                res
+            };
+            // Don't leave commands running when the view failed
+            if res.is_err() {
+                runner.cancel();
+            }
+            res
+        });
+        res?;
+
+        let mut results = Vec::new();
+        for job in jobs_state.iter() {
+            let error = match &job.state {
+                jobs::JobState::Ok => None,
+                jobs::JobState::Failed(reason) => Some(reason.clone()),
+                state => Some(String::from(state.as_str())),
+            };
+            if let Some(error) = &error {
+                log::error!("{}: {error}", job.project);
+            }
+            results.push(RunResult {
+                project: &job.project,
+                ok: error.is_none(),
+                error,
+            });
+        }
+        let success = results.iter().all(|result| result.ok);
+        if args.format == cli::OutputFormat::Json {
+            print_json(&results)?;
+        }
+        Ok(if success {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+
+    /// Print the output of parallel jobs line by line as it arrives, optionally prefixed with
+    /// the project tags. With `to_stderr`, standard output of the jobs is printed to stderr as well.
+    fn print_jobs(
+        &self,
+        rx: &mpsc::Receiver<jobs::Message>,
+        jobs_state: &mut [jobs::Job],
+        prefix: bool,
+        to_stderr: bool,
+    ) -> Result<()> {
+        // Tags are colored only on terminals, so that piped output stays plain
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let width = jobs_state
+            .iter()
+            .map(|job| job.project.chars().count() + 2)
+            .max()
+            .unwrap_or_default();
+        let tags = |colored: bool| -> Vec<String> {
+            jobs_state
+                .iter()
+                .enumerate()
+                .map(|(index, job)| match prefix {
+                    true => jobs::line_tag(&job.project, index, width, colored && !no_color),
+                    false => String::new(),
+                })
+                .collect()
+        };
+        let stdout_tags = tags(std::io::stdout().is_terminal());
+        let stderr_tags = tags(std::io::stderr().is_terminal());
+
+        while let Ok(message) = rx.recv() {
+            match message {
+                jobs::Message::Started(index) => jobs_state[index].state = jobs::JobState::Running,
+                jobs::Message::Output(index, stream, line) => match stream {
+                    jobs::Stream::Stdout if !to_stderr => println!("{}{line}", stdout_tags[index]),
+                    jobs::Stream::Stdout => eprintln!("{}{line}", stderr_tags[index]),
+                    jobs::Stream::Stderr => eprintln!("{}{line}", stderr_tags[index]),
+                },
+                jobs::Message::Finished(index, state) => jobs_state[index].state = state,
+            }
+        }
+        Ok(())
+    }
+
+    /// Show the state and output of parallel jobs in a full-screen view until the user quits.
+    /// Quitting cancels the jobs that are still running.
+    fn show_jobs(
+        &self,
+        rx: &mpsc::Receiver<jobs::Message>,
+        runner: &jobs::Runner,
+        jobs_state: &mut [jobs::Job],
+    ) -> Result<()> {
+        let mut view = jobs::JobsView::new(std::io::stderr())?;
+        let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM, SIGWINCH])?;
+        let mut quitting = false;
+        view.render(jobs_state)?;
+
+        loop {
+            let mut render = false;
+            for signal in signals.pending() {
+                if signal != SIGWINCH {
+                    runner.cancel();
+                    bail!("Interrupted by signal {signal}");
+                }
+                render = true;
+            }
+            while let Ok(message) = rx.try_recv() {
+                match message {
+                    jobs::Message::Started(index) => {
+                        jobs_state[index].state = jobs::JobState::Running
+                    }
+                    jobs::Message::Output(_, _, _) if quitting => {}
+                    jobs::Message::Output(index, _, line) => jobs_state[index].push_output(&line),
+                    jobs::Message::Finished(index, state) => jobs_state[index].state = state,
+                }
+                render = true;
+            }
+            let all_done = jobs_state.iter().all(|job| job.state.is_done());
+            if quitting && all_done {
+                return Ok(());
+            }
+
+            for event in tui::read_events(TUI_POLL_INTERVAL)? {
+                match view.handle_event(event, jobs_state.len()) {
+                    Some(jobs::ViewEvent::Quit) if all_done => return Ok(()),
+                    Some(jobs::ViewEvent::Quit) => {
+                        quitting = true;
+                        runner.cancel();
+                        view.set_message(String::from("Cancelling running commands..."));
+                        render = true;
+                    }
+                    Some(jobs::ViewEvent::Render) => render = true,
+                    None => {}
+                }
+            }
+            if render {
+                view.render(jobs_state)?;
+            }
+        }
+    }
+
+    fn handle_find(&self, args: &cli::FindArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        self.check_index_age()?;
+        let options = self.prompt_options(args.full_screen, args.layout);
+        if let Some(selection) = self.find_project(query, options)? {
+            self.record_selection(&selection);
+            if args.full_path {
+                let path = self.project_path(&selection.project);
+                println!("{}", &path.to_string_lossy());
+            } else {
+                println!("{}", selection.project);
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_open(&self, args: &cli::OpenArgs) -> Result<ExitCode> {
+        let query = args.query.join(" ");
+        self.check_index_age()?;
+        // Without a query, the project containing the current directory is opened if there is one
+        let current = if query.is_empty()
+            && let Some(db) = DB::load(&self.cfg.index_file_path)?
+        {
+            self.project_of_dir(&db, &std::env::current_dir()?)?
+                .map(|project| Selection {
+                    project: String::from(project),
+                    query: String::new(),
+                    rank: 0,
+                })
+        } else {
+            None
+        };
+        let selection = match current {
+            Some(selection) => selection,
+            None => {
+                let options = self.prompt_options(false, None);
+                let Some(selection) = self.find_project(query, options)? else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                selection
+            }
+        };
+        let path = self.project_path(&selection.project);
+        let languages = lang::detect(&path);
+        let project_cfg = ProjectConfig::load_or_default(&path);
+        let template = opener::choose(
+            args.with.as_deref(),
+            project_cfg.opener.as_deref(),
+            &languages,
+            self.cfg.opener.as_deref(),
+            &self.cfg.openers,
+            &self.cfg.lang_openers,
+        )?
+        .map(String::from)
+        .unwrap_or_else(opener::editor);
+        let command_line = opener::command_line(&template, &path)?;
+        self.record_selection(&selection);
+
+        log::debug!(
+            "Opening {} with: {}",
+            selection.project,
+            command_line.join(" ")
+        );
+        let status = match std::process::Command::new(&command_line[0])
+            .args(&command_line[1..])
+            .current_dir(&path)
+            .status()
+        {
+            Ok(status) => status,
+            Err(err) => {
+                log::error!("Failed to run {}: {err}", command_line[0]);
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+        if !status.success() {
+            log::error!("Opener failed for {}: {status}", selection.project);
+            return Ok(ExitCode::FAILURE);
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn prompt_options(&self, full_screen: bool, layout: Option<Layout>) -> tui::PromptOptions {
+        let mut options = tui::PromptOptions {
+            full_screen: full_screen || self.cfg.full_screen,
+            layout: layout.unwrap_or(self.cfg.layout),
+            max_items: self.cfg.max_find_items,
+            mouse: self.cfg.mouse,
+        };
+        if options.full_screen {
+            let (_, height) = termion::terminal_size().unwrap_or((80, 80));
+            // Leave room for the prompt, and in the default layout the line below the last match
+            options.max_items = match options.layout {
+                Layout::Default => (height as usize).saturating_sub(2),
+                Layout::Reverse => (height as usize).saturating_sub(1),
+            };
+        }
+        options
+    }
+
+    /// Select a project matching the query, interactively when there are several matches
+    fn find_project(
+        &self,
+        query: String,
+        options: tui::PromptOptions,
+    ) -> Result<Option<Selection>> {
+        match self.daemon_client() {
+            Some(client) => {
+                let mut finder = daemon::DaemonFinder::new(client)?;
+                self.select_project(&mut finder, query, options)
+            }
+            None => {
+                let db = self.load_db_or_fail()?;
+                let mut db_view = db
+                    .view()
+                    .with_index()
+                    .with_exact_segment_bonus(self.cfg.exact_segment_bonus);
+                self.select_project(&mut db_view, query, options)
+            }
+        }
+    }
+
+    fn select_project<F: Finder + Send>(
+        &self,
+        finder: &mut F,
+        mut query: String,
+        options: tui::PromptOptions,
+    ) -> Result<Option<Selection>> {
+        finder.search(&query, &|| false)?;
+        let max_find_items = options.max_items;
+
+        {
+            let mut matches = finder.matches();
+            if let (Some(project), None) = (matches.next(), matches.next()) {
+                return Ok(Some(Selection {
+                    project: String::from(project),
+                    query,
+                    rank: 0,
+                }));
+            }
+        }
+        let header = DB::load_header(&self.cfg.index_file_path)?.unwrap_or_default();
+        let hosts = finder.hosts()?;
+        let orgs = finder.orgs()?;
+        let find_scope = FindScope::new(&header, &hosts, &orgs);
+        let mut matches = FoundMatches::from_finder(finder, max_find_items, &find_scope);
+
+        let (tx, rx) = mpsc::channel();
+        let (query_tx, query_rx) = mpsc::channel::<(u64, String, FindScope)>();
+        let latest_generation = AtomicU64::new(0);
+        let input_stopped = AtomicBool::new(false);
+
+        // Signals are turned into messages so that the prompt is
+        // cleaned up the same way as when the user exits it.
+        let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+        let signals_handle = signals.handle();
+        let signal_tx = tx.clone();
+        std::thread::spawn(move || {
+            for signal in signals.forever() {
+                if signal_tx.send(FindMessage::Signal(signal)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        std::thread::scope(|scope| {
+            let latest_generation = &latest_generation;
+            let input_stopped = &input_stopped;
+
+            // Input is polled instead of read in a blocking loop, so that the reader stops
+            // before the selected project is opened, e.g. in an editor on the same terminal
+            let input_tx = tx.clone();
+            scope.spawn(move || {
+                while !input_stopped.load(Ordering::Relaxed) {
+                    let events = match tui::read_events(TUI_POLL_INTERVAL) {
+                        Ok(events) => events,
+                        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                        Err(err) => {
+                            let _ = input_tx.send(FindMessage::Input(Err(err)));
                             break;
                         }
+                    };
+                    for event in events {
+                        if input_tx.send(FindMessage::Input(Ok(event))).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+
+            scope.spawn(move || {
+                while let Ok(mut job) = query_rx.recv() {
+                    // Wait for the input to settle before scoring
+                    while let Ok(newer_job) = query_rx.recv_timeout(FIND_DEBOUNCE) {
+                        job = newer_job;
+                    }
+                    let (generation, query, scope) = job;
+                    let cancelled = || latest_generation.load(Ordering::Relaxed) != generation;
+                    let res = finder.search(&query, &cancelled).map(|completed| {
+                        completed.then(|| FoundMatches::from_finder(finder, max_find_items, &scope))
+                    });
+                    let message = match res {
+                        Ok(Some(matches)) => FindMessage::Matches(generation, matches),
+                        Ok(None) => continue,
+                        Err(err) => FindMessage::Error(err),
+                    };
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut query_sender = QuerySender {
+                tx: query_tx,
+                latest_generation,
+                generation: 0,
+            };
+            let res = self.run_prompt(
+                &rx,
+                &mut query_sender,
+                &mut query,
+                &mut matches,
+                find_scope,
+                options,
+            );
+
+            // Stop the input and scoring threads so that the scope can finish
+            input_stopped.store(true, Ordering::Relaxed);
+            latest_generation.store(u64::MAX, Ordering::Relaxed);
+            drop(query_sender);
+            signals_handle.close();
+            res
+        })
+    }
+
+    fn run_prompt<'a>(
+        &self,
+        rx: &mpsc::Receiver<FindMessage>,
+        query_sender: &mut QuerySender<'a>,
+        query: &mut String,
+        matches: &mut FoundMatches,
+        mut scope: FindScope<'a>,
+        options: tui::PromptOptions,
+    ) -> Result<Option<Selection>> {
+        let statuses = StatusCache::load(&self.cfg.status_file_path)?;
+        let checkouts = self.checkouts(scope.header);
+        let stderr = std::io::stderr();
+        let mut ui = tui::PromptUI::new(stderr, query, options)?;
+        let mut display = MatchesDisplay {
+            statuses: &statuses,
+            checkouts: &checkouts,
+            show_full_path: self.cfg.show_full_path,
+            // Hosts are only worth showing when there's more than one of them
+            show_hosts: scope.hosts.len() > 1,
+            explain: false,
+        };
+        self.render_matches(&mut ui, matches, query, &display)?;
+
+        while let Ok(message) = rx.recv() {
+            let ui_event = match message {
+                FindMessage::Input(event) => ui.handle_event(event?),
+                FindMessage::Matches(matches_generation, new_matches) => {
+                    if matches_generation != query_sender.generation {
+                        continue;
+                    }
+                    *matches = new_matches;
+                    self.render_matches(&mut ui, matches, query, &display)?;
+                    continue;
+                }
+                FindMessage::Error(err) => return Err(err),
+                FindMessage::Signal(signal) => bail!("Interrupted by signal {signal}"),
+            };
+            match ui_event {
+                Some(tui::PromptUIEvent::SelectionDone) => {
+                    let selected_item = ui.selected_item() as usize;
+                    if let Some(project) = matches.projects.get(selected_item) {
+                        return Ok(Some(Selection {
+                            project: project.clone(),
+                            query: query.clone(),
+                            rank: selected_item,
+                        }));
+                    }
+                }
+                Some(tui::PromptUIEvent::Exit) => break,
+                Some(tui::PromptUIEvent::PromptUpdated) => {
+                    query.clear();
+                    query.push_str(ui.text_input());
+                    query_sender.send(query, scope)?;
+                }
+                Some(tui::PromptUIEvent::CycleHost) => {
+                    scope.cycle_host();
+                    ui.set_scope(scope.label());
+                    query_sender.send(query, scope)?;
+                }
+                Some(tui::PromptUIEvent::CycleOrg) => {
+                    scope.cycle_org();
+                    ui.set_scope(scope.label());
+                    query_sender.send(query, scope)?;
+                }
+                Some(tui::PromptUIEvent::ToggleArchived) => {
+                    scope.toggle_archived();
+                    ui.set_scope(scope.label());
+                    query_sender.send(query, scope)?;
+                }
+                Some(tui::PromptUIEvent::CopySelection) => {
+                    let selected_item = ui.selected_item() as usize;
+                    if let Some(project) = matches.projects.get(selected_item) {
+                        let path = self.project_path(project);
+                        ui.copy_to_clipboard(&path.to_string_lossy())?;
+                    }
+                    continue;
+                }
+                Some(tui::PromptUIEvent::ToggleFullPath) => {
+                    display.show_full_path = !display.show_full_path
+                }
+                Some(tui::PromptUIEvent::ToggleExplain) => display.explain = !display.explain,
+                Some(tui::PromptUIEvent::SelectionUpdated) => {}
+                Some(tui::PromptUIEvent::CursorUpdated) => {}
+                None => {}
+            }
+            if ui_event.is_some() {
+                self.render_matches(&mut ui, matches, query, &display)?;
+            }
+        }
+        Ok(None)
+    }
+
+    fn render_matches(
+        &self,
+        ui: &mut tui::PromptUI<std::io::Stderr>,
+        matches: &FoundMatches,
+        query: &str,
+        display: &MatchesDisplay,
+    ) -> Result<()> {
+        let MatchesDisplay {
+            statuses,
+            checkouts,
+            show_full_path,
+            show_hosts,
+            explain,
+        } = *display;
+        ui.set_match_count(matches.count, matches.total);
+        // Full paths don't start with the host, so there's no host column for them
+        let host_column_width = (show_hosts && !show_full_path)
+            .then(|| {
+                matches
+                    .projects
+                    .iter()
+                    .map(|project| text::str_width(EntryParts::parse(project).host))
+                    .max()
+            })
+            .flatten();
+        ui.set_host_column(host_column_width);
+        if show_full_path
+            || explain
+            || !statuses.is_empty()
+            || !checkouts.is_empty()
+            || matches.descriptions.iter().any(|d| d.is_some())
+        {
+            let items: Vec<String> = matches
+                .projects
+                .iter()
+                .zip(matches.descriptions.iter())
+                .map(|(project, description)| {
+                    let mut item = if show_full_path {
+                        let path = self.project_path(project);
+                        path.to_string_lossy().into_owned()
+                    } else {
+                        project.clone()
+                    };
+                    if let Some(checkout) = checkouts.describe(project, &self.project_path(project))
+                    {
+                        item.push_str(" (");
+                        item.push_str(&checkout);
+                        item.push(')');
+                    }
+                    if let Some(status) = statuses.get(project) {
+                        item.push_str(" [");
+                        item.push_str(&status.summary());
+                        item.push(']');
+                    }
+                    if let Some(description) = description {
+                        item.push_str(" - ");
+                        item.push_str(description);
+                    }
+                    if explain {
+                        let explanation = fuzzy::explain(
+                            query,
+                            project,
+                            description.as_deref(),
+                            self.cfg.exact_segment_bonus,
+                        );
+                        item.push_str(" (");
+                        item.push_str(&explanation.summary());
+                        item.push(')');
+                    }
+                    item
+                })
+                .collect();
+            ui.render(items.iter().map(|s| s.as_str()))?;
+        } else {
+            ui.render(matches.projects.iter().map(|s| s.as_str()))?;
+        }
+        Ok(())
+    }
+
+    fn handle_completions(&self, args: &cli::CompletionsArgs) -> Result<ExitCode> {
+        print!("{}", args.shell.script());
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Find the project of a directory for shell prompts. Prompts run this on every command,
+    /// so it only reads the index and the cached status without scanning or running Git.
+    fn handle_prompt_info(&self, args: &cli::PromptInfoArgs) -> Result<ExitCode> {
+        let Some(db) = DB::load(&self.cfg.index_file_path)? else {
+            return Ok(ExitCode::FAILURE);
+        };
+        let Some(project) = self.project_of_dir(&db, &args.dir)? else {
+            return Ok(ExitCode::FAILURE);
+        };
+
+        if args.format == cli::OutputFormat::Json {
+            let statuses = StatusCache::load(&self.cfg.status_file_path)?;
+            print_json(&PromptInfo {
+                project,
+                path: self.project_path(project).to_string_lossy().into_owned(),
+                description: db.description(project),
+                remote: db.remote(project),
+                status: statuses.get(project).map(StatusInfo::from),
+            })?;
+        } else {
+            println!("{project}");
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_complete(&self, args: &cli::CompleteArgs) -> Result<ExitCode> {
+        let mut cmd = Cli::command();
+        cmd.build();
+        let mut stdout = std::io::stdout().lock();
+        match completions::complete(&cmd, &args.words) {
+            completions::Completion::Words(words) => {
+                for word in words {
+                    writeln!(stdout, "{word}")?;
+                }
+            }
+            completions::Completion::Projects => {
+                let loaded_cfg;
+                let cfg = match completions::config_arg(&args.words) {
+                    Some(path) => {
+                        loaded_cfg = Config::load(Some(Path::new(path)))?;
+                        &loaded_cfg
+                    }
+                    None => &self.cfg,
+                };
+                let query = args.words.last().map(|s| s.as_str()).unwrap_or_default();
+                let db = DB::load(&cfg.index_file_path)?.unwrap_or_default();
+                if query.is_empty() {
+                    for project in db.entries() {
+                        writeln!(stdout, "{project}")?;
+                    }
+                } else {
+                    let mut db_view = db
+                        .view()
+                        .with_index()
+                        .with_exact_segment_bonus(cfg.exact_segment_bonus);
+                    for (project, _) in db_view.find_matches(query) {
+                        writeln!(stdout, "{project}")?;
+                    }
+                }
+            }
+            completions::Completion::Nothing => {}
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_man(&self, args: &cli::ManArgs) -> Result<ExitCode> {
+        let cmd = Cli::command();
+        match &args.output_dir {
+            Some(dir) => {
+                log::debug!("Writing man pages to {}", dir.to_string_lossy());
+                std::fs::create_dir_all(dir)?;
+                clap_mangen::generate_to(cmd, dir)?;
+            }
+            None => {
+                let mut stdout = std::io::stdout().lock();
+                clap_mangen::Man::new(cmd).render(&mut stdout)?;
+            }
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_tidy(&self, args: &cli::TidyArgs) -> Result<ExitCode> {
+        if !std::fs::exists(&self.cfg.projects_path)? {
+            log::error!(
+                "Project directory does not exist: {}",
+                &self.cfg.projects_path.to_string_lossy(),
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+        let git_cmd = self.git_cmd();
+        let lowercase_paths = self.cfg.lowercase_paths;
+        let exclude = self.scan_exclude();
+        let findings = tidy::scan(
+            &self.cfg.projects_path,
+            db.pinned(),
+            &exclude,
+            lowercase_paths,
+            |entry| match db.remote(entry) {
+                Some(url) => Some(String::from(url)),
+                None => git_cmd
+                    .remote_get_url(&self.cfg.git_remote_name, self.project_path(entry))
+                    .unwrap_or_else(|err| {
+                        log::error!("{err}");
+                        None
+                    }),
+            },
+        )?;
+
+        let stdout = std::io::stdout().lock();
+        let mut w = std::io::BufWriter::new(stdout);
+        let mut remaining = 0;
+        let mut db_changed = false;
+        for finding in findings.iter() {
+            let fixed = args.fix
+                && match self.fix_finding(&mut db, finding) {
+                    Ok(fixed) => fixed,
+                    Err(err) => {
+                        log::error!("Failed to fix {}: {err}", finding.entry());
+                        false
                     }
-                    Some(tui::PromptUIEvent::Exit) => break,
-                    Some(tui::PromptUIEvent::PromptUpdated) => {
-                        query.clear();
-                        query.extend(ui.text_input());
-                        db_view.find_matches(&query, &mut results);
+                };
+            if fixed {
+                db_changed |= matches!(finding, Finding::Misplaced { .. });
+                writeln!(w, "fixed\t{}\t{}", finding.entry(), finding.suggestion())?;
+            } else {
+                remaining += 1;
+                writeln!(
+                    w,
+                    "{}\t{}\t{}",
+                    finding.kind(),
+                    finding.entry(),
+                    finding.suggestion()
+                )?;
+            }
+        }
+        w.flush()?;
+        if db_changed {
+            db.commit()?;
+        }
+
+        Ok(if remaining == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+
+    /// Apply the suggested action of a finding when it is safe to do so.
+    /// Returns false when the finding can't be fixed automatically.
+    fn fix_finding(&self, db: &mut DB, finding: &Finding) -> Result<bool> {
+        match finding {
+            Finding::Empty(entry) => {
+                log::debug!("Removing empty directory {entry}");
+                tidy::remove_empty_dir(&self.project_path(entry))?;
+                self.log_operation(Action::Remove, entry, "");
+                Ok(true)
+            }
+            Finding::NoRepository(_) => Ok(false),
+            Finding::Misplaced { entry, expected } => self.move_project(db, entry, expected),
+        }
+    }
+
+    /// Move a project to a new path in the projects directory and rename its index entry.
+    /// Returns false when the new path is already taken.
+    fn move_project(&self, db: &mut DB, entry: &str, new_entry: &str) -> Result<bool> {
+        let moved = self.rename_project(db, entry, new_entry)?;
+        if moved {
+            self.log_operation(Action::Move, entry, new_entry);
+        }
+        Ok(moved)
+    }
+
+    /// Move a project directory and its index entry without recording the operation.
+    /// Returns false when the destination already exists.
+    fn rename_project(&self, db: &mut DB, entry: &str, new_entry: &str) -> Result<bool> {
+        let path = self.project_path(entry);
+        let new_path = self.project_path(new_entry);
+        if new_path.try_exists()? {
+            log::warn!(
+                "Cannot move {entry}: {} already exists",
+                new_path.to_string_lossy()
+            );
+            return Ok(false);
+        }
+        if let Some(parent) = new_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        log::debug!("Moving {entry} to {new_entry}");
+        std::fs::rename(&path, &new_path)?;
+        tidy::remove_empty_parents(&path, &self.cfg.projects_path);
+        if !db.rename(entry, new_entry)? {
+            db.add(new_entry)?;
+        }
+        Ok(true)
+    }
+
+    /// Point the remote of a project to the URL the remote redirected to,
+    /// and offer to move the project to the path derived from the new URL.
+    fn follow_redirect(&self, db: &mut DB, entry: &str, new_url: &str) -> Result<()> {
+        git_url::check_scheme(new_url, &self.cfg.allowed_url_schemes)?;
+        let new_entry = git_url::to_path(new_url, self.cfg.lowercase_paths)?.join("/");
+        eprintln!("{entry} has moved to {new_url}");
+        self.git_cmd().remote_set_url(
+            &self.cfg.git_remote_name,
+            new_url,
+            self.project_path(entry),
+        )?;
+        db.set_remote(entry, new_url)?;
+
+        if new_entry == entry || db::is_out_of_tree(entry) {
+            return Ok(());
+        }
+        if self.confirm(&format!("Move {entry} to {new_entry}?"))? {
+            if self.move_project(db, entry, &new_entry)? {
+                eprintln!("Moved {entry} to {new_entry}");
+            }
+        } else {
+            eprintln!("Use `gorg tidy --fix` to move {entry} to {new_entry} later");
+        }
+        Ok(())
+    }
+
+    /// Explain how to recover from a corrupt index and offer to rebuild it.
+    /// Returns true when the index was rebuilt.
+    fn recover_corrupt_db(&self, corrupt: &db::CorruptDB) -> Result<bool> {
+        log::error!("{corrupt}");
+        if let Some(backup_path) = &corrupt.backup_path {
+            eprintln!(
+                "A copy of the corrupt index was saved to {}",
+                backup_path.to_string_lossy()
+            );
+        }
+        if self.read_only() {
+            return Ok(false);
+        }
+        if !self.confirm("Rebuild the index from the project directory?")? {
+            eprintln!(
+                "Run `gorg doctor --fix` to remove the invalid lines, or `gorg update-index --rebuild` to rebuild the index"
+            );
+            return Ok(false);
+        }
+        let args = cli::UpdateIndexArgs {
+            dry_run: false,
+            refresh_remotes: false,
+            strict: false,
+            full: true,
+            rebuild: true,
+            format: cli::OutputFormat::Text,
+        };
+        Ok(self.handle_update_index(&args)? == ExitCode::SUCCESS)
+    }
+
+    /// Ask a yes/no question on the terminal. Defaults to no when input is not available.
+    fn confirm(&self, question: &str) -> Result<bool> {
+        let stdin = std::io::stdin();
+        if self.cli.no_input || !stdin.is_terminal() {
+            return Ok(false);
+        }
+        eprint!("{question} [y/N] ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        stdin.read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    }
+
+    fn handle_ui(&self) -> Result<ExitCode> {
+        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            log::error!("The dashboard requires a terminal");
+            return Ok(ExitCode::FAILURE);
+        }
+        let mut db = self.load_db_or_fail()?;
+        let mut statuses = StatusCache::load(&self.cfg.status_file_path)?;
+        let mut rows: Vec<dashboard::Row> = db
+            .entries()
+            .map(|entry| self.dashboard_row(&db, &statuses, entry))
+            .collect();
+
+        let res = self.run_dashboard(&mut db, &mut statuses, &mut rows);
+        statuses.save(&self.cfg.status_file_path)?;
+        res?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn dashboard_row(&self, db: &DB, statuses: &StatusCache, entry: &str) -> dashboard::Row {
+        let status = statuses.get(entry);
+        let fetched = match status.map(|status| status.fetched_at) {
+            Some(Some(fetched_at)) => format!("{} ago", status::format_age(fetched_at)),
+            Some(None) => String::from("never"),
+            None => String::new(),
+        };
+        dashboard::Row {
+            project: String::from(entry),
+            status: status.map(|status| status.summary()).unwrap_or_default(),
+            fetched,
+            description: String::from(db.description(entry).unwrap_or_default()),
+        }
+    }
+
+    fn run_dashboard(
+        &self,
+        db: &mut DB,
+        statuses: &mut StatusCache,
+        rows: &mut Vec<dashboard::Row>,
+    ) -> Result<()> {
+        let mut ui = Dashboard::new(std::io::stderr())?;
+        let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM, SIGWINCH])?;
+        // Fetches run in the background and report back the new status of the project
+        let (tx, rx) = mpsc::channel::<(String, Result<status::RepoStatus>)>();
+        let mut visible = dashboard::filter_rows(rows, ui.filter(), self.cfg.exact_segment_bonus);
+        ui.render(rows, &visible)?;
+
+        loop {
+            let mut render = false;
+            for signal in signals.pending() {
+                if signal != SIGWINCH {
+                    bail!("Interrupted by signal {signal}");
+                }
+                render = true;
+            }
+            while let Ok((entry, res)) = rx.try_recv() {
+                match res {
+                    Ok(status) => {
+                        statuses.set(&entry, status);
+                        ui.set_message(format!("Fetched {entry}"));
                     }
-                    Some(tui::PromptUIEvent::SelectionUpdated) => {}
-                    Some(tui::PromptUIEvent::CursorUpdated) => {}
-                    None => {}
+                    Err(err) => ui.set_message(format!("{err}")),
                 }
-                if ui_event.is_some() {
-                    ui.render(
-                        results
-                            .iter()
-                            .take(self.cfg.max_find_items)
-                            .map(|(item, _)| *item),
-                    )?;
+                if let Some(row) = rows.iter_mut().find(|row| row.project == entry) {
+                    *row = self.dashboard_row(db, statuses, &entry);
                 }
+                render = true;
             }
+
+            for event in tui::read_events(TUI_POLL_INTERVAL)? {
+                let Some(action) = ui.handle_event(event, visible.len()) else {
+                    continue;
+                };
+                render = true;
+                let selected = visible.get(ui.selected()).map(|i| rows[*i].project.clone());
+                match (action, selected) {
+                    (dashboard::Action::Exit, _) => return Ok(()),
+                    (dashboard::Action::Render, _) => {}
+                    (dashboard::Action::FilterUpdated, _) => {
+                        visible =
+                            dashboard::filter_rows(rows, ui.filter(), self.cfg.exact_segment_bonus);
+                    }
+                    (dashboard::Action::Fetch, Some(entry)) => {
+                        ui.set_message(format!("Fetching {entry}..."));
+                        self.fetch_in_background(entry, tx.clone());
+                    }
+                    (dashboard::Action::Shell, Some(entry)) => {
+                        let shell = std::env::var("SHELL").unwrap_or_else(|_| String::from("sh"));
+                        let mut cmd = std::process::Command::new(shell);
+                        self.run_suspended(&mut ui, &entry, &mut cmd, false)?;
+                        self.refresh_dashboard_row(db, statuses, rows, &entry);
+                    }
+                    (dashboard::Action::Run(command), Some(entry)) => {
+                        let mut cmd = std::process::Command::new("sh");
+                        cmd.arg("-c").arg(&command);
+                        self.run_suspended(&mut ui, &entry, &mut cmd, true)?;
+                        self.refresh_dashboard_row(db, statuses, rows, &entry);
+                    }
+                    (dashboard::Action::Browse, Some(entry)) => {
+                        let message = match self.open_in_browser(db, &entry) {
+                            Ok(url) => format!("Opened {url}"),
+                            Err(err) => format!("{err}"),
+                        };
+                        ui.set_message(message);
+                    }
+                    (dashboard::Action::Archive, Some(entry)) => {
+                        let mut tx = Transaction::begin(&self.cfg.index_file_path)?;
+                        let message = match self.archive_project(&mut tx, &entry) {
+                            Ok(()) => {
+                                tx.commit()?;
+                                db.remove(&entry);
+                                rows.retain(|row| row.project != entry);
+                                visible = dashboard::filter_rows(
+                                    rows,
+                                    ui.filter(),
+                                    self.cfg.exact_segment_bonus,
+                                );
+                                format!("Archived {entry}")
+                            }
+                            Err(err) => format!("{err}"),
+                        };
+                        ui.set_message(message);
+                    }
+                    (_, None) => {}
+                }
+            }
+
+            if render {
+                ui.render(rows, &visible)?;
+            }
+        }
+    }
+
+    fn fetch_in_background(
+        &self,
+        entry: String,
+        tx: mpsc::Sender<(String, Result<status::RepoStatus>)>,
+    ) {
+        // Git must not prompt for credentials while the dashboard owns the terminal
+        let git_cmd = git_cmd::GitCmd::new(self.cfg.git_command.clone(), true);
+        let dir = self.project_path(&entry);
+        std::thread::spawn(move || {
+            let res = git_cmd
+                .fetch(&dir)
+                .and_then(|_| status::read_status(&git_cmd, &dir));
+            let _ = tx.send((entry, res));
+        });
+    }
+
+    /// Run a command in a project with the terminal given back to it.
+    /// Optionally waits for the user before returning to the dashboard.
+    fn run_suspended(
+        &self,
+        ui: &mut Dashboard<std::io::Stderr>,
+        entry: &str,
+        cmd: &mut std::process::Command,
+        wait: bool,
+    ) -> Result<()> {
+        ui.suspend()?;
+        let res = cmd.current_dir(self.project_path(entry)).status();
+        match &res {
+            Ok(status) if !status.success() => eprintln!("Command failed: {status}"),
+            Ok(_) => {}
+            Err(err) => eprintln!("Failed to run command: {err}"),
         }
+        if wait {
+            eprint!("Press Enter to return");
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+        }
+        ui.resume()?;
+        match res {
+            Ok(status) if status.success() => ui.set_message(String::new()),
+            Ok(status) => ui.set_message(format!("Command failed: {status}")),
+            Err(err) => ui.set_message(format!("Failed to run command: {err}")),
+        }
+        Ok(())
+    }
+
+    fn refresh_dashboard_row(
+        &self,
+        db: &DB,
+        statuses: &mut StatusCache,
+        rows: &mut [dashboard::Row],
+        entry: &str,
+    ) {
+        let dir = self.project_path(entry);
+        if !dir.join(".git").exists() {
+            return;
+        }
+        if let Ok(status) = status::read_status(&self.git_cmd(), &dir) {
+            statuses.set(entry, status);
+        }
+        if let Some(row) = rows.iter_mut().find(|row| row.project == entry) {
+            *row = self.dashboard_row(db, statuses, entry);
+        }
+    }
+
+    /// Open the web page of the project's remote. Returns the opened URL.
+    fn open_in_browser(&self, db: &DB, entry: &str) -> Result<String> {
+        let url = match db.remote(entry) {
+            Some(url) => Some(String::from(url)),
+            None => self
+                .git_cmd()
+                .remote_get_url(&self.cfg.git_remote_name, self.project_path(entry))?,
+        };
+        let Some(url) = url else {
+            bail!("{entry} has no remote");
+        };
+        let web_url = git_url::to_web_url(&url, &self.cfg.web_urls)?;
+        let opener = if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        std::process::Command::new(opener)
+            .arg(&web_url)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()?;
+        Ok(web_url)
+    }
+
+    fn handle_undo(&self, args: &cli::UndoArgs) -> Result<ExitCode> {
+        let log_file_path = config::required_path("log_file_path", &self.cfg.log_file_path)?;
+        let operations = oplog::read(log_file_path)?;
+        let Some(operation) = oplog::last_undoable(&operations) else {
+            log::error!("Nothing to undo");
+            return Ok(ExitCode::FAILURE);
+        };
+        let entry = operation.target.as_str();
+        let time = oplog::format_time(operation.time);
 
-        if let Some(index) = selection {
-            let project = results[index].0;
-            print_project(project);
+        match operation.action {
+            Action::Archive => {
+                let archived_path = PathBuf::from(&operation.details);
+                println!("Restore {entry} archived at {time}");
+                if args.dry_run {
+                    return Ok(ExitCode::SUCCESS);
+                }
+                if !archived_path.is_dir() {
+                    log::error!(
+                        "Cannot undo archiving {entry}: {} no longer exists",
+                        archived_path.to_string_lossy()
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+                let path = self.project_path(entry);
+                if path.try_exists()? {
+                    log::error!(
+                        "Cannot undo archiving {entry}: {} already exists",
+                        path.to_string_lossy()
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&archived_path, &path)?;
+                tidy::remove_empty_parents(&archived_path, &self.cfg.archive_path);
+                let remote_url = self
+                    .git_cmd()
+                    .remote_get_url(&self.cfg.git_remote_name, &path)?;
+                self.index_project(entry, remote_url.as_deref())?;
+            }
+            Action::Move => {
+                let moved_to = operation.details.as_str();
+                println!("Move {moved_to} back to {entry} (moved at {time})");
+                if args.dry_run {
+                    return Ok(ExitCode::SUCCESS);
+                }
+                if !self.project_path(moved_to).is_dir() {
+                    log::error!("Cannot undo moving {entry}: {moved_to} no longer exists");
+                    return Ok(ExitCode::FAILURE);
+                }
+                let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+                if !self.rename_project(&mut db, moved_to, entry)? {
+                    return Ok(ExitCode::FAILURE);
+                }
+                db.commit()?;
+            }
+            action => {
+                log::error!("Cannot undo {} of {entry} at {time}", action.as_str());
+                return Ok(ExitCode::FAILURE);
+            }
         }
+        self.log_operation(Action::Undo, entry, &operation.undo_details());
         Ok(ExitCode::SUCCESS)
     }
 
-    fn handle_update_index(&self) -> Result<ExitCode> {
+    fn handle_update_index(&self, args: &cli::UpdateIndexArgs) -> Result<ExitCode> {
         if !std::fs::exists(&self.cfg.projects_path)? {
             log::error!(
                 "Project directory does not exist: {}",
@@ -273,41 +3439,507 @@ impl App {
             return Ok(ExitCode::FAILURE);
         }
 
-        let iter =
-            git_dir::GitDirIterator::new(self.cfg.projects_path.clone()).filter_map(
-                |res| match res {
-                    Ok(dir) => match dir
-                        .strip_prefix(&self.cfg.projects_path)
-                        .expect("Project dir should be prefix of iterated dirs")
-                        .to_str()
-                    {
-                        Some(dir) => Some(String::from(dir)),
-                        None => {
-                            log::error!(
-                                "Cannot read directory as a string: {}",
-                                dir.to_string_lossy()
-                            );
-                            None
-                        }
-                    },
-                    Err(err) => {
-                        log::error!("Failed to read file: {}", err);
-                        None
-                    }
+        let mut iter = match git_dir::GitDirIterator::new(&self.cfg.projects_path) {
+            Ok(iter) => iter
+                .with_follow_symlinks(self.cfg.follow_symlinks)
+                .with_exclude(self.scan_exclude()),
+            Err(err) => {
+                log::error!(
+                    "Cannot scan project directory {}: {err}",
+                    self.cfg.projects_path.to_string_lossy()
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+        };
+        if !args.full {
+            match ScanCache::load(&self.cfg.scan_cache_file_path, &self.cfg.projects_path) {
+                Ok(cache) => iter = iter.with_cache(cache),
+                Err(err) => log::warn!("Failed to read the scan cache: {err}"),
+            }
+        }
+        let mut progress = Progress::new("Scanning");
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(res) = iter.next() {
+            match res {
+                Ok(dir) => match db::entry_from_path(
+                    dir.strip_prefix(&self.cfg.projects_path)
+                        .expect("Project dir should be prefix of iterated dirs"),
+                ) {
+                    Some(entry) => entries.push(entry),
+                    None => errors.push(git_dir::ScanError {
+                        path: dir,
+                        error: std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "path is not valid UTF-8 or contains backslashes",
+                        ),
+                    }),
                 },
+                Err(err) => {
+                    log::debug!("Skipping directory {err}");
+                    errors.push(err);
+                }
+            }
+            progress.update(format_args!(
+                "{} directories, {} repositories",
+                iter.dirs_scanned(),
+                entries.len(),
+            ));
+        }
+        progress.finish();
+        let scan_cache = iter.take_cache();
+        if !errors.is_empty() {
+            eprintln!(
+                "{} repositories indexed, {} directories skipped due to errors:",
+                entries.len(),
+                errors.len()
+            );
+            for err in errors.iter() {
+                eprintln!("  {err}");
+            }
+            if args.strict {
+                log::error!("Index not updated due to the scan errors");
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+
+        // Only the changes are printed in read-only mode
+        let dry_run = args.dry_run || self.read_only();
+        let mut db = DB::from_entries(entries.into_iter());
+        let mut tx = match (dry_run, args.rebuild) {
+            // Dry runs don't lock the index, so that they work on read-only file systems
+            (true, _) => None,
+            (false, true) => Some(Transaction::begin_empty(&self.cfg.index_file_path)?),
+            (false, false) => Some(Transaction::begin(&self.cfg.index_file_path)?),
+        };
+        // The transaction gets the new DB when it's committed
+        let mut old_db = match tx.as_mut() {
+            Some(tx) => std::mem::take(&mut **tx),
+            None => DB::load(&self.cfg.index_file_path)?.unwrap_or_default(),
+        };
+        let renames = self.reconcile_case(&db, &mut old_db)?;
+        for entry in old_db.pinned() {
+            if self.project_path(entry).is_dir() {
+                db.pin(entry)?;
+            } else {
+                log::info!("Dropping pinned entry {entry}: directory not found");
+            }
+        }
+        for entry in self.cfg.pinned_dirs.iter() {
+            let entry = entry.trim_end_matches('/');
+            if self.project_path(entry).is_dir() {
+                db.pin(entry)?;
+            } else {
+                log::warn!("Pinned directory in the config not found: {entry}");
+            }
+        }
+        for (entry, used_at) in old_db.recent().rev() {
+            if self.project_path(entry).is_dir() {
+                db.mark_used(entry, used_at);
+            }
+        }
+        for (entry, description) in old_db.descriptions() {
+            if self.project_path(entry).is_dir() {
+                db.describe(entry, description)?;
+            }
+        }
+        for entry in old_db.mirrored() {
+            if self.project_path(entry).is_dir() {
+                db.mark_mirrored(entry);
+            }
+        }
+        for pick in old_db.picks() {
+            if self.project_path(&pick.entry).is_dir() {
+                db.push_pick(pick.clone());
+            }
+        }
+        self.update_remotes(&mut db, &old_db, args.refresh_remotes)?;
+        for entry in db.entries().map(String::from).collect::<Vec<_>>() {
+            let path = self.project_path(&entry);
+            db.set_languages(&entry, &lang::detect(&path));
+            db.set_tags(&entry, &ProjectConfig::load_or_default(&path).tags);
+        }
+
+        let json = args.format == cli::OutputFormat::Json;
+        let Some(mut tx) = tx else {
+            let changes = old_db.diff(&db);
+            if json {
+                print_json(&IndexChanges::new(&changes, &renames, &errors))?;
+            } else {
+                let stdout = std::io::stdout().lock();
+                let mut w = std::io::BufWriter::new(stdout);
+                for (old_entry, entry) in renames.iter() {
+                    writeln!(w, "- {old_entry}")?;
+                    writeln!(w, "+ {entry}")?;
+                }
+                for change in changes.iter() {
+                    match change {
+                        Change::Added(entry) => writeln!(w, "+ {entry}")?,
+                        Change::Removed(entry) => writeln!(w, "- {entry}")?,
+                    }
+                }
+            }
+            return Ok(if changes.is_empty() && renames.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        };
+
+        let changes = old_db.diff(&db);
+        let added = changes
+            .iter()
+            .filter(|change| matches!(change, Change::Added(_)))
+            .count();
+        let removed = changes.len() - added;
+        let changed = !changes.is_empty();
+        let index_changes = json.then(|| IndexChanges::new(&changes, &renames, &errors));
+        *tx = db;
+        tx.commit()?;
+        if let Err(err) = scan_cache.save(&self.cfg.scan_cache_file_path, &self.cfg.projects_path) {
+            log::warn!("Failed to save the scan cache: {err}");
+        }
+        if changed {
+            self.log_operation(Action::UpdateIndex, "", &format!("+{added} -{removed}"));
+        }
+        if let Some(index_changes) = index_changes {
+            print_json(&index_changes)?;
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Rename the entries of the old DB whose directories were renamed to a different case,
+    /// so that their metadata is carried over to the new DB. Casing is compared like in
+    /// the project paths derived from remote URLs. Returns the renamed entries.
+    fn reconcile_case(&self, db: &DB, old_db: &mut DB) -> Result<Vec<(String, String)>> {
+        let normalize = |entry: &str| git_url::normalize_path_case(entry, self.cfg.lowercase_paths);
+        let mut by_case: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for entry in db.entries() {
+            by_case.entry(normalize(entry)).or_default().push(entry);
+        }
+        for entries in by_case.values().filter(|entries| entries.len() > 1) {
+            log::warn!(
+                "Projects differ only in case: {}. Run `gorg tidy` to find the misplaced ones.",
+                entries.join(", ")
+            );
+        }
+
+        let old_entries: Vec<&str> = old_db.entries().collect();
+        let mut renames = Vec::new();
+        for old_entry in old_entries.iter() {
+            if self.project_path(old_entry).is_dir() {
+                continue;
+            }
+            if let Some([entry]) = by_case.get(&normalize(old_entry)).map(|e| e.as_slice())
+                && old_entries.binary_search(entry).is_err()
+            {
+                renames.push((String::from(*old_entry), String::from(*entry)));
+            }
+        }
+        for (old_entry, entry) in renames.iter() {
+            log::info!("Carrying over {old_entry} to {entry}");
+            old_db.rename(old_entry, entry)?;
+        }
+        Ok(renames)
+    }
+
+    /// Record the Git remote URLs of the entries in the DB.
+    /// Remotes are read from the repositories only for entries missing from the old DB,
+    /// unless a refresh is requested.
+    fn update_remotes(&self, db: &mut DB, old_db: &DB, refresh: bool) -> Result<()> {
+        let git_cmd = self.git_cmd();
+        let entries: Vec<String> = db.entries().map(String::from).collect();
+        let mut progress = Progress::new("Reading remotes");
+        let mut read = 0;
+        for entry in entries.iter() {
+            if !refresh && let Some(url) = old_db.remote(entry) {
+                db.set_remote(entry, url)?;
+                continue;
+            }
+            let path = self.project_path(entry);
+            if !path.join(".git").exists() {
+                continue;
+            }
+            match git_cmd.remote_get_url(&self.cfg.git_remote_name, &path) {
+                Ok(Some(url)) => db.set_remote(entry, &url)?,
+                Ok(None) => log::debug!("No remote {} found for {entry}", self.cfg.git_remote_name),
+                Err(err) => log::error!("Failed to read remote for {entry}: {err}"),
+            }
+            read += 1;
+            progress.update(format_args!("{read} repositories"));
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    fn handle_pin(&self, args: &cli::PinArgs) -> Result<ExitCode> {
+        let path = std::fs::canonicalize(&args.path)?;
+        if !path.is_dir() {
+            log::error!("Not a directory: {}", path.to_string_lossy());
+            return Ok(ExitCode::FAILURE);
+        }
+        let projects_path = std::fs::canonicalize(&self.cfg.projects_path)?;
+        let Some(entry) = path
+            .strip_prefix(&projects_path)
+            .ok()
+            .and_then(db::entry_from_path)
+        else {
+            log::error!(
+                "Path {} is not inside the project directory {}",
+                path.to_string_lossy(),
+                projects_path.to_string_lossy(),
+            );
+            return Ok(ExitCode::FAILURE);
+        };
+
+        let mut db = Transaction::begin(&self.cfg.index_file_path)?;
+        if args.remove {
+            log::debug!("Unpinning {entry}");
+            if !db.unpin(&entry) {
+                log::error!("Entry is not pinned: {entry}");
+                return Ok(ExitCode::FAILURE);
+            }
+            if !path.join(".git").exists() {
+                db.remove(&entry);
+            }
+        } else {
+            log::debug!("Pinning {entry}");
+            db.pin(&entry)?;
+        }
+        db.commit()?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Indexed project that contains the directory, like Git finds the repository
+    /// of the current directory
+    fn project_of_dir<'a>(&self, db: &'a DB, dir: &Path) -> Result<Option<&'a str>> {
+        let dir = std::path::absolute(dir)?;
+        // The current directory of a process has symbolic links resolved,
+        // so the projects directory is compared both as configured and resolved
+        let resolved_projects_path = std::fs::canonicalize(&self.cfg.projects_path).ok();
+        let rel_path = [
+            Some(&self.cfg.projects_path),
+            resolved_projects_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .find_map(|projects_path| dir.strip_prefix(projects_path).ok());
+        let path = match rel_path {
+            Some(rel_path) => db::entry_from_path(rel_path),
+            None => db::out_of_tree_entry(&dir),
+        };
+        Ok(path.and_then(|path| db.find_containing(&path)))
+    }
+
+    /// Project that contains the current directory, for commands run without a query
+    fn current_project(&self, db: &DB) -> Result<Option<String>> {
+        let dir = std::env::current_dir()?;
+        let project = self.project_of_dir(db, &dir)?;
+        if project.is_none() {
+            log::error!(
+                "Not in an indexed project: {}. Give a query to choose the project.",
+                dir.to_string_lossy()
+            );
+        }
+        Ok(project.map(String::from))
+    }
+
+    /// Find the project that the query matches exactly or the only project the query matches.
+    /// Logs an error and returns None when there's no such project.
+    fn find_single_project(&self, db: &DB, query: &str) -> Option<String> {
+        let query = query.trim();
+        let mut view = db.view();
+        let matches = view.find_matches(query);
+        match matches {
+            _ if matches.iter().any(|(project, _)| *project == query) => Some(String::from(query)),
+            [(project, _)] => Some(String::from(*project)),
+            [] => {
+                log::error!("No project matches the query: {query}");
+                None
+            }
+            _ => {
+                log::error!(
+                    "Query matches {} projects. Use a more specific query.",
+                    matches.len()
+                );
+                None
+            }
+        }
+    }
+
+    fn handle_describe(&self, args: &cli::DescribeArgs) -> Result<ExitCode> {
+        let mut db = self.begin_db_or_fail()?;
+        let project = match &args.query {
+            Some(query) => self.find_single_project(&db, query),
+            None => self.current_project(&db)?,
+        };
+        let Some(project) = project else {
+            return Ok(ExitCode::FAILURE);
+        };
+
+        if args.remove {
+            if !db.remove_description(&project) {
+                log::error!("Project has no description: {project}");
+                return Ok(ExitCode::FAILURE);
+            }
+        } else if let Some(description) = &args.description {
+            log::debug!("Describing {project}: {description}");
+            db.describe(&project, description)?;
+        } else {
+            match db.description(&project) {
+                Some(description) => println!("{description}"),
+                None => {
+                    log::error!("Project has no description: {project}");
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+        db.commit()?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_rename(&self, args: &cli::RenameArgs) -> Result<ExitCode> {
+        let mut db = self.begin_db_or_fail()?;
+        let Some(entry) = self.find_single_project(&db, &args.query) else {
+            return Ok(ExitCode::FAILURE);
+        };
+        if db::is_out_of_tree(&entry) {
+            log::error!("Cannot rename {entry}: it is outside the project directory");
+            return Ok(ExitCode::FAILURE);
+        }
+        let new_entry = args.new_path.trim().trim_matches('/');
+        git_url::check_path(new_entry)?;
+        let new_entry = git_url::normalize_path_case(new_entry, self.cfg.lowercase_paths);
+        if new_entry == entry {
+            log::error!("Project is already at {new_entry}");
+            return Ok(ExitCode::FAILURE);
+        }
+        if self.project_path(&new_entry).try_exists()? {
+            log::error!("Cannot rename {entry}: {new_entry} already exists");
+            return Ok(ExitCode::FAILURE);
+        }
+
+        // Work out the new remote before moving anything, so that nothing is left half-done
+        let git_cmd = self.git_cmd();
+        let new_url = if args.update_remote {
+            let remote_name = &self.cfg.git_remote_name;
+            let Some(url) = git_cmd.remote_get_url(remote_name, self.project_path(&entry))? else {
+                log::error!("Project {entry} has no remote named {remote_name}");
+                return Ok(ExitCode::FAILURE);
+            };
+            Some(git_url::replace_path(&url, &new_entry)?)
+        } else {
+            None
+        };
+
+        if !self.rename_project(&mut db, &entry, &new_entry)? {
+            return Ok(ExitCode::FAILURE);
+        }
+        let result = match &new_url {
+            Some(url) => git_cmd
+                .remote_set_url(
+                    &self.cfg.git_remote_name,
+                    url,
+                    self.project_path(&new_entry),
+                )
+                .and_then(|_| db.set_remote(&new_entry, url)),
+            None => Ok(()),
+        }
+        .and_then(|_| db.commit());
+        if let Err(err) = result {
+            // Put the project back where the index expects it to be
+            let path = self.project_path(&entry);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(self.project_path(&new_entry), &path)?;
+            tidy::remove_empty_parents(&self.project_path(&new_entry), &self.cfg.projects_path);
+            return Err(err);
+        }
+
+        self.log_operation(Action::Move, &entry, &new_entry);
+        println!("Moved {entry} to {new_entry}");
+        if let Some(url) = new_url {
+            println!("Remote {} set to {url}", self.cfg.git_remote_name);
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_daemon(&self) -> Result<ExitCode> {
+        daemon::serve(
+            &self.cfg.daemon_socket_path,
+            &self.cfg.index_file_path,
+            self.cfg.exact_segment_bonus,
+        )?;
+        Ok(ExitCode::SUCCESS)
+    }
+
+    fn handle_watch(&self) -> Result<ExitCode> {
+        if !std::fs::exists(&self.cfg.projects_path)? {
+            log::error!(
+                "Project directory does not exist: {}",
+                &self.cfg.projects_path.to_string_lossy(),
             );
-        let db = DB::from_entries(iter);
-        db.save(&self.cfg.index_file_path)?;
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let git_cmd = self.git_cmd();
+        watch::watch(
+            &self.cfg.projects_path,
+            &self.cfg.index_file_path,
+            &self.cfg.status_file_path,
+            &git_cmd,
+            self.cfg.follow_symlinks,
+            &self.scan_exclude(),
+        )?;
         Ok(ExitCode::SUCCESS)
     }
 
     fn handle(&mut self) -> Result<ExitCode> {
+        if self.read_only()
+            && let Some(command) = &self.cli.command
+            && let Some(name) = changing_command(command)
+        {
+            log::error!("Cannot run `{name}` in read-only mode");
+            return Ok(ExitCode::FAILURE);
+        }
         match &self.cli.command {
-            Some(cli::Commands::Init(args)) => self.handle_init(&args),
-            Some(cli::Commands::List(args)) => self.handle_list(&args),
-            Some(cli::Commands::Run(args)) => self.handle_run(&args),
-            Some(cli::Commands::Find(args)) => self.handle_find(&args),
-            Some(cli::Commands::UpdateIndex) => self.handle_update_index(),
+            Some(cli::Commands::Auth(args)) => self.handle_auth(args),
+            Some(cli::Commands::Backup(args)) => self.handle_backup(args),
+            Some(cli::Commands::Bench(args)) => self.handle_bench(args),
+            Some(cli::Commands::Branches(args)) => self.handle_branches(args),
+            Some(cli::Commands::Complete(args)) => self.handle_complete(args),
+            Some(cli::Commands::PromptInfo(args)) => self.handle_prompt_info(args),
+            Some(cli::Commands::Completions(args)) => self.handle_completions(args),
+            Some(cli::Commands::Config(args)) => self.handle_config(args),
+            Some(cli::Commands::Describe(args)) => self.handle_describe(args),
+            Some(cli::Commands::Doctor(args)) => self.handle_doctor(args),
+            Some(cli::Commands::Du(args)) => self.handle_du(args),
+            Some(cli::Commands::Init(args)) => self.handle_init(args),
+            Some(cli::Commands::Last(args)) => self.handle_last(args),
+            Some(cli::Commands::List(args)) => self.handle_list(args),
+            Some(cli::Commands::Log(args)) => self.handle_log(args),
+            Some(cli::Commands::New(args)) => self.handle_new(args),
+            Some(cli::Commands::Open(args)) => self.handle_open(args),
+            Some(cli::Commands::Pin(args)) => self.handle_pin(args),
+            Some(cli::Commands::PushCheck(args)) => self.handle_push_check(args),
+            Some(cli::Commands::Recent(args)) => self.handle_recent(args),
+            Some(cli::Commands::Rename(args)) => self.handle_rename(args),
+            Some(cli::Commands::Restore(args)) => self.handle_restore(args),
+            Some(cli::Commands::Run(args)) => self.handle_run(args),
+            Some(cli::Commands::Status(args)) => self.handle_status(args),
+            Some(cli::Commands::Switch(args)) => self.handle_switch(args),
+            Some(cli::Commands::Sync(args)) => self.handle_sync(args),
+            Some(cli::Commands::SyncOrgs(args)) => self.handle_sync_orgs(args),
+            Some(cli::Commands::Daemon) => self.handle_daemon(),
+            Some(cli::Commands::Find(args)) => self.handle_find(args),
+            Some(cli::Commands::Man(args)) => self.handle_man(args),
+            Some(cli::Commands::Tidy(args)) => self.handle_tidy(args),
+            Some(cli::Commands::Ui) => self.handle_ui(),
+            Some(cli::Commands::Undo(args)) => self.handle_undo(args),
+            Some(cli::Commands::UpdateIndex(args)) => self.handle_update_index(args),
+            Some(cli::Commands::Watch) => self.handle_watch(),
             None => {
                 let mut cmd = Cli::command();
                 cmd.error(ErrorKind::MissingSubcommand, "No sub-command specified")
@@ -330,15 +3962,143 @@ pub fn run() -> Result<ExitCode> {
         },
     };
 
-    let cfg = match cli.config.as_ref() {
-        Some(config_path) => Config::read_from_file(config_path)?,
-        None => Config::from_env()?,
-    };
+    // The version is printed before loading the configuration, so that it works with a broken one
+    if cli.version {
+        if cli.verbose {
+            print!("{}", build_info::verbose_version());
+        } else {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let cfg = Config::load(cli.config.as_deref())?;
+    if let Some(secret) = crypt::Secret::from_env_or_key_file(cfg.encryption_key_file.as_deref())? {
+        crypt::init(secret);
+    }
     let mut app = App { cli, cfg };
-    app.handle()
+    match app.handle() {
+        Err(err) => match err.downcast_ref::<db::CorruptDB>() {
+            Some(corrupt) if app.recover_corrupt_db(corrupt)? => app.handle(),
+            Some(_) => Ok(ExitCode::FAILURE),
+            None => Err(err),
+        },
+        res => res,
+    }
 }
 
 fn write_project<W: Write>(w: &mut W, project: &str) -> Result<()> {
-    write!(w, "{project}\n")?;
+    writeln!(w, "{project}")?;
+    Ok(())
+}
+
+/// Name of the sub-command when it changes the projects or the index.
+/// `update-index` is left out, as it only prints the changes in read-only mode.
+fn changing_command(command: &cli::Commands) -> Option<&'static str> {
+    match command {
+        cli::Commands::Describe(args) if args.description.is_some() || args.remove => {
+            Some("describe")
+        }
+        cli::Commands::Doctor(args) if args.fix => Some("doctor --fix"),
+        cli::Commands::Init(_) => Some("init"),
+        cli::Commands::New(_) => Some("new"),
+        cli::Commands::Pin(_) => Some("pin"),
+        cli::Commands::Rename(_) => Some("rename"),
+        cli::Commands::Restore(_) => Some("restore"),
+        cli::Commands::Switch(args) if !args.dry => Some("switch"),
+        cli::Commands::Sync(_) => Some("sync"),
+        cli::Commands::SyncOrgs(args) if !args.dry => Some("sync-orgs"),
+        cli::Commands::Tidy(args) if args.fix => Some("tidy --fix"),
+        cli::Commands::Ui => Some("ui"),
+        cli::Commands::Undo(args) if !args.dry_run => Some("undo"),
+        cli::Commands::Watch => Some("watch"),
+        _ => None,
+    }
+}
+
+/// Commands joined like in a shell, e.g. `make && make test`
+fn format_commands(commands: &[Vec<String>]) -> String {
+    commands
+        .iter()
+        .map(|command| command.join(" "))
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
+
+/// Write the cached Git status of each project on its own line
+fn write_statuses<W: Write>(w: &mut W, projects: &[&str], statuses: &StatusCache) -> Result<()> {
+    for project in projects {
+        let Some(status) = statuses.get(project) else {
+            writeln!(w, "{project}\t(unknown)")?;
+            continue;
+        };
+        let fetched = match status.fetched_at {
+            Some(fetched_at) => format!("fetched {} ago", status::format_age(fetched_at)),
+            None => String::from("never fetched"),
+        };
+        writeln!(w, "{project}\t{}\t{fetched}", status.summary())?;
+    }
+    Ok(())
+}
+
+fn write_status_table<W: Write>(
+    w: &mut W,
+    projects: &[&str],
+    statuses: &StatusCache,
+) -> Result<()> {
+    let rows: Vec<Vec<String>> = projects
+        .iter()
+        .map(|project| {
+            let mut row = vec![String::from(*project)];
+            let Some(status) = statuses.get(project) else {
+                row.push(String::from("(unknown)"));
+                row.resize(6, String::new());
+                return row;
+            };
+            let (ahead, behind) = match status.ahead_behind {
+                Some((ahead, behind)) => (ahead.to_string(), behind.to_string()),
+                None => (String::from("no upstream"), String::new()),
+            };
+            let fetched = match status.fetched_at {
+                Some(fetched_at) => format!("{} ago", status::format_age(fetched_at)),
+                None => String::from("never"),
+            };
+            row.extend([
+                String::from(status.branch.as_deref().unwrap_or("(detached)")),
+                String::from(if status.dirty { "yes" } else { "no" }),
+                ahead,
+                behind,
+                fetched,
+            ]);
+            row
+        })
+        .collect();
+    text::write_markdown_table(
+        w,
+        &["Project", "Branch", "Dirty", "Ahead", "Behind", "Fetched"],
+        &rows,
+    )?;
+    Ok(())
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let stdout = std::io::stdout().lock();
+    let mut w = std::io::BufWriter::new(stdout);
+    serde_json::to_writer_pretty(&mut w, value)?;
+    writeln!(w)?;
+    w.flush()?;
     Ok(())
 }
+
+/// Host or organization that follows the given one. None follows the last one,
+/// so that all of them are included again.
+fn next_scope<'a, I>(scopes: I, scope: Option<&str>) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut scopes = scopes.into_iter();
+    if let Some(scope) = scope {
+        scopes.find(|s| *s == scope)?;
+    }
+    scopes.next().map(|s| s.as_str())
+}
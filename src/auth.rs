@@ -0,0 +1,176 @@
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+const KEYRING_SERVICE: &str = "gorg";
+const TOKEN_FILE_MODE: u32 = 0o600;
+
+/// Where a token was stored
+pub enum Storage {
+    Keyring,
+    File,
+}
+
+/// API tokens for forge hosts (e.g. github.com) stored in the OS keyring.
+/// Tokens are stored in a file that only the user can read when the keyring is not available.
+pub struct TokenStore {
+    file_path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(file_path: PathBuf) -> Self {
+        Self { file_path }
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    pub fn get(&self, host: &str) -> Result<Option<String>> {
+        let host = normalize_host(host);
+        match keyring_entry(&host).and_then(|entry| entry.get_password()) {
+            Ok(token) => return Ok(Some(token)),
+            Err(keyring::Error::NoEntry) => {}
+            Err(err) => log::debug!("Keyring not available: {err}"),
+        }
+        let tokens = read_tokens(&self.file_path)?;
+        Ok(find_token(&tokens, &host).map(String::from))
+    }
+
+    pub fn set(&self, host: &str, token: &str) -> Result<Storage> {
+        let host = normalize_host(host);
+        let token = token.trim();
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            bail!("Invalid host: {host}");
+        }
+        if token.is_empty() || token.contains(char::is_whitespace) {
+            bail!("Token cannot be empty or contain whitespace");
+        }
+
+        match keyring_entry(&host).and_then(|entry| entry.set_password(token)) {
+            Ok(()) => {
+                // Avoid leaving an old token behind in the file
+                self.remove_from_file(&host)?;
+                Ok(Storage::Keyring)
+            }
+            Err(err) => {
+                log::debug!("Keyring not available: {err}");
+                let mut tokens = read_tokens(&self.file_path)?;
+                tokens.retain(|(h, _)| *h != host);
+                tokens.push((host, String::from(token)));
+                tokens.sort();
+                write_tokens(&self.file_path, &tokens)?;
+                Ok(Storage::File)
+            }
+        }
+    }
+
+    /// Remove the token of a host from both the keyring and the file.
+    /// Returns false when no token was found.
+    pub fn remove(&self, host: &str) -> Result<bool> {
+        let host = normalize_host(host);
+        let removed_from_keyring =
+            match keyring_entry(&host).and_then(|entry| entry.delete_credential()) {
+                Ok(()) => true,
+                Err(keyring::Error::NoEntry) => false,
+                Err(err) => {
+                    log::debug!("Keyring not available: {err}");
+                    false
+                }
+            };
+        let removed_from_file = self.remove_from_file(&host)?;
+        Ok(removed_from_keyring || removed_from_file)
+    }
+
+    fn remove_from_file(&self, host: &str) -> Result<bool> {
+        let mut tokens = read_tokens(&self.file_path)?;
+        let len = tokens.len();
+        tokens.retain(|(h, _)| h != host);
+        if tokens.len() == len {
+            return Ok(false);
+        }
+        write_tokens(&self.file_path, &tokens)?;
+        Ok(true)
+    }
+}
+
+fn keyring_entry(host: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, host)
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim().to_lowercase()
+}
+
+fn read_tokens(path: &Path) -> Result<Vec<(String, String)>> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => Ok(parse_tokens(&data)),
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+fn write_tokens(path: &Path, tokens: &[(String, String)]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(TOKEN_FILE_MODE)
+        .open(path)?;
+    // The mode is only applied to new files
+    file.set_permissions(std::fs::Permissions::from_mode(TOKEN_FILE_MODE))?;
+    file.write_all(format_tokens(tokens).as_bytes())?;
+    Ok(())
+}
+
+/// Parse `host<TAB>token` lines
+fn parse_tokens(data: &str) -> Vec<(String, String)> {
+    data.lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(host, token)| (String::from(host.trim()), String::from(token.trim())))
+        .collect()
+}
+
+fn format_tokens(tokens: &[(String, String)]) -> String {
+    let mut s = String::new();
+    for (host, token) in tokens.iter() {
+        s.push_str(host);
+        s.push('\t');
+        s.push_str(token);
+        s.push('\n');
+    }
+    s
+}
+
+fn find_token<'a>(tokens: &'a [(String, String)], host: &str) -> Option<&'a str> {
+    tokens
+        .iter()
+        .find(|(h, _)| h == host)
+        .map(|(_, token)| token.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_file_round_trip() {
+        let tokens = vec![
+            (String::from("github.com"), String::from("ghp_abc")),
+            (String::from("gitlab.com"), String::from("glpat-xyz")),
+        ];
+        let s = format_tokens(&tokens);
+        assert_eq!(s, "github.com\tghp_abc\ngitlab.com\tglpat-xyz\n");
+        let parsed = parse_tokens(&s);
+        assert_eq!(parsed, tokens);
+        assert_eq!(find_token(&parsed, "gitlab.com"), Some("glpat-xyz"));
+        assert_eq!(find_token(&parsed, "example.com"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+const HOSTS: [&str; 4] = [
+    "github.com",
+    "gitlab.com",
+    "bitbucket.org",
+    "git.example.com",
+];
+const WORDS: [&str; 24] = [
+    "api", "app", "auth", "billing", "cli", "core", "data", "docs", "edge", "gateway", "infra",
+    "kit", "lib", "mobile", "notes", "payments", "platform", "proxy", "search", "service", "tools",
+    "ui", "web", "worker",
+];
+
+/// Queries for measuring the scoring, from common to rare matches
+pub const QUERIES: [&str; 6] = ["api", "pay svc", "github core", "gl/infra", "wrkr", "zzzz"];
+
+/// Deterministic pseudo-random number generator for repeatable synthetic data
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+
+    fn word(&mut self) -> &'static str {
+        WORDS[self.next(WORDS.len())]
+    }
+}
+
+/// Unique project entries that look like the ones cloned from forges,
+/// e.g. `github.com/payments-7/api-service`. The same count always gives the same entries.
+pub fn synthetic_entries(count: usize) -> Vec<String> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    (0..count)
+        .map(|i| {
+            let host = HOSTS[rng.next(HOSTS.len())];
+            let org = format!("{}-{}", rng.word(), rng.next(500));
+            // The index keeps the entries unique
+            let repo = format!("{}-{}-{i}", rng.word(), rng.word());
+            format!("{host}/{org}/{repo}")
+        })
+        .collect()
+}
+
+/// Create an empty Git directory for each entry under the given directory
+pub fn create_project_tree(dir: &Path, entries: &[String]) -> Result<()> {
+    for entry in entries {
+        std::fs::create_dir_all(crate::db::entry_path(dir, entry).join(".git"))?;
+    }
+    Ok(())
+}
+
+/// Results of a benchmark run
+#[derive(Serialize)]
+pub struct Report {
+    /// Number of projects in the index
+    pub projects: usize,
+    /// Time to load the index file
+    #[serde(serialize_with = "serialize_millis")]
+    pub load: Duration,
+    /// Projects scored per second when matching against all projects
+    pub scoring_per_sec: f64,
+    /// Time to build the trigram index used by `find`
+    #[serde(serialize_with = "serialize_millis")]
+    pub index_build: Duration,
+    /// Latency of the matching after each keystroke in `find`
+    pub keystroke: Latency,
+    /// None when scanning wasn't measured
+    pub scan: Option<ScanReport>,
+}
+
+#[derive(Serialize)]
+pub struct ScanReport {
+    pub repos: usize,
+    pub dirs: usize,
+    #[serde(serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+    pub dirs_per_sec: f64,
+}
+
+/// Summary of repeated measurements
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Latency {
+    pub samples: usize,
+    #[serde(serialize_with = "serialize_millis")]
+    pub min: Duration,
+    #[serde(serialize_with = "serialize_millis")]
+    pub median: Duration,
+    #[serde(serialize_with = "serialize_millis")]
+    pub max: Duration,
+}
+
+impl Latency {
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        Self {
+            samples: samples.len(),
+            min: samples.first().copied().unwrap_or_default(),
+            median: samples.get(samples.len() / 2).copied().unwrap_or_default(),
+            max: samples.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "min {}, median {}, max {} ({} samples)",
+            format_duration(self.min),
+            format_duration(self.median),
+            format_duration(self.max),
+            self.samples
+        )
+    }
+}
+
+/// Items per second, or 0 when no time was measured
+pub fn throughput(items: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0. { items as f64 / secs } else { 0. }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.)
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthetic_entries_are_repeatable_and_unique() {
+        let entries = synthetic_entries(1000);
+        assert_eq!(entries, synthetic_entries(1000));
+        let mut unique = entries.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 1000);
+        assert!(entries.iter().all(|entry| entry.split('/').count() == 3));
+    }
+
+    #[test]
+    fn summarize_latency() {
+        let ms = Duration::from_millis;
+        let latency = Latency::new(vec![ms(5), ms(1), ms(3), ms(9)]);
+        assert_eq!(
+            latency,
+            Latency {
+                samples: 4,
+                min: ms(1),
+                median: ms(5),
+                max: ms(9),
+            }
+        );
+        assert_eq!(Latency::new(Vec::new()).max, Duration::ZERO);
+        assert_eq!(throughput(10, Duration::from_millis(500)), 20.);
+        assert_eq!(throughput(10, Duration::ZERO), 0.);
+    }
+}
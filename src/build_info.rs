@@ -0,0 +1,27 @@
+use std::time::{Duration, SystemTime};
+
+use crate::oplog;
+
+const GIT_COMMIT: &str = env!("GORG_GIT_COMMIT");
+const BUILD_SECS: &str = env!("GORG_BUILD_SECS");
+const TARGET: &str = env!("GORG_TARGET");
+const PROFILE: &str = env!("GORG_PROFILE");
+const FEATURES: &str = env!("GORG_FEATURES");
+
+/// Version along with the details of the build for identifying it in bug reports
+pub fn verbose_version() -> String {
+    let built_at = BUILD_SECS
+        .parse()
+        .map(|secs| oplog::format_time(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)))
+        .unwrap_or_else(|_| String::from("unknown"));
+    let features = if FEATURES.is_empty() {
+        "none"
+    } else {
+        FEATURES
+    };
+    format!(
+        "{} {}\ncommit:   {GIT_COMMIT}\nbuilt:    {built_at}\ntarget:   {TARGET}\nprofile:  {PROFILE}\nfeatures: {features}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+    )
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+const GIT_DIR_NAME: &str = ".git";
+
+/// Projects in the index that are checkouts of the same remote repository,
+/// e.g. the canonical clone, a scratch clone, and linked worktrees
+pub struct Checkouts<'a> {
+    /// Main checkout of each project that shares its remote with other projects.
+    /// Main checkouts themselves are not included.
+    main_checkouts: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Checkouts<'a> {
+    /// Group the projects by their remotes. Remotes are compared by the project paths derived
+    /// from them using the given function, so that e.g. HTTPS and SSH remotes of the same
+    /// repository are grouped together. The project at the derived path is the main checkout.
+    /// When there's no project at the derived path, the first project is the main checkout.
+    pub fn new<I, F>(remotes: I, derive_path: F) -> Self
+    where
+        I: Iterator<Item = (&'a str, &'a str)>,
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut by_path: HashMap<String, Vec<&'a str>> = HashMap::new();
+        for (entry, url) in remotes {
+            if let Some(path) = derive_path(url) {
+                by_path.entry(path).or_default().push(entry);
+            }
+        }
+        let mut main_checkouts = HashMap::new();
+        for (path, mut entries) in by_path.into_iter().filter(|(_, e)| e.len() > 1) {
+            entries.sort_unstable();
+            let main = entries
+                .iter()
+                .copied()
+                .find(|entry| *entry == path)
+                .unwrap_or(entries[0]);
+            for entry in entries.into_iter().filter(|entry| *entry != main) {
+                main_checkouts.insert(entry, main);
+            }
+        }
+        Self { main_checkouts }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.main_checkouts.is_empty()
+    }
+
+    /// Main checkout of the remote of a project that is an additional checkout.
+    /// Returns None for main checkouts and projects that are the only checkout of their remote.
+    pub fn main_checkout(&self, entry: &str) -> Option<&'a str> {
+        self.main_checkouts.get(entry).copied()
+    }
+
+    /// Description of an additional checkout, e.g. `worktree of github.com/jpallari/gorg`.
+    /// Returns None for main checkouts and projects that are the only checkout of their remote.
+    pub fn describe(&self, entry: &str, path: &Path) -> Option<String> {
+        let main = self.main_checkout(entry)?;
+        // Linked worktrees have a file pointing to the Git directory of the main worktree
+        let kind = if path.join(GIT_DIR_NAME).is_file() {
+            "worktree"
+        } else {
+            "checkout"
+        };
+        Some(format!("{kind} of {main}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_checkouts() {
+        let remotes = [
+            ("a.com/x", "https://a.com/x.git"),
+            ("a.com/x-scratch", "git@a.com:x.git"),
+            ("a.com/x-review", "https://a.com/x.git"),
+            ("a.com/y", "https://a.com/y.git"),
+            ("b.com/z-1", "https://b.com/z.git"),
+            ("b.com/z-2", "https://b.com/z.git"),
+        ];
+        let checkouts = Checkouts::new(remotes.into_iter(), |url| {
+            crate::git_url::to_path(url, false)
+                .ok()
+                .map(|path| path.join("/"))
+        });
+        assert_eq!(checkouts.main_checkout("a.com/x"), None);
+        assert_eq!(checkouts.main_checkout("a.com/x-scratch"), Some("a.com/x"));
+        assert_eq!(checkouts.main_checkout("a.com/x-review"), Some("a.com/x"));
+        assert_eq!(checkouts.main_checkout("a.com/y"), None);
+        assert_eq!(checkouts.main_checkout("b.com/z-1"), None);
+        assert_eq!(checkouts.main_checkout("b.com/z-2"), Some("b.com/z-1"));
+        assert_eq!(
+            checkouts.describe("b.com/z-2", Path::new("/nonexistent")),
+            Some(String::from("checkout of b.com/z-1"))
+        );
+
+        let checkouts = Checkouts::new(remotes.into_iter(), |_| None);
+        assert!(checkouts.is_empty());
+    }
+}
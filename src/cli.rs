@@ -1,13 +1,37 @@
-use clap::{Args, Parser, Subcommand};
+use clap::builder::{EnumValueParser, PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::ffi::OsStr;
 use std::path::PathBuf;
 
+use crate::completions::{COMPLETE_COMMAND, Shell};
+use crate::config::Layout;
+use crate::lang;
+
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, disable_version_flag = true)]
 pub struct Cli {
     /// Path to the gorg configuration file
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<PathBuf>,
 
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// Print the Git commit, build date, target, and features of the build along with the version
+    #[arg(long, requires = "version")]
+    pub verbose: bool,
+
+    /// Fail instead of prompting for input such as Git credentials
+    #[arg(long, global = true)]
+    pub no_input: bool,
+
+    /// Don't change the projects, the index, or the other files of gorg, e.g. when the project
+    /// directory is a read-only snapshot. Commands that would change them fail,
+    /// and `update-index` only prints the changes.
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
     /// Command to execute
     #[command(subcommand)]
     pub command: Option<Commands>,
@@ -15,21 +39,233 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Print completion candidates for the given command line words
+    #[command(name = COMPLETE_COMMAND, hide = true)]
+    Complete(CompleteArgs),
+
+    /// Print the indexed project that contains the given directory. Exits with a non-zero code
+    /// when the directory is not in an indexed project. Meant for shell prompts.
+    #[command(name = "__prompt-info", hide = true)]
+    PromptInfo(PromptInfoArgs),
+
+    /// Manage API tokens for forges such as GitHub and GitLab
+    Auth(AuthArgs),
+
+    /// Write Git bundles of all (matching) projects and their index entries to a backup directory
+    Backup(BackupArgs),
+
+    /// Measure the speed of fuzzy matching, finding, and scanning on synthetic or given data
+    #[command(hide = true)]
+    Bench(BenchArgs),
+
+    /// List the checked out branch of all (matching) projects
+    Branches(BranchesArgs),
+
+    /// Generate a shell completion script
+    Completions(CompletionsArgs),
+
+    /// Inspect the configuration merged from the configuration files
+    Config(ConfigArgs),
+
+    /// Run a daemon that keeps the index in memory and serves queries over a Unix socket
+    Daemon,
+
+    /// Check the index for problems, such as unsorted or duplicate entries after editing it by hand
+    Doctor(DoctorArgs),
+
+    /// Set or print the description of a project. Descriptions are included in fuzzy matching.
+    Describe(DescribeArgs),
+
+    /// Print the disk usage of all (matching) projects
+    Du(DuArgs),
+
     /// Find a project using a fuzzy matcher (interactive)
     Find(FindArgs),
 
     /// Initializes a repository for the given remote
     Init(InitArgs),
 
+    /// Print the path of the most recently used project
+    Last(LastArgs),
+
     /// List all projects that match the given fuzzy query (alias "ls")
     #[command(alias = "ls")]
     List(ListArgs),
 
+    /// Show the log of operations done by gorg, newest first
+    Log(LogArgs),
+
+    /// Generate man pages for gorg and its sub-commands
+    Man(ManArgs),
+
+    /// Add a directory to the index and keep it there across index updates
+    Pin(PinArgs),
+
+    /// Create a new local project without a remote
+    New(NewArgs),
+
+    /// Open a project with the editor or another configured opener
+    Open(OpenArgs),
+
+    /// Report projects with commits or stashes that haven't been pushed to any remote.
+    /// Exits with a non-zero code when such projects are found.
+    PushCheck(PushCheckArgs),
+
+    /// List the most recently used projects
+    Recent(RecentArgs),
+
+    /// Move a project to another path in the project directory and update its index entry
+    Rename(RenameArgs),
+
+    /// Re-create projects from the bundles in a backup directory
+    Restore(RestoreArgs),
+
     /// Run a given command in all (matching) projects
     Run(RunArgs),
 
+    /// Print the cached Git status of all (matching) projects
+    Status(StatusArgs),
+
+    /// Fetch all (matching) projects and refresh their cached Git status
+    Sync(SyncArgs),
+
+    /// Clone new projects, archive removed projects, and update remotes of the mirrored organizations
+    SyncOrgs(SyncOrgsArgs),
+
+    /// Check out a branch in all (matching) projects that have it.
+    /// Projects with uncommitted changes are skipped.
+    Switch(SwitchArgs),
+
+    /// Report directories without Git repositories, empty directories, and misplaced repositories
+    /// in the project directory
+    Tidy(TidyArgs),
+
+    /// Manage projects in an interactive full-screen dashboard
+    Ui,
+
+    /// Undo the most recent archive or move of a project recorded in the operations log
+    Undo(UndoArgs),
+
     /// Scan the project directory for all Git projects and update the index file
-    UpdateIndex,
+    UpdateIndex(UpdateIndexArgs),
+
+    /// Watch the project directory for new and removed Git projects and update the index file
+    Watch,
+}
+
+#[derive(Args)]
+pub struct AuthArgs {
+    #[command(subcommand)]
+    pub command: AuthCommands,
+}
+
+#[derive(Subcommand)]
+pub enum AuthCommands {
+    /// Store an API token for a host in the OS keyring. The token is read from stdin.
+    Login(AuthHostArgs),
+
+    /// Remove the stored API token of a host
+    Logout(AuthHostArgs),
+
+    /// Print the stored API token of a host
+    Token(AuthHostArgs),
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the configuration in use, including the default values
+    Show(ConfigShowArgs),
+}
+
+#[derive(Args)]
+pub struct ConfigShowArgs {
+    /// Show which configuration file set each value
+    #[arg(long)]
+    pub origin: bool,
+}
+
+#[derive(Args)]
+pub struct AuthHostArgs {
+    /// Forge host (e.g. github.com)
+    pub host: String,
+}
+
+#[derive(Args)]
+pub struct BackupArgs {
+    /// Fuzzy find query. All projects will be backed up when not used.
+    pub query: Vec<String>,
+
+    /// Directory to write the backup to
+    #[arg(long, value_name = "DIR")]
+    pub to: PathBuf,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Index file to measure matching with. An index of synthetic projects is used when not set.
+    #[arg(long, value_name = "FILE")]
+    pub db: Option<PathBuf>,
+
+    /// Number of projects in the synthetic index
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 100_000,
+        conflicts_with = "db"
+    )]
+    pub projects: usize,
+
+    /// Number of projects in the synthetic directory tree to scan. Scanning is skipped when 0.
+    #[arg(long, value_name = "N", default_value_t = 2_000)]
+    pub scan_projects: usize,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct BranchesArgs {
+    /// Fuzzy find query. All projects will be listed when not used.
+    pub query: Vec<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Same as `--format json`
+    #[arg(long, hide = true, conflicts_with = "format")]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct CompleteArgs {
+    /// Command line words after the program name. The last word is the one being completed.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub words: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct PromptInfoArgs {
+    /// Directory to find the project for, usually the current directory of the shell
+    pub dir: PathBuf,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    #[arg(value_enum)]
+    pub shell: Shell,
 }
 
 #[derive(Args)]
@@ -40,6 +276,68 @@ pub struct InitArgs {
     /// When set, repository cloning is not performed.
     #[arg(long)]
     pub no_clone: bool,
+
+    /// Name of the template from the configuration to apply to the new project
+    #[arg(short, long, value_name = "NAME", requires = "no_clone")]
+    pub template: Option<String>,
+
+    /// Clone to a directory named after the repository in the current directory
+    /// instead of the projects directory. The project is still added to the index.
+    #[arg(long, conflicts_with = "path")]
+    pub here: bool,
+
+    /// Clone to the given directory instead of the projects directory.
+    /// The project is still added to the index.
+    #[arg(long, value_name = "DIR")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DescribeArgs {
+    /// Fuzzy find query matching a single project. The project containing the current directory
+    /// is used when not set.
+    pub query: Option<String>,
+
+    /// Description to set. The current description is printed when not set.
+    pub description: Option<String>,
+
+    /// Remove the description
+    #[arg(short, long, conflicts_with = "description")]
+    pub remove: bool,
+}
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// Check that the entries in the index file are sorted, unique, valid UTF-8, and free of
+    /// empty lines and surrounding whitespace. All checks are run when none are selected.
+    #[arg(long)]
+    pub verify_db: bool,
+
+    /// Repair the problems found. Lines that aren't valid UTF-8 are removed.
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Args)]
+pub struct DuArgs {
+    /// Fuzzy find query. All projects will be listed when not used.
+    pub query: Vec<String>,
+
+    /// Order of the listed projects
+    #[arg(long, value_enum, default_value_t = DuSort::Name)]
+    pub sort: DuSort,
+
+    /// Print the size of the Git directory and the working tree separately
+    #[arg(short, long)]
+    pub git: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DuSort {
+    /// Alphabetical order
+    Name,
+    /// Largest projects first
+    Size,
 }
 
 #[derive(Args)]
@@ -50,6 +348,21 @@ pub struct FindArgs {
     /// Print full path instead of just the project name
     #[arg(short, long)]
     pub full_path: bool,
+
+    /// Use the whole terminal for listing matches
+    #[arg(long)]
+    pub full_screen: bool,
+
+    /// Where to place the prompt. Defaults to the layout in the configuration.
+    #[arg(long, value_enum)]
+    pub layout: Option<Layout>,
+}
+
+#[derive(Args)]
+pub struct LastArgs {
+    /// Print the project name instead of the full path
+    #[arg(short, long)]
+    pub name: bool,
 }
 
 #[derive(Args)]
@@ -64,6 +377,374 @@ pub struct ListArgs {
     // Use a prefix query instead of a fuzzy query
     #[arg(short, long)]
     pub prefix_search: bool,
+
+    /// Order of the listed projects. Prefix search results are always sorted by name.
+    #[arg(long, value_enum, default_value_t = ListSort::Score)]
+    pub sort: ListSort,
+
+    /// Print the fuzzy match score before each project
+    #[arg(long, conflicts_with_all = ["prefix_search", "sort"])]
+    pub scores: bool,
+
+    /// Only list projects with at least the given fuzzy match score
+    #[arg(long, value_name = "SCORE", conflicts_with_all = ["prefix_search", "sort"])]
+    pub min_score: Option<f32>,
+
+    /// Print how the fuzzy match score of each of the best matching projects adds up
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "prefix_search", "sort", "scores", "min_score", "remotes", "status", "checkouts",
+            "lang", "tag"
+        ]
+    )]
+    pub explain: bool,
+
+    /// Number of projects to explain the score for
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "explain")]
+    pub explain_limit: usize,
+
+    /// Print only the number of matching projects
+    #[arg(long, conflicts_with_all = ["full_path", "scores", "explain"])]
+    pub count: bool,
+
+    /// Print the Git remote URL recorded in the index after each project
+    #[arg(short, long, conflicts_with = "count")]
+    pub remotes: bool,
+
+    /// Print the cached Git status after each project
+    #[arg(long, conflicts_with = "count")]
+    pub status: bool,
+
+    /// Print which project each additional checkout or worktree of the same remote belongs to
+    #[arg(long, conflicts_with = "count")]
+    pub checkouts: bool,
+
+    /// Only list projects with uncommitted changes or untracked files in the cached Git status.
+    /// When several status filters are set, projects must match all of them.
+    #[arg(long, conflicts_with = "explain")]
+    pub dirty: bool,
+
+    /// Only list projects with commits not pushed to the upstream branch in the cached Git status
+    #[arg(long, conflicts_with = "explain")]
+    pub ahead: bool,
+
+    /// Only list projects with commits not pulled from the upstream branch in the cached Git status
+    #[arg(long, conflicts_with = "explain")]
+    pub behind: bool,
+
+    /// Only list projects with a detached HEAD in the cached Git status
+    #[arg(long, conflicts_with = "explain")]
+    pub detached: bool,
+
+    /// Only list projects whose branch has no upstream branch in the cached Git status
+    #[arg(long, conflicts_with = "explain")]
+    pub no_upstream: bool,
+
+    /// Only list projects of the given ecosystem, detected from marker files such as `Cargo.toml`
+    #[arg(long, value_name = "LANG", value_parser = PossibleValuesParser::new(lang::NAMES))]
+    pub lang: Option<String>,
+
+    /// Only list projects with the given tag in their `.gorg.toml` file
+    #[arg(long, value_name = "TAG")]
+    pub tag: Option<String>,
+
+    /// Output format of the listed projects, or a template for the line of each project,
+    /// e.g. `git@{host}:{org}/{repo}.git`. Templates can contain the `{project}`, `{path}`,
+    /// `{host}`, `{org}`, `{repo}`, `{remote}`, `{web_url}`, and `{description}` placeholders.
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = ListOutputParser,
+        default_value = "text",
+        conflicts_with_all = ["count", "scores", "remotes", "status", "checkouts", "explain"]
+    )]
+    pub format: ListOutput,
+}
+
+/// Output of the listed projects
+#[derive(Clone, PartialEq)]
+pub enum ListOutput {
+    Format(ListFormat),
+    /// Line for each project with the placeholders filled in
+    Template(String),
+}
+
+/// Parser for the list formats that reads the values containing `{` as templates
+#[derive(Clone)]
+struct ListOutputParser;
+
+impl TypedValueParser for ListOutputParser {
+    type Value = ListOutput;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &OsStr,
+    ) -> Result<ListOutput, clap::Error> {
+        if let Some(template) = value.to_str().filter(|value| value.contains('{')) {
+            return Ok(ListOutput::Template(String::from(template)));
+        }
+        EnumValueParser::<ListFormat>::new()
+            .parse_ref(cmd, arg, value)
+            .map(ListOutput::Format)
+    }
+
+    fn possible_values(&self) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+        let values = ListFormat::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value);
+        Some(Box::new(values))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ListSort {
+    /// Best fuzzy matches first
+    Score,
+    /// Alphabetical order
+    Name,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// One project per line
+    Text,
+    /// VS Code multi-root workspace file with the projects as folders
+    VscodeWorkspace,
+    /// Sublime Text project file with the projects as folders
+    SublimeProject,
+    /// JSON array of the projects with their paths, remotes, languages, and cached Git status
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// JSON for other tools
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// Human-readable text
+    Text,
+    /// JSON for other tools
+    Json,
+    /// Markdown table for wikis, issues, and pull request descriptions
+    Markdown,
+}
+
+#[derive(Args)]
+pub struct ManArgs {
+    /// Directory to write the man pages to.
+    /// When not set, only the main man page is printed to stdout.
+    #[arg(short, long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct NewArgs {
+    /// Host part of the project path (e.g. github.com)
+    pub host: String,
+
+    /// Organization or user part of the project path
+    pub org: String,
+
+    /// Name of the project
+    pub name: String,
+
+    /// Template directory to pass to `git init --template`
+    #[arg(long, value_name = "DIR")]
+    pub git_template: Option<PathBuf>,
+
+    /// Name of the template from the configuration to apply to the new project
+    #[arg(short, long, value_name = "NAME")]
+    pub template: Option<String>,
+}
+
+#[derive(Args)]
+pub struct OpenArgs {
+    /// Fuzzy find query. The project is selected interactively when several projects match.
+    /// The project containing the current directory is opened when not set.
+    pub query: Vec<String>,
+
+    /// Name of the opener from the configuration to use, or "editor" for the editor from `$EDITOR`
+    #[arg(short, long, value_name = "NAME")]
+    pub with: Option<String>,
+}
+
+#[derive(Args)]
+pub struct PinArgs {
+    /// Directory inside the project directory to pin
+    pub path: PathBuf,
+
+    /// Remove the pin instead of adding it
+    #[arg(short, long)]
+    pub remove: bool,
+}
+
+#[derive(Args)]
+pub struct PushCheckArgs {
+    /// Fuzzy find query. All projects will be checked when not used.
+    pub query: Vec<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Same as `--format json`
+    #[arg(long, hide = true, conflicts_with = "format")]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct TidyArgs {
+    /// Remove empty directories and move misplaced repositories to the paths derived from their remotes.
+    /// Directories that contain files are never removed.
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Args)]
+pub struct UndoArgs {
+    /// Only print what would be undone
+    #[arg(short, long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args)]
+pub struct UpdateIndexArgs {
+    /// Print the entries that would be added to or removed from the index without updating it.
+    /// Exits with a non-zero code when there are differences.
+    #[arg(short, long)]
+    pub dry_run: bool,
+
+    /// Read the Git remote URLs of all projects from the repositories.
+    /// By default, only the remotes of new projects are read.
+    #[arg(long)]
+    pub refresh_remotes: bool,
+
+    /// Fail without updating the index when some directories can't be scanned.
+    /// By default, such directories are skipped and listed after the scan.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Read all directories instead of skipping the ones that haven't changed since the last scan
+    #[arg(long)]
+    pub full: bool,
+
+    /// Build the index from the scan alone without reading the current index, e.g. when it is corrupt.
+    /// Pins, descriptions, and picks of the current index are lost, but recent uses from the history log are kept.
+    #[arg(long, conflicts_with = "dry_run")]
+    pub rebuild: bool,
+
+    /// Output format of the changes to the index
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct RecentArgs {
+    /// Number of projects to list
+    #[arg(short = 'n', long, value_name = "N", default_value_t = 10)]
+    pub number: usize,
+
+    /// Print full path instead of just the project name
+    #[arg(short, long)]
+    pub full_path: bool,
+}
+
+#[derive(Args)]
+pub struct RenameArgs {
+    /// Fuzzy find query matching a single project
+    pub query: String,
+
+    /// New path of the project relative to the project directory (e.g. github.com/org/repo)
+    pub new_path: String,
+
+    /// Point the remote of the project to the new path too
+    #[arg(short, long)]
+    pub update_remote: bool,
+}
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    /// Backup directory created with the backup sub-command
+    #[arg(long, value_name = "DIR")]
+    pub from: PathBuf,
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Fuzzy find query. All projects will be listed when not used.
+    pub query: Vec<String>,
+
+    /// Read the Git status from the matching projects before printing it
+    #[arg(short, long)]
+    pub refresh: bool,
+
+    /// Keep refreshing and printing the Git status on a cleared screen until interrupted
+    #[arg(short, long, conflicts_with = "format")]
+    pub watch: bool,
+
+    /// Seconds to wait between refreshes when watching
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 2,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "watch"
+    )]
+    pub interval: u64,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    /// Fuzzy find query. All projects will be fetched when not used.
+    pub query: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct SyncOrgsArgs {
+    /// Only print the projects that would be cloned (+), archived (-), or have their remote updated (~)
+    #[arg(short, long)]
+    pub dry: bool,
+
+    /// Move the projects removed from the organizations to the archive directory after a confirmation.
+    /// Without it, the removed projects are only listed.
+    #[arg(long, conflicts_with = "dry")]
+    pub archive: bool,
+}
+
+#[derive(Args)]
+pub struct SwitchArgs {
+    /// Branch to check out
+    pub branch: String,
+
+    /// Fuzzy find query. All projects will be targeted when not used.
+    pub query: Vec<String>,
+
+    /// Create the branch from the remote branch of the same name when it only exists in the remote
+    #[arg(short, long)]
+    pub create: bool,
+
+    /// Only print what would be done in each project
+    #[arg(short, long)]
+    pub dry: bool,
+}
+
+#[derive(Args)]
+pub struct LogArgs {
+    /// Maximum number of operations to show
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<usize>,
 }
 
 #[derive(Args)]
@@ -81,6 +762,54 @@ pub struct RunArgs {
     #[arg(long)]
     pub quiet: bool,
 
-    /// The command to run and the parameters to give to the command
+    /// Run the command in up to this many projects at a time.
+    /// Output lines are prefixed with a `[project]` tag unless `--quiet` or `--no-prefix` is set.
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Print the output lines of parallel runs without the `[project]` tags
+    #[arg(long, requires = "jobs")]
+    pub no_prefix: bool,
+
+    /// Show the state and output of each project in a full-screen view while running with `--jobs`
+    #[arg(long, requires = "jobs")]
+    pub tui: bool,
+
+    /// Run the command in this directory relative to each project.
+    /// Projects without the directory are skipped.
+    #[arg(long, value_name = "DIR")]
+    pub subdir: Option<PathBuf>,
+
+    /// Command to run instead of the positional command. Can be given several times
+    /// to run the commands one after another, stopping at the first command that fails.
+    /// Quotes can be used for grouping words like in a shell.
+    #[arg(
+        short = 'c',
+        long = "command",
+        value_name = "COMMAND",
+        conflicts_with = "command"
+    )]
+    pub commands: Vec<String>,
+
+    /// Only run the command in projects of the given ecosystem,
+    /// detected from marker files such as `Cargo.toml`
+    #[arg(long, value_name = "LANG", value_parser = PossibleValuesParser::new(lang::NAMES))]
+    pub lang: Option<String>,
+
+    /// Only run the command in projects with the given tag in their `.gorg.toml` file
+    #[arg(long, value_name = "TAG")]
+    pub tag: Option<String>,
+
+    /// Script file to run in each project using the shell from `$SHELL`
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["command", "commands"])]
+    pub script: Option<PathBuf>,
+
+    /// Output format of the summary printed after running the command.
+    /// In the JSON format, the output of the command is printed to stderr.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with = "dry")]
+    pub format: OutputFormat,
+
+    /// The command to run and the parameters to give to the command.
+    /// When no command is given, the task from the `.gorg.toml` file of each project is run.
     pub command: Vec<String>,
 }
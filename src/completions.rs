@@ -0,0 +1,252 @@
+use clap::{Arg, Command, ValueEnum};
+
+/// Name of the hidden sub-command the completion scripts call for candidates
+pub const COMPLETE_COMMAND: &str = "__complete";
+
+/// Argument ID used for fuzzy queries in the sub-commands
+const QUERY_ARG_ID: &str = "query";
+
+#[derive(ValueEnum, Copy, Clone)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn script(&self) -> &'static str {
+        match self {
+            Self::Bash => BASH_SCRIPT,
+            Self::Zsh => ZSH_SCRIPT,
+            Self::Fish => FISH_SCRIPT,
+        }
+    }
+}
+
+const BASH_SCRIPT: &str = r#"_gorg() {
+    local IFS=$'\n'
+    COMPREPLY=($(gorg __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+complete -o default -F _gorg gorg
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef gorg
+_gorg() {
+    local -a candidates
+    candidates=(${(f)"$(gorg __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -U -- "${candidates[@]}"
+    else
+        _files
+    fi
+}
+compdef _gorg gorg
+"#;
+
+const FISH_SCRIPT: &str = r#"function __gorg_complete
+    set -l tokens (commandline -opc) (commandline -ct)
+    gorg __complete -- $tokens[2..-1] 2>/dev/null
+end
+complete -c gorg -f -a '(__gorg_complete)'
+"#;
+
+#[derive(Debug, PartialEq)]
+pub enum Completion {
+    /// Sub-commands, flags, or argument values starting with the current word
+    Words(Vec<String>),
+    /// Projects matching the current word
+    Projects,
+    /// Nothing to complete. Shells may fall back to completing files.
+    Nothing,
+}
+
+/// Find completion candidates for the given command line words.
+/// The words exclude the program name, and the last word is the one being completed.
+/// The command must be built before calling this.
+pub fn complete(cmd: &Command, words: &[String]) -> Completion {
+    let Some((current, previous)) = words.split_last() else {
+        return Completion::Nothing;
+    };
+
+    let mut cmd = cmd;
+    let mut positionals = 0;
+    let mut value_for: Option<&Arg> = None;
+    for word in previous.iter() {
+        if value_for.take().is_some() {
+            continue;
+        }
+        if word.starts_with('-') {
+            value_for = find_option(cmd, word)
+                .filter(|arg| arg.get_action().takes_values() && !word.contains('='));
+            continue;
+        }
+        if let Some(subcommand) = cmd.find_subcommand(word) {
+            cmd = subcommand;
+            positionals = 0;
+            continue;
+        }
+        positionals += 1;
+    }
+
+    if let Some(arg) = value_for {
+        return complete_value(arg, current);
+    }
+    let candidates: Vec<String> = if current.starts_with('-') {
+        cmd.get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| arg.get_long())
+            .map(|long| format!("--{long}"))
+            .collect()
+    } else if cmd.has_subcommands() {
+        cmd.get_subcommands()
+            .filter(|subcommand| !subcommand.is_hide_set())
+            .map(|subcommand| subcommand.get_name().to_string())
+            .collect()
+    } else {
+        // Multi-value positionals take the rest of the words
+        let args: Vec<&Arg> = cmd.get_positionals().collect();
+        let arg = args.get(positionals).or_else(|| {
+            args.last()
+                .filter(|arg| arg.get_num_args().is_some_and(|n| n.max_values() > 1))
+        });
+        return match arg {
+            Some(arg) => complete_value(arg, current),
+            None => Completion::Nothing,
+        };
+    };
+    filter_words(candidates, current)
+}
+
+fn complete_value(arg: &Arg, current: &str) -> Completion {
+    if arg.get_id() == QUERY_ARG_ID {
+        return Completion::Projects;
+    }
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if values.is_empty() {
+        Completion::Nothing
+    } else {
+        filter_words(values, current)
+    }
+}
+
+fn filter_words(mut words: Vec<String>, current: &str) -> Completion {
+    words.retain(|word| word.starts_with(current));
+    Completion::Words(words)
+}
+
+fn find_option<'a>(cmd: &'a Command, word: &str) -> Option<&'a Arg> {
+    if let Some(long) = word.strip_prefix("--") {
+        let long = long.split_once('=').map_or(long, |(name, _)| name);
+        cmd.get_arguments().find(|arg| arg.get_long() == Some(long))
+    } else {
+        let mut shorts = word.strip_prefix('-')?.chars();
+        let short = shorts.next()?;
+        // Values given in the same word (e.g. `-qfoo`) don't need a separate value word
+        if shorts.next().is_some() {
+            return None;
+        }
+        cmd.get_arguments()
+            .find(|arg| arg.get_short() == Some(short))
+    }
+}
+
+/// Value of the config file option in the given command line words
+pub fn config_arg(words: &[String]) -> Option<&str> {
+    let mut iter = words.iter();
+    while let Some(word) = iter.next() {
+        if word == "-c" || word == "--config" {
+            return iter.next().map(|s| s.as_str());
+        }
+        if let Some(path) = word.strip_prefix("--config=") {
+            return Some(path);
+        }
+        if !word.starts_with('-') {
+            // Config can only be given before the sub-command
+            break;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    fn complete_words(words: &[&str]) -> Completion {
+        let mut cmd = Cli::command();
+        cmd.build();
+        let words: Vec<String> = words.iter().map(|s| s.to_string()).collect();
+        complete(&cmd, &words)
+    }
+
+    fn words(words: &[&str]) -> Completion {
+        Completion::Words(words.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn complete_subcommands() {
+        assert_eq!(complete_words(&["f"]), words(&["find"]));
+        assert_eq!(
+            complete_words(&["-c", "gorg.toml", "p"]),
+            words(&["pin", "push-check"])
+        );
+    }
+
+    #[test]
+    fn complete_flags() {
+        assert_eq!(
+            complete_words(&["find", "--f"]),
+            words(&["--full-path", "--full-screen"])
+        );
+        assert_eq!(complete_words(&["--c"]), words(&["--config"]));
+    }
+
+    #[test]
+    fn complete_queries() {
+        assert_eq!(complete_words(&["find", "go"]), Completion::Projects);
+        assert_eq!(
+            complete_words(&["list", "-f", "jp", "go"]),
+            Completion::Projects
+        );
+        assert_eq!(complete_words(&["run", "-q", "git"]), Completion::Projects);
+        assert_eq!(
+            complete_words(&["run", "--query", ""]),
+            Completion::Projects
+        );
+    }
+
+    #[test]
+    fn complete_values() {
+        assert_eq!(
+            complete_words(&["find", "--layout", "r"]),
+            words(&["reverse"])
+        );
+        assert_eq!(
+            complete_words(&["run", "-q", "git", "l"]),
+            Completion::Nothing
+        );
+        assert_eq!(complete_words(&["pin", ""]), Completion::Nothing);
+    }
+
+    #[test]
+    fn config_from_words() {
+        let words =
+            |words: &[&str]| -> Vec<String> { words.iter().map(|s| s.to_string()).collect() };
+        assert_eq!(
+            config_arg(&words(&["-c", "a.toml", "find"])),
+            Some("a.toml")
+        );
+        assert_eq!(
+            config_arg(&words(&["--config=b.toml", "find"])),
+            Some("b.toml")
+        );
+        assert_eq!(config_arg(&words(&["run", "git", "-c", "x"])), None);
+    }
+}
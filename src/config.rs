@@ -1,15 +1,31 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
-use serde::Deserialize;
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::fuzzy;
+use crate::git_url;
+use crate::text;
 
 const CONFIG_ENV_VAR_NAME: &str = "GORG_CONFIG";
+const HOME_ENV_VAR_NAME: &str = "GORG_HOME";
+const SYSTEM_CONFIG_PATH: &str = "/etc/gorg/config.toml";
+const TREE_CONFIG_FILE_NAME: &str = ".gorg.toml";
 const DEFAULT_CONFIG_DIRNAME: &str = "gorg";
 const DEFAULT_CONFIG_FILENAME: &str = "config.toml";
 const DEFAULT_PROJECT_DIR_NAME: &str = "projects";
 const DEFAULT_DB_FILE_NAME: &str = ".gorg-db";
+const DEFAULT_DAEMON_SOCKET_FILE_NAME: &str = ".gorg.sock";
+const DEFAULT_STATUS_FILE_NAME: &str = ".gorg-status";
+const DEFAULT_SCAN_CACHE_FILE_NAME: &str = ".gorg-scan-cache";
+const DEFAULT_TOKEN_FILE_NAME: &str = "tokens";
+const DEFAULT_ARCHIVE_DIR_NAME: &str = "archive";
+const DEFAULT_LOG_FILE_NAME: &str = "log";
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// Path where all of the Git repositories will be placed
     #[serde(default = "default_projects_path")]
@@ -19,39 +35,279 @@ pub struct Config {
     #[serde(default = "default_index_file_path")]
     pub index_file_path: PathBuf,
 
+    /// Path to the Unix socket used by the gorg daemon
+    #[serde(default = "default_daemon_socket_path")]
+    pub daemon_socket_path: PathBuf,
+
+    /// Path where the cached Git statuses of the projects will be stored
+    #[serde(default = "default_status_file_path")]
+    pub status_file_path: PathBuf,
+
+    /// Path where the directory modification times from the last scan will be stored
+    #[serde(default = "default_scan_cache_file_path")]
+    pub scan_cache_file_path: PathBuf,
+
+    /// Path where forge API tokens are stored when the OS keyring is not available
+    #[serde(default = "default_token_file_path")]
+    pub token_file_path: PathBuf,
+
+    /// Path where projects removed from mirrored organizations are moved to
+    #[serde(default = "default_archive_path")]
+    pub archive_path: PathBuf,
+
+    /// Path where the log of operations done by gorg is stored
+    #[serde(default = "default_log_file_path")]
+    pub log_file_path: PathBuf,
+
+    /// Path to a key file for encrypting the index and the other files listing the projects.
+    /// The passphrase in `GORG_PASSPHRASE` is used when not set.
+    #[serde(default)]
+    pub encryption_key_file: Option<PathBuf>,
+
     /// Maximum number of items to list when finding projects interactively
     #[serde(default = "default_max_find_items")]
     pub max_find_items: usize,
 
+    /// Whether to find projects interactively on the alternate screen using the full terminal height
+    #[serde(default)]
+    pub full_screen: bool,
+
+    /// Where to place the prompt when finding projects interactively
+    #[serde(default)]
+    pub layout: Layout,
+
+    /// Whether to scroll and select matches using the mouse when finding projects interactively
+    #[serde(default)]
+    pub mouse: bool,
+
+    /// Whether to show full paths instead of project names when finding projects interactively
+    #[serde(default)]
+    pub show_full_path: bool,
+
+    /// Score bonus for query words that are equal to a whole segment of a project path
+    #[serde(default = "default_exact_segment_bonus")]
+    pub exact_segment_bonus: f32,
+
     /// Command to use for Git actions
     #[serde(default = "default_git_command")]
     pub git_command: String,
 
+    /// Maximum number of projects of the same host to fetch at a time
+    #[serde(default = "default_max_fetches_per_host")]
+    pub max_fetches_per_host: usize,
+
     /// Name to use for the remote repository for new Git projects
     #[serde(default = "default_git_remote_name")]
     pub git_remote_name: String,
+
+    /// URL schemes that are allowed for remotes of cloned projects.
+    /// SCP-like URLs (e.g. `git@github.com:jpallari/gorg.git`) use the "ssh" scheme.
+    #[serde(default = "default_allowed_url_schemes")]
+    pub allowed_url_schemes: Vec<String>,
+
+    /// Whether to lowercase the organization and repository parts of the project paths
+    /// derived from remote URLs. Hosts are always lowercased.
+    #[serde(default)]
+    pub lowercase_paths: bool,
+
+    /// Whether to apply the `url.<base>.insteadOf` rewrites from the Git configuration
+    /// to remote URLs before deriving the project paths from them
+    #[serde(default)]
+    pub git_url_rewrites: bool,
+
+    /// Whether to run every command in read-only mode like with `--read-only`
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// What to do when querying an index older than `auto_refresh_after_secs`
+    #[serde(default)]
+    pub auto_refresh: AutoRefresh,
+
+    /// Age in seconds after which the index is considered stale
+    #[serde(default = "default_auto_refresh_after_secs")]
+    pub auto_refresh_after_secs: u64,
+
+    /// Whether to follow symbolic links to directories when scanning for Git repositories
+    #[serde(default)]
+    pub follow_symlinks: bool,
+
+    /// Glob patterns for directories relative to `projects_path` to skip when scanning
+    /// for Git repositories. `*` matches within a path segment and `**` across segments.
+    #[serde(default)]
+    pub scan_exclude: Vec<String>,
+
+    /// Directories relative to `projects_path` that are kept in the index like pinned
+    /// directories, even when they are not Git repositories
+    #[serde(default)]
+    pub pinned_dirs: Vec<String>,
+
+    /// Templates by name for bootstrapping new local projects
+    #[serde(default)]
+    pub templates: BTreeMap<String, Template>,
+
+    /// Command template for opening projects. `{path}` is replaced with the project path.
+    /// The editor from `VISUAL` or `EDITOR` is used when not set.
+    #[serde(default)]
+    pub opener: Option<String>,
+
+    /// Command templates by name for opening projects with other tools
+    #[serde(default)]
+    pub openers: BTreeMap<String, String>,
+
+    /// Command templates by language for opening projects in which the language is detected
+    #[serde(default)]
+    pub lang_openers: BTreeMap<String, String>,
+
+    /// Forge types or URL templates by host for the web pages of projects
+    #[serde(default)]
+    pub web_urls: BTreeMap<String, String>,
+
+    /// Organizations to mirror locally
+    #[serde(default, rename = "mirror")]
+    pub mirrors: Vec<Mirror>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Mirror {
+    /// Forge host (e.g. github.com)
+    pub host: String,
+
+    /// Organization, user, or group to mirror
+    pub org: String,
+
+    /// Type of the forge API. Detected from the host when not set.
+    #[serde(default)]
+    pub forge: Option<Forge>,
+
+    /// Base URL of the forge API. Defaults to the public API URL for the forge.
+    #[serde(default)]
+    pub api_url: Option<String>,
+
+    /// Protocol to use for the remote URLs
+    #[serde(default)]
+    pub protocol: CloneProtocol,
 }
 
-fn home_dir() -> PathBuf {
-    std::env::home_dir().expect("Home dir must be defined for the user")
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    GitHub,
+    GitLab,
 }
 
+#[derive(Deserialize, Serialize, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CloneProtocol {
+    #[default]
+    Https,
+    Ssh,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Template {
+    /// Directory whose contents are copied to the new project
+    Dir(PathBuf),
+    /// Shell command to run in the new project directory
+    Command(String),
+}
+
+#[derive(Deserialize, Serialize, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoRefresh {
+    /// Print a warning about the stale index
+    Warn,
+    /// Update the index in a background process
+    Background,
+    /// Don't check the index age
+    #[default]
+    Off,
+}
+
+#[derive(Deserialize, Serialize, ValueEnum, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Prompt at the top with the matches below it
+    #[default]
+    Default,
+    /// Prompt at the bottom with the matches above it
+    Reverse,
+}
+
+/// Directory the default paths are based on: `$GORG_HOME` or the home directory of the user.
+/// None when neither is available, e.g. in containers without `$HOME`.
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os(HOME_ENV_VAR_NAME)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(std::env::home_dir)
+}
+
+// Defaults that need the home directory are empty paths without it.
+// `validated` derives them from other settings when it can, and `required_path`
+// reports the rest when they are used.
+
 fn default_projects_path() -> PathBuf {
-    let mut path = home_dir();
-    path.push(DEFAULT_PROJECT_DIR_NAME);
-    path
+    home_dir()
+        .map(|path| path.join(DEFAULT_PROJECT_DIR_NAME))
+        .unwrap_or_default()
 }
 
 fn default_index_file_path() -> PathBuf {
-    let mut path = default_projects_path();
-    path.push(DEFAULT_DB_FILE_NAME);
-    path
+    in_dir(default_projects_path(), DEFAULT_DB_FILE_NAME)
+}
+
+fn default_daemon_socket_path() -> PathBuf {
+    in_dir(default_projects_path(), DEFAULT_DAEMON_SOCKET_FILE_NAME)
+}
+
+fn default_status_file_path() -> PathBuf {
+    in_dir(default_projects_path(), DEFAULT_STATUS_FILE_NAME)
+}
+
+fn default_scan_cache_file_path() -> PathBuf {
+    in_dir(default_projects_path(), DEFAULT_SCAN_CACHE_FILE_NAME)
+}
+
+fn default_token_file_path() -> PathBuf {
+    config_dir()
+        .map(|path| path.join(DEFAULT_TOKEN_FILE_NAME))
+        .unwrap_or_default()
+}
+
+fn default_archive_path() -> PathBuf {
+    data_dir()
+        .map(|path| path.join(DEFAULT_ARCHIVE_DIR_NAME))
+        .unwrap_or_default()
+}
+
+fn default_log_file_path() -> PathBuf {
+    data_dir()
+        .map(|path| path.join(DEFAULT_LOG_FILE_NAME))
+        .unwrap_or_default()
+}
+
+/// Path of the file in the directory, or an empty path when the directory is empty
+fn in_dir(dir: PathBuf, file_name: &str) -> PathBuf {
+    if dir.as_os_str().is_empty() {
+        return dir;
+    }
+    dir.join(file_name)
 }
 
 fn default_max_find_items() -> usize {
     10
 }
 
+fn default_max_fetches_per_host() -> usize {
+    8
+}
+
+fn default_exact_segment_bonus() -> f32 {
+    fuzzy::DEFAULT_EXACT_SEGMENT_BONUS
+}
+
 fn default_git_command() -> String {
     String::from("git")
 }
@@ -60,21 +316,63 @@ fn default_git_remote_name() -> String {
     String::from("origin")
 }
 
-fn config_path() -> PathBuf {
+fn default_allowed_url_schemes() -> Vec<String> {
+    ["https", "ssh", "git", "http", "rsync"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn default_auto_refresh_after_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// Path of the user configuration file. None when it can't be determined without a home directory.
+fn config_path() -> Option<PathBuf> {
     if let Ok(config_path) = std::env::var(CONFIG_ENV_VAR_NAME) {
-        return config_path.into();
+        return Some(config_path.into());
     }
-    let mut path = std::env::var("XDG_CONFIG_HOME")
-        .map(PathBuf::from)
-        .ok()
-        .unwrap_or_else(|| {
-            let mut path = home_dir();
+    let mut path = config_dir()?;
+    path.push(DEFAULT_CONFIG_FILENAME);
+    Some(path)
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let mut path = match std::env::var("XDG_DATA_HOME") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let mut path = home_dir()?;
+            path.push(".local");
+            path.push("share");
+            path
+        }
+    };
+    path.push(DEFAULT_CONFIG_DIRNAME);
+    Some(path)
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let mut path = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let mut path = home_dir()?;
             path.push(".config");
             path
-        });
+        }
+    };
     path.push(DEFAULT_CONFIG_DIRNAME);
-    path.push(DEFAULT_CONFIG_FILENAME);
-    path
+    Some(path)
+}
+
+/// Fail with an explanation when the path is empty because its default
+/// needs a home directory that isn't available
+pub fn required_path<'a>(key: &str, path: &'a Path) -> Result<&'a Path> {
+    if path.as_os_str().is_empty() {
+        bail!(
+            "No default for {key} without a home directory. Set $HOME or ${HOME_ENV_VAR_NAME}, or set {key} in the configuration."
+        );
+    }
+    Ok(path)
 }
 
 impl Default for Config {
@@ -82,39 +380,380 @@ impl Default for Config {
         Config {
             projects_path: default_projects_path(),
             index_file_path: default_index_file_path(),
+            daemon_socket_path: default_daemon_socket_path(),
+            status_file_path: default_status_file_path(),
+            scan_cache_file_path: default_scan_cache_file_path(),
+            token_file_path: default_token_file_path(),
+            archive_path: default_archive_path(),
+            log_file_path: default_log_file_path(),
+            encryption_key_file: None,
+            read_only: false,
             max_find_items: default_max_find_items(),
+            full_screen: false,
+            layout: Layout::default(),
+            mouse: false,
+            show_full_path: false,
+            exact_segment_bonus: default_exact_segment_bonus(),
             git_command: default_git_command(),
+            max_fetches_per_host: default_max_fetches_per_host(),
             git_remote_name: default_git_remote_name(),
+            allowed_url_schemes: default_allowed_url_schemes(),
+            lowercase_paths: false,
+            git_url_rewrites: false,
+            auto_refresh: AutoRefresh::default(),
+            auto_refresh_after_secs: default_auto_refresh_after_secs(),
+            follow_symlinks: false,
+            scan_exclude: Vec::new(),
+            pinned_dirs: Vec::new(),
+            templates: BTreeMap::new(),
+            opener: None,
+            openers: BTreeMap::new(),
+            lang_openers: BTreeMap::new(),
+            web_urls: BTreeMap::new(),
+            mirrors: Vec::new(),
         }
     }
 }
 
+/// Configuration files that set the values of the configuration
+#[derive(Default)]
+pub struct Origins {
+    /// Files by key. Keys of table entries are joined with a dot, e.g. `templates.notes`.
+    files: BTreeMap<String, PathBuf>,
+}
+
+impl Origins {
+    /// File that set the value of the key. None when the default value is used.
+    pub fn file(&self, key: &str) -> Option<&Path> {
+        self.files.get(key).map(PathBuf::as_path)
+    }
+}
+
 impl Config {
-    pub fn from_env() -> Result<Config> {
-        let path = config_path();
-        let path_str = path.to_string_lossy();
-
-        log::debug!("Reading config from path: {path_str}");
-
-        match std::fs::read_to_string(&path) {
-            Ok(contents) => Self::from_str(&contents),
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::NotFound => {
-                    log::debug!("Config not found from {path_str}. Using default configuration.");
-                    Ok(Self::default())
+    /// Load the configuration by merging the system configuration file, the user configuration
+    /// file, and the configuration file in the projects directory, in that order.
+    /// The user configuration file is read from the given path when set.
+    pub fn load(user_path: Option<&Path>) -> Result<Config> {
+        Ok(Self::load_with_origins(user_path)?.0)
+    }
+
+    /// Load the configuration like `load` along with the files that set each value
+    pub fn load_with_origins(user_path: Option<&Path>) -> Result<(Config, Origins)> {
+        let mut table = toml::Table::new();
+        let mut origins = Origins::default();
+        if let Some(layer) = read_layer(Path::new(SYSTEM_CONFIG_PATH), false)? {
+            merge(
+                &mut table,
+                layer,
+                Path::new(SYSTEM_CONFIG_PATH),
+                &mut origins,
+            );
+        }
+        let user_path = match user_path {
+            Some(path) => Some((path.to_path_buf(), true)),
+            None => config_path().map(|path| (path, false)),
+        };
+        match user_path {
+            Some((user_path, required)) => {
+                if let Some(layer) = read_layer(&user_path, required)? {
+                    merge(&mut table, layer, &user_path, &mut origins);
                 }
-                _ => Err(e.into()),
-            },
+            }
+            None => log::debug!("No user config without a home directory"),
+        }
+
+        // The projects directory can't be moved from within itself
+        let config: Config = toml::Value::Table(table.clone()).try_into()?;
+        let projects_path = required_path("projects_path", &config.projects_path)?;
+        let tree_path = projects_path.join(TREE_CONFIG_FILE_NAME);
+        if let Some(layer) = read_layer(&tree_path, false)? {
+            if layer.contains_key("projects_path") {
+                bail!(
+                    "projects_path can't be set in {}",
+                    tree_path.to_string_lossy()
+                );
+            }
+            merge(&mut table, layer, &tree_path, &mut origins);
         }
+
+        let config: Config = toml::Value::Table(table).try_into()?;
+        Ok((config.with_project_file_defaults().validated()?, origins))
     }
 
-    pub fn read_from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Config> {
-        let contents = std::fs::read_to_string(&path)?;
-        Self::from_str(&contents)
+    /// Format the configuration as TOML. With the origins, each value is followed by a comment
+    /// naming the file that set it.
+    pub fn to_toml(&self, origins: Option<&Origins>) -> Result<String> {
+        let table = toml::Table::try_from(self)?;
+        let comment = |key: &str| match origins.map(|origins| origins.file(key)) {
+            Some(Some(file)) => format!("  # {}", file.to_string_lossy()),
+            Some(None) => String::from("  # default"),
+            None => String::new(),
+        };
+        let mut out = String::new();
+        // Plain values go first, so that they are not read as part of the tables
+        for (key, value) in table.iter() {
+            if !is_table_like(value) {
+                out.push_str(&format!("{} = {value}{}\n", toml_key(key), comment(key)));
+            }
+        }
+        for (key, value) in table.iter() {
+            match value {
+                toml::Value::Table(entries) => {
+                    out.push_str(&format!("\n[{}]\n", toml_key(key)));
+                    for (name, value) in entries.iter() {
+                        let comment = comment(&format!("{key}.{name}"));
+                        out.push_str(&format!("{} = {value}{comment}\n", toml_key(name)));
+                    }
+                }
+                toml::Value::Array(items) if is_table_like(value) => {
+                    for item in items.iter().filter_map(toml::Value::as_table) {
+                        out.push_str(&format!("\n[[{}]]{}\n", toml_key(key), comment(key)));
+                        for (name, value) in item.iter() {
+                            out.push_str(&format!("{} = {value}\n", toml_key(name)));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(out)
     }
 
     fn from_str(s: &str) -> Result<Config> {
-        let config: Self = toml::from_str(s)?;
-        Ok(config)
+        toml::from_str(s).map_err(|err| match unknown_key_message(s, &err) {
+            Some(message) => anyhow!(message),
+            None => err.into(),
+        })
+    }
+
+    /// Place the files that default to the projects directory in the configured projects
+    /// directory when the home directory for their defaults isn't available
+    fn with_project_file_defaults(mut self) -> Self {
+        for (path, file_name) in [
+            (&mut self.index_file_path, DEFAULT_DB_FILE_NAME),
+            (
+                &mut self.daemon_socket_path,
+                DEFAULT_DAEMON_SOCKET_FILE_NAME,
+            ),
+            (&mut self.status_file_path, DEFAULT_STATUS_FILE_NAME),
+            (&mut self.scan_cache_file_path, DEFAULT_SCAN_CACHE_FILE_NAME),
+        ] {
+            if path.as_os_str().is_empty() {
+                *path = self.projects_path.join(file_name);
+            }
+        }
+        self
+    }
+
+    /// Check that the configured paths exist or can be created.
+    /// Paths left empty without a home directory are checked when they are used.
+    fn validated(self) -> Result<Config> {
+        for (key, path) in [
+            ("projects_path", &self.projects_path),
+            ("archive_path", &self.archive_path),
+        ] {
+            check_dir_path(key, path)?;
+        }
+        for (key, path) in [
+            ("index_file_path", &self.index_file_path),
+            ("daemon_socket_path", &self.daemon_socket_path),
+            ("status_file_path", &self.status_file_path),
+            ("scan_cache_file_path", &self.scan_cache_file_path),
+            ("token_file_path", &self.token_file_path),
+            ("log_file_path", &self.log_file_path),
+        ] {
+            if path.is_dir() {
+                bail!(
+                    "Invalid {key} in the configuration: {} is a directory",
+                    path.to_string_lossy()
+                );
+            }
+            if let Some(parent) = path.parent() {
+                check_dir_path(key, parent)?;
+            }
+        }
+        for (host, template) in self.web_urls.iter() {
+            git_url::web_url_template(template)
+                .with_context(|| format!("Invalid web URL for {host} in the configuration"))?;
+        }
+        Ok(self)
+    }
+}
+
+/// Read a configuration file as a table. Returns None when an optional file doesn't exist.
+fn read_layer(path: &Path, required: bool) -> Result<Option<toml::Table>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {
+            log::debug!("Config not found from {}", path.to_string_lossy());
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(anyhow!(err).context(format!(
+                "Failed to read configuration file {}",
+                path.to_string_lossy()
+            )));
+        }
+    };
+    log::debug!("Reading config from path: {}", path.to_string_lossy());
+    // Each file is checked on its own, so that errors point to the right file and line
+    let context = || format!("Invalid configuration file {}", path.to_string_lossy());
+    Config::from_str(&contents).with_context(context)?;
+    let table = toml::from_str(&contents).with_context(context)?;
+    Ok(Some(table))
+}
+
+/// Merge a configuration file into the configuration. Tables such as `templates`
+/// are merged by their entries, while other values replace the earlier values.
+fn merge(config: &mut toml::Table, layer: toml::Table, path: &Path, origins: &mut Origins) {
+    for (key, value) in layer {
+        match (config.get_mut(&key), value) {
+            (Some(toml::Value::Table(entries)), toml::Value::Table(layer_entries)) => {
+                for (name, value) in layer_entries {
+                    origins
+                        .files
+                        .insert(format!("{key}.{name}"), path.to_path_buf());
+                    entries.insert(name, value);
+                }
+            }
+            (_, value) => {
+                origins
+                    .files
+                    .retain(|k, _| !k.starts_with(&format!("{key}.")));
+                if let toml::Value::Table(entries) = &value {
+                    for name in entries.keys() {
+                        origins
+                            .files
+                            .insert(format!("{key}.{name}"), path.to_path_buf());
+                    }
+                }
+                origins.files.insert(key.clone(), path.to_path_buf());
+                config.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Whether the value is written as a table or an array of tables in TOML
+fn is_table_like(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Table(_) => true,
+        toml::Value::Array(items) => !items.is_empty() && items.iter().all(toml::Value::is_table),
+        _ => false,
+    }
+}
+
+/// Key quoted when it can't be written as a bare key
+fn toml_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-');
+    if is_bare {
+        String::from(key)
+    } else {
+        toml::Value::String(String::from(key)).to_string()
+    }
+}
+
+/// Check that the path is a directory or that it can be created as one,
+/// i.e. that its closest existing ancestor is a directory
+fn check_dir_path(key: &str, path: &Path) -> Result<()> {
+    for ancestor in path.ancestors() {
+        match std::fs::metadata(ancestor) {
+            Ok(metadata) if metadata.is_dir() => return Ok(()),
+            Ok(_) => bail!(
+                "Invalid {key} in the configuration: {} is not a directory",
+                ancestor.to_string_lossy()
+            ),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::NotFound | std::io::ErrorKind::NotADirectory
+                ) =>
+            {
+                continue;
+            }
+            Err(err) => bail!(
+                "Invalid {key} in the configuration: cannot access {}: {err}",
+                ancestor.to_string_lossy()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Describe an unknown key error with the line of the key and the closest known key.
+/// Returns None for other errors.
+fn unknown_key_message(s: &str, err: &toml::de::Error) -> Option<String> {
+    let message = err.message();
+    let rest = message
+        .strip_prefix("unknown field `")
+        .or_else(|| message.strip_prefix("unknown variant `"))?;
+    let (key, expected) = rest.split_once('`')?;
+    let mut description = format!("Unknown key `{key}` in the configuration");
+    if let Some(span) = err.span() {
+        let line = s[..span.start].matches('\n').count() + 1;
+        description.push_str(&format!(" on line {line}"));
+    }
+    // Expected keys are listed in backticks, e.g. "expected one of `a`, `b`"
+    let suggestion = expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|known| (text::edit_distance(key, known), known))
+        .filter(|(distance, _)| *distance <= (key.chars().count() / 3).max(1))
+        .min_by_key(|(distance, _)| *distance);
+    if let Some((_, known)) = suggestion {
+        description.push_str(&format!(". Did you mean `{known}`?"));
+    }
+    Some(description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_keys() {
+        let err = Config::from_str("mouse = true\nproject_path = \"/p\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown key `project_path` in the configuration on line 2. Did you mean `projects_path`?"
+        );
+        let err =
+            Config::from_str("[[mirror]]\nhost = \"a.com\"\norg = \"x\"\nprotocl = \"ssh\"\n")
+                .err()
+                .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown key `protocl` in the configuration on line 4. Did you mean `protocol`?"
+        );
+        let err = Config::from_str("colors = true\n").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown key `colors` in the configuration on line 1"
+        );
+        assert!(Config::from_str("mouse = 1\n").is_err());
+        assert!(Config::from_str("mouse = true\n").is_ok());
+    }
+
+    #[test]
+    fn paths_without_home_dir() {
+        let cfg = Config::from_str(
+            "projects_path = \"/p\"\nindex_file_path = \"\"\nstatus_file_path = \"/s\"\nlog_file_path = \"\"\n",
+        )
+        .unwrap()
+        .with_project_file_defaults();
+        assert_eq!(cfg.index_file_path, Path::new("/p/.gorg-db"));
+        assert_eq!(cfg.status_file_path, Path::new("/s"));
+        assert!(required_path("status_file_path", &cfg.status_file_path).is_ok());
+        let err = required_path("log_file_path", &cfg.log_file_path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No default for log_file_path without a home directory. Set $HOME or $GORG_HOME, or set log_file_path in the configuration."
+        );
     }
 }
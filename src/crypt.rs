@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64;
+use ring::aead::{self, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{hkdf, pbkdf2};
+
+/// Environment variable for the passphrase to encrypt the files with
+pub const PASSPHRASE_ENV_VAR_NAME: &str = "GORG_PASSPHRASE";
+
+/// Start of encrypted files. Files without it are read as plain text.
+const MAGIC: &[u8] = b"gorg-encrypted-1\n";
+/// Start of encrypted lines in the append-only logs
+const LINE_PREFIX: char = '~';
+const SALT_LEN: usize = 16;
+/// Iterations for stretching passphrases, as recommended by OWASP for PBKDF2-HMAC-SHA256
+const PBKDF2_ITERATIONS: NonZeroU32 = NonZeroU32::new(600_000).unwrap();
+const HKDF_INFO: &[u8] = b"gorg file encryption";
+
+static CIPHER: OnceLock<Cipher> = OnceLock::new();
+
+/// Secret the encryption keys are derived from
+pub enum Secret {
+    /// Contents of a key file, e.g. 32 random bytes
+    KeyFile(Vec<u8>),
+    /// Passphrase that is stretched before use, as it may be guessable
+    Passphrase(String),
+}
+
+impl Secret {
+    /// Secret from the key file when set or the passphrase in the environment.
+    /// None when neither is set.
+    pub fn from_env_or_key_file(key_file: Option<&Path>) -> Result<Option<Self>> {
+        if let Some(path) = key_file {
+            let key = match std::fs::read(path) {
+                Ok(key) => key,
+                Err(err) => bail!("Failed to read key file {}: {err}", path.to_string_lossy()),
+            };
+            if key.trim_ascii().is_empty() {
+                bail!("Key file {} is empty", path.to_string_lossy());
+            }
+            return Ok(Some(Self::KeyFile(key)));
+        }
+        match std::env::var(PASSPHRASE_ENV_VAR_NAME) {
+            Ok(passphrase) if !passphrase.is_empty() => Ok(Some(Self::Passphrase(passphrase))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Authenticated encryption with ChaCha20-Poly1305. Each encrypted blob is
+/// `<salt><nonce><ciphertext><tag>`, where the key is derived from the secret and the salt.
+pub struct Cipher {
+    secret: Secret,
+    /// Keys derived for the salts seen so far. The last one is used for encrypting,
+    /// so that a passphrase is usually stretched only once per process.
+    keys: Mutex<Vec<([u8; SALT_LEN], LessSafeKey)>>,
+    rng: SystemRandom,
+}
+
+impl Cipher {
+    pub fn new(secret: Secret) -> Self {
+        Self {
+            secret,
+            keys: Mutex::new(Vec::new()),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    fn derive_key(&self, salt: &[u8]) -> LessSafeKey {
+        let key = match &self.secret {
+            Secret::KeyFile(key) => {
+                let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(key);
+                let okm = prk
+                    .expand(&[HKDF_INFO], &aead::CHACHA20_POLY1305)
+                    .expect("key length is valid for HKDF-SHA256");
+                UnboundKey::from(okm)
+            }
+            Secret::Passphrase(passphrase) => {
+                let mut key = [0; 32];
+                pbkdf2::derive(
+                    pbkdf2::PBKDF2_HMAC_SHA256,
+                    PBKDF2_ITERATIONS,
+                    salt,
+                    passphrase.as_bytes(),
+                    &mut key,
+                );
+                UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
+                    .expect("key length matches ChaCha20-Poly1305")
+            }
+        };
+        LessSafeKey::new(key)
+    }
+
+    /// Key for the given salt, or for the salt used last when not given
+    fn key(&self, salt: Option<&[u8]>) -> Result<([u8; SALT_LEN], LessSafeKey)> {
+        let mut keys = self.keys.lock().unwrap();
+        let found = match salt {
+            Some(salt) => keys.iter().rfind(|(s, _)| s == salt),
+            None => keys.last(),
+        };
+        if let Some((salt, key)) = found {
+            return Ok((*salt, key.clone()));
+        }
+        let mut new_salt = [0; SALT_LEN];
+        match salt {
+            Some(salt) => new_salt.copy_from_slice(salt),
+            None => self
+                .rng
+                .fill(&mut new_salt)
+                .map_err(|_| anyhow!("Failed to generate a salt"))?,
+        }
+        let key = self.derive_key(&new_salt);
+        keys.push((new_salt, key.clone()));
+        Ok((new_salt, key))
+    }
+
+    pub fn seal(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let (salt, key) = self.key(None)?;
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+        let mut blob =
+            Vec::with_capacity(SALT_LEN + NONCE_LEN + data.len() + key.algorithm().tag_len());
+        blob.extend_from_slice(&salt);
+        blob.extend_from_slice(&nonce);
+        let mut in_out = data.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+        blob.extend_from_slice(&in_out);
+        Ok(blob)
+    }
+
+    /// Decrypt a blob made by `seal`. None when the secret is wrong or the blob is damaged.
+    pub fn open(&self, blob: &[u8]) -> Option<Vec<u8>> {
+        if blob.len() < SALT_LEN + NONCE_LEN {
+            return None;
+        }
+        let (salt, rest) = blob.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let (_, key) = self.key(Some(salt)).ok()?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut in_out = ciphertext.to_vec();
+        let len = key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .ok()?
+            .len();
+        in_out.truncate(len);
+        Some(in_out)
+    }
+
+    fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = MAGIC.to_vec();
+        out.extend(self.seal(data)?);
+        Ok(out)
+    }
+
+    fn encrypt_line(&self, line: &str) -> Result<String> {
+        Ok(format!(
+            "{LINE_PREFIX}{}",
+            BASE64.encode(self.seal(line.as_bytes())?)
+        ))
+    }
+}
+
+/// Encrypt the files written from now on with the given secret
+pub fn init(secret: Secret) {
+    if CIPHER.set(Cipher::new(secret)).is_err() {
+        log::warn!("Encryption was already set up");
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Cipher for decrypting the encrypted file
+fn cipher_for(path: &Path) -> Result<&'static Cipher> {
+    match CIPHER.get() {
+        Some(cipher) => Ok(cipher),
+        None => bail!(
+            "{} is encrypted. Set {PASSPHRASE_ENV_VAR_NAME} or encryption_key_file in the configuration to read it.",
+            path.to_string_lossy()
+        ),
+    }
+}
+
+/// Encrypt the contents of a file to write, or return them as is when encryption is not set up
+pub fn encrypt(data: Vec<u8>) -> Result<Vec<u8>> {
+    match CIPHER.get() {
+        Some(cipher) => cipher.encrypt(&data),
+        None => Ok(data),
+    }
+}
+
+/// Decrypt the contents read from the file. Plain contents are returned as is,
+/// so that files written before setting up encryption can still be read.
+pub fn decrypt(path: &Path, data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(blob) = data.strip_prefix(MAGIC) else {
+        return Ok(data);
+    };
+    let cipher = cipher_for(path)?;
+    match cipher.open(blob) {
+        Some(data) => Ok(data),
+        None => bail!(
+            "Failed to decrypt {}: wrong passphrase or key file, or the file is damaged",
+            path.to_string_lossy()
+        ),
+    }
+}
+
+/// Read and decrypt a file. None when the file doesn't exist.
+pub fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(decrypt(path, data)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Read and decrypt a text file. None when the file doesn't exist.
+pub fn read_to_string(path: &Path) -> Result<Option<String>> {
+    match read(path)? {
+        Some(data) => match String::from_utf8(data) {
+            Ok(data) => Ok(Some(data)),
+            Err(_) => bail!("{} is not valid UTF-8", path.to_string_lossy()),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Encrypt a line (without the line break) of an append-only log,
+/// or return it as is when encryption is not set up
+pub fn encrypt_line(line: &str) -> Result<Cow<'_, str>> {
+    match CIPHER.get() {
+        Some(cipher) => Ok(Cow::Owned(cipher.encrypt_line(line)?)),
+        None => Ok(Cow::Borrowed(line)),
+    }
+}
+
+/// Decrypt a line of an append-only log. Plain lines are returned as is.
+pub fn decrypt_line<'a>(path: &Path, line: &'a str) -> Result<Cow<'a, str>> {
+    let Some(encoded) = line.strip_prefix(LINE_PREFIX) else {
+        return Ok(Cow::Borrowed(line));
+    };
+    let cipher = cipher_for(path)?;
+    let line = BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|blob| cipher.open(&blob))
+        .and_then(|data| String::from_utf8(data).ok());
+    match line {
+        Some(line) => Ok(Cow::Owned(line)),
+        None => bail!(
+            "Failed to decrypt a line in {}: wrong passphrase or key file, or the line is damaged",
+            path.to_string_lossy()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let cipher = Cipher::new(Secret::KeyFile(b"0123456789abcdef".to_vec()));
+        let blob = cipher.seal(b"github.com/acme/secret").unwrap();
+        assert_eq!(cipher.open(&blob).unwrap(), b"github.com/acme/secret");
+        // Fresh nonces make the same contents encrypt differently
+        assert_ne!(blob, cipher.seal(b"github.com/acme/secret").unwrap());
+
+        let other = Cipher::new(Secret::KeyFile(b"fedcba9876543210".to_vec()));
+        assert_eq!(other.open(&blob), None);
+        let mut damaged = blob.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert_eq!(cipher.open(&damaged), None);
+        assert_eq!(cipher.open(&blob[..10]), None);
+
+        let line = cipher.encrypt_line("1700000000\ta.com/x").unwrap();
+        assert!(line.starts_with(LINE_PREFIX));
+        assert!(!line.contains(['\n', '\t']));
+    }
+}
@@ -0,0 +1,371 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Result, bail};
+
+use crate::db::{self, DB, DBView, Finder};
+
+/// Time a client gets for sending the request and reading the response.
+/// Requests are handled one at a time, so a stuck client would block the others.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone)]
+pub enum Request {
+    /// Fuzzy matches sorted by score with their descriptions after a tab
+    Find,
+    /// Fuzzy matches sorted by score with the score before each match
+    Scores,
+    /// Fuzzy matches in index order
+    List,
+    /// Prefix matches in index order
+    Prefix,
+    /// Number of projects in the index
+    Count,
+    /// Sorted hosts of the projects in the index
+    Hosts,
+    /// Sorted organizations of the projects in the index including their hosts
+    Orgs,
+}
+
+impl Request {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Find => "find",
+            Self::Scores => "scores",
+            Self::List => "list",
+            Self::Prefix => "prefix",
+            Self::Count => "count",
+            Self::Hosts => "hosts",
+            Self::Orgs => "orgs",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "find" => Some(Self::Find),
+            "scores" => Some(Self::Scores),
+            "list" => Some(Self::List),
+            "prefix" => Some(Self::Prefix),
+            "count" => Some(Self::Count),
+            "hosts" => Some(Self::Hosts),
+            "orgs" => Some(Self::Orgs),
+            _ => None,
+        }
+    }
+}
+
+pub fn serve(socket_path: &Path, index_file_path: &Path, exact_segment_bonus: f32) -> Result<()> {
+    if socket_path.try_exists()? {
+        if UnixStream::connect(socket_path).is_ok() {
+            bail!(
+                "Daemon is already running at {}",
+                socket_path.to_string_lossy()
+            );
+        }
+        log::debug!("Removing stale socket {}", socket_path.to_string_lossy());
+        std::fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    log::info!("Listening on {}", socket_path.to_string_lossy());
+
+    // Picks from the history log affect the scores, so changes to it reload the DB too
+    let history_path = db::history_path(index_file_path);
+    let mut line = String::new();
+    let mut pending = None;
+    loop {
+        let db_modified = modified_times(index_file_path, &history_path)?;
+        log::debug!("Loading DB {}", index_file_path.to_string_lossy());
+        let db = DB::load(index_file_path)?.unwrap_or_default();
+
+        // The view and its buffers are reused for as long as the DB file stays unchanged.
+        let mut view = db
+            .view()
+            .with_index()
+            .with_exact_segment_bonus(exact_segment_bonus);
+
+        if let Some(stream) = pending.take() {
+            handle_logged(&db, &mut view, stream, &mut line);
+        }
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::error!("Failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            if modified_times(index_file_path, &history_path)? != db_modified {
+                pending = Some(stream);
+                break;
+            }
+            handle_logged(&db, &mut view, stream, &mut line);
+        }
+        if pending.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+fn modified_times(
+    index_file_path: &Path,
+    history_path: &Path,
+) -> Result<(Option<SystemTime>, Option<SystemTime>)> {
+    Ok((
+        modified_time(index_file_path)?,
+        modified_time(history_path)?,
+    ))
+}
+
+fn modified_time(path: &Path) -> Result<Option<SystemTime>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(Some(metadata.modified()?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn handle_logged(db: &DB, view: &mut DBView, stream: UnixStream, line: &mut String) {
+    if let Err(err) = handle(db, view, stream, line) {
+        log::error!("Failed to handle request: {err}");
+    }
+}
+
+fn handle(db: &DB, view: &mut DBView, stream: UnixStream, line: &mut String) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    line.clear();
+    BufReader::new(&stream).read_line(line)?;
+    if line.is_empty() {
+        // Connection probe
+        return Ok(());
+    }
+
+    let line = line.trim_end_matches('\n');
+    let (request, query) = line.split_once(' ').unwrap_or((line, ""));
+    let Some(request) = Request::from_str(request) else {
+        bail!("Unknown request: {request}");
+    };
+    log::debug!("Request {} with query: {query}", request.as_str());
+
+    let mut w = std::io::BufWriter::new(&stream);
+    match request {
+        Request::Find => {
+            view.find_matches(query);
+            for project in view.matches() {
+                match view.description(project) {
+                    Some(description) => writeln!(w, "{project}\t{description}")?,
+                    None => writeln!(w, "{project}")?,
+                }
+            }
+        }
+        Request::Scores => {
+            for (project, score) in view.find_matches(query) {
+                writeln!(w, "{score}\t{project}")?;
+            }
+        }
+        Request::List => {
+            for project in db.find_matches(query) {
+                writeln!(w, "{project}")?;
+            }
+        }
+        Request::Prefix => {
+            for project in db.find_by_prefix(query) {
+                writeln!(w, "{project}")?;
+            }
+        }
+        Request::Count => {
+            writeln!(w, "{}", view.total())?;
+        }
+        Request::Hosts => {
+            for host in view.hosts() {
+                writeln!(w, "{host}")?;
+            }
+        }
+        Request::Orgs => {
+            for org in view.orgs() {
+                writeln!(w, "{org}")?;
+            }
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+pub struct Client {
+    socket_path: PathBuf,
+}
+
+impl Client {
+    /// Returns a client when there's a daemon listening on the given socket
+    pub fn connect(socket_path: &Path) -> Option<Self> {
+        match UnixStream::connect(socket_path) {
+            Ok(_) => {
+                log::debug!("Using daemon at {}", socket_path.to_string_lossy());
+                Some(Self {
+                    socket_path: socket_path.to_path_buf(),
+                })
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub fn request(&self, request: Request, query: &str, response: &mut String) -> Result<()> {
+        if query.contains('\n') {
+            bail!("Query cannot contain new lines");
+        }
+        let mut stream = UnixStream::connect(&self.socket_path)?;
+        writeln!(stream, "{} {query}", request.as_str())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        response.clear();
+        stream.read_to_string(response)?;
+        Ok(())
+    }
+}
+
+pub struct DaemonFinder {
+    client: Client,
+    response: String,
+    total: usize,
+}
+
+impl DaemonFinder {
+    pub fn new(client: Client) -> Result<Self> {
+        let mut response = String::new();
+        client.request(Request::Count, "", &mut response)?;
+        let Ok(total) = response.trim().parse() else {
+            bail!("Unexpected project count from daemon. Try restarting the daemon.");
+        };
+        Ok(Self {
+            client,
+            response,
+            total,
+        })
+    }
+
+    /// Lines of the response to a request without a query
+    fn lines(&self, request: Request) -> Result<Vec<String>> {
+        let mut response = String::new();
+        self.client.request(request, "", &mut response)?;
+        Ok(response
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect())
+    }
+}
+
+impl Finder for DaemonFinder {
+    fn search(&mut self, matcher: &str, _cancelled: &dyn Fn() -> bool) -> Result<bool> {
+        // Requests are quick to serve, so there's no need for cancelling them.
+        self.client
+            .request(Request::Find, matcher, &mut self.response)?;
+        Ok(true)
+    }
+
+    fn matches(&self) -> impl Iterator<Item = &str> {
+        self.response
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split_once('\t').map_or(line, |(project, _)| project))
+    }
+
+    fn total(&self) -> usize {
+        self.total
+    }
+
+    fn description(&self, project: &str) -> Option<&str> {
+        self.response
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .find(|(p, _)| *p == project)
+            .map(|(_, description)| description)
+    }
+
+    fn hosts(&self) -> Result<Vec<String>> {
+        self.lines(Request::Hosts)
+    }
+
+    fn orgs(&self) -> Result<Vec<String>> {
+        self.lines(Request::Orgs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gorg-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn request_names() {
+        let requests = [
+            Request::Find,
+            Request::Scores,
+            Request::List,
+            Request::Prefix,
+            Request::Count,
+            Request::Hosts,
+            Request::Orgs,
+        ];
+        for request in requests {
+            let parsed = Request::from_str(request.as_str()).unwrap();
+            assert_eq!(parsed.as_str(), request.as_str());
+        }
+        assert!(Request::from_str("nope").is_none());
+    }
+
+    #[test]
+    fn find_through_daemon() {
+        let dir = test_dir("daemon");
+        let index_path = dir.join("index");
+        let socket_path = dir.join("sock");
+        let mut db = DB::from_entries(
+            ["a.com/org/app", "a.com/org/lib"]
+                .into_iter()
+                .map(String::from),
+        );
+        db.describe("a.com/org/app", "The app").unwrap();
+        db.save(&index_path).unwrap();
+
+        let (socket, index) = (socket_path.clone(), index_path.clone());
+        std::thread::spawn(move || serve(&socket, &index, 0.));
+        let client = loop {
+            if let Some(client) = Client::connect(&socket_path) {
+                break client;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        let mut finder = DaemonFinder::new(client).unwrap();
+        assert_eq!(finder.total(), 2);
+        finder.search("org", &|| false).unwrap();
+        let mut matches: Vec<&str> = finder.matches().collect();
+        matches.sort();
+        assert_eq!(matches, vec!["a.com/org/app", "a.com/org/lib"]);
+        assert_eq!(finder.description("a.com/org/app"), Some("The app"));
+        assert_eq!(finder.description("a.com/org/lib"), None);
+
+        // Changes to the index are picked up on the next request
+        db.add("b.com/org/tool").unwrap();
+        db.save(&index_path).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&index_path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        finder.search("tool", &|| false).unwrap();
+        assert_eq!(finder.matches().collect::<Vec<_>>(), vec!["b.com/org/tool"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,507 @@
+use std::io::{self, Write};
+use std::os::fd::{AsFd, AsRawFd};
+
+use termion::event::{Event, Key};
+use termion::raw::{IntoRawMode, RawTerminal};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::fuzzy;
+use crate::text;
+use crate::tui;
+
+const FILTER_PROMPT: &str = "/";
+const KEY_HELP: &str = "/ filter  f fetch  s shell  o open  r run  a archive  q quit";
+const STATUS_COLUMN_WIDTH: usize = 20;
+const FETCHED_COLUMN_WIDTH: usize = 12;
+const COLUMN_GAP: usize = 2;
+/// Lines used by everything else than the project rows
+const CHROME_LINES: usize = 4;
+
+/// Project row shown in the dashboard
+pub struct Row {
+    pub project: String,
+    /// Summary of the cached Git status
+    pub status: String,
+    /// Age of the last fetch
+    pub fetched: String,
+    pub description: String,
+}
+
+/// Action requested by the user for the selected project
+pub enum Action {
+    Exit,
+    Render,
+    FilterUpdated,
+    Fetch,
+    Shell,
+    Browse,
+    Archive,
+    Run(String),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    Filter,
+    Command,
+    ConfirmArchive,
+}
+
+/// Full-screen view listing projects with their cached statuses
+pub struct Dashboard<W: Write + AsFd> {
+    writer: RawTerminal<W>,
+    mode: Mode,
+    filter: String,
+    /// Command being entered for running in the selected project
+    command: String,
+    /// Index of the selected row among the visible rows
+    selected: usize,
+    /// Index of the first visible row shown on the screen
+    offset: usize,
+    message: String,
+}
+
+impl<W: Write + AsFd> Drop for Dashboard<W> {
+    fn drop(&mut self) {
+        if !tui::release_terminal() {
+            // Already restored by the panic hook
+            return;
+        }
+        if let Err(err) = self.quit() {
+            eprintln!("Failed to quit dashboard: {}", err);
+        }
+    }
+}
+
+impl<W: Write + AsFd> Dashboard<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let options = tui::PromptOptions {
+            full_screen: true,
+            ..Default::default()
+        };
+        tui::guard_terminal(writer.as_fd().as_raw_fd(), options)?;
+        let mut writer = writer.into_raw_mode()?;
+        write!(
+            writer,
+            "{}{}",
+            termion::screen::ToAlternateScreen,
+            termion::cursor::Hide
+        )?;
+        Ok(Self {
+            writer,
+            mode: Mode::Normal,
+            filter: String::new(),
+            command: String::new(),
+            selected: 0,
+            offset: 0,
+            message: String::new(),
+        })
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Show a message below the project rows until the next message
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    /// Give the terminal back for running interactive programs
+    pub fn suspend(&mut self) -> io::Result<()> {
+        write!(
+            self.writer,
+            "{}{}",
+            termion::screen::ToMainScreen,
+            termion::cursor::Show
+        )?;
+        self.writer.flush()?;
+        self.writer.suspend_raw_mode()
+    }
+
+    pub fn resume(&mut self) -> io::Result<()> {
+        self.writer.activate_raw_mode()?;
+        write!(
+            self.writer,
+            "{}{}",
+            termion::screen::ToAlternateScreen,
+            termion::cursor::Hide
+        )?;
+        Ok(())
+    }
+
+    fn quit(&mut self) -> io::Result<()> {
+        write!(
+            self.writer,
+            "{}{}",
+            termion::screen::ToMainScreen,
+            termion::cursor::Show
+        )?;
+        self.writer.flush()
+    }
+
+    /// Render the rows at the given indices
+    pub fn render(&mut self, rows: &[Row], visible: &[usize]) -> io::Result<()> {
+        let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+        let width = width as usize;
+        let row_lines = (height as usize).saturating_sub(CHROME_LINES);
+        self.selected = self.selected.min(visible.len().saturating_sub(1));
+        self.offset = scroll_offset(self.offset, self.selected, row_lines);
+
+        write!(
+            self.writer,
+            "{}{}",
+            termion::clear::All,
+            termion::cursor::Goto(1, 1)
+        )?;
+
+        // Filter and the number of visible projects
+        let count = format!("{}/{}", visible.len(), rows.len());
+        let filter = match (self.mode, self.filter.is_empty()) {
+            (Mode::Filter, _) | (_, false) => format!("{FILTER_PROMPT}{}", self.filter),
+            _ => String::new(),
+        };
+        let filter = text::truncate_to_width(&filter, width.saturating_sub(count.len() + 1));
+        let padding = width.saturating_sub(text::str_width(filter) + count.len());
+        write!(self.writer, "{filter}{}{count}\r\n", " ".repeat(padding))?;
+
+        let project_width = rows
+            .iter()
+            .map(|row| text::str_width(&row.project))
+            .max()
+            .unwrap_or_default();
+        let columns = column_widths(width, project_width);
+        let heading = format_row(&columns, ["PROJECT", "STATUS", "FETCHED", "DESCRIPTION"]);
+        write!(
+            self.writer,
+            "{}{heading}{}\r\n",
+            termion::style::Bold,
+            termion::style::Reset
+        )?;
+
+        for line in 0..row_lines {
+            let index = self.offset + line;
+            if let Some(row) = visible.get(index).and_then(|i| rows.get(*i)) {
+                let fields = [
+                    row.project.as_str(),
+                    &row.status,
+                    &row.fetched,
+                    &row.description,
+                ];
+                let s = format_row(&columns, fields);
+                if index == self.selected {
+                    write!(
+                        self.writer,
+                        "{}{s}{}",
+                        termion::style::Invert,
+                        termion::style::Reset
+                    )?;
+                } else {
+                    self.writer.write_all(s.as_bytes())?;
+                }
+            }
+            self.writer.write_all(b"\r\n")?;
+        }
+
+        let project = visible
+            .get(self.selected)
+            .and_then(|i| rows.get(*i))
+            .map(|row| row.project.as_str())
+            .unwrap_or_default();
+        let status_line = match self.mode {
+            Mode::Command => format!("Run in {project}: {}", self.command),
+            Mode::ConfirmArchive => format!("Archive {project}? [y/N]"),
+            Mode::Normal | Mode::Filter => self.message.clone(),
+        };
+        write!(
+            self.writer,
+            "{}\r\n{}{}{}",
+            text::truncate_to_width(&status_line, width),
+            termion::style::Faint,
+            text::truncate_to_width(KEY_HELP, width),
+            termion::style::Reset
+        )?;
+        self.writer.flush()
+    }
+
+    /// Handle a terminal event given the number of visible rows
+    pub fn handle_event(&mut self, event: Event, visible_rows: usize) -> Option<Action> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        if key == Key::Ctrl('c') {
+            return Some(Action::Exit);
+        }
+        match self.mode {
+            Mode::Normal => self.handle_normal_key(key, visible_rows),
+            Mode::Filter => self.handle_filter_key(key, visible_rows),
+            Mode::Command => self.handle_command_key(key),
+            Mode::ConfirmArchive => {
+                self.mode = Mode::Normal;
+                if key == Key::Char('y') || key == Key::Char('Y') {
+                    Some(Action::Archive)
+                } else {
+                    self.message = String::from("Archive cancelled");
+                    Some(Action::Render)
+                }
+            }
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: Key, visible_rows: usize) -> Option<Action> {
+        if visible_rows == 0 && matches!(key, Key::Char('f' | 's' | 'o' | 'r' | 'a')) {
+            return None;
+        }
+        match key {
+            Key::Char('q') => Some(Action::Exit),
+            Key::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.selected = 0;
+                Some(Action::FilterUpdated)
+            }
+            Key::Char('/') => {
+                self.mode = Mode::Filter;
+                Some(Action::Render)
+            }
+            Key::Char('f') => Some(Action::Fetch),
+            Key::Char('s') => Some(Action::Shell),
+            Key::Char('o') => Some(Action::Browse),
+            Key::Char('r') => {
+                self.mode = Mode::Command;
+                self.command.clear();
+                Some(Action::Render)
+            }
+            Key::Char('a') => {
+                self.mode = Mode::ConfirmArchive;
+                Some(Action::Render)
+            }
+            key => self.move_selection(key, visible_rows),
+        }
+    }
+
+    fn handle_filter_key(&mut self, key: Key, visible_rows: usize) -> Option<Action> {
+        match key {
+            Key::Char('\n') | Key::Esc => {
+                self.mode = Mode::Normal;
+                Some(Action::Render)
+            }
+            Key::Char(ch) if !ch.is_control() => {
+                self.filter.push(ch);
+                self.selected = 0;
+                Some(Action::FilterUpdated)
+            }
+            Key::Backspace => {
+                let changed = delete_last_grapheme(&mut self.filter);
+                self.selected = 0;
+                changed.then_some(Action::FilterUpdated)
+            }
+            Key::Ctrl('w') => {
+                let changed = delete_last_word(&mut self.filter);
+                self.selected = 0;
+                changed.then_some(Action::FilterUpdated)
+            }
+            Key::Ctrl('u') => {
+                self.filter.clear();
+                self.selected = 0;
+                Some(Action::FilterUpdated)
+            }
+            key => self.move_selection(key, visible_rows),
+        }
+    }
+
+    fn handle_command_key(&mut self, key: Key) -> Option<Action> {
+        match key {
+            Key::Char('\n') => {
+                self.mode = Mode::Normal;
+                let command = std::mem::take(&mut self.command);
+                if command.trim().is_empty() {
+                    return Some(Action::Render);
+                }
+                Some(Action::Run(command))
+            }
+            Key::Esc => {
+                self.mode = Mode::Normal;
+                Some(Action::Render)
+            }
+            Key::Char(ch) if !ch.is_control() => {
+                self.command.push(ch);
+                Some(Action::Render)
+            }
+            Key::Backspace => delete_last_grapheme(&mut self.command).then_some(Action::Render),
+            Key::Ctrl('w') => delete_last_word(&mut self.command).then_some(Action::Render),
+            _ => None,
+        }
+    }
+
+    fn move_selection(&mut self, key: Key, visible_rows: usize) -> Option<Action> {
+        let page = termion::terminal_size()
+            .map(|(_, height)| (height as usize).saturating_sub(CHROME_LINES))
+            .unwrap_or(1)
+            .max(1);
+        let last = visible_rows.saturating_sub(1);
+        let selected = match key {
+            Key::Up | Key::Char('k') | Key::Ctrl('p') => self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') | Key::Ctrl('n') => (self.selected + 1).min(last),
+            Key::PageUp => self.selected.saturating_sub(page),
+            Key::PageDown => (self.selected + page).min(last),
+            Key::Home | Key::Char('g') => 0,
+            Key::End | Key::Char('G') => last,
+            _ => return None,
+        };
+        if selected == self.selected {
+            return None;
+        }
+        self.selected = selected;
+        Some(Action::Render)
+    }
+}
+
+/// Indices of the rows matching the filter. Rows are ordered by the match score
+/// when filtering, and kept in their original order otherwise.
+pub fn filter_rows(rows: &[Row], filter: &str, exact_segment_bonus: f32) -> Vec<usize> {
+    if filter.trim().is_empty() {
+        return (0..rows.len()).collect();
+    }
+    let mut scored: Vec<(usize, f32)> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let description = (!row.description.is_empty()).then_some(row.description.as_str());
+            let score = fuzzy::calc_score_with_bonus(
+                filter,
+                &row.project,
+                description,
+                exact_segment_bonus,
+            );
+            (i, score)
+        })
+        .filter(|(_, score)| *score != 0.)
+        .collect();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+
+/// Widths of the project, status, and fetched columns.
+/// The description column gets the rest of the terminal width.
+fn column_widths(width: usize, project_width: usize) -> [usize; 3] {
+    let fixed = STATUS_COLUMN_WIDTH + FETCHED_COLUMN_WIDTH + 3 * COLUMN_GAP;
+    let project_width = project_width.min(width.saturating_sub(fixed) * 2 / 3);
+    [project_width, STATUS_COLUMN_WIDTH, FETCHED_COLUMN_WIDTH]
+}
+
+fn format_row(columns: &[usize; 3], fields: [&str; 4]) -> String {
+    let mut s = String::new();
+    for (field, width) in fields.iter().zip(columns.iter()) {
+        let field = text::truncate_to_width(field, *width);
+        s.push_str(field);
+        s.push_str(&" ".repeat(width - text::str_width(field) + COLUMN_GAP));
+    }
+    s.push_str(fields[3]);
+    let (width, _) = termion::terminal_size().unwrap_or((80, 24));
+    String::from(text::truncate_to_width(&s, width as usize))
+}
+
+/// Offset of the first row to show, so that the selected row stays on the screen
+fn scroll_offset(offset: usize, selected: usize, lines: usize) -> usize {
+    if selected < offset {
+        selected
+    } else if lines > 0 && selected >= offset + lines {
+        selected + 1 - lines
+    } else {
+        offset
+    }
+}
+
+fn delete_last_grapheme(s: &mut String) -> bool {
+    match s.grapheme_indices(true).next_back() {
+        Some((index, _)) => {
+            s.truncate(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Delete the last word and the separators after it
+fn delete_last_word(s: &mut String) -> bool {
+    if s.is_empty() {
+        return false;
+    }
+    let trimmed = s.trim_end_matches(text::is_punctuation);
+    let index = trimmed
+        .rfind(text::is_punctuation)
+        .map(|index| index + trimmed[index..].chars().next().map_or(0, char::len_utf8))
+        .unwrap_or(0);
+    s.truncate(index);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(project: &str, description: &str) -> Row {
+        Row {
+            project: String::from(project),
+            status: String::new(),
+            fetched: String::new(),
+            description: String::from(description),
+        }
+    }
+
+    #[test]
+    fn filter_rows_by_project_and_description() {
+        let rows = [
+            row("github.com/acme/api", ""),
+            row("github.com/acme/web", "payments frontend"),
+            row("gitlab.com/other/tool", ""),
+        ];
+        assert_eq!(
+            filter_rows(&rows, "", fuzzy::DEFAULT_EXACT_SEGMENT_BONUS),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            filter_rows(&rows, "acme", fuzzy::DEFAULT_EXACT_SEGMENT_BONUS),
+            vec![0, 1]
+        );
+        assert_eq!(
+            filter_rows(&rows, "payments", fuzzy::DEFAULT_EXACT_SEGMENT_BONUS),
+            vec![1]
+        );
+        assert!(filter_rows(&rows, "missing", fuzzy::DEFAULT_EXACT_SEGMENT_BONUS).is_empty());
+    }
+
+    #[test]
+    fn scroll_keeps_selection_visible() {
+        assert_eq!(scroll_offset(0, 3, 10), 0);
+        assert_eq!(scroll_offset(0, 10, 10), 1);
+        assert_eq!(scroll_offset(5, 2, 10), 2);
+        assert_eq!(scroll_offset(5, 14, 10), 5);
+    }
+
+    #[test]
+    fn delete_words_and_graphemes() {
+        let mut s = String::from("acme/pay svc  ");
+        assert!(delete_last_word(&mut s));
+        assert_eq!(s, "acme/pay ");
+        assert!(delete_last_word(&mut s));
+        assert_eq!(s, "acme/");
+        assert!(delete_last_word(&mut s));
+        assert_eq!(s, "");
+        assert!(!delete_last_word(&mut s));
+
+        let mut s = String::from("cafe\u{301}");
+        assert!(delete_last_grapheme(&mut s));
+        assert_eq!(s, "caf");
+    }
+
+    #[test]
+    fn project_column_width() {
+        assert_eq!(column_widths(120, 30), [30, 20, 12]);
+        assert_eq!(column_widths(80, 60), [28, 20, 12]);
+    }
+}
@@ -1,12 +1,296 @@
+use crate::crypt;
 use crate::fuzzy;
+use crate::history::{self, History};
+use crate::text;
+use crate::trigram::TrigramIndex;
 use anyhow::{Result, bail};
+use std::io::{BufRead, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::fd::AsRawFd;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Version of the DB file format. Increase it when older versions of gorg
+/// would misread the files written in the new format.
+pub const FORMAT_VERSION: u32 = 1;
+const HEADER_PREFIX: char = '#';
+const TRIGRAM_INDEX_MIN_LINES: usize = 5000;
+const PARALLEL_SCORING_MIN_LINES: usize = 20_000;
+const CANCEL_CHECK_LINES: usize = 32_768;
+const HEADER_VERSION: &str = "version";
+const HEADER_BUILT_AT: &str = "built";
+const HEADER_PIN: &str = "pin";
+const HEADER_USED: &str = "used";
+const HEADER_DESCRIPTION: &str = "desc";
+const HEADER_REMOTE: &str = "remote";
+const HEADER_PICK: &str = "pick";
+const HEADER_LANGUAGES: &str = "lang";
+const HEADER_TAGS: &str = "tags";
+const HEADER_MIRRORED: &str = "mirrored";
+const MAX_RECENT_ENTRIES: usize = 100;
+const MAX_PICKS: usize = 100;
+/// Score bonus for each time an entry was picked for the same query
+const PICK_BONUS: f32 = 2.;
+/// Number of picks after which the bonus stops growing
+const MAX_PICK_BONUS_COUNT: u32 = 5;
+const LOCK_FILE_SUFFIX: &str = "lock";
+const HISTORY_FILE_SUFFIX: &str = "history";
+const TEMP_FILE_SUFFIX: &str = "tmp";
+const CORRUPT_FILE_SUFFIX: &str = "corrupt";
+const REFRESH_FILE_SUFFIX: &str = "refresh";
 
 pub struct DB {
+    header: Header,
     data: String,
 }
 
+/// Metadata stored at the start of the DB file.
+/// Each header line starts with `#` and contains a `key=value` pair.
+#[derive(Default)]
+pub struct Header {
+    /// Format version of the file the header was read from.
+    /// Files written before the version was recorded are in version 1.
+    pub version: Option<u32>,
+
+    /// Time when the DB was built from a full scan of the projects directory
+    pub built_at: Option<SystemTime>,
+
+    /// Entries that are kept in the DB across full scans
+    pub pinned: Vec<String>,
+
+    /// Recently used entries and the times they were used, most recent first
+    pub recent: Vec<(String, SystemTime)>,
+
+    /// Free-text descriptions for entries sorted by the entry
+    pub descriptions: Vec<(String, String)>,
+
+    /// Git remote URLs for entries sorted by the entry
+    pub remotes: Vec<(String, String)>,
+
+    /// Entries picked from the interactive finder over better matches, most recent first
+    pub picks: Vec<Pick>,
+
+    /// Comma-separated ecosystems detected for entries sorted by the entry
+    pub languages: Vec<(String, String)>,
+
+    /// Comma-separated tags from the project configuration files sorted by the entry
+    pub tags: Vec<(String, String)>,
+
+    /// Entries listed by the forge of a mirrored organization in an earlier `sync-orgs`, sorted
+    pub mirrored: Vec<String>,
+}
+
+/// Entry picked for a query in the interactive finder although it wasn't the best match
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pick {
+    /// Query normalized with `normalize_query`
+    pub query: String,
+    pub entry: String,
+    /// Number of times the entry was picked for the query
+    pub count: u32,
+}
+
+/// Exclusive read-modify-write access to the DB file.
+/// Other transactions on the same file wait until this one is committed or dropped.
+/// Dropping the transaction without committing it discards the changes.
+pub struct Transaction {
+    path: PathBuf,
+    db: DB,
+    /// Length of the history log that was applied to the DB when it was loaded
+    history_len: u64,
+    /// Lock on a file next to the DB file, held until the transaction ends
+    _lock: std::fs::File,
+}
+
+impl Transaction {
+    /// Lock the DB file and load the DB from it. Starts with an empty DB when the file does not exist.
+    pub fn begin<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lock = lock_file(&sibling_path(&path, LOCK_FILE_SUFFIX))?;
+        let (db, history_len) = DB::load_with_history(&path)?.unwrap_or_default();
+        Ok(Self {
+            path,
+            db,
+            history_len,
+            _lock: lock,
+        })
+    }
+
+    /// Lock the DB file and start with an empty DB without loading the file,
+    /// e.g. for replacing a corrupt DB. Uses from the history log are kept.
+    pub fn begin_empty<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let lock = lock_file(&sibling_path(&path, LOCK_FILE_SUFFIX))?;
+        Ok(Self {
+            path,
+            db: DB::empty(),
+            history_len: 0,
+            _lock: lock,
+        })
+    }
+
+    /// Save the changes and release the lock. The history log is compacted into the DB,
+    /// including the records appended since the DB was loaded.
+    pub fn commit(mut self) -> Result<()> {
+        let mut history = History::lock_exclusive(&history_path(&self.path))?;
+        let (records, _) = history.read(self.history_len)?;
+        self.db.header.apply_history(&records);
+        self.db.save(&self.path)?;
+        history.clear()
+    }
+}
+
+impl Deref for Transaction {
+    type Target = DB;
+
+    fn deref(&self) -> &DB {
+        &self.db
+    }
+}
+
+impl DerefMut for Transaction {
+    fn deref_mut(&mut self) -> &mut DB {
+        &mut self.db
+    }
+}
+
+/// DB file that can't be read as a DB. The file is copied next to it before it gets replaced,
+/// so that it can be inspected later.
+#[derive(Debug)]
+pub struct CorruptDB {
+    pub path: PathBuf,
+    /// None when the copy couldn't be made
+    pub backup_path: Option<PathBuf>,
+    pub reason: String,
+}
+
+impl CorruptDB {
+    fn new(path: &Path, reason: String) -> Self {
+        let backup_path = sibling_path(path, CORRUPT_FILE_SUFFIX);
+        let backup_path = match std::fs::copy(path, &backup_path) {
+            Ok(_) => Some(backup_path),
+            Err(err) => {
+                log::warn!("Failed to copy {}: {err}", path.to_string_lossy());
+                None
+            }
+        };
+        Self {
+            path: path.to_path_buf(),
+            backup_path,
+            reason,
+        }
+    }
+}
+
+impl std::fmt::Display for CorruptDB {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Index at {} is corrupt: {}",
+            self.path.to_string_lossy(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for CorruptDB {}
+
+/// Problem in the lines of a DB file that breaks the invariants of the DB,
+/// e.g. after editing the file by hand
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// Line that is not valid UTF-8 or contains NUL bytes
+    InvalidText {
+        line: usize,
+    },
+    EmptyLine {
+        line: usize,
+    },
+    /// Whitespace before or after the entry
+    Whitespace {
+        line: usize,
+    },
+    Duplicate {
+        line: usize,
+        entry: String,
+    },
+    /// Entry sorted before the entry on the previous line
+    Unsorted {
+        line: usize,
+        entry: String,
+    },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidText { line } => write!(f, "{line}: invalid UTF-8 or NUL bytes"),
+            Self::EmptyLine { line } => write!(f, "{line}: empty line"),
+            Self::Whitespace { line } => write!(f, "{line}: whitespace around the entry"),
+            Self::Duplicate { line, entry } => write!(f, "{line}: duplicate entry {entry}"),
+            Self::Unsorted { line, entry } => write!(f, "{line}: entry {entry} is out of order"),
+        }
+    }
+}
+
+pub enum Change<'a> {
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Entry split into the host, organization, and repository parts, e.g. `github.com/jpallari/gorg`
+#[derive(Debug, PartialEq)]
+pub struct EntryParts<'a> {
+    pub host: &'a str,
+    /// Everything between the host and the repository. Empty for entries directly under the host.
+    pub org: &'a str,
+    pub repo: &'a str,
+}
+
+impl<'a> EntryParts<'a> {
+    pub fn parse(entry: &'a str) -> Self {
+        let Some((host, path)) = entry.split_once('/') else {
+            return Self {
+                host: entry,
+                org: "",
+                repo: "",
+            };
+        };
+        let (org, repo) = path.rsplit_once('/').unwrap_or(("", path));
+        Self { host, org, repo }
+    }
+}
+
 pub struct DBView<'a> {
     lines: Vec<&'a str>,
+    descriptions: &'a [(String, String)],
+    results: Vec<(&'a str, f32)>,
+    index: Option<TrigramIndex>,
+    candidates: Vec<u32>,
+    exact_segment_bonus: f32,
+    picks: &'a [Pick],
+}
+
+/// Source of fuzzy matched projects for interactive finding
+pub trait Finder {
+    /// Update matches using the given fuzzy query until the given function signals cancellation.
+    /// Returns false when the search was cancelled.
+    fn search(&mut self, matcher: &str, cancelled: &dyn Fn() -> bool) -> Result<bool>;
+
+    /// Matches from the latest query sorted by score
+    fn matches(&self) -> impl Iterator<Item = &str>;
+
+    /// Number of projects the matches are searched from
+    fn total(&self) -> usize;
+
+    /// Description of a matched project
+    fn description(&self, project: &str) -> Option<&str>;
+
+    /// Sorted hosts of all projects
+    fn hosts(&self) -> Result<Vec<String>>;
+
+    /// Sorted organizations of all projects including their hosts, e.g. `github.com/jpallari`
+    fn orgs(&self) -> Result<Vec<String>>;
 }
 
 impl Default for DB {
@@ -18,28 +302,97 @@ impl Default for DB {
 impl DB {
     pub fn empty() -> Self {
         Self {
+            header: Header::default(),
             data: String::new(),
         }
     }
 
+    /// Load the DB from the given file with the project uses from the history log next to it
     pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Option<Self>> {
-        match std::fs::read_to_string(path) {
-            Ok(data) => Ok(Some(Self { data })),
+        Ok(Self::load_with_history(path.as_ref())?.map(|(db, _)| db))
+    }
+
+    /// Load the DB with the history log applied. Returns the DB and the length of the log applied.
+    fn load_with_history(path: &Path) -> Result<Option<(Self, u64)>> {
+        // The log is locked while reading the DB, so that it isn't compacted in between
+        let mut history = History::lock_shared(&history_path(path))?;
+        let mut db = match std::fs::read(path) {
+            Ok(data) => {
+                let mut data = match decode_data(crypt::decrypt(path, data)?) {
+                    Ok(data) => data,
+                    Err(reason) => return Err(CorruptDB::new(path, reason).into()),
+                };
+                let mut header = Header::default();
+                let header_len = header.parse_lines(&data);
+                header.check_version(path)?;
+                data.drain(..header_len);
+                if data.contains('\\') {
+                    data = normalize_data(&data);
+                }
+                Self { header, data }
+            }
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => return Err(err.into()),
+            },
+        };
+        let (records, history_len) = history.read(0)?;
+        db.header.apply_history(&records);
+        Ok(Some((db, history_len)))
+    }
+
+    /// Read only the header of the DB file without loading the entries
+    pub fn load_header<P: AsRef<std::path::Path>>(path: P) -> Result<Option<Header>> {
+        let path = path.as_ref();
+        let mut history = History::lock_shared(&history_path(path))?;
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
             Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => Ok(None),
-                _ => Err(err.into()),
+                std::io::ErrorKind::NotFound => return Ok(None),
+                _ => return Err(err.into()),
             },
+        };
+        let mut header = Header::default();
+        let mut reader = std::io::BufReader::new(file);
+        if crypt::is_encrypted(reader.fill_buf()?) {
+            // The whole file is needed for decrypting it
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let data = match decode_data(crypt::decrypt(path, data)?) {
+                Ok(data) => data,
+                Err(reason) => return Err(CorruptDB::new(path, reason).into()),
+            };
+            header.parse_lines(&data);
+        } else {
+            for line in reader.split(b'\n') {
+                let line = match decode_data(line?) {
+                    Ok(line) => line,
+                    Err(reason) => return Err(CorruptDB::new(path, reason).into()),
+                };
+                if !line.starts_with(HEADER_PREFIX) {
+                    break;
+                }
+                header.parse_lines(&line);
+            }
         }
+        header.check_version(path)?;
+        let (records, _) = history.read(0)?;
+        header.apply_history(&records);
+        Ok(Some(header))
     }
 
+    /// Save the DB to the given file. The file is replaced atomically,
+    /// so readers never see a partially written DB.
     pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
-        let _ = std::fs::write(path, &self.data)?;
-        Ok(())
+        let mut contents = String::with_capacity(self.data.len() + 100);
+        self.header.write_lines(&mut contents);
+        contents.push_str(&self.data);
+        write_atomically(path.as_ref(), &crypt::encrypt(contents.into_bytes())?)
     }
 
     pub fn add(&mut self, entry: &str) -> Result<()> {
         let entry = entry.trim();
-        if entry.contains(|c: char| c == '\n') {
+        if entry.contains('\n') {
             bail!("Cannot insert entries that contain new lines: {entry}")
         }
 
@@ -47,6 +400,37 @@ impl DB {
         Ok(())
     }
 
+    pub fn remove(&mut self, entry: &str) -> bool {
+        let entry = entry.trim();
+        self.retain(|line| line != entry) > 0
+    }
+
+    /// Removes the entry matching the given path and all entries nested under it.
+    pub fn remove_tree(&mut self, path: &str) -> usize {
+        let path = path.trim().trim_end_matches('/');
+        self.retain(|line| !is_same_or_nested_path(line, path))
+    }
+
+    fn retain<F: Fn(&str) -> bool>(&mut self, keep: F) -> usize {
+        let mut removed = 0;
+        let mut data = String::with_capacity(self.data.len());
+        for line in self.data.split('\n').map(|l| l.trim()) {
+            if line.is_empty() {
+                continue;
+            }
+            if keep(line) {
+                data.push_str(line);
+                data.push('\n');
+            } else {
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            self.data = data;
+        }
+        removed
+    }
+
     pub fn from_entries<T: Iterator<Item = String>>(entries: T) -> Self {
         let mut entries_vec = Vec::from_iter(entries);
         entries_vec.sort();
@@ -55,59 +439,1126 @@ impl DB {
             data.push_str(entry);
             data.push('\n');
         }
-        Self { data }
+        let header = Header {
+            built_at: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        Self { header, data }
     }
 
-    pub fn find_matches<'b>(&self, matcher: &'b str) -> impl Iterator<Item = &str> {
-        let is_empty = matcher.is_empty();
-        self.data.split('\n').filter_map(move |a| {
-            if is_empty {
-                // If the matcher is not specified, we capture all results.
-                return Some(a);
+    pub fn pinned(&self) -> &[String] {
+        &self.header.pinned
+    }
+
+    /// Add an entry that is kept in the DB across full scans
+    pub fn pin(&mut self, entry: &str) -> Result<()> {
+        self.add(entry)?;
+        let entry = entry.trim();
+        if let Err(index) = self
+            .header
+            .pinned
+            .binary_search_by(|p| p.as_str().cmp(entry))
+        {
+            self.header.pinned.insert(index, String::from(entry));
+        }
+        Ok(())
+    }
+
+    /// Remove the pin from an entry. The entry itself is kept in the DB.
+    pub fn unpin(&mut self, entry: &str) -> bool {
+        let entry = entry.trim();
+        match self
+            .header
+            .pinned
+            .binary_search_by(|p| p.as_str().cmp(entry))
+        {
+            Ok(index) => {
+                self.header.pinned.remove(index);
+                true
             }
-            let a = a.trim();
-            if a.is_empty() {
-                return None;
+            Err(_) => false,
+        }
+    }
+
+    /// Recently used entries and the times they were used, most recent first
+    pub fn recent(&self) -> impl DoubleEndedIterator<Item = (&str, SystemTime)> {
+        self.header
+            .recent
+            .iter()
+            .map(|(entry, used_at)| (entry.as_str(), *used_at))
+    }
+
+    /// Record the time an entry was used. Only the most recent uses are kept.
+    pub fn mark_used(&mut self, entry: &str, used_at: SystemTime) {
+        self.header.mark_used(entry, used_at);
+    }
+
+    /// Entries picked for queries over better matches, most recent first
+    pub fn picks(&self) -> &[Pick] {
+        &self.header.picks
+    }
+
+    /// Add a pick after the existing ones, e.g. when carrying picks over to a new DB
+    pub fn push_pick(&mut self, pick: Pick) {
+        if self.header.picks.len() < MAX_PICKS {
+            self.header.picks.push(pick);
+        }
+    }
+
+    pub fn description(&self, entry: &str) -> Option<&str> {
+        find_by_entry(&self.header.descriptions, entry.trim())
+    }
+
+    pub fn descriptions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.header
+            .descriptions
+            .iter()
+            .map(|(entry, description)| (entry.as_str(), description.as_str()))
+    }
+
+    /// Set a free-text description for an entry. Descriptions are included in fuzzy matching.
+    pub fn describe(&mut self, entry: &str, description: &str) -> Result<()> {
+        let entry = entry.trim();
+        let description = description.trim();
+        if description.contains('\n') {
+            bail!("Description cannot contain new lines");
+        }
+        set_by_entry(&mut self.header.descriptions, entry, description);
+        Ok(())
+    }
+
+    pub fn remove_description(&mut self, entry: &str) -> bool {
+        remove_by_entry(&mut self.header.descriptions, entry.trim())
+    }
+
+    /// Git remote URL recorded for an entry
+    pub fn remote(&self, entry: &str) -> Option<&str> {
+        self.header.remote(entry)
+    }
+
+    /// Record the Git remote URL of an entry
+    pub fn set_remote(&mut self, entry: &str, url: &str) -> Result<()> {
+        let url = url.trim();
+        if url.contains('\n') {
+            bail!("Remote URL cannot contain new lines");
+        }
+        set_by_entry(&mut self.header.remotes, entry.trim(), url);
+        Ok(())
+    }
+
+    /// Ecosystems detected for an entry, such as `rust`
+    pub fn languages(&self, entry: &str) -> impl Iterator<Item = &str> {
+        self.header.languages(entry)
+    }
+
+    /// Tags of an entry from its project configuration file
+    pub fn tags(&self, entry: &str) -> impl Iterator<Item = &str> {
+        self.header.tags(entry)
+    }
+
+    /// Record the tags of an entry
+    pub fn set_tags(&mut self, entry: &str, tags: &[String]) {
+        let entry = entry.trim();
+        if tags.is_empty() {
+            remove_by_entry(&mut self.header.tags, entry);
+        } else {
+            set_by_entry(&mut self.header.tags, entry, &tags.join(","));
+        }
+    }
+
+    /// Record the ecosystems detected for an entry
+    pub fn set_languages(&mut self, entry: &str, languages: &[&str]) {
+        let entry = entry.trim();
+        if languages.is_empty() {
+            remove_by_entry(&mut self.header.languages, entry);
+        } else {
+            set_by_entry(&mut self.header.languages, entry, &languages.join(","));
+        }
+    }
+
+    pub fn mirrored(&self) -> &[String] {
+        &self.header.mirrored
+    }
+
+    pub fn is_mirrored(&self, entry: &str) -> bool {
+        self.header
+            .mirrored
+            .binary_search_by(|m| m.as_str().cmp(entry.trim()))
+            .is_ok()
+    }
+
+    /// Record the entry as listed by the forge of a mirrored organization
+    pub fn mark_mirrored(&mut self, entry: &str) {
+        let entry = entry.trim();
+        if let Err(index) = self
+            .header
+            .mirrored
+            .binary_search_by(|m| m.as_str().cmp(entry))
+        {
+            self.header.mirrored.insert(index, String::from(entry));
+        }
+    }
+
+    pub fn unmark_mirrored(&mut self, entry: &str) -> bool {
+        let entry = entry.trim();
+        match self
+            .header
+            .mirrored
+            .binary_search_by(|m| m.as_str().cmp(entry))
+        {
+            Ok(index) => {
+                self.header.mirrored.remove(index);
+                true
             }
-            match fuzzy::calc_score(matcher, a) {
-                0. => None,
-                _ => Some(a),
+            Err(_) => false,
+        }
+    }
+
+    /// Entries under the prefix that an earlier sync recorded as mirrored,
+    /// but that are missing from the current listing of the organization
+    pub fn unlisted_mirrored(&self, prefix: &str, listed: &[String]) -> Vec<String> {
+        self.entries()
+            .filter(|entry| entry.starts_with(prefix) && self.is_mirrored(entry))
+            .filter(|entry| !listed.iter().any(|l| l == entry))
+            .map(String::from)
+            .collect()
+    }
+
+    /// Rename an entry along with its pin, usage, picks, description, remote URL, and ecosystems.
+    /// Returns false when the entry is not found.
+    pub fn rename(&mut self, entry: &str, new_entry: &str) -> Result<bool> {
+        let entry = entry.trim();
+        let new_entry = new_entry.trim();
+        if !self.remove(entry) {
+            return Ok(false);
+        }
+        if self.unpin(entry) {
+            self.pin(new_entry)?;
+        } else {
+            self.add(new_entry)?;
+        }
+        if self.unmark_mirrored(entry) {
+            self.mark_mirrored(new_entry);
+        }
+        for (e, _) in self.header.recent.iter_mut() {
+            if e == entry {
+                *e = String::from(new_entry);
+            }
+        }
+        for pick in self.header.picks.iter_mut() {
+            if pick.entry == entry {
+                pick.entry = String::from(new_entry);
+            }
+        }
+        for pairs in [
+            &mut self.header.descriptions,
+            &mut self.header.remotes,
+            &mut self.header.languages,
+            &mut self.header.tags,
+        ] {
+            if let Some(value) = find_by_entry(pairs, entry).map(String::from) {
+                remove_by_entry(pairs, entry);
+                set_by_entry(pairs, new_entry, &value);
             }
+        }
+        Ok(true)
+    }
+
+    pub fn find_matches(&self, matcher: &str) -> impl Iterator<Item = &str> {
+        let is_empty = matcher.is_empty();
+        self.entries().filter(move |a| {
+            // If the matcher is not specified, we capture all results.
+            is_empty || fuzzy::calc_score(matcher, a, self.description(a)) != 0.
+        })
+    }
+
+    /// Entries starting with the given prefix. The prefix and the entries are folded with
+    /// `text::fold` before comparing them, so use `entries` for exact comparisons.
+    pub fn find_by_prefix(&self, prefix: &str) -> impl Iterator<Item = &str> {
+        let prefix_folded = text::fold(prefix.trim());
+        self.entries().filter(move |a| {
+            prefix_folded.is_empty() || text::fold(a).starts_with(prefix_folded.as_ref())
         })
     }
 
-    pub fn find_by_prefix<'b>(&self, prefix: &'b str) -> impl Iterator<Item = &str> {
-        let prefix_trimmed = prefix.trim();
+    /// Entry of the given path or of a directory containing it.
+    /// The innermost entry is returned when entries are nested.
+    pub fn find_containing(&self, path: &str) -> Option<&str> {
+        self.entries()
+            .filter(|entry| is_same_or_nested_path(path, entry))
+            .max_by_key(|entry| entry.len())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &str> {
         self.data
             .split('\n')
-            .filter(move |a| prefix_trimmed.is_empty() || a.trim().starts_with(prefix_trimmed))
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty())
+    }
+
+    /// List the entries added to and removed from this DB in the other DB
+    pub fn diff<'a>(&'a self, other: &'a DB) -> Vec<Change<'a>> {
+        let mut old: Vec<&str> = self.entries().collect();
+        let mut new: Vec<&str> = other.entries().collect();
+        old.sort_unstable();
+        new.sort_unstable();
+
+        let mut changes = Vec::new();
+        let mut old_iter = old.into_iter().peekable();
+        let mut new_iter = new.into_iter().peekable();
+        loop {
+            match (old_iter.peek(), new_iter.peek()) {
+                (Some(o), Some(n)) if o == n => {
+                    old_iter.next();
+                    new_iter.next();
+                }
+                (Some(o), Some(n)) if o < n => {
+                    changes.push(Change::Removed(o));
+                    old_iter.next();
+                }
+                (_, Some(n)) => {
+                    changes.push(Change::Added(n));
+                    new_iter.next();
+                }
+                (Some(o), None) => {
+                    changes.push(Change::Removed(o));
+                    old_iter.next();
+                }
+                (None, None) => break,
+            }
+        }
+        changes
     }
 
-    pub fn view<'a>(&'a self) -> DBView<'a> {
-        let lines: Vec<&str> = self.data.split('\n').map(|a| a.trim()).collect();
-        DBView { lines }
+    pub fn view(&self) -> DBView<'_> {
+        let lines: Vec<&str> = self
+            .data
+            .split('\n')
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty())
+            .collect();
+        DBView {
+            lines,
+            descriptions: &self.header.descriptions,
+            results: Vec::new(),
+            index: None,
+            exact_segment_bonus: fuzzy::DEFAULT_EXACT_SEGMENT_BONUS,
+            picks: &self.header.picks,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+impl Header {
+    fn mark_used(&mut self, entry: &str, used_at: SystemTime) {
+        let entry = entry.trim();
+        let recent = &mut self.recent;
+        recent.retain(|(e, _)| e != entry);
+        let index = recent.partition_point(|(_, t)| *t > used_at);
+        recent.insert(index, (String::from(entry), used_at));
+        recent.truncate(MAX_RECENT_ENTRIES);
+    }
+
+    /// Record that the entry was picked for the query over better matches.
+    /// Matches for the same query are boosted for each pick. Only the most recent picks are kept.
+    fn record_pick(&mut self, query: &str, entry: &str) {
+        let query = normalize_query(query);
+        if query.is_empty() {
+            return;
+        }
+        let entry = entry.trim();
+        let picks = &mut self.picks;
+        let count = match picks
+            .iter()
+            .position(|pick| pick.query == query && pick.entry == entry)
+        {
+            Some(index) => picks.remove(index).count + 1,
+            None => 1,
+        };
+        let entry = String::from(entry);
+        picks.insert(
+            0,
+            Pick {
+                query,
+                entry,
+                count,
+            },
+        );
+        picks.truncate(MAX_PICKS);
+    }
+
+    /// Apply the project uses recorded in the history log in the order they were recorded
+    fn apply_history(&mut self, records: &[history::Record]) {
+        for record in records {
+            self.mark_used(&record.entry, record.used_at);
+            if let Some(query) = &record.query {
+                self.record_pick(query, &record.entry);
+            }
+        }
+    }
+
+    /// Free-text description recorded for an entry
+    pub fn description(&self, entry: &str) -> Option<&str> {
+        find_by_entry(&self.descriptions, entry.trim())
+    }
+
+    /// Git remote URL recorded for an entry
+    pub fn remote(&self, entry: &str) -> Option<&str> {
+        find_by_entry(&self.remotes, entry.trim())
+    }
+
+    /// Ecosystems detected for an entry
+    pub fn languages(&self, entry: &str) -> impl Iterator<Item = &str> {
+        find_by_entry(&self.languages, entry.trim())
+            .unwrap_or_default()
+            .split(',')
+            .filter(|language| !language.is_empty())
+    }
+
+    /// Tags of an entry from its project configuration file
+    pub fn tags(&self, entry: &str) -> impl Iterator<Item = &str> {
+        find_by_entry(&self.tags, entry.trim())
+            .unwrap_or_default()
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+    }
+
+    /// Parse header lines from the start of the given string.
+    /// Returns the number of bytes the header lines span.
+    fn parse_lines(&mut self, s: &str) -> usize {
+        let mut len = 0;
+        for line in s.split_inclusive('\n') {
+            let Some(field) = line.strip_prefix(HEADER_PREFIX) else {
+                break;
+            };
+            len += line.len();
+            let Some((key, value)) = field.trim().split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                HEADER_VERSION => {
+                    // Versions that can't be parsed are from a newer format too
+                    self.version = Some(value.trim().parse().unwrap_or(u32::MAX));
+                }
+                HEADER_BUILT_AT => {
+                    self.built_at = value
+                        .trim()
+                        .parse()
+                        .ok()
+                        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+                }
+                HEADER_PIN => {
+                    let entry = &normalize_entry(value.trim());
+                    if let Err(index) = self.pinned.binary_search_by(|p| p.as_str().cmp(entry)) {
+                        self.pinned.insert(index, String::from(entry));
+                    }
+                }
+                HEADER_USED => {
+                    let Some((secs, entry)) = value.trim().split_once(' ') else {
+                        continue;
+                    };
+                    let Ok(secs) = secs.parse() else {
+                        continue;
+                    };
+                    let used_at = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+                    self.recent.push((normalize_entry(entry.trim()), used_at));
+                }
+                HEADER_DESCRIPTION => {
+                    if let Some((entry, description)) = value.trim().split_once('\t') {
+                        set_by_entry(
+                            &mut self.descriptions,
+                            &normalize_entry(entry.trim()),
+                            description.trim(),
+                        );
+                    }
+                }
+                HEADER_REMOTE => {
+                    if let Some((entry, url)) = value.trim().split_once('\t') {
+                        set_by_entry(
+                            &mut self.remotes,
+                            &normalize_entry(entry.trim()),
+                            url.trim(),
+                        );
+                    }
+                }
+                HEADER_LANGUAGES => {
+                    if let Some((entry, languages)) = value.trim().split_once('\t') {
+                        set_by_entry(
+                            &mut self.languages,
+                            &normalize_entry(entry.trim()),
+                            languages.trim(),
+                        );
+                    }
+                }
+                HEADER_MIRRORED => {
+                    let entry = &normalize_entry(value.trim());
+                    if let Err(index) = self.mirrored.binary_search_by(|m| m.as_str().cmp(entry)) {
+                        self.mirrored.insert(index, String::from(entry));
+                    }
+                }
+                HEADER_TAGS => {
+                    if let Some((entry, tags)) = value.trim().split_once('\t') {
+                        set_by_entry(&mut self.tags, &normalize_entry(entry.trim()), tags.trim());
+                    }
+                }
+                HEADER_PICK => {
+                    let Some((count, rest)) = value.trim().split_once(' ') else {
+                        continue;
+                    };
+                    let (Ok(count), Some((entry, query))) = (count.parse(), rest.split_once('\t'))
+                    else {
+                        continue;
+                    };
+                    self.picks.push(Pick {
+                        query: normalize_query(query),
+                        entry: normalize_entry(entry.trim()),
+                        count,
+                    });
+                }
+                key => log::debug!("Ignoring unknown DB header: {key}"),
+            }
+        }
+        len
+    }
+
+    /// Fail when the file was written in a newer format than this version of gorg can read.
+    /// Files in older formats are read as they are, since the format hasn't changed yet.
+    fn check_version(&self, path: &Path) -> Result<()> {
+        match self.version {
+            Some(version) if version > FORMAT_VERSION => bail!(
+                "Index at {} was created by a newer version of gorg (format version {}, supported up to {FORMAT_VERSION}). Update gorg to use it.",
+                path.to_string_lossy(),
+                if version == u32::MAX {
+                    String::from("unknown")
+                } else {
+                    version.to_string()
+                },
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_lines(&self, out: &mut String) {
+        // Always the current version, since the header is converted to the current format on read
+        out.push(HEADER_PREFIX);
+        out.push_str(HEADER_VERSION);
+        out.push('=');
+        out.push_str(&FORMAT_VERSION.to_string());
+        out.push('\n');
+        if let Some(built_at) = self.built_at {
+            let secs = unix_secs(built_at);
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_BUILT_AT);
+            out.push('=');
+            out.push_str(&secs.to_string());
+            out.push('\n');
+        }
+        for entry in self.pinned.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_PIN);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\n');
+        }
+        for entry in self.mirrored.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_MIRRORED);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\n');
+        }
+        for (entry, used_at) in self.recent.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_USED);
+            out.push('=');
+            out.push_str(&unix_secs(*used_at).to_string());
+            out.push(' ');
+            out.push_str(entry);
+            out.push('\n');
+        }
+        for (entry, description) in self.descriptions.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_DESCRIPTION);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\t');
+            out.push_str(description);
+            out.push('\n');
+        }
+        for (entry, url) in self.remotes.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_REMOTE);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\t');
+            out.push_str(url);
+            out.push('\n');
+        }
+        for (entry, languages) in self.languages.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_LANGUAGES);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\t');
+            out.push_str(languages);
+            out.push('\n');
+        }
+        for (entry, tags) in self.tags.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_TAGS);
+            out.push('=');
+            out.push_str(entry);
+            out.push('\t');
+            out.push_str(tags);
+            out.push('\n');
+        }
+        for pick in self.picks.iter() {
+            out.push(HEADER_PREFIX);
+            out.push_str(HEADER_PICK);
+            out.push('=');
+            out.push_str(&pick.count.to_string());
+            out.push(' ');
+            out.push_str(&pick.entry);
+            out.push('\t');
+            out.push_str(&pick.query);
+            out.push('\n');
+        }
+    }
+
+    /// Time elapsed since the DB was built from a full scan
+    pub fn age(&self) -> Option<Duration> {
+        self.built_at
+            .map(|built_at| built_at.elapsed().unwrap_or_default())
     }
 }
 
 impl<'a> DBView<'a> {
-    pub fn find_matches<'b>(&self, matcher: &'b str, results: &mut Vec<(&'a str, f32)>) {
-        results.clear();
-        results.extend(
-            self.lines
+    /// Use a trigram index for narrowing down the lines to score.
+    /// Only worth it for large DBs that are queried more than once.
+    pub fn with_index(mut self) -> Self {
+        if self.lines.len() < TRIGRAM_INDEX_MIN_LINES {
+            return self;
+        }
+        if self.descriptions.is_empty() {
+            self.index = Some(TrigramIndex::new(&self.lines));
+        } else {
+            // Descriptions are matched too, so their trigrams must lead to the lines as well
+            let searchable: Vec<String> = self
+                .lines
                 .iter()
-                .filter_map(|a| match fuzzy::calc_score(matcher, a) {
-                    0. => None,
-                    score => Some((*a, score)),
-                }),
-        );
+                .map(|line| match find_by_entry(self.descriptions, line) {
+                    Some(description) => format!("{line}\n{description}"),
+                    None => String::from(*line),
+                })
+                .collect();
+            let searchable: Vec<&str> = searchable.iter().map(|s| s.as_str()).collect();
+            self.index = Some(TrigramIndex::new(&searchable));
+        }
+        self
+    }
+
+    /// Score query parts that are equal to a whole segment of a line with the given bonus
+    pub fn with_exact_segment_bonus(mut self, bonus: f32) -> Self {
+        self.exact_segment_bonus = bonus;
+        self
+    }
+
+    pub fn description(&self, entry: &str) -> Option<&'a str> {
+        find_by_entry(self.descriptions, entry)
+    }
+
+    /// Score bonus the entry gets for having been picked for the query
+    pub fn pick_bonus(&self, matcher: &str, entry: &str) -> f32 {
+        let query = normalize_query(matcher);
+        let picks: Vec<&Pick> = self.picks.iter().filter(|p| p.query == query).collect();
+        pick_bonus(&picks, entry)
+    }
+
+    /// Sorted hosts of all entries without duplicates
+    pub fn hosts(&self) -> Vec<&'a str> {
+        let mut hosts: Vec<&str> = self
+            .lines
+            .iter()
+            .map(|line| EntryParts::parse(line).host)
+            // Empty lines and projects outside of the projects directory have no host
+            .filter(|host| !host.is_empty())
+            .collect();
+        hosts.sort_unstable();
+        hosts.dedup();
+        hosts
+    }
+
+    /// Sorted organizations of all entries including their hosts without duplicates
+    pub fn orgs(&self) -> Vec<&'a str> {
+        let mut orgs: Vec<&str> = self
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let parts = EntryParts::parse(line);
+                (!parts.host.is_empty() && !parts.org.is_empty())
+                    .then(|| &line[..parts.host.len() + 1 + parts.org.len()])
+            })
+            .collect();
+        orgs.sort_unstable();
+        orgs.dedup();
+        orgs
+    }
+
+    pub fn find_matches(&mut self, matcher: &str) -> &[(&'a str, f32)] {
+        self.find_matches_until(matcher, &|| false);
+        &self.results
+    }
+
+    /// Find matches until the given function signals cancellation.
+    /// Returns false when the search was cancelled.
+    pub fn find_matches_until(&mut self, matcher: &str, cancelled: &dyn Fn() -> bool) -> bool {
+        let results = &mut self.results;
+        results.clear();
+        if matcher.trim().is_empty() {
+            // If the matcher is not specified, we capture all results.
+            results.extend(
+                self.lines
+                    .iter()
+                    .filter(|a| !a.is_empty())
+                    .map(|a| (*a, 0.)),
+            );
+            return true;
+        }
+        let lines = &self.lines;
+        let descriptions = self.descriptions;
+        let exact_segment_bonus = self.exact_segment_bonus;
+        let query = normalize_query(matcher);
+        let picks: Vec<&Pick> = self.picks.iter().filter(|p| p.query == query).collect();
+        let score_line = |a: &'a str| {
+            let description = find_by_entry(descriptions, a);
+            match fuzzy::calc_score_with_bonus(matcher, a, description, exact_segment_bonus) {
+                0. => None,
+                score => Some((a, score + pick_bonus(&picks, a))),
+            }
+        };
+        let completed = match &self.index {
+            Some(index) if index.candidates(matcher, &mut self.candidates) => {
+                extend_scored_until(results, &self.candidates, cancelled, |i| {
+                    lines.get(*i as usize).and_then(|a| score_line(a))
+                })
+            }
+            _ => extend_scored_until(results, lines, cancelled, |a| score_line(a)),
+        };
+        if !completed {
+            results.clear();
+            return false;
+        }
         results.sort_by(|(_, score1), (_, score2)| {
             score2
                 .partial_cmp(score1)
                 .expect("Score comparison must be comparable")
         });
+        true
     }
 }
 
+impl Finder for DBView<'_> {
+    fn search(&mut self, matcher: &str, cancelled: &dyn Fn() -> bool) -> Result<bool> {
+        Ok(self.find_matches_until(matcher, cancelled))
+    }
+
+    fn matches(&self) -> impl Iterator<Item = &str> {
+        self.results.iter().map(|(item, _)| *item)
+    }
+
+    fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn description(&self, project: &str) -> Option<&str> {
+        find_by_entry(self.descriptions, project)
+    }
+
+    fn hosts(&self) -> Result<Vec<String>> {
+        Ok(DBView::hosts(self).into_iter().map(String::from).collect())
+    }
+
+    fn orgs(&self) -> Result<Vec<String>> {
+        Ok(DBView::orgs(self).into_iter().map(String::from).collect())
+    }
+}
+
+/// Normalize a query for comparing it to the queries of picks
+fn normalize_query(query: &str) -> String {
+    text::fold(query)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Score bonus for an entry from the picks of the query
+fn pick_bonus(picks: &[&Pick], entry: &str) -> f32 {
+    picks
+        .iter()
+        .find(|pick| pick.entry == entry)
+        .map_or(0., |pick| {
+            PICK_BONUS * pick.count.min(MAX_PICK_BONUS_COUNT) as f32
+        })
+}
+
+/// Look up the value of an entry from a list of pairs sorted by the entry
+fn find_by_entry<'a>(pairs: &'a [(String, String)], entry: &str) -> Option<&'a str> {
+    pairs
+        .binary_search_by(|(e, _)| e.as_str().cmp(entry))
+        .ok()
+        .map(|index| pairs[index].1.as_str())
+}
+
+/// Insert or replace the value of an entry in a list of pairs sorted by the entry
+fn set_by_entry(pairs: &mut Vec<(String, String)>, entry: &str, value: &str) {
+    match pairs.binary_search_by(|(e, _)| e.as_str().cmp(entry)) {
+        Ok(index) => pairs[index].1 = String::from(value),
+        Err(index) => pairs.insert(index, (String::from(entry), String::from(value))),
+    }
+}
+
+fn remove_by_entry(pairs: &mut Vec<(String, String)>, entry: &str) -> bool {
+    match pairs.binary_search_by(|(e, _)| e.as_str().cmp(entry)) {
+        Ok(index) => {
+            pairs.remove(index);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Write a file by writing a temporary file next to it and renaming it over the file
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    // Replace the target of a symbolic link instead of the link
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let tmp_path = sibling_path(&path, &format!("{TEMP_FILE_SUFFIX}.{}", std::process::id()));
+    let res = (|| -> Result<()> {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    })();
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return res;
+    }
+    // Make the rename itself durable
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+/// Take an exclusive lock on the given file, waiting for other holders to release it.
+/// The lock is released when the returned file is closed.
+fn lock_file(path: &Path) -> Result<std::fs::File> {
+    let file = match std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(err) => bail!("Failed to open {}: {err}", path.to_string_lossy()),
+    };
+    flock(&file, path, libc::LOCK_EX)?;
+    Ok(file)
+}
+
+/// Whether a transaction on the DB file is in progress, e.g. in another process
+pub fn is_locked(path: &Path) -> bool {
+    let Ok(file) = std::fs::File::open(sibling_path(path, LOCK_FILE_SUFFIX)) else {
+        return false;
+    };
+    // SAFETY: the file descriptor is valid while the file is open
+    let res = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    res != 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::WouldBlock
+}
+
+/// Apply an advisory lock operation (e.g. `LOCK_SH` or `LOCK_EX`) on the file at the given path,
+/// waiting for conflicting locks to be released
+pub fn flock(file: &std::fs::File, path: &Path, operation: libc::c_int) -> Result<()> {
+    loop {
+        // SAFETY: the file descriptor is valid while the file is open
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            bail!("Failed to lock {}: {err}", path.to_string_lossy());
+        }
+    }
+}
+
+/// Check the lines of the DB file for problems without loading it.
+/// Returns None when the file does not exist.
+pub fn verify_file(path: &Path) -> Result<Option<Vec<Problem>>> {
+    match crypt::read(path)? {
+        Some(data) => Ok(Some(verify_data(&data))),
+        None => Ok(None),
+    }
+}
+
+/// Repair the problems in the lines of the DB file. Lines that aren't valid text are dropped.
+pub fn repair_file(path: &Path) -> Result<()> {
+    let _lock = lock_file(&sibling_path(path, LOCK_FILE_SUFFIX))?;
+    let Some(data) = crypt::read(path)? else {
+        bail!("DB not found at {}", path.to_string_lossy());
+    };
+    write_atomically(path, &crypt::encrypt(repair_data(&data).into_bytes())?)
+}
+
+/// Lines of the DB data without the line break at the end
+fn data_lines(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    // Splitting empty data would give a single empty line
+    data.split(|&b| b == b'\n')
+        .filter(move |_| !data.is_empty())
+}
+
+/// Line as text, or None when it's not valid UTF-8 or contains NUL bytes
+fn line_text(line: &[u8]) -> Option<&str> {
+    std::str::from_utf8(line)
+        .ok()
+        .filter(|line| !line.contains('\0'))
+}
+
+fn verify_data(data: &[u8]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut in_header = true;
+    let mut prev_entry: Option<&str> = None;
+    for (index, line) in data_lines(data).enumerate() {
+        let line_number = index + 1;
+        let Some(line) = line_text(line) else {
+            problems.push(Problem::InvalidText { line: line_number });
+            continue;
+        };
+        if in_header && line.starts_with(HEADER_PREFIX) {
+            continue;
+        }
+        in_header = false;
+        let entry = line.trim();
+        if entry.is_empty() {
+            problems.push(Problem::EmptyLine { line: line_number });
+            continue;
+        }
+        if entry != line {
+            problems.push(Problem::Whitespace { line: line_number });
+        }
+        match prev_entry {
+            Some(prev) if entry == prev => problems.push(Problem::Duplicate {
+                line: line_number,
+                entry: String::from(entry),
+            }),
+            Some(prev) if entry < prev => problems.push(Problem::Unsorted {
+                line: line_number,
+                entry: String::from(entry),
+            }),
+            _ => {}
+        }
+        prev_entry = Some(entry);
+    }
+    problems
+}
+
+/// DB data with the header kept and the entries trimmed, sorted, and deduplicated.
+/// Lines that aren't valid text are dropped.
+fn repair_data(data: &[u8]) -> String {
+    let mut header = String::new();
+    let mut entries = String::new();
+    for line in data_lines(data).filter_map(line_text) {
+        if entries.is_empty() && line.starts_with(HEADER_PREFIX) {
+            header.push_str(line);
+            header.push('\n');
+        } else {
+            entries.push_str(line);
+            entries.push('\n');
+        }
+    }
+    header + &normalize_data(&entries)
+}
+
+/// Path of the log of project uses next to the DB file
+pub fn history_path(path: &Path) -> PathBuf {
+    sibling_path(path, HISTORY_FILE_SUFFIX)
+}
+
+/// Path of the file next to the DB file that marks when a background refresh was started
+pub fn refresh_marker_path(path: &Path) -> PathBuf {
+    sibling_path(path, REFRESH_FILE_SUFFIX)
+}
+
+/// Contents of the DB file as text. Returns the reason when the contents are not
+/// text, e.g. when the file was truncated mid-character or overwritten with binary data.
+fn decode_data(data: Vec<u8>) -> Result<String, String> {
+    let data = match String::from_utf8(data) {
+        Ok(data) => data,
+        Err(err) => {
+            let offset = err.utf8_error().valid_up_to();
+            return Err(format!("invalid UTF-8 at byte {offset}"));
+        }
+    };
+    if let Some(offset) = data.find('\0') {
+        return Err(format!("unexpected NUL byte at byte {offset}"));
+    }
+    Ok(data)
+}
+
+/// Path of a file next to the given file with the suffix added to the file name
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(".");
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Score the given items in chunks, checking for cancellation between each chunk.
+/// Returns false when the scoring was cancelled.
+fn extend_scored_until<'a, T, F>(
+    results: &mut Vec<(&'a str, f32)>,
+    items: &[T],
+    cancelled: &dyn Fn() -> bool,
+    score: F,
+) -> bool
+where
+    T: Sync,
+    F: Fn(&T) -> Option<(&'a str, f32)> + Sync,
+{
+    for chunk in items.chunks(CANCEL_CHECK_LINES) {
+        if cancelled() {
+            return false;
+        }
+        extend_scored(results, chunk, &score);
+    }
+    true
+}
+
+/// Score the given items, splitting the work across threads for large inputs.
+/// Results are kept in the same order as the items.
+fn extend_scored<'a, T, F>(results: &mut Vec<(&'a str, f32)>, items: &[T], score: F)
+where
+    T: Sync,
+    F: Fn(&T) -> Option<(&'a str, f32)> + Sync,
+{
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    extend_scored_in_threads(results, items, threads, score);
+}
+
+fn extend_scored_in_threads<'a, T, F>(
+    results: &mut Vec<(&'a str, f32)>,
+    items: &[T],
+    threads: usize,
+    score: F,
+) where
+    T: Sync,
+    F: Fn(&T) -> Option<(&'a str, f32)> + Sync,
+{
+    if threads <= 1 || items.len() < PARALLEL_SCORING_MIN_LINES {
+        results.extend(items.iter().filter_map(score));
+        return;
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    let score = &score;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().filter_map(score).collect::<Vec<_>>()))
+            .collect();
+        for handle in handles {
+            results.extend(handle.join().expect("Scoring thread must not panic"));
+        }
+    });
+}
+
+/// Entry for a path relative to the projects directory.
+/// Entries separate the directories with `/` on all platforms, so that the same DB works
+/// on both Windows and Unix-like systems. Returns None for paths that can't be stored as entries.
+pub fn entry_from_path(rel_path: &Path) -> Option<String> {
+    let mut dirs = Vec::new();
+    for component in rel_path.components() {
+        match component {
+            Component::Normal(dir) => dirs.push(dir.to_str().filter(|d| !d.contains('\\'))?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if dirs.is_empty() {
+        return None;
+    }
+    Some(dirs.join("/"))
+}
+
+/// Entry for a project outside of the projects directory, which is stored with its absolute path.
+/// Returns None for paths that can't be stored as entries.
+pub fn out_of_tree_entry(path: &Path) -> Option<String> {
+    let path = path.to_str().filter(|_| path.is_absolute())?;
+    match std::path::MAIN_SEPARATOR {
+        '/' if path.contains('\\') => None,
+        separator => Some(path.replace(separator, "/")),
+    }
+}
+
+/// Whether the entry is a project outside of the projects directory
+pub fn is_out_of_tree(entry: &str) -> bool {
+    Path::new(entry).is_absolute()
+}
+
+/// Filesystem path of an entry. Entries are relative to the projects directory
+/// unless they are outside of it.
+pub fn entry_path(projects_path: &Path, entry: &str) -> PathBuf {
+    if is_out_of_tree(entry) {
+        return PathBuf::from(entry);
+    }
+    let mut path = projects_path.to_path_buf();
+    path.extend(entry.split('/').filter(|dir| !dir.is_empty()));
+    path
+}
+
+/// Convert the `\` separators of entries written on Windows by older versions to `/`
+fn normalize_entry(entry: &str) -> String {
+    entry.replace('\\', "/")
+}
+
+/// Normalize the separators of all entries in the DB data and restore the sort order
+fn normalize_data(data: &str) -> String {
+    let mut entries: Vec<String> = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(normalize_entry)
+        .collect();
+    entries.sort();
+    entries.dedup();
+    let mut data = entries.join("\n");
+    if !data.is_empty() {
+        data.push('\n');
+    }
+    data
+}
+
+fn is_same_or_nested_path(entry: &str, path: &str) -> bool {
+    match entry.strip_prefix(path) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[allow(clippy::single_char_add_str)]
 fn str_sorted_insert(dest: &mut String, source: &str) {
     let mut count: usize = 0;
     for line in dest.split('\n') {
@@ -124,6 +1575,9 @@ fn str_sorted_insert(dest: &mut String, source: &str) {
     if count < dest.len() {
         dest.insert_str(count, source);
         dest.insert_str(count + source.len(), "\n");
+    } else if dest.is_empty() || dest.ends_with('\n') {
+        dest.push_str(source);
+        dest.push('\n');
     } else {
         dest.push('\n');
         dest.push_str(source);
@@ -131,6 +1585,7 @@ fn str_sorted_insert(dest: &mut String, source: &str) {
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec, clippy::useless_conversion)]
 mod tests {
     use super::*;
 
@@ -164,6 +1619,502 @@ mod tests {
         );
     }
 
+    #[test]
+    fn remove_entry() {
+        let mut db = DB::from_entries(
+            ["a.com/x/y", "a.com/x/yy", "b.com/z"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert!(db.remove("a.com/x/y"));
+        assert!(!db.remove("a.com/x/y"));
+        assert_eq!(db.data, "a.com/x/yy\nb.com/z\n");
+    }
+
+    #[test]
+    fn remove_tree_entries() {
+        let mut db = DB::from_entries(
+            ["a.com/x/y", "a.com/x/yy", "a.com/xx/y", "b.com/z"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert_eq!(db.remove_tree("a.com/x/"), 2);
+        assert_eq!(db.data, "a.com/xx/y\nb.com/z\n");
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let built_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1700000000);
+        let header = Header {
+            version: None,
+            built_at: Some(built_at),
+            pinned: vec![String::from("a.com/notes")],
+            recent: vec![(String::from("a.com/x/y"), built_at)],
+            descriptions: vec![(String::from("a.com/x/y"), String::from("x and y"))],
+            remotes: vec![(String::from("a.com/x/y"), String::from("git@a.com:x/y.git"))],
+            picks: vec![Pick {
+                query: String::from("x y"),
+                entry: String::from("a.com/x/y"),
+                count: 2,
+            }],
+            languages: vec![(String::from("a.com/x/y"), String::from("node,rust"))],
+            tags: vec![(String::from("a.com/x/y"), String::from("backend"))],
+            mirrored: vec![String::from("a.com/x/y")],
+        };
+        let mut s = String::new();
+        header.write_lines(&mut s);
+        assert_eq!(
+            s,
+            "#version=1\n#built=1700000000\n#pin=a.com/notes\n#mirrored=a.com/x/y\n#used=1700000000 a.com/x/y\n#desc=a.com/x/y\tx and y\n#remote=a.com/x/y\tgit@a.com:x/y.git\n#lang=a.com/x/y\tnode,rust\n#tags=a.com/x/y\tbackend\n#pick=2 a.com/x/y\tx y\n"
+        );
+
+        let header_len = s.len();
+        s.push_str("a.com/x/y\n");
+        let mut parsed = Header::default();
+        assert_eq!(parsed.parse_lines(&s), header_len);
+        assert_eq!(parsed.version, Some(FORMAT_VERSION));
+        assert_eq!(parsed.built_at, Some(built_at));
+        assert_eq!(parsed.pinned, header.pinned);
+        assert_eq!(parsed.recent, header.recent);
+        assert_eq!(parsed.descriptions, header.descriptions);
+        assert_eq!(parsed.remotes, header.remotes);
+        assert_eq!(parsed.picks, header.picks);
+        assert_eq!(parsed.languages, header.languages);
+        assert_eq!(
+            parsed.languages("a.com/x/y").collect::<Vec<_>>(),
+            ["node", "rust"]
+        );
+        assert_eq!(parsed.languages("a.com/notes").count(), 0);
+        assert_eq!(parsed.tags, header.tags);
+        assert_eq!(parsed.mirrored, header.mirrored);
+        assert_eq!(parsed.tags("a.com/x/y").collect::<Vec<_>>(), ["backend"]);
+    }
+
+    #[test]
+    fn unlisted_mirrored_entries() {
+        let mut db = DB::from_entries(
+            [
+                "a.com/acme/api",
+                "a.com/acme/old",
+                "a.com/acme/private",
+                "a.com/acme/local",
+                "a.com/other/x",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+        for entry in [
+            "a.com/acme/api",
+            "a.com/acme/old",
+            "a.com/acme/private",
+            "a.com/other/x",
+        ] {
+            db.mark_mirrored(entry);
+        }
+        // Local projects that were never listed are kept
+        let listed = vec![
+            String::from("a.com/acme/api"),
+            String::from("a.com/acme/private"),
+        ];
+        assert_eq!(
+            db.unlisted_mirrored("a.com/acme/", &listed),
+            ["a.com/acme/old"]
+        );
+        assert!(db.unlisted_mirrored("a.com/acme/", &[]).len() == 3);
+
+        db.unmark_mirrored("a.com/acme/old");
+        assert!(db.unlisted_mirrored("a.com/acme/", &listed).is_empty());
+        assert!(
+            db.rename("a.com/acme/private", "a.com/acme/secret")
+                .unwrap()
+        );
+        assert!(db.is_mirrored("a.com/acme/secret"));
+        assert!(!db.is_mirrored("a.com/acme/private"));
+    }
+
+    #[test]
+    fn apply_history_records() {
+        let record = |secs: u64, entry: &str, query: Option<&str>| history::Record {
+            used_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            entry: String::from(entry),
+            query: query.map(String::from),
+        };
+        let mut header = Header::default();
+        header.mark_used("a.com/x", SystemTime::UNIX_EPOCH + Duration::from_secs(1));
+        header.apply_history(&[
+            record(3, "a.com/y", Some("api")),
+            record(2, "a.com/z", None),
+            record(4, "a.com/y", Some("api")),
+        ]);
+        let recent: Vec<&str> = header.recent.iter().map(|(e, _)| e.as_str()).collect();
+        assert_eq!(recent, ["a.com/y", "a.com/z", "a.com/x"]);
+        assert_eq!(
+            header.picks,
+            [Pick {
+                query: String::from("api"),
+                entry: String::from("a.com/y"),
+                count: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn decode_corrupt_data() {
+        assert_eq!(
+            decode_data(b"#built=1\na.com/x\n".to_vec()).as_deref(),
+            Ok("#built=1\na.com/x\n")
+        );
+        assert_eq!(
+            decode_data(b"a.com/x\na.com/\xe2\x82".to_vec()),
+            Err(String::from("invalid UTF-8 at byte 14"))
+        );
+        assert_eq!(
+            decode_data(b"a.com/x\n\0\0\0".to_vec()),
+            Err(String::from("unexpected NUL byte at byte 8"))
+        );
+    }
+
+    #[test]
+    fn verify_and_repair_data() {
+        let data =
+            b"#version=1\n#built=1\na.com/x\na.com/z \n\na.com/y\na.com/y\nb.com/\xff\nb.com/o\n";
+        assert_eq!(
+            verify_data(data),
+            vec![
+                Problem::Whitespace { line: 4 },
+                Problem::EmptyLine { line: 5 },
+                Problem::Unsorted {
+                    line: 6,
+                    entry: String::from("a.com/y")
+                },
+                Problem::Duplicate {
+                    line: 7,
+                    entry: String::from("a.com/y")
+                },
+                Problem::InvalidText { line: 8 },
+            ]
+        );
+        let repaired = repair_data(data);
+        assert_eq!(
+            repaired,
+            "#version=1\n#built=1\na.com/x\na.com/y\na.com/z\nb.com/o\n"
+        );
+        assert_eq!(verify_data(repaired.as_bytes()), Vec::new());
+        assert_eq!(verify_data(b""), Vec::new());
+    }
+
+    #[test]
+    fn record_picks() {
+        let mut db = DB::empty();
+        db.header.record_pick("api", "a.com/payments/api");
+        db.header.record_pick("  api ", "a.com/payments/api");
+        db.header.record_pick("web", "a.com/web");
+        db.header.record_pick(" ", "a.com/web");
+        let picks: Vec<(&str, &str, u32)> = db
+            .picks()
+            .iter()
+            .map(|p| (p.query.as_str(), p.entry.as_str(), p.count))
+            .collect();
+        assert_eq!(
+            picks,
+            [("web", "a.com/web", 1), ("api", "a.com/payments/api", 2)]
+        );
+    }
+
+    #[test]
+    fn picks_boost_matches() {
+        let mut db = DB::from_entries(
+            ["a.com/api", "a.com/payments/api"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        fn best<'a>(db: &'a DB, query: &str) -> Option<&'a str> {
+            db.view().find_matches(query).first().map(|(e, _)| *e)
+        }
+        assert_eq!(best(&db, "api"), Some("a.com/api"));
+        db.header.record_pick("api", "a.com/payments/api");
+        assert_eq!(best(&db, "api"), Some("a.com/payments/api"));
+        assert_eq!(best(&db, "api com"), Some("a.com/api"));
+    }
+
+    #[test]
+    fn describe_entry() {
+        let mut db = DB::from_entries(
+            ["a.com/acme/pay-svc", "a.com/acme/web"]
+                .into_iter()
+                .map(String::from),
+        );
+        db.describe("a.com/acme/pay-svc", "payment service")
+            .unwrap();
+        assert_eq!(
+            db.description("a.com/acme/pay-svc"),
+            Some("payment service")
+        );
+        assert!(db.describe("a.com/acme/web", "multi\nline").is_err());
+
+        let matches: Vec<&str> = db.find_matches("acme payment").collect();
+        assert_eq!(matches, ["a.com/acme/pay-svc"]);
+        let mut view = db.view();
+        let matches: Vec<&str> = view
+            .find_matches("acme payment")
+            .iter()
+            .map(|(entry, _)| *entry)
+            .collect();
+        assert_eq!(matches, ["a.com/acme/pay-svc"]);
+
+        assert!(db.remove_description("a.com/acme/pay-svc"));
+        assert!(!db.remove_description("a.com/acme/pay-svc"));
+        assert_eq!(db.find_matches("acme payment").count(), 0);
+    }
+
+    #[test]
+    fn mark_used_orders_by_time() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let mut db = DB::empty();
+        db.mark_used("a.com/x", at(10));
+        db.mark_used("a.com/y", at(20));
+        db.mark_used("a.com/z", at(15));
+        db.mark_used("a.com/x", at(30));
+        let recent: Vec<&str> = db.recent().map(|(entry, _)| entry).collect();
+        assert_eq!(recent, ["a.com/x", "a.com/y", "a.com/z"]);
+    }
+
+    #[test]
+    fn mark_used_keeps_most_recent() {
+        let mut db = DB::empty();
+        for i in 0..(MAX_RECENT_ENTRIES as u64 + 5) {
+            let used_at = SystemTime::UNIX_EPOCH + Duration::from_secs(i);
+            db.mark_used(&format!("a.com/{i}"), used_at);
+        }
+        assert_eq!(db.recent().count(), MAX_RECENT_ENTRIES);
+        assert_eq!(
+            db.recent().next().map(|(entry, _)| entry),
+            Some("a.com/104")
+        );
+    }
+
+    #[test]
+    fn rename_entry() {
+        let mut db = DB::from_entries(["a.com/x", "a.com/y"].iter().map(|s| s.to_string()));
+        db.describe("a.com/x", "x").unwrap();
+        db.set_remote("a.com/x", "https://b.com/x.git").unwrap();
+        db.set_languages("a.com/x", &["rust"]);
+        db.set_tags("a.com/x", &[String::from("backend")]);
+        db.mark_used("a.com/x", SystemTime::UNIX_EPOCH);
+        assert!(db.rename("a.com/x", "b.com/x").unwrap());
+        assert!(!db.rename("a.com/x", "b.com/x").unwrap());
+        assert_eq!(db.data, "a.com/y\nb.com/x\n");
+        assert_eq!(db.description("b.com/x"), Some("x"));
+        assert_eq!(db.remote("b.com/x"), Some("https://b.com/x.git"));
+        assert_eq!(db.remote("a.com/x"), None);
+        assert_eq!(db.languages("b.com/x").collect::<Vec<_>>(), ["rust"]);
+        assert_eq!(db.tags("b.com/x").collect::<Vec<_>>(), ["backend"]);
+        assert_eq!(db.recent().next().map(|(e, _)| e), Some("b.com/x"));
+    }
+
+    #[test]
+    fn pin_entry() {
+        let mut db = DB::empty();
+        db.pin("a.com/notes").unwrap();
+        db.pin("a.com/notes").unwrap();
+        assert_eq!(db.pinned(), ["a.com/notes"]);
+        assert_eq!(db.find_by_prefix("a.com").count(), 1);
+
+        assert!(db.unpin("a.com/notes"));
+        assert!(!db.unpin("a.com/notes"));
+        assert!(db.pinned().is_empty());
+    }
+
+    #[test]
+    fn diff_entries() {
+        let old = DB::from_entries(["a", "b", "d"].iter().map(|s| s.to_string()));
+        let new = DB::from_entries(["b", "c", "d", "e"].iter().map(|s| s.to_string()));
+        let changes: Vec<String> = old
+            .diff(&new)
+            .iter()
+            .map(|change| match change {
+                Change::Added(entry) => format!("+{entry}"),
+                Change::Removed(entry) => format!("-{entry}"),
+            })
+            .collect();
+        assert_eq!(changes, ["-a", "+c", "+e"]);
+    }
+
+    #[test]
+    fn find_containing_entries() {
+        let db = DB::from_entries(
+            ["a.com/x", "a.com/x/sub", "a.com/xy", "/opt/notes"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert_eq!(db.find_containing("a.com/x"), Some("a.com/x"));
+        assert_eq!(db.find_containing("a.com/x/src/lib"), Some("a.com/x"));
+        assert_eq!(db.find_containing("a.com/x/sub/src"), Some("a.com/x/sub"));
+        assert_eq!(db.find_containing("a.com/xy/src"), Some("a.com/xy"));
+        assert_eq!(db.find_containing("/opt/notes/2025"), Some("/opt/notes"));
+        assert_eq!(db.find_containing("a.com"), None);
+        assert_eq!(db.find_containing("/opt"), None);
+    }
+
+    #[test]
+    fn find_by_prefix_folds_diacritics() {
+        let db = DB::from_entries(
+            ["a.com/josé/x", "a.com/jose/y", "a.com/joe/z"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        let found: Vec<&str> = db.find_by_prefix("a.com/jose").collect();
+        assert_eq!(found, ["a.com/jose/y", "a.com/josé/x"]);
+    }
+
+    #[test]
+    fn split_entry_parts() {
+        let parts = |host, org, repo| EntryParts { host, org, repo };
+        assert_eq!(
+            EntryParts::parse("github.com/jpallari/gorg"),
+            parts("github.com", "jpallari", "gorg")
+        );
+        assert_eq!(
+            EntryParts::parse("gitlab.com/group/sub/proj"),
+            parts("gitlab.com", "group/sub", "proj")
+        );
+        assert_eq!(EntryParts::parse("a.com/x"), parts("a.com", "", "x"));
+        assert_eq!(EntryParts::parse("local"), parts("local", "", ""));
+    }
+
+    #[test]
+    fn view_hosts() {
+        let db = DB::from_entries(
+            ["b.com/x", "a.com/y/z", "b.com/w", "a.com/v"]
+                .iter()
+                .map(|s| s.to_string()),
+        );
+        assert_eq!(db.view().hosts(), ["a.com", "b.com"]);
+    }
+
+    #[test]
+    fn view_orgs() {
+        let db = DB::from_entries(
+            [
+                "b.com/x/y",
+                "a.com/g/s/z",
+                "b.com/w",
+                "a.com/g/v",
+                "/home/u/notes",
+            ]
+            .iter()
+            .map(|s| s.to_string()),
+        );
+        assert_eq!(db.view().orgs(), ["a.com/g", "a.com/g/s", "b.com/x"]);
+    }
+
+    #[test]
+    fn convert_entries_and_paths() {
+        assert_eq!(
+            entry_from_path(Path::new("a.com/x/./y")).as_deref(),
+            Some("a.com/x/y")
+        );
+        assert_eq!(entry_from_path(Path::new("")), None);
+        assert_eq!(entry_from_path(Path::new("a.com/../x")), None);
+        assert_eq!(entry_from_path(Path::new("/a.com/x")), None);
+        assert_eq!(entry_from_path(Path::new("a.com/x\\y")), None);
+
+        let path = entry_path(Path::new("/p"), "a.com/x/y");
+        assert_eq!(path, Path::new("/p").join("a.com").join("x").join("y"));
+        assert_eq!(
+            entry_path(Path::new("/p"), "a.com//x/"),
+            Path::new("/p/a.com/x")
+        );
+        assert_eq!(
+            entry_from_path(path.strip_prefix("/p").unwrap()).as_deref(),
+            Some("a.com/x/y")
+        );
+
+        assert_eq!(out_of_tree_entry(Path::new("tmp/x")), None);
+        let entry = out_of_tree_entry(&std::env::temp_dir().join("x")).unwrap();
+        assert!(is_out_of_tree(&entry));
+        assert!(!is_out_of_tree("a.com/x"));
+        assert_eq!(
+            entry_path(Path::new("/p"), &entry),
+            std::env::temp_dir().join("x")
+        );
+    }
+
+    #[test]
+    fn normalize_windows_separators() {
+        let mut header = Header::default();
+        header.parse_lines(
+            "#pin=a.com\\notes\n#remote=a.com\\x\\y\tgit@a.com:x/y.git\n#pick=1 a.com\\x\\y\tx\n",
+        );
+        assert_eq!(header.pinned, ["a.com/notes"]);
+        assert_eq!(header.remote("a.com/x/y"), Some("git@a.com:x/y.git"));
+        assert_eq!(header.picks[0].entry, "a.com/x/y");
+
+        assert_eq!(
+            normalize_data("b.com\\x\na.com/y\nb.com/x\na.com\\x\\z\n"),
+            "a.com/x/z\na.com/y\nb.com/x\n"
+        );
+        assert_eq!(normalize_data(""), "");
+    }
+
+    #[test]
+    fn extend_scored_keeps_order() {
+        let data: Vec<String> = (0..PARALLEL_SCORING_MIN_LINES * 2)
+            .map(|i| i.to_string())
+            .collect();
+        let items: Vec<&str> = data.iter().map(|item| item.as_str()).collect();
+        let mut results = Vec::new();
+        extend_scored_in_threads(&mut results, &items, 3, |item| {
+            item.ends_with('7').then_some((*item, 1.))
+        });
+        let expected: Vec<&str> = items
+            .iter()
+            .copied()
+            .filter(|item| item.ends_with('7'))
+            .collect();
+        let actual: Vec<&str> = results.iter().map(|(item, _)| *item).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn header_format_version() {
+        let path = Path::new("index");
+        let mut old = Header::default();
+        old.parse_lines("#built=1\na.com/x\n");
+        assert_eq!(old.version, None);
+        assert!(old.check_version(path).is_ok());
+
+        let mut newer = Header::default();
+        newer.parse_lines(&format!("#version={}\n", FORMAT_VERSION + 1));
+        assert_eq!(newer.version, Some(FORMAT_VERSION + 1));
+        assert!(newer.check_version(path).is_err());
+
+        let mut unknown = Header::default();
+        unknown.parse_lines("#version=2.1\n");
+        assert!(unknown.check_version(path).is_err());
+    }
+
+    #[test]
+    fn header_missing() {
+        let mut parsed = Header::default();
+        assert_eq!(parsed.parse_lines("a.com/x/y\n#built=1\n"), 0);
+        assert_eq!(parsed.built_at, None);
+    }
+
+    #[test]
+    fn str_sorted_insert_end_with_trailing_newline() {
+        let mut target = String::from("aabb\nbbcc\n");
+        str_sorted_insert(&mut target, "cddd");
+        assert_eq!(target, "aabb\nbbcc\ncddd\n");
+    }
+
+    #[test]
+    fn str_sorted_insert_empty() {
+        let mut target = String::new();
+        str_sorted_insert(&mut target, "aabb");
+        assert_eq!(target, "aabb\n");
+    }
+
     #[test]
     fn str_sorted_insert_dupe() {
         let mut target = String::from(vec!["aabb", "bbcc", "ccdd"].join("\n"));
@@ -173,4 +2124,61 @@ mod tests {
             String::from(vec!["aabb", "bbcc", "ccdd",].join("\n"))
         );
     }
+
+    /// Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gorg-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn transactions_are_exclusive() {
+        let dir = test_dir("transactions");
+        let path = dir.join("index");
+        let mut first = Transaction::begin(&path).unwrap();
+        first.add("a.com/x").unwrap();
+        assert!(is_locked(&path));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let second = Transaction::begin(&path).unwrap();
+                tx.send(second.entries().map(String::from).collect::<Vec<_>>())
+                    .unwrap();
+            });
+            assert!(
+                rx.recv_timeout(Duration::from_millis(200)).is_err(),
+                "second transaction began while the first one was held"
+            );
+            first.commit().unwrap();
+            // The second transaction sees the changes of the first one
+            let entries = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(entries, ["a.com/x"]);
+        });
+        assert!(!is_locked(&path));
+
+        // Only the DB and its lock file are left after committing
+        let mut files: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["index", "index.lock"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_keeps_old_file() {
+        let dir = test_dir("failed-write");
+        let path = dir.join("index");
+        write_atomically(&path, b"a.com/x\n").unwrap();
+        // Temporary file can't be created where a directory is in the way
+        let tmp_path = sibling_path(&path, &format!("{TEMP_FILE_SUFFIX}.{}", std::process::id()));
+        std::fs::create_dir(&tmp_path).unwrap();
+        assert!(write_atomically(&path, b"b.com/y\n").is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"a.com/x\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
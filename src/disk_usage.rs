@@ -0,0 +1,83 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use anyhow::Result;
+
+const GIT_DIR_NAME: &str = ".git";
+const BLOCK_SIZE: u64 = 512;
+const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+
+/// Disk space used by a project
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// Bytes used by the Git directory
+    pub git: u64,
+
+    /// Bytes used by everything else in the project directory
+    pub work_tree: u64,
+}
+
+impl DiskUsage {
+    pub fn total(&self) -> u64 {
+        self.git + self.work_tree
+    }
+}
+
+/// Measure the disk space used by the project in the given directory.
+/// Symbolic links are not followed.
+pub fn measure(project_path: &Path) -> Result<DiskUsage> {
+    let mut usage = DiskUsage::default();
+    for entry in std::fs::read_dir(project_path)? {
+        let entry = entry?;
+        let size = dir_entry_size(&entry)?;
+        if entry.file_name() == GIT_DIR_NAME {
+            usage.git += size;
+        } else {
+            usage.work_tree += size;
+        }
+    }
+    Ok(usage)
+}
+
+/// Allocated size of a directory entry, including the contents of directories
+fn dir_entry_size(entry: &std::fs::DirEntry) -> Result<u64> {
+    let metadata = entry.metadata()?;
+    let mut size = metadata.blocks() * BLOCK_SIZE;
+    if metadata.is_dir() {
+        for child in std::fs::read_dir(entry.path())? {
+            size += dir_entry_size(&child?)?;
+        }
+    }
+    Ok(size)
+}
+
+/// Human-readable size using binary units, e.g. `1.5M`
+pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}{}", UNITS[0])
+    } else if size < 10.0 {
+        format!("{size:.1}{}", UNITS[unit])
+    } else {
+        format!("{size:.0}{}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(20 * 1024 * 1024), "20M");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0G");
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::auth::TokenStore;
+use crate::config::{CloneProtocol, Forge, Mirror};
+
+const PER_PAGE: usize = 100;
+const USER_AGENT: &str = concat!("gorg/", env!("CARGO_PKG_VERSION"));
+
+/// Repository listed by a forge API
+pub struct RemoteRepo {
+    pub https_url: String,
+    pub ssh_url: String,
+}
+
+impl RemoteRepo {
+    pub fn url(&self, protocol: CloneProtocol) -> &str {
+        match protocol {
+            CloneProtocol::Https => &self.https_url,
+            CloneProtocol::Ssh => &self.ssh_url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubRepo {
+    clone_url: String,
+    ssh_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabProject {
+    http_url_to_repo: String,
+    ssh_url_to_repo: String,
+}
+
+/// Client for forge APIs. Tokens stored for the hosts are attached to the requests.
+pub struct ForgeClient<'a> {
+    agent: ureq::Agent,
+    tokens: &'a TokenStore,
+}
+
+impl<'a> ForgeClient<'a> {
+    pub fn new(tokens: &'a TokenStore) -> Self {
+        Self {
+            agent: ureq::Agent::new_with_defaults(),
+            tokens,
+        }
+    }
+
+    /// List all repositories of the organization, user, or group of the given mirror
+    pub fn list_repos(&self, mirror: &Mirror) -> Result<Vec<RemoteRepo>> {
+        let Some(forge) = mirror.forge.or_else(|| detect_forge(&mirror.host)) else {
+            bail!(
+                "Cannot detect the forge type for {}. Set `forge` for the mirror.",
+                mirror.host
+            );
+        };
+        let token = self.tokens.get(&mirror.host)?;
+        if token.is_none() {
+            log::debug!(
+                "No token found for {}. Only public repositories are listed.",
+                mirror.host
+            );
+        }
+        let api_url = match &mirror.api_url {
+            Some(api_url) => api_url.trim_end_matches('/').to_string(),
+            None => default_api_url(forge, &mirror.host),
+        };
+
+        match forge {
+            Forge::GitHub => {
+                let auth = token.map(|token| ("Authorization", format!("Bearer {token}")));
+                // Organizations and users are listed from different endpoints
+                let org_url = format!("{api_url}/orgs/{}/repos", mirror.org);
+                let repos: Vec<GitHubRepo> = match self.get_pages(&org_url, "", &auth) {
+                    Err(ureq::Error::StatusCode(404)) => {
+                        let user_url = format!("{api_url}/users/{}/repos", mirror.org);
+                        self.get_pages(&user_url, "", &auth)?
+                    }
+                    res => res?,
+                };
+                Ok(repos
+                    .into_iter()
+                    .map(|repo| RemoteRepo {
+                        https_url: repo.clone_url,
+                        ssh_url: repo.ssh_url,
+                    })
+                    .collect())
+            }
+            Forge::GitLab => {
+                let auth = token.map(|token| ("PRIVATE-TOKEN", token));
+                let url = format!(
+                    "{api_url}/groups/{}/projects",
+                    mirror.org.replace('/', "%2F")
+                );
+                let projects: Vec<GitLabProject> =
+                    self.get_pages(&url, "&include_subgroups=true", &auth)?;
+                Ok(projects
+                    .into_iter()
+                    .map(|project| RemoteRepo {
+                        https_url: project.http_url_to_repo,
+                        ssh_url: project.ssh_url_to_repo,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Get all pages of a paginated list
+    fn get_pages<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &str,
+        auth: &Option<(&str, String)>,
+    ) -> Result<Vec<T>, ureq::Error> {
+        let mut items = Vec::new();
+        for page in 1.. {
+            let page_url = format!("{url}?per_page={PER_PAGE}&page={page}{query}");
+            log::debug!("GET {page_url}");
+            let mut request = self
+                .agent
+                .get(&page_url)
+                .header("User-Agent", USER_AGENT)
+                .header("Accept", "application/json");
+            if let Some((name, value)) = auth {
+                request = request.header(*name, value);
+            }
+            let page_items: Vec<T> = request.call()?.body_mut().read_json()?;
+            let last_page = page_items.len() < PER_PAGE;
+            items.extend(page_items);
+            if last_page {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+fn detect_forge(host: &str) -> Option<Forge> {
+    let host = host.to_lowercase();
+    if host == "github.com" || host.starts_with("github.") {
+        Some(Forge::GitHub)
+    } else if host == "gitlab.com" || host.starts_with("gitlab.") {
+        Some(Forge::GitLab)
+    } else {
+        None
+    }
+}
+
+fn default_api_url(forge: Forge, host: &str) -> String {
+    match forge {
+        Forge::GitHub if host == "github.com" => String::from("https://api.github.com"),
+        Forge::GitHub => format!("https://{host}/api/v3"),
+        Forge::GitLab => format!("https://{host}/api/v4"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_forge_from_host() {
+        assert!(detect_forge("github.com") == Some(Forge::GitHub));
+        assert!(detect_forge("github.example.com") == Some(Forge::GitHub));
+        assert!(detect_forge("gitlab.com") == Some(Forge::GitLab));
+        assert!(detect_forge("git.example.com").is_none());
+    }
+
+    #[test]
+    fn api_urls() {
+        assert_eq!(
+            default_api_url(Forge::GitHub, "github.com"),
+            "https://api.github.com"
+        );
+        assert_eq!(
+            default_api_url(Forge::GitHub, "github.example.com"),
+            "https://github.example.com/api/v3"
+        );
+        assert_eq!(
+            default_api_url(Forge::GitLab, "gitlab.com"),
+            "https://gitlab.com/api/v4"
+        );
+    }
+}
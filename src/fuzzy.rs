@@ -1,6 +1,34 @@
+use std::fmt;
+
 use crate::text;
 
-pub fn calc_score(matcher: &str, target: &str) -> f32 {
+/// Weight of description matches relative to path matches
+const DESCRIPTION_WEIGHT: f32 = 0.5;
+
+/// Default bonus for query parts that are equal to a whole target segment.
+/// It's large enough for an exact segment match to beat several partial matches.
+pub const DEFAULT_EXACT_SEGMENT_BONUS: f32 = 2.;
+
+/// Weight of initials matches relative to the best possible initials match
+const INITIALS_WEIGHT: f32 = 0.5;
+
+/// Score how well the target matches the fuzzy query.
+/// The query and the target are folded with `text::fold` before matching.
+/// Query parts that don't match the target may also match the description with a lower score.
+pub fn calc_score(matcher: &str, target: &str, description: Option<&str>) -> f32 {
+    calc_score_with_bonus(matcher, target, description, DEFAULT_EXACT_SEGMENT_BONUS)
+}
+
+/// Score like `calc_score` using the given bonus for query parts that are equal to a whole segment
+pub fn calc_score_with_bonus(
+    matcher: &str,
+    target: &str,
+    description: Option<&str>,
+    exact_segment_bonus: f32,
+) -> f32 {
+    let matcher = text::fold(matcher);
+    let target = text::fold(target);
+    let description = description.map(text::fold);
     let mut score: f32 = 0.;
 
     for (pi, p) in matcher
@@ -8,28 +36,15 @@ pub fn calc_score(matcher: &str, target: &str) -> f32 {
         .filter(|p| !p.trim().is_empty())
         .enumerate()
     {
-        let mut part_score: f32 = 0.;
-        for (ti, t) in target
-            .split(text::is_punctuation)
-            .filter(|p| !p.is_empty())
-            .enumerate()
+        let mut part_score = calc_part_score(pi, p, &target, exact_segment_bonus);
+        if part_score == 0. {
+            part_score = calc_initials_score(p, &target);
+        }
+        if part_score == 0.
+            && let Some(description) = &description
         {
-            part_score += t
-                .match_indices(p)
-                .next()
-                .map(|(i, _)| {
-                    let distance = match ti.max(pi) - ti.min(pi) {
-                        0 => 1.,
-                        1 => 0.9,
-                        2 => 0.8,
-                        3 => 0.7,
-                        _ => 0.6,
-                    };
-                    let filled = p.len() as f32 / t.len() as f32;
-                    let index = 1. - (i as f32 / t.len() as f32);
-                    filled * 2. + index * 2. * distance
-                })
-                .unwrap_or(0.)
+            part_score =
+                calc_part_score(pi, p, description, exact_segment_bonus) * DESCRIPTION_WEIGHT;
         }
         if part_score == 0. {
             // If the part does not match any of the target parts,
@@ -41,41 +56,393 @@ pub fn calc_score(matcher: &str, target: &str) -> f32 {
     score
 }
 
+/// What a query part matched in `explain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartMatch {
+    /// Segments of the target contain the part
+    Segments,
+    /// The part matched the initials of the target segments
+    Initials,
+    /// Segments of the description contain the part
+    Description,
+    /// The part didn't match, so the whole query doesn't match
+    None,
+}
+
+/// Score breakdown of a query part
+#[derive(Debug)]
+pub struct PartExplanation {
+    pub part: String,
+    pub matched: PartMatch,
+    /// Matched segments and their scores before weighting
+    pub segments: Vec<(String, f32)>,
+    pub score: f32,
+}
+
+/// Score breakdown of a target for a fuzzy query
+#[derive(Debug)]
+pub struct Explanation {
+    pub parts: Vec<PartExplanation>,
+    pub score: f32,
+}
+
+impl Explanation {
+    /// Score of each part on a single line, e.g. `5.80 = go 3.80 + x 2.00`
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = self
+            .parts
+            .iter()
+            .map(|part| format!("{} {:.2}", part.part, part.score))
+            .collect();
+        format!("{:.2} = {}", self.score, parts.join(" + "))
+    }
+}
+
+impl fmt::Display for PartExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let segments = || {
+            let segments: Vec<String> = self
+                .segments
+                .iter()
+                .map(|(segment, score)| format!("{segment} {score:.3}"))
+                .collect();
+            segments.join(" + ")
+        };
+        match self.matched {
+            PartMatch::Segments => write!(
+                f,
+                "{:?} matched segments: {} = {:.3}",
+                self.part,
+                segments(),
+                self.score
+            ),
+            PartMatch::Initials => {
+                write!(f, "{:?} matched initials = {:.3}", self.part, self.score)
+            }
+            PartMatch::Description => write!(
+                f,
+                "{:?} matched description: ({}) x {DESCRIPTION_WEIGHT} = {:.3}",
+                self.part,
+                segments(),
+                self.score
+            ),
+            PartMatch::None => write!(f, "{:?} did not match", self.part),
+        }
+    }
+}
+
+/// Break down the score from `calc_score_with_bonus` by query part and target segment
+pub fn explain(
+    matcher: &str,
+    target: &str,
+    description: Option<&str>,
+    exact_segment_bonus: f32,
+) -> Explanation {
+    let matcher = text::fold(matcher);
+    let target = text::fold(target);
+    let description = description.map(text::fold);
+    let mut parts = Vec::new();
+    for (pi, p) in matcher
+        .split(text::is_punctuation)
+        .filter(|p| !p.trim().is_empty())
+        .enumerate()
+    {
+        let collect = |target: &str| -> Vec<(String, f32)> {
+            segment_scores(pi, p, target, exact_segment_bonus)
+                .map(|(segment, score)| (String::from(segment), score))
+                .collect()
+        };
+        let mut segments = collect(&target);
+        let mut score: f32 = segments.iter().map(|(_, score)| score).sum();
+        let mut matched = PartMatch::Segments;
+        if score == 0. {
+            score = calc_initials_score(p, &target);
+            matched = PartMatch::Initials;
+        }
+        if score == 0.
+            && let Some(description) = &description
+        {
+            segments = collect(description);
+            score = segments.iter().map(|(_, score)| score).sum::<f32>() * DESCRIPTION_WEIGHT;
+            matched = PartMatch::Description;
+        }
+        if score == 0. {
+            matched = PartMatch::None;
+        }
+        parts.push(PartExplanation {
+            part: String::from(p),
+            matched,
+            segments,
+            score,
+        });
+    }
+    let score = if parts.iter().any(|part| part.matched == PartMatch::None) {
+        0.
+    } else {
+        parts.iter().map(|part| part.score).sum()
+    };
+    Explanation { parts, score }
+}
+
+fn calc_part_score(pi: usize, p: &str, target: &str, exact_segment_bonus: f32) -> f32 {
+    segment_scores(pi, p, target, exact_segment_bonus)
+        .map(|(_, score)| score)
+        .sum()
+}
+
+/// Scores of the target segments that contain the query part
+fn segment_scores<'a>(
+    pi: usize,
+    p: &'a str,
+    target: &'a str,
+    exact_segment_bonus: f32,
+) -> impl Iterator<Item = (&'a str, f32)> {
+    target
+        .split(text::is_punctuation)
+        .filter(|p| !p.is_empty())
+        .enumerate()
+        .filter_map(move |(ti, t)| {
+            let (i, _) = t.match_indices(p).next()?;
+            let distance = match ti.max(pi) - ti.min(pi) {
+                0 => 1.,
+                1 => 0.9,
+                2 => 0.8,
+                3 => 0.7,
+                _ => 0.6,
+            };
+            let filled = p.len() as f32 / t.len() as f32;
+            let index = 1. - (i as f32 / t.len() as f32);
+            let bonus = if p == t { exact_segment_bonus } else { 0. };
+            Some((t, filled * 2. + index * 2. * distance + bonus))
+        })
+}
+
+/// Score the query part as the initials of the target segments,
+/// e.g. `gg` for `github.com/jpallari/gorg`.
+/// The first character must start a segment. The characters after it may also continue
+/// the same segment, e.g. `tf` for `terraform-modules`, but once a character starts a later
+/// segment, the rest must start segments too. The more of them start a segment, the higher the score.
+fn calc_initials_score(p: &str, target: &str) -> f32 {
+    let mut query = p.chars();
+    let Some(first) = query.next() else {
+        return 0.;
+    };
+    let query_len = p.chars().count();
+
+    let mut best: f32 = 0.;
+    for (start, _, _, _) in
+        segment_chars(target, 0, true).filter(|(_, ch, is_initial, _)| *is_initial && *ch == first)
+    {
+        // Query characters never match punctuation, so the previous character never is one
+        let mut pos = start + first.len_utf8();
+        let mut initials = 1;
+        let mut in_first_segment = true;
+        let mut matched = true;
+        for q in query.clone() {
+            let next_initial = segment_chars(target, pos, false)
+                .find(|(_, ch, is_initial, _)| *is_initial && *ch == q);
+            let next_in_segment = || {
+                segment_chars(target, pos, false)
+                    .take_while(|(_, _, _, is_punctuation)| !is_punctuation)
+                    .find(|(_, ch, _, _)| *ch == q)
+            };
+            match (next_initial, in_first_segment) {
+                (Some((i, _, _, _)), _) => {
+                    initials += 1;
+                    in_first_segment = false;
+                    pos = i + q.len_utf8();
+                }
+                (None, true) if let Some((i, _, _, _)) = next_in_segment() => {
+                    pos = i + q.len_utf8()
+                }
+                _ => {
+                    matched = false;
+                    break;
+                }
+            }
+        }
+        if matched {
+            let score = INITIALS_WEIGHT * (1. + initials as f32 / query_len as f32);
+            best = best.max(score);
+        }
+    }
+    best
+}
+
+/// Characters of the target from the given byte offset with their offsets and flags for
+/// whether each starts a segment and whether it's punctuation
+fn segment_chars(
+    target: &str,
+    start: usize,
+    prev_is_punctuation: bool,
+) -> impl Iterator<Item = (usize, char, bool, bool)> + '_ {
+    target[start..]
+        .char_indices()
+        .scan(prev_is_punctuation, move |prev_is_punctuation, (i, ch)| {
+            let is_punctuation = text::is_punctuation(ch);
+            let is_initial = *prev_is_punctuation && !is_punctuation;
+            *prev_is_punctuation = is_punctuation;
+            Some((start + i, ch, is_initial, is_punctuation))
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn score_no_match() {
-        let score = calc_score("foo", "github.com/jpallari/gorg");
+        let score = calc_score("foo", "github.com/jpallari/gorg", None);
         assert_eq!(score, 0.);
     }
 
     #[test]
     fn score_partial_match() {
-        let score = calc_score("jp foo", "github.com/jpallari/gorg");
+        let score = calc_score("jp foo", "github.com/jpallari/gorg", None);
         assert_eq!(score, 0.);
     }
 
     #[test]
     fn score_full_match_1() {
-        let score = calc_score("g jp go", "github.com/jpallari/gorg");
+        let score = calc_score("g jp go", "github.com/jpallari/gorg", None);
         assert!(score > 0., "{score} > 0");
     }
 
     #[test]
     fn score_full_match_2() {
-        let score = calc_score("gi jp", "github.com/jpallari/gorg");
+        let score = calc_score("gi jp", "github.com/jpallari/gorg", None);
+        assert!(score > 0., "{score} > 0");
+    }
+
+    #[test]
+    fn score_non_ascii_separators() {
+        let score = calc_score("日本 メモ", "example.com/日本\u{3002}メモ", None);
+        assert!(score > 0., "{score} > 0");
+        let score = calc_score("日本メモ", "example.com/日本\u{3002}メモ", None);
+        assert_eq!(score, 0.);
+    }
+
+    #[test]
+    fn score_folds_diacritics() {
+        let score = calc_score("jose", "github.com/acme/josé-utils", None);
+        assert!(score > 0., "{score} > 0");
+        let score = calc_score("josé", "github.com/acme/jose-utils", None);
+        assert!(score > 0., "{score} > 0");
+        let score = calc_score("utils", "github.com/acme/svc", Some("José's utils"));
+        assert!(score > 0., "{score} > 0");
+    }
+
+    #[test]
+    fn score_description_match() {
+        let target = "github.com/acme/pay-svc";
+        let description = Some("payment service");
+        assert_eq!(calc_score("payment", target, None), 0.);
+        let score = calc_score("acme payment", target, description);
+        assert!(score > 0., "{score} > 0");
+        let score = calc_score("acme refund", target, description);
+        assert_eq!(score, 0.);
+    }
+
+    #[test]
+    fn score_description_lower_than_path() {
+        let path_score = calc_score("pay", "github.com/acme/pay", None);
+        let description_score = calc_score("pay", "github.com/acme/svc", Some("pay"));
+        assert!(
+            path_score > description_score,
+            "{path_score} > {description_score}"
+        );
+    }
+
+    #[test]
+    fn score_initials_match() {
+        let score = calc_score("tf", "github.com/acme/terraform-modules", None);
+        assert!(score > 0., "{score} > 0");
+        let score = calc_score("gg", "github.com/jpallari/gorg", None);
         assert!(score > 0., "{score} > 0");
+        let score = calc_score("og", "github.com/jpallari/gorg", None);
+        assert_eq!(score, 0.);
+        let score = calc_score("gjor", "github.com/jpallari/gorg", None);
+        assert_eq!(score, 0.);
+    }
+
+    #[test]
+    fn score_initials_lower_than_substring() {
+        let initials_score = calc_score("tm", "github.com/acme/terraform-modules", None);
+        let partial_initials_score = calc_score("tf", "github.com/acme/terraform-modules", None);
+        let substring_score = calc_score("tm", "github.com/acme/html", None);
+        assert!(
+            initials_score > partial_initials_score,
+            "{initials_score} > {partial_initials_score}"
+        );
+        assert!(
+            substring_score > initials_score,
+            "{substring_score} > {initials_score}"
+        );
+    }
+
+    #[test]
+    fn score_exact_segment_bonus() {
+        let exact = "github.com/jpallari/go";
+        let partial = "github.com/golang/golang-tools";
+        let exact_score = calc_score("go", exact, None);
+        let partial_score = calc_score("go", partial, None);
+        assert!(
+            exact_score > partial_score,
+            "{exact_score} > {partial_score}"
+        );
+
+        // Without the bonus, several partial matches add up to more than a single exact match
+        let exact_score = calc_score_with_bonus("go", exact, None, 0.);
+        let partial_score = calc_score_with_bonus("go", partial, None, 0.);
+        assert!(
+            partial_score > exact_score,
+            "{partial_score} > {exact_score}"
+        );
+    }
+
+    #[test]
+    fn explain_matches_score() {
+        let cases = [
+            ("g jp go", "github.com/jpallari/gorg", None),
+            ("tf", "github.com/acme/terraform-modules", None),
+            (
+                "acme payment",
+                "github.com/acme/pay-svc",
+                Some("payment service"),
+            ),
+            ("jp foo", "github.com/jpallari/gorg", None),
+        ];
+        for (matcher, target, description) in cases {
+            let explanation = explain(matcher, target, description, DEFAULT_EXACT_SEGMENT_BONUS);
+            let score = calc_score(matcher, target, description);
+            assert_eq!(explanation.score, score, "{matcher} {target}");
+        }
+    }
+
+    #[test]
+    fn explain_parts() {
+        let explanation = explain("go pay", "github.com/golang/go", Some("payments"), 2.);
+        let matched: Vec<PartMatch> = explanation.parts.iter().map(|p| p.matched).collect();
+        assert_eq!(matched, [PartMatch::Segments, PartMatch::Description]);
+        let segments: Vec<&str> = explanation.parts[0]
+            .segments
+            .iter()
+            .map(|(segment, _)| segment.as_str())
+            .collect();
+        assert_eq!(segments, ["golang", "go"]);
+        assert_eq!(
+            explanation.parts[1].to_string(),
+            "\"pay\" matched description: (payments 2.550) x 0.5 = 1.275"
+        );
     }
 
     #[test]
     fn score_comparative() {
         let matcher = "go";
-        let score1 = calc_score(matcher, "github.com/golang/go");
-        let score2 = calc_score(matcher, "github.com/jpallari/go");
-        let score3 = calc_score(matcher, "github.com/jpallari/gorg");
-        let score4 = calc_score(matcher, "github.com/jpallari/hugo");
+        let score1 = calc_score(matcher, "github.com/golang/go", None);
+        let score2 = calc_score(matcher, "github.com/jpallari/go", None);
+        let score3 = calc_score(matcher, "github.com/jpallari/gorg", None);
+        let score4 = calc_score(matcher, "github.com/jpallari/hugo", None);
         assert!(score1 > score3, "{score1} > {score3}");
         assert!(score2 > score3, "{score2} > {score3}");
         assert!(score3 > score4, "{score3} > {score4}");
@@ -1,22 +1,103 @@
-use std::{ffi::OsStr, path::Path, process::Command};
+use std::{
+    ffi::OsStr,
+    io::{IsTerminal, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
 
 use anyhow::{Result, bail};
 
+use crate::git_url::UrlRewrite;
+
+const REDIRECT_WARNING: &str = "warning: redirecting to ";
+/// Suffix of the `url.<base>.insteadOf` keys, which Git prints in lowercase
+const INSTEAD_OF_SUFFIX: &str = ".insteadof";
+/// Errors from Git and SSH when they would have needed to prompt for credentials or confirmations
+const INPUT_NEEDED_ERRORS: [&str; 5] = [
+    "terminal prompts disabled",
+    "could not read Username",
+    "could not read Password",
+    "Permission denied (publickey",
+    "Host key verification failed",
+];
+
 pub struct GitCmd {
     git_command: String,
+    no_input: bool,
+    /// SSH command with batch mode enabled, looked up on first use
+    batch_ssh_command: OnceLock<String>,
 }
 
 impl GitCmd {
-    pub fn new(git_command: String) -> Self {
-        Self { git_command }
+    /// When `no_input` is set, Git commands fail instead of prompting for input (e.g. credentials).
+    /// Otherwise, prompts are shown on the terminal as usual.
+    pub fn new(git_command: String, no_input: bool) -> Self {
+        Self {
+            git_command,
+            no_input,
+            batch_ssh_command: OnceLock::new(),
+        }
     }
 
-    pub fn init<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
-        let status = Command::new(&self.git_command)
-            .args(["init"])
-            .current_dir(&dir)
-            .spawn()?
-            .wait()?;
+    /// Same Git command that fails instead of prompting for input
+    pub fn without_input(&self) -> Self {
+        Self {
+            git_command: self.git_command.clone(),
+            no_input: true,
+            batch_ssh_command: OnceLock::new(),
+        }
+    }
+
+    /// Whether Git commands may prompt for input
+    pub fn is_interactive(&self) -> bool {
+        !self.no_input
+    }
+
+    /// Git command that inherits the environment, including variables such as
+    /// `GIT_SSH_COMMAND`, `SSH_AUTH_SOCK`, and `GIT_ASKPASS`.
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.git_command);
+        if self.no_input {
+            cmd.stdin(Stdio::null())
+                .env("GIT_TERMINAL_PROMPT", "0")
+                .env("GCM_INTERACTIVE", "never");
+            // SSH asks for passphrases and host key confirmations on the terminal
+            // unless batch mode is enabled. User's own SSH command from the environment
+            // is left as is, and the one from the Git configuration gets batch mode added.
+            if std::env::var_os("GIT_SSH_COMMAND").is_none()
+                && std::env::var_os("GIT_SSH").is_none()
+            {
+                let ssh_command = self
+                    .batch_ssh_command
+                    .get_or_init(|| with_batch_mode(self.configured_ssh_command().as_deref()));
+                cmd.env("GIT_SSH_COMMAND", ssh_command);
+            }
+        }
+        cmd
+    }
+
+    /// SSH command set with `core.sshCommand` in the user's or system's Git configuration
+    fn configured_ssh_command(&self) -> Option<String> {
+        let output = Command::new(&self.git_command)
+            .args(["config", "--get", "core.sshCommand"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        let ssh_command = String::from_utf8(output.stdout).ok()?;
+        let ssh_command = ssh_command.trim();
+        (output.status.success() && !ssh_command.is_empty()).then(|| String::from(ssh_command))
+    }
+
+    /// Initialize a repository, optionally using the given Git template directory
+    pub fn init<P: AsRef<Path>>(&self, dir: P, template: Option<&Path>) -> Result<()> {
+        let mut cmd = self.command();
+        cmd.arg("init");
+        if let Some(template) = template {
+            cmd.arg("--template").arg(template);
+        }
+        let status = cmd.current_dir(&dir).spawn()?.wait()?;
         if !status.success() {
             bail!(
                 "Failed to init Git in {}: exit code = {:?}",
@@ -27,16 +108,33 @@ impl GitCmd {
         Ok(())
     }
 
-    pub fn clone_repo<P: AsRef<OsStr>>(&self, repo_url: &str, dir: P) -> Result<()> {
-        let status = Command::new(&self.git_command)
-            .args([
-                OsStr::new("clone"),
-                OsStr::new("--"),
-                OsStr::new(repo_url),
-                &dir.as_ref(),
-            ])
-            .spawn()?
-            .wait()?;
+    /// Clone a repository. Returns the new URL when the remote redirected the clone elsewhere.
+    pub fn clone_repo<P: AsRef<OsStr>>(&self, repo_url: &str, dir: P) -> Result<Option<String>> {
+        let mut cmd = self.command();
+        cmd.arg("clone");
+        // Git only shows progress on a terminal, and stderr is read here to detect redirects
+        let progress = std::io::stderr().is_terminal();
+        if progress {
+            cmd.arg("--progress");
+        }
+        let mut child = cmd
+            .args([OsStr::new("--"), OsStr::new(repo_url), dir.as_ref()])
+            .env("LC_ALL", "C")
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut child_stderr = child.stderr.take().expect("Git stderr must be piped");
+        let mut stderr = std::io::stderr();
+        let mut output = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = child_stderr.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            stderr.write_all(&buf[..n])?;
+            output.extend_from_slice(&buf[..n]);
+        }
+        let status = child.wait()?;
         if !status.success() {
             bail!(
                 "Failed to clone {repo_url} to {}: exit code = {:?}",
@@ -44,25 +142,283 @@ impl GitCmd {
                 status.code(),
             );
         }
+        Ok(redirect_url(&String::from_utf8_lossy(&output)))
+    }
+
+    /// Write all references and the objects they need into a bundle file
+    pub fn bundle_create<P: AsRef<Path>>(&self, bundle_path: &Path, dir: P) -> Result<()> {
+        let output = self
+            .command()
+            .args([
+                OsStr::new("bundle"),
+                OsStr::new("create"),
+                OsStr::new("--quiet"),
+            ])
+            .arg(bundle_path)
+            .arg("--all")
+            .current_dir(&dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to create bundle of {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
         Ok(())
     }
 
-    pub fn remote_list<P: AsRef<Path>>(&self, dir: P) -> Result<String> {
-        let output = Command::new(&self.git_command)
-            .args(["remote"])
+    /// Clone a repository from a bundle file. All references in the bundle are restored
+    /// as they were in the bundled repository instead of as remote-tracking branches.
+    /// The bundle is added as the given remote, so that it can be replaced with the original one.
+    pub fn clone_bundle(&self, bundle_path: &Path, dir: &Path, remote_name: &str) -> Result<()> {
+        let output = self
+            .command()
+            .args(["clone", "--quiet", "--origin", remote_name, "--"])
+            .arg(bundle_path)
+            .arg(dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to clone {} to {}: {}",
+                bundle_path.to_string_lossy(),
+                dir.to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let output = self
+            .command()
+            .args(["fetch", "--quiet", "--prune", "--update-head-ok"])
+            .arg(bundle_path)
+            .arg("+refs/*:refs/*")
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to fetch references from {} to {}: {}",
+                bundle_path.to_string_lossy(),
+                dir.to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// `insteadOf` URL rewrites from the Git configuration
+    pub fn url_rewrites(&self) -> Result<Vec<UrlRewrite>> {
+        let output = self
+            .command()
+            .args(["config", "--null", "--get-regexp", r"^url\..*\.insteadof$"])
+            .output()?;
+        match output.status.code() {
+            Some(0) => Ok(parse_url_rewrites(&String::from_utf8_lossy(&output.stdout))),
+            // No matching keys
+            Some(1) => Ok(Vec::new()),
+            _ => bail!(
+                "Failed to read URL rewrites from the Git configuration: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        }
+    }
+
+    /// Machine-readable status of the working tree including the branch information
+    pub fn status<P: AsRef<Path>>(&self, dir: P) -> Result<String> {
+        // Optional locks are skipped to avoid refreshing the index while other Git commands run.
+        let output = self
+            .command()
+            .args([
+                "--no-optional-locks",
+                "status",
+                "--porcelain=v2",
+                "--branch",
+            ])
+            .current_dir(&dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to read Git status in {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let status = String::from_utf8(output.stdout)?;
+        Ok(status)
+    }
+
+    /// Fetch from the default remote. Returns the new URL when the remote redirected the fetch elsewhere.
+    pub fn fetch<P: AsRef<Path>>(&self, dir: P) -> Result<Option<String>> {
+        // Redirect warnings are not shown in quiet mode
+        let output = self
+            .command()
+            .arg("fetch")
+            .env("LC_ALL", "C")
+            .current_dir(&dir)
+            .output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            bail!(
+                "Failed to fetch in {}: {}",
+                dir.as_ref().to_string_lossy(),
+                stderr.trim()
+            );
+        }
+        Ok(redirect_url(&stderr))
+    }
+
+    /// Name of the checked out branch. Returns None when HEAD is detached.
+    pub fn current_branch<P: AsRef<Path>>(&self, dir: P) -> Result<Option<String>> {
+        let output = self
+            .command()
+            .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+            .current_dir(&dir)
+            .output()?;
+        match output.status.code() {
+            Some(0) => Ok(Some(String::from_utf8(output.stdout)?.trim().to_string())),
+            Some(1) => Ok(None),
+            code => bail!(
+                "Failed to read current branch in {}: exit code = {code:?}",
+                dir.as_ref().to_string_lossy(),
+            ),
+        }
+    }
+
+    /// Default branch of the given remote based on its HEAD reference.
+    /// Returns None when the remote HEAD is not known.
+    pub fn default_branch<P: AsRef<Path>>(
+        &self,
+        remote_name: &str,
+        dir: P,
+    ) -> Result<Option<String>> {
+        let output = self
+            .command()
+            .args([
+                "symbolic-ref",
+                "--quiet",
+                "--short",
+                &format!("refs/remotes/{remote_name}/HEAD"),
+            ])
+            .current_dir(&dir)
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let head = String::from_utf8(output.stdout)?;
+        let head = head.trim();
+        let branch = head
+            .strip_prefix(remote_name)
+            .and_then(|branch| branch.strip_prefix('/'))
+            .unwrap_or(head);
+        Ok(Some(String::from(branch)))
+    }
+
+    /// Check whether the given reference (e.g. `refs/heads/main`) exists
+    pub fn has_ref<P: AsRef<Path>>(&self, refname: &str, dir: P) -> Result<bool> {
+        let status = self
+            .command()
+            .args(["rev-parse", "--verify", "--quiet", refname])
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .status()?;
+        Ok(status.success())
+    }
+
+    /// Check out the given branch. When a start point is given,
+    /// the branch is created from it and set to track it.
+    pub fn checkout<P: AsRef<Path>>(
+        &self,
+        branch: &str,
+        start_point: Option<&str>,
+        dir: P,
+    ) -> Result<()> {
+        let mut cmd = self.command();
+        cmd.args(["checkout", "--quiet"]);
+        match start_point {
+            Some(start_point) => cmd.args(["--track", "-b", branch, start_point]),
+            None => cmd.args([branch, "--"]),
+        };
+        let output = cmd.current_dir(&dir).output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to check out {branch} in {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of commits in local branches that are not in any remote-tracking branch
+    pub fn count_unpushed<P: AsRef<Path>>(&self, dir: P) -> Result<u32> {
+        let output = self
+            .command()
+            .args(["rev-list", "--count", "--branches", "--not", "--remotes"])
             .current_dir(&dir)
             .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to count unpushed commits in {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?.trim().parse()?)
+    }
+
+    /// Number of stashed changes
+    pub fn count_stashes<P: AsRef<Path>>(&self, dir: P) -> Result<u32> {
+        let output = self
+            .command()
+            .args(["stash", "list"])
+            .current_dir(&dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to list stashes in {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output
+            .stdout
+            .split(|b| *b == b'\n')
+            .filter(|l| !l.is_empty())
+            .count() as u32)
+    }
+
+    pub fn remote_list<P: AsRef<Path>>(&self, dir: P) -> Result<String> {
+        let output = self.command().args(["remote"]).current_dir(&dir).output()?;
         let remotes = String::from_utf8(output.stdout)?;
         Ok(remotes)
     }
 
+    /// URL of the given remote. Returns None when the remote is not found.
+    pub fn remote_get_url<P: AsRef<Path>>(
+        &self,
+        remote_name: &str,
+        dir: P,
+    ) -> Result<Option<String>> {
+        let output = self
+            .command()
+            .args(["remote", "get-url", "--", remote_name])
+            .current_dir(&dir)
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let url = String::from_utf8(output.stdout)?;
+        Ok(Some(String::from(url.trim())))
+    }
+
     pub fn remote_add<P: AsRef<Path>>(
         &self,
         remote_name: &str,
         repo_url: &str,
         dir: P,
     ) -> Result<()> {
-        let status = Command::new(&self.git_command)
+        let status = self
+            .command()
             .args([
                 OsStr::new("remote"),
                 OsStr::new("add"),
@@ -82,13 +438,30 @@ impl GitCmd {
         Ok(())
     }
 
+    pub fn remote_remove<P: AsRef<Path>>(&self, remote_name: &str, dir: P) -> Result<()> {
+        let output = self
+            .command()
+            .args(["remote", "remove", remote_name])
+            .current_dir(&dir)
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "Failed to remove remote {remote_name} from {}: {}",
+                dir.as_ref().to_string_lossy(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
     pub fn remote_set_url<P: AsRef<Path>>(
         &self,
         remote_name: &str,
         repo_url: &str,
         dir: P,
     ) -> Result<()> {
-        let status = Command::new(&self.git_command)
+        let status = self
+            .command()
             .args([
                 OsStr::new("remote"),
                 OsStr::new("set-url"),
@@ -108,3 +481,182 @@ impl GitCmd {
         Ok(())
     }
 }
+
+/// Parse the output of `git config --null --get-regexp` for `url.<base>.insteadOf` keys.
+/// Each key is followed by a newline and the value, and each value ends with a null character.
+fn parse_url_rewrites(output: &str) -> Vec<UrlRewrite> {
+    output
+        .split('\0')
+        .filter_map(|item| {
+            let (key, instead_of) = item.trim_start_matches('\n').split_once('\n')?;
+            let base = key.strip_prefix("url.")?;
+            let base = base.get(..base.len().checked_sub(INSTEAD_OF_SUFFIX.len())?)?;
+            Some(UrlRewrite {
+                base: String::from(base),
+                instead_of: String::from(instead_of),
+            })
+        })
+        .collect()
+}
+
+/// Whether the error is from a Git command that failed because it couldn't prompt for input
+pub fn needs_input(err: &anyhow::Error) -> bool {
+    let message = err.to_string();
+    INPUT_NEEDED_ERRORS
+        .iter()
+        .any(|error| message.contains(error))
+}
+
+/// Find the URL a remote redirected Git to from the output of clone or fetch
+fn redirect_url(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(REDIRECT_WARNING))
+        .map(|url| String::from(url.trim().trim_end_matches('/')))
+}
+
+/// SSH command that fails instead of prompting, based on the configured one
+fn with_batch_mode(ssh_command: Option<&str>) -> String {
+    format!("{} -o BatchMode=yes", ssh_command.unwrap_or("ssh"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {args:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let work_dir =
+            std::env::temp_dir().join(format!("gorg-test-bundle-{}", std::process::id()));
+        let repo = work_dir.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "--quiet", "--initial-branch", "main"]);
+        git(
+            &repo,
+            &[
+                "-c",
+                "user.name=Test",
+                "-c",
+                "user.email=test@example.com",
+                "-c",
+                "commit.gpgSign=false",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "Initial commit",
+            ],
+        );
+        git(&repo, &["branch", "feature"]);
+
+        let git_cmd = GitCmd::new(String::from("git"), true);
+        let bundle_path = work_dir.join("repo.bundle");
+        git_cmd.bundle_create(&bundle_path, &repo).unwrap();
+        let restored = work_dir.join("restored");
+        git_cmd
+            .clone_bundle(&bundle_path, &restored, "upstream")
+            .unwrap();
+
+        assert_eq!(git(&restored, &["remote"]), "upstream\n");
+        assert_eq!(
+            git(&restored, &["branch", "--format=%(refname:short)"]),
+            "feature\nmain\n"
+        );
+        assert_eq!(
+            git(&restored, &["rev-parse", "main"]),
+            git(&repo, &["rev-parse", "main"])
+        );
+        std::fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn ssh_command_with_batch_mode() {
+        assert_eq!(with_batch_mode(None), "ssh -o BatchMode=yes");
+        assert_eq!(
+            with_batch_mode(Some("ssh -i ~/.ssh/work_key")),
+            "ssh -i ~/.ssh/work_key -o BatchMode=yes"
+        );
+    }
+
+    #[test]
+    fn no_input_keeps_configured_ssh_command() {
+        if std::env::var_os("GIT_SSH_COMMAND").is_some() || std::env::var_os("GIT_SSH").is_some() {
+            return;
+        }
+        // Stand-in for Git that prints the configuration lookup as the configured value
+        let git_command = String::from("echo");
+        let ssh_command = |git_cmd: &GitCmd| {
+            git_cmd
+                .command()
+                .get_envs()
+                .find(|(name, _)| *name == "GIT_SSH_COMMAND")
+                .and_then(|(_, value)| value.map(|v| v.to_string_lossy().into_owned()))
+        };
+        let git_cmd = GitCmd::new(git_command.clone(), true);
+        assert_eq!(
+            ssh_command(&git_cmd).as_deref(),
+            Some("config --get core.sshCommand -o BatchMode=yes")
+        );
+        let git_cmd = GitCmd::new(git_command, false);
+        assert_eq!(ssh_command(&git_cmd), None);
+    }
+
+    #[test]
+    fn find_redirect_url() {
+        let output = "Cloning into 'repo'...\nwarning: redirecting to https://github.com/acme/new-name.git/\nremote: Enumerating objects: 5, done.\n";
+        assert_eq!(
+            redirect_url(output).as_deref(),
+            Some("https://github.com/acme/new-name.git")
+        );
+        assert_eq!(redirect_url("From https://github.com/acme/repo\n"), None);
+    }
+
+    #[test]
+    fn parse_rewrites() {
+        let output = "url.git@github.com:.insteadof\nhttps://github.com/\0url.https://a.com/my repos/.insteadof\na:\0";
+        assert_eq!(
+            parse_url_rewrites(output),
+            vec![
+                UrlRewrite {
+                    base: String::from("git@github.com:"),
+                    instead_of: String::from("https://github.com/"),
+                },
+                UrlRewrite {
+                    base: String::from("https://a.com/my repos/"),
+                    instead_of: String::from("a:"),
+                },
+            ]
+        );
+        assert!(parse_url_rewrites("").is_empty());
+    }
+
+    #[test]
+    fn detect_errors_needing_input() {
+        let err = anyhow::anyhow!(
+            "Failed to fetch in /p: fatal: could not read Username for 'https://a.com': terminal prompts disabled"
+        );
+        assert!(needs_input(&err));
+        let err = anyhow::anyhow!(
+            "Failed to fetch in /p: git@a.com: Permission denied (publickey).\nfatal: Could not read from remote repository."
+        );
+        assert!(needs_input(&err));
+        let err = anyhow::anyhow!(
+            "Failed to fetch in /p: fatal: unable to access 'https://a.com/': Could not resolve host: a.com"
+        );
+        assert!(!needs_input(&err));
+    }
+}
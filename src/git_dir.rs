@@ -1,35 +1,332 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::crypt;
+use crate::db;
+use crate::glob;
+
+const CACHE_FOLLOW_SYMLINKS: &str = "#follow_symlinks";
+/// Directories modified this recently are not cached, because later changes within
+/// the resolution of the modification time would go unnoticed.
+const RACY_MTIME_WINDOW: Duration = Duration::from_secs(2);
+
+/// Directory that couldn't be scanned
+#[derive(Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.to_string_lossy(), self.error)
+    }
+}
+
+/// Contents of the directories seen in a scan along with their modification times.
+/// A directory's modification time changes when entries are added to it or removed from it,
+/// so the cached contents can be used as long as the modification time stays the same.
+#[derive(Default)]
+pub struct ScanCache {
+    follow_symlinks: bool,
+    dirs: HashMap<PathBuf, CachedDir>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CachedDir {
+    mtime: SystemTime,
+    /// Whether the directory contains a Git repository
+    is_repo: bool,
+    /// Names of the sub-directories to scan
+    children: Vec<OsString>,
+}
+
+impl ScanCache {
+    /// Load the cache for the given scan directory from a file.
+    /// Returns an empty cache when the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P, start_dir: &Path) -> Result<Self> {
+        match crypt::read_to_string(path.as_ref())? {
+            Some(data) => Ok(Self::parse(&data, start_dir)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, start_dir: &Path) -> Result<()> {
+        let mut contents = String::with_capacity(self.dirs.len() * 100);
+        self.write_lines(start_dir, &mut contents);
+        db::write_atomically(path.as_ref(), &crypt::encrypt(contents.into_bytes())?)
+    }
+
+    /// Parse lines in the format `<mtime>\t<r|d>\t<dir>\t<child>/<child>...`
+    /// where the directory is relative to the scan directory
+    fn parse(data: &str, start_dir: &Path) -> Self {
+        let mut cache = Self::default();
+        for line in data.lines() {
+            if let Some(value) = line.strip_prefix(CACHE_FOLLOW_SYMLINKS) {
+                cache.follow_symlinks = value == "=true";
+                continue;
+            }
+            match parse_line(line) {
+                Some((dir, cached)) => {
+                    cache.dirs.insert(start_dir.join(dir), cached);
+                }
+                None => log::debug!("Ignoring invalid scan cache line: {line}"),
+            }
+        }
+        cache
+    }
+
+    fn write_lines(&self, start_dir: &Path, out: &mut String) {
+        out.push_str(&format!(
+            "{CACHE_FOLLOW_SYMLINKS}={}\n",
+            self.follow_symlinks
+        ));
+        let mut dirs: Vec<(&str, &CachedDir)> = self
+            .dirs
+            .iter()
+            .filter_map(|(dir, cached)| {
+                let dir = dir.strip_prefix(start_dir).ok()?.to_str()?;
+                Some((dir, cached))
+            })
+            .filter(|(dir, _)| !dir.contains(['\t', '\n']))
+            .collect();
+        dirs.sort_by_key(|(dir, _)| *dir);
+        for (dir, cached) in dirs {
+            let Some(children) = cached
+                .children
+                .iter()
+                .map(|child| child.to_str().filter(|c| !c.contains(['\t', '\n', '/'])))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let mtime = cached
+                .mtime
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "{}.{:09}\t{}\t{dir}\t{}\n",
+                mtime.as_secs(),
+                mtime.subsec_nanos(),
+                if cached.is_repo { 'r' } else { 'd' },
+                children.join("/"),
+            ));
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(&str, CachedDir)> {
+    let mut fields = line.split('\t');
+    let (secs, nanos) = fields.next()?.split_once('.')?;
+    let mtime = SystemTime::UNIX_EPOCH
+        + Duration::from_secs(secs.parse().ok()?)
+        + Duration::from_nanos(nanos.parse().ok()?);
+    let is_repo = match fields.next()? {
+        "r" => true,
+        "d" => false,
+        _ => return None,
+    };
+    let dir = fields.next()?;
+    let children = fields
+        .next()?
+        .split('/')
+        .filter(|child| !child.is_empty())
+        .map(OsString::from)
+        .collect();
+    Some((
+        dir,
+        CachedDir {
+            mtime,
+            is_repo,
+            children,
+        },
+    ))
+}
 
 pub struct GitDirIterator {
+    start_dir: PathBuf,
     search_stack: Vec<PathBuf>,
+    dirs_scanned: usize,
+    follow_symlinks: bool,
+    /// Device and inode numbers of the directories found so far.
+    /// Only tracked when following symbolic links, which can lead back to the same directory.
+    visited: HashSet<(u64, u64)>,
+    started_at: SystemTime,
+    /// Cache from an earlier scan
+    cache: ScanCache,
+    /// Cache of the directories seen in this scan
+    new_cache: HashMap<PathBuf, CachedDir>,
+    /// Patterns for the directories to skip, relative to the start directory
+    exclude: Vec<glob::Pattern>,
 }
 
 impl GitDirIterator {
-    pub fn new<P: AsRef<Path>>(start_dir: P) -> Self {
+    /// Create an iterator for the Git repositories under the given directory.
+    /// Fails when the directory doesn't exist or is not a directory.
+    pub fn new<P: AsRef<Path>>(start_dir: P) -> std::io::Result<Self> {
         let start_dir = start_dir.as_ref();
-        if !start_dir.is_dir() {
-            panic!("Given path is not a directory");
+        if !std::fs::metadata(start_dir)?.is_dir() {
+            return Err(std::io::ErrorKind::NotADirectory.into());
         }
-        Self {
+        Ok(Self {
+            start_dir: start_dir.to_path_buf(),
             search_stack: vec![start_dir.to_path_buf()],
+            dirs_scanned: 0,
+            follow_symlinks: false,
+            visited: HashSet::new(),
+            started_at: SystemTime::now(),
+            cache: ScanCache::default(),
+            new_cache: HashMap::new(),
+            exclude: Vec::new(),
+        })
+    }
+
+    /// Descend into symbolic links that point to directories.
+    /// Directories that have already been found through another path are skipped.
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Skip reading the directories that haven't been modified since the cached scan
+    pub fn with_cache(mut self, cache: ScanCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Skip the directories matching any of the patterns along with their contents.
+    /// The patterns are matched against the paths relative to the start directory.
+    pub fn with_exclude(mut self, exclude: Vec<glob::Pattern>) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Cache of the directories seen so far, to be used for the next scan
+    pub fn take_cache(&mut self) -> ScanCache {
+        ScanCache {
+            follow_symlinks: self.follow_symlinks,
+            dirs: std::mem::take(&mut self.new_cache),
         }
     }
+
+    /// Number of directories read so far
+    pub fn dirs_scanned(&self) -> usize {
+        self.dirs_scanned
+    }
+
+    fn is_excluded(&self, dir: &Path) -> bool {
+        if self.exclude.is_empty() {
+            return false;
+        }
+        let Some(rel_path) = dir
+            .strip_prefix(&self.start_dir)
+            .ok()
+            .and_then(|p| p.to_str())
+        else {
+            return false;
+        };
+        !rel_path.is_empty() && self.exclude.iter().any(|pattern| pattern.matches(rel_path))
+    }
+
+    /// Whether the directory entry is a directory that should be searched
+    fn should_search(&self, entry: &std::fs::DirEntry) -> bool {
+        let Ok(file_type) = entry.file_type() else {
+            return false;
+        };
+        // File types of directory entries don't follow symbolic links
+        if !self.follow_symlinks {
+            return file_type.is_dir();
+        }
+        std::fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir())
+    }
+
+    /// Cached contents of the directory if it hasn't been modified since the cached scan
+    fn cached_dir(&mut self, dir: &Path, mtime: Option<SystemTime>) -> Option<CachedDir> {
+        if self.cache.follow_symlinks != self.follow_symlinks {
+            return None;
+        }
+        let cached = self.cache.dirs.remove(dir)?;
+        (Some(cached.mtime) == mtime).then_some(cached)
+    }
+
+    /// Cache the directory along with the sub-directories last pushed to the search stack
+    fn cache_dir(&mut self, dir: &Path, mtime: Option<SystemTime>, is_repo: bool, pushed: usize) {
+        let Some(mtime) = mtime.filter(|mtime| *mtime + RACY_MTIME_WINDOW <= self.started_at)
+        else {
+            return;
+        };
+        let children = if is_repo {
+            Vec::new()
+        } else {
+            self.search_stack[self.search_stack.len() - pushed..]
+                .iter()
+                .filter_map(|path| path.file_name().map(OsString::from))
+                .collect()
+        };
+        let cached = CachedDir {
+            mtime,
+            is_repo,
+            children,
+        };
+        self.new_cache.insert(dir.to_path_buf(), cached);
+    }
 }
 
 impl Iterator for GitDirIterator {
-    type Item = std::io::Result<PathBuf>;
+    /// Directory containing a Git repository, or a directory that was skipped due to an error
+    type Item = Result<PathBuf, ScanError>;
 
+    #[allow(clippy::question_mark)]
     fn next(&mut self) -> Option<Self::Item> {
         let git_os_str = std::ffi::OsStr::new(".git");
         loop {
             let Some(next_dir) = self.search_stack.pop() else {
                 return None;
             };
+            // Excluded directories are still cached as sub-directories of their parents,
+            // so that they are found again when the patterns change
+            if self.is_excluded(&next_dir) {
+                continue;
+            }
+            self.dirs_scanned += 1;
+
+            let metadata = match std::fs::metadata(&next_dir) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    return Some(Err(ScanError {
+                        path: next_dir,
+                        error,
+                    }));
+                }
+            };
+            if self.follow_symlinks && !self.visited.insert((metadata.dev(), metadata.ino())) {
+                continue;
+            }
+            let mtime = metadata.modified().ok();
+            if let Some(cached) = self.cached_dir(&next_dir, mtime) {
+                let is_repo = cached.is_repo;
+                for child in cached.children.iter() {
+                    self.search_stack.push(next_dir.join(child));
+                }
+                self.new_cache.insert(next_dir.clone(), cached);
+                if is_repo {
+                    return Some(Ok(next_dir));
+                }
+                continue;
+            }
 
             let entries = match std::fs::read_dir(&next_dir) {
                 Ok(entries) => entries,
-                Err(err) => {
-                    return Some(Err(err));
+                Err(error) => {
+                    return Some(Err(ScanError {
+                        path: next_dir,
+                        error,
+                    }));
                 }
             };
 
@@ -38,22 +335,31 @@ impl Iterator for GitDirIterator {
             'entry: for entry in entries {
                 let entry = match entry {
                     Ok(entry) => entry,
-                    Err(err) => {
-                        result = Some(Err(err));
+                    Err(error) => {
+                        result = Some(Err(ScanError {
+                            path: next_dir.clone(),
+                            error,
+                        }));
                         break 'entry;
                     }
                 };
                 let path = entry.path();
-                if path.is_dir() && path.file_name() == Some(git_os_str) {
-                    result = Some(Ok(next_dir));
+                // Linked worktrees have a `.git` file instead of a directory
+                if path.file_name() == Some(git_os_str) && path.exists() {
+                    result = Some(Ok(next_dir.clone()));
                     break 'entry;
                 }
-                if path.is_dir() {
+                if self.should_search(&entry) {
                     pushed_items += 1;
                     self.search_stack.push(path);
                 }
             }
 
+            match &result {
+                None => self.cache_dir(&next_dir, mtime, false, pushed_items),
+                Some(Ok(_)) => self.cache_dir(&next_dir, mtime, true, 0),
+                Some(Err(_)) => {}
+            }
             if result.is_some() {
                 if pushed_items > 0 {
                     self.search_stack
@@ -64,3 +370,54 @@ impl Iterator for GitDirIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_cache_round_trip() {
+        let start_dir = Path::new("/projects");
+        let mtime = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let mut cache = ScanCache {
+            follow_symlinks: true,
+            dirs: HashMap::new(),
+        };
+        let dir = |is_repo, children: &[&str]| CachedDir {
+            mtime,
+            is_repo,
+            children: children.iter().map(OsString::from).collect(),
+        };
+        cache
+            .dirs
+            .insert(PathBuf::from("/projects"), dir(false, &["a.com"]));
+        cache
+            .dirs
+            .insert(PathBuf::from("/projects/a.com"), dir(false, &["x", "y"]));
+        cache
+            .dirs
+            .insert(PathBuf::from("/projects/a.com/x"), dir(true, &[]));
+        cache
+            .dirs
+            .insert(PathBuf::from("/projects/a.com/y"), dir(false, &["a\tb"]));
+
+        let mut s = String::new();
+        cache.write_lines(start_dir, &mut s);
+        assert_eq!(
+            s,
+            "#follow_symlinks=true\n1700000000.000000005\td\t\ta.com\n1700000000.000000005\td\ta.com\tx/y\n1700000000.000000005\tr\ta.com/x\t\n"
+        );
+
+        let parsed = ScanCache::parse(&s, start_dir);
+        assert!(parsed.follow_symlinks);
+        assert_eq!(parsed.dirs.len(), 3);
+        assert_eq!(
+            parsed.dirs.get(Path::new("/projects/a.com")),
+            cache.dirs.get(Path::new("/projects/a.com"))
+        );
+        assert_eq!(
+            parsed.dirs.get(Path::new("/projects/a.com/x")),
+            cache.dirs.get(Path::new("/projects/a.com/x"))
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
 
 enum UrlScheme {
     Ssh,
@@ -10,6 +12,10 @@ enum UrlScheme {
 }
 
 const URL_SCHEME_MAX_LEN: usize = 5 + 3;
+const BITBUCKET_SERVER_SCM_DIR: &str = "scm/";
+const CODECOMMIT_SCHEME: &str = "codecommit:";
+const CODECOMMIT_DIR: &str = "codecommit";
+const BITBUCKET_SERVER_SSH_PORT: &str = "7999";
 
 impl UrlScheme {
     fn from_str(s: &str) -> Option<Self> {
@@ -25,6 +31,7 @@ impl UrlScheme {
     }
 }
 
+#[allow(clippy::needless_borrow)]
 fn join_strs(from: &[String], to: String, separator: char) -> String {
     let mut to = from
         .iter()
@@ -130,17 +137,25 @@ fn right_of(s: &str, c: char) -> &str {
     s.split_once(c).map(|(_, r)| r).unwrap_or(s)
 }
 
-pub fn to_path(url: &str) -> Result<Vec<&str>> {
+/// Convert a Git URL to the parts of the project path, e.g. `[github.com, jpallari, gorg]`.
+/// The host is always lowercased, because hosts are case-insensitive.
+/// When `lowercase_all` is set, the rest of the parts are lowercased too,
+/// which suits forges that treat organizations and repositories case-insensitively.
+#[allow(clippy::partialeq_to_none)]
+pub fn to_path(url: &str, lowercase_all: bool) -> Result<Vec<String>> {
     let url = url.trim();
     if url.is_empty() {
         bail!("Empty URL cannot be converted to a path");
     }
+    if let Some(path) = codecommit_path(url) {
+        return checked_path(path, url);
+    }
 
     let Some((url_left, url_right)) = url.split_once(':') else {
         bail!("Unsupported URL: {url}");
     };
 
-    let mut path: Vec<&str> = Vec::new();
+    let mut path: Vec<String> = Vec::new();
     let host_part: &str;
     let path_part: &str;
 
@@ -155,7 +170,7 @@ pub fn to_path(url: &str) -> Result<Vec<&str>> {
                 bail!("Invalid URL: {url}");
             };
             host_part = left_of(right_of(url_left, '@'), ':');
-            path_part = url_right;
+            path_part = strip_bitbucket_server_prefix(url_left, url_right);
         }
         None => {
             // No URL scheme found => Handle as SSH URL
@@ -165,7 +180,7 @@ pub fn to_path(url: &str) -> Result<Vec<&str>> {
     }
 
     // Add the host part
-    path.push(host_part);
+    path.push(host_part.to_lowercase());
 
     // Add the rest of the parts to the list
     let mut parts = path_part.split('/').map(|p| p.trim()).peekable();
@@ -175,17 +190,297 @@ pub fn to_path(url: &str) -> Result<Vec<&str>> {
             part = part.strip_suffix(".git").unwrap_or(part);
         }
         if !part.is_empty() {
-            path.push(part);
+            path.push(normalize_part_case(part, lowercase_all));
         }
     }
 
+    strip_service_segments(&mut path);
+    checked_path(path, url)
+}
+
+fn checked_path(path: Vec<String>, url: &str) -> Result<Vec<String>> {
     if path.len() <= 1 {
         bail!("Not enough parts in URL to convert it to a path");
     }
+    for part in path.iter() {
+        check_path_part(part).map_err(|err| anyhow!("{err} in URL: {url:?}"))?;
+    }
     Ok(path)
 }
 
+/// Path parts for the AWS CodeCommit remotes of git-remote-codecommit,
+/// e.g. `codecommit::us-east-1://profile@repo`. The repositories are placed under the same host
+/// as the HTTPS remotes of the region, or under `codecommit` when the region is not set.
+fn codecommit_path(url: &str) -> Option<Vec<String>> {
+    let rest = url.strip_prefix(CODECOMMIT_SCHEME)?;
+    let (host, repo) = match rest.strip_prefix(':') {
+        Some(rest) => {
+            let (region, repo) = rest.split_once("://")?;
+            (format!("git-codecommit.{region}.amazonaws.com"), repo)
+        }
+        None => (String::from(CODECOMMIT_DIR), rest.strip_prefix("//")?),
+    };
+    let repo = right_of(repo, '@');
+    Some(vec![host.to_lowercase(), String::from(repo)])
+}
+
+/// Remove the fixed segments that some hosting services have around the repository names,
+/// e.g. `https://git-codecommit.us-east-1.amazonaws.com/v1/repos/<repo>`
+/// and `https://source.developers.google.com/p/<project>/r/<repo>`
+fn strip_service_segments(path: &mut Vec<String>) {
+    let host = path[0].as_str();
+    let is_segment = |index: usize, segment: &str| path[index].eq_ignore_ascii_case(segment);
+    if host.starts_with("git-codecommit.")
+        && host.ends_with(".amazonaws.com")
+        && path.len() == 4
+        && is_segment(1, "v1")
+        && is_segment(2, "repos")
+    {
+        path.drain(1..3);
+    } else if host == "source.developers.google.com"
+        && path.len() == 5
+        && is_segment(1, "p")
+        && is_segment(3, "r")
+    {
+        path.remove(3);
+        path.remove(1);
+    }
+}
+
+/// Bitbucket Server serves repositories over HTTP(S) as `/scm/<project>/<repo>.git`,
+/// and over SSH on port 7999 with or without the `scm` segment.
+/// Remove the segment, so that the repositories end up in `<host>/<project>/<repo>`.
+fn strip_bitbucket_server_prefix<'a>(authority: &str, path: &'a str) -> &'a str {
+    let Some(rest) = path.strip_prefix(BITBUCKET_SERVER_SCM_DIR) else {
+        return path;
+    };
+    let is_ssh_port = right_of(authority, '@')
+        .split_once(':')
+        .is_some_and(|(_, port)| port == BITBUCKET_SERVER_SSH_PORT);
+    let is_project_repo = rest.trim_end_matches('/').split('/').count() == 2;
+    if is_ssh_port || is_project_repo {
+        rest
+    } else {
+        path
+    }
+}
+
+/// Check that a project path relative to the projects directory only contains
+/// components that stay inside the directory and are safe to pass to shells
+pub fn check_path(path: &str) -> Result<()> {
+    for part in path.split('/') {
+        check_path_part(part).map_err(|err| anyhow!("{err} in path: {path:?}"))?;
+    }
+    Ok(())
+}
+
+fn check_path_part(part: &str) -> Result<()> {
+    if part.is_empty() || part == "." || part == ".." {
+        bail!("Invalid path component {part:?}");
+    }
+    if part.starts_with('-') {
+        bail!("Path component cannot start with a dash: {part:?}");
+    }
+    if part
+        .chars()
+        .any(|c| c.is_control() || c == '\\' || c == '/')
+    {
+        bail!("Path component cannot contain control characters or slashes: {part:?}");
+    }
+    Ok(())
+}
+
+/// Scheme of a Git URL in lowercase. SCP-like URLs (e.g. `git@github.com:jpallari/gorg.git`)
+/// use SSH, and URLs with a transport prefix (e.g. `ext::command`) use the transport name.
+/// Returns None for local paths.
+pub fn scheme(url: &str) -> Option<String> {
+    let url = url.trim();
+    if let Some((scheme, _)) = url.split_once("://")
+        && is_scheme_name(scheme)
+    {
+        return Some(scheme.to_lowercase());
+    }
+    if let Some((transport, _)) = url.split_once("::")
+        && is_scheme_name(transport)
+    {
+        return Some(transport.to_lowercase());
+    }
+    // SCP-like URLs have a colon before the first slash
+    match (url.find(':'), url.find('/')) {
+        (Some(colon), Some(slash)) if colon < slash => Some(String::from("ssh")),
+        (Some(_), None) => Some(String::from("ssh")),
+        _ => None,
+    }
+}
+
+fn is_scheme_name(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+}
+
+/// Fail when the scheme of the URL is not in the given list of allowed schemes
+pub fn check_scheme(url: &str, allowed_schemes: &[String]) -> Result<()> {
+    let Some(scheme) = scheme(url) else {
+        bail!("Local paths are not supported as remote URLs: {url:?}");
+    };
+    if !allowed_schemes
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(&scheme))
+    {
+        bail!(
+            "URL scheme {scheme} is not allowed. Allowed schemes: {}",
+            allowed_schemes.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// `url.<base>.insteadOf` rule from the Git configuration
+#[derive(Debug, PartialEq)]
+pub struct UrlRewrite {
+    /// Replacement for the prefix
+    pub base: String,
+    /// Prefix of the URLs to rewrite
+    pub instead_of: String,
+}
+
+/// Rewrite the URL like Git does, using the rule with the longest matching prefix
+pub fn rewrite(url: &str, rewrites: &[UrlRewrite]) -> String {
+    rewrites
+        .iter()
+        .filter(|rewrite| !rewrite.instead_of.is_empty() && url.starts_with(&rewrite.instead_of))
+        .max_by_key(|rewrite| rewrite.instead_of.len())
+        .map_or_else(
+            || String::from(url),
+            |rewrite| format!("{}{}", rewrite.base, &url[rewrite.instead_of.len()..]),
+        )
+}
+
+/// Point a Git URL to another project path (e.g. `github.com/jpallari/gorg`) on the same
+/// or another host. The scheme, user, port, and the rest of the URL are kept as they are.
+/// Fails when the resulting URL doesn't convert back to the given project path.
+pub fn replace_path(url: &str, new_path: &str) -> Result<String> {
+    let url = url.trim();
+    let old_path = to_path(url, false)?;
+    let (old_host, old_rest) = (&old_path[0], old_path[1..].join("/"));
+    let Some((new_host, new_rest)) = new_path.split_once('/') else {
+        bail!("Not enough parts in path: {new_path:?}");
+    };
+
+    // Look for the repository path before the `.git` suffix, which could match short names
+    let body = url.trim_end_matches('/');
+    let body = body.strip_suffix(".git").unwrap_or(body);
+    let Some(rest_start) = body.rfind(old_rest.as_str()) else {
+        bail!("Cannot find the repository path in URL: {url}");
+    };
+    let mut host_part = String::from(&url[..rest_start]);
+    if !new_host.eq_ignore_ascii_case(old_host) {
+        let Some(host_start) = host_part.to_lowercase().rfind(old_host.as_str()) else {
+            bail!("Cannot find the host in URL: {url}");
+        };
+        host_part.replace_range(host_start..host_start + old_host.len(), new_host);
+    }
+    let new_url = format!(
+        "{host_part}{new_rest}{}",
+        &url[rest_start + old_rest.len()..]
+    );
+
+    if to_path(&new_url, false)?.join("/") != normalize_path_case(new_path, false) {
+        bail!("Cannot point URL {url} to {new_path}");
+    }
+    Ok(new_url)
+}
+
+/// Web page URL templates of repositories by the forge type
+const WEB_URL_TEMPLATES: &[(&str, &str)] = &[
+    ("github", "https://{host}/{owner}/{repo}"),
+    ("gitlab", "https://{host}/{path}"),
+    ("gitea", "https://{host}/{owner}/{repo}"),
+    ("bitbucket", "https://{host}/{owner}/{repo}"),
+    (
+        "bitbucket-server",
+        "https://{host}/projects/{owner}/repos/{repo}",
+    ),
+];
+const DEFAULT_WEB_URL_TEMPLATE: &str = "https://{host}/{path}";
+
+/// Web page URL of a repository on its forge (e.g. `https://github.com/jpallari/gorg`).
+/// The URL is built from the template or forge type set for the host in `web_urls`.
+/// Hosts without one use the template of the forge type detected from the host name.
+/// Templates can contain the `{host}`, `{path}`, `{owner}`, and `{repo}` placeholders,
+/// where the owner is everything between the host and the repository.
+pub fn to_web_url(url: &str, web_urls: &BTreeMap<String, String>) -> Result<String> {
+    let path = to_path(url, false)?;
+    let (host, parts) = path.split_first().expect("Paths have at least two parts");
+    let template = match web_urls.get(host) {
+        Some(template) => web_url_template(template)?,
+        None => detect_web_url_template(host),
+    };
+    let (repo, owner) = parts.split_last().expect("Paths have at least two parts");
+    Ok(template
+        .replace("{host}", host)
+        .replace("{path}", &parts.join("/"))
+        .replace("{owner}", &owner.join("/"))
+        .replace("{repo}", repo))
+}
+
+/// Web page URL template for a forge type or a template set in the configuration
+pub fn web_url_template(template: &str) -> Result<&str> {
+    if template.contains('{') {
+        return Ok(template);
+    }
+    match WEB_URL_TEMPLATES
+        .iter()
+        .find(|(forge, _)| *forge == template)
+    {
+        Some((_, template)) => Ok(template),
+        None => {
+            let forges: Vec<&str> = WEB_URL_TEMPLATES.iter().map(|(forge, _)| *forge).collect();
+            bail!(
+                "Unknown forge type for web URLs: {template}. Use a URL template or one of: {}",
+                forges.join(", ")
+            );
+        }
+    }
+}
+
+fn detect_web_url_template(host: &str) -> &'static str {
+    let name = host.split('.').find(|part| *part != "www").unwrap_or(host);
+    let forge = match name {
+        "codeberg" | "forgejo" => "gitea",
+        // Bitbucket Cloud is only available on bitbucket.org
+        "bitbucket" if host != "bitbucket.org" => "bitbucket-server",
+        _ => name,
+    };
+    WEB_URL_TEMPLATES
+        .iter()
+        .find(|(f, _)| *f == forge)
+        .map_or(DEFAULT_WEB_URL_TEMPLATE, |(_, template)| template)
+}
+
+/// Normalize the casing of a project path the same way as `to_path` does
+pub fn normalize_path_case(path: &str, lowercase_all: bool) -> String {
+    match path.split_once('/') {
+        Some((host, rest)) => format!(
+            "{}/{}",
+            host.to_lowercase(),
+            normalize_part_case(rest, lowercase_all)
+        ),
+        None => path.to_lowercase(),
+    }
+}
+
+fn normalize_part_case(part: &str, lowercase_all: bool) -> String {
+    if lowercase_all {
+        part.to_lowercase()
+    } else {
+        String::from(part)
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::useless_vec)]
 mod tests {
     use super::*;
 
@@ -285,56 +580,276 @@ mod tests {
 
     #[test]
     fn to_path_empty() {
-        assert_eq!(to_path("").is_err(), true);
+        assert_eq!(to_path("", false).is_err(), true);
     }
 
     #[test]
     fn to_path_invalid_url() {
-        assert_eq!(to_path("https://").is_err(), true);
-        assert_eq!(to_path("file:///path/to/repo").is_err(), true);
-        assert_eq!(to_path("/path/to/repo").is_err(), true);
-        assert_eq!(to_path("~/path/to/repo").is_err(), true);
+        assert_eq!(to_path("https://", false).is_err(), true);
+        assert_eq!(to_path("file:///path/to/repo", false).is_err(), true);
+        assert_eq!(to_path("/path/to/repo", false).is_err(), true);
+        assert_eq!(to_path("~/path/to/repo", false).is_err(), true);
     }
 
     #[test]
     fn to_path_https() {
         let url = "https://github.com/jpallari/gorg.git";
         let path = vec!["github.com", "jpallari", "gorg"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
     }
 
     #[test]
     fn to_path_ssh() {
         let url = "ssh://git@github.com/jpallari/gorg.git";
         let path = vec!["github.com", "jpallari", "gorg"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
     }
 
     #[test]
     fn to_path_ssh_with_port() {
         let url = "ssh://git@github.com:2022/jpallari/gorg.git";
         let path = vec!["github.com", "jpallari", "gorg"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
     }
 
     #[test]
     fn to_path_ssh_implied() {
         let url = "git@github.com:jpallari/gorg.git";
         let path = vec!["github.com", "jpallari", "gorg"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
     }
 
     #[test]
     fn to_path_ssh_with_user_home() {
         let url = "git@host.xyz:~user/repo.git";
         let path = vec!["host.xyz", "user", "repo"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
     }
 
     #[test]
     fn to_path_ssh_with_home_in_path() {
         let url = "ssh://git@host.xyz:~/user/repo.git";
         let path = vec!["host.xyz", "user", "repo"];
-        assert_eq!(to_path(url).unwrap(), path);
+        assert_eq!(to_path(url, false).unwrap(), path);
+    }
+
+    #[test]
+    fn to_path_bitbucket_server() {
+        let path = vec!["bitbucket.corp", "proj", "repo"];
+        for url in [
+            "https://bitbucket.corp/scm/proj/repo.git",
+            "https://user@bitbucket.corp/scm/proj/repo.git",
+            "ssh://git@bitbucket.corp:7999/proj/repo.git",
+            "ssh://git@bitbucket.corp:7999/scm/proj/repo.git",
+        ] {
+            assert_eq!(to_path(url, false).unwrap(), path, "{url}");
+        }
+        assert_eq!(
+            to_path("https://gitlab.com/scm/group/sub/repo.git", false).unwrap(),
+            vec!["gitlab.com", "scm", "group", "sub", "repo"]
+        );
+        assert_eq!(
+            to_path("git@github.com:scm/repo.git", false).unwrap(),
+            vec!["github.com", "scm", "repo"]
+        );
+    }
+
+    #[test]
+    fn to_path_cloud_services() {
+        let path = vec!["git-codecommit.us-east-1.amazonaws.com", "repo"];
+        for url in [
+            "codecommit::us-east-1://repo",
+            "codecommit::us-east-1://profile@repo",
+            "https://git-codecommit.us-east-1.amazonaws.com/v1/repos/repo",
+            "ssh://git-codecommit.us-east-1.amazonaws.com/v1/repos/repo",
+        ] {
+            assert_eq!(to_path(url, false).unwrap(), path, "{url}");
+        }
+        assert_eq!(
+            to_path("codecommit://profile@repo", false).unwrap(),
+            vec!["codecommit", "repo"]
+        );
+        assert!(to_path("codecommit::us-east-1://", false).is_err());
+        assert!(to_path("codecommit::us-east-1://../x", false).is_err());
+
+        let path = vec!["source.developers.google.com", "proj", "repo"];
+        for url in [
+            "https://source.developers.google.com/p/proj/r/repo",
+            "ssh://user@source.developers.google.com:2022/p/proj/r/repo",
+        ] {
+            assert_eq!(to_path(url, false).unwrap(), path, "{url}");
+        }
+    }
+
+    #[test]
+    fn to_path_normalizes_case() {
+        let url = "https://GitHub.com/JPallari/Gorg.git";
+        assert_eq!(
+            to_path(url, false).unwrap(),
+            vec!["github.com", "JPallari", "Gorg"]
+        );
+        assert_eq!(
+            to_path(url, true).unwrap(),
+            vec!["github.com", "jpallari", "gorg"]
+        );
+    }
+
+    #[test]
+    fn normalize_path_case_like_to_path() {
+        assert_eq!(
+            normalize_path_case("GitHub.com/JPallari/Gorg", false),
+            "github.com/JPallari/Gorg"
+        );
+        assert_eq!(
+            normalize_path_case("GitHub.com/JPallari/Gorg", true),
+            "github.com/jpallari/gorg"
+        );
+        assert_eq!(normalize_path_case("Notes", false), "notes");
+    }
+
+    #[test]
+    fn rewrite_urls() {
+        let rule = |base: &str, instead_of: &str| UrlRewrite {
+            base: String::from(base),
+            instead_of: String::from(instead_of),
+        };
+        let rewrites = [
+            rule("git@github.com:", "https://github.com/"),
+            rule("git@work.github.com:acme/", "https://github.com/acme/"),
+            rule("https://gitlab.com/", "gl:"),
+        ];
+        assert_eq!(
+            rewrite("https://github.com/jpallari/gorg.git", &rewrites),
+            "git@github.com:jpallari/gorg.git"
+        );
+        assert_eq!(
+            rewrite("https://github.com/acme/repo.git", &rewrites),
+            "git@work.github.com:acme/repo.git"
+        );
+        assert_eq!(
+            rewrite("gl:group/repo.git", &rewrites),
+            "https://gitlab.com/group/repo.git"
+        );
+        assert_eq!(
+            rewrite("https://a.com/x.git", &rewrites),
+            "https://a.com/x.git"
+        );
+    }
+
+    #[test]
+    fn replace_url_paths() {
+        assert_eq!(
+            replace_path(
+                "https://github.com/jpallari/gorg.git",
+                "github.com/acme/gorg"
+            )
+            .unwrap(),
+            "https://github.com/acme/gorg.git"
+        );
+        assert_eq!(
+            replace_path("git@github.com:jpallari/g.git", "gitlab.com/group/sub/g").unwrap(),
+            "git@gitlab.com:group/sub/g.git"
+        );
+        assert_eq!(
+            replace_path("ssh://git@GitHub.com:22/a/b/", "github.com/a/c").unwrap(),
+            "ssh://git@GitHub.com:22/a/c/"
+        );
+        assert_eq!(
+            replace_path(
+                "ssh://git@bb.example.com:7999/scm/prj/repo.git",
+                "bb.example.com/prj/new"
+            )
+            .unwrap(),
+            "ssh://git@bb.example.com:7999/scm/prj/new.git"
+        );
+        assert!(replace_path("https://github.com/a/b.git", "github.com").is_err());
+        assert!(replace_path("https://github.com/a/b.git", "github.com/a/../b").is_err());
+        assert!(
+            replace_path(
+                "https://source.developers.google.com/p/proj/r/repo",
+                "source.developers.google.com/proj/other"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn web_urls() {
+        let web_urls = BTreeMap::from([
+            (String::from("git.example.com"), String::from("gitea")),
+            (
+                String::from("code.example.com"),
+                String::from("https://code.example.com/projects/{owner}/repos/{repo}"),
+            ),
+            (String::from("bad.example.com"), String::from("svn")),
+        ]);
+        let web_url = |url| to_web_url(url, &web_urls).unwrap();
+        assert_eq!(
+            web_url("git@github.com:jpallari/gorg.git"),
+            "https://github.com/jpallari/gorg"
+        );
+        assert_eq!(
+            web_url("ssh://git@gitlab.com:2022/acme/group/repo.git"),
+            "https://gitlab.com/acme/group/repo"
+        );
+        assert_eq!(
+            web_url("https://git.example.com/team/tool.git"),
+            "https://git.example.com/team/tool"
+        );
+        assert_eq!(
+            web_url("ssh://git@code.example.com:7999/PROJ/repo.git"),
+            "https://code.example.com/projects/PROJ/repos/repo"
+        );
+        assert_eq!(
+            web_url("git@codeberg.org:x/y.git"),
+            "https://codeberg.org/x/y"
+        );
+        assert_eq!(
+            web_url("https://bitbucket.corp/scm/proj/repo.git"),
+            "https://bitbucket.corp/projects/proj/repos/repo"
+        );
+        assert_eq!(
+            web_url("git@bitbucket.org:team/repo.git"),
+            "https://bitbucket.org/team/repo"
+        );
+        assert!(to_web_url("git@bad.example.com:x/y.git", &web_urls).is_err());
+        assert!(web_url_template("bitbucket").is_ok());
+    }
+
+    #[test]
+    fn to_path_rejects_unsafe_parts() {
+        assert!(to_path("https://github.com/../../etc/x.git", false).is_err());
+        assert!(to_path("https://github.com/./x/y.git", false).is_err());
+        assert!(to_path("https:///x/y.git", false).is_err());
+        assert!(to_path("git@-oProxyCommand=x:y/z.git", false).is_err());
+        assert!(to_path("https://github.com/x/-y.git", false).is_err());
+        assert!(to_path("https://github.com/x/y\nz.git", false).is_err());
+        assert!(to_path("https://github.com/x\\y/z.git", false).is_err());
+        assert!(check_path("github.com/x/y").is_ok());
+        assert!(check_path("github.com/../y").is_err());
+        assert!(check_path("/github.com/y").is_err());
+    }
+
+    #[test]
+    fn url_schemes() {
+        assert_eq!(
+            scheme("https://github.com/x/y.git").as_deref(),
+            Some("https")
+        );
+        assert_eq!(
+            scheme("SSH://git@github.com/x/y.git").as_deref(),
+            Some("ssh")
+        );
+        assert_eq!(scheme("git@github.com:x/y.git").as_deref(), Some("ssh"));
+        assert_eq!(scheme("ext::sh -c touch% /tmp/x").as_deref(), Some("ext"));
+        assert_eq!(scheme("/path/to/repo"), None);
+        assert_eq!(scheme("./x:y"), None);
+
+        let allowed = vec![String::from("https"), String::from("ssh")];
+        assert!(check_scheme("https://github.com/x/y.git", &allowed).is_ok());
+        assert!(check_scheme("git@github.com:x/y.git", &allowed).is_ok());
+        assert!(check_scheme("git://github.com/x/y.git", &allowed).is_err());
+        assert!(check_scheme("ext::sh", &allowed).is_err());
+        assert!(check_scheme("/path/to/repo", &allowed).is_err());
     }
 }
@@ -0,0 +1,135 @@
+/// Glob pattern for paths separated by `/`.
+/// `*` matches any characters within a path segment, `?` matches a single character,
+/// and `**` matches any number of whole segments, including none.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    segments: Vec<String>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let segments = pattern
+            .trim_matches('/')
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(String::from)
+            .collect();
+        Self { segments }
+    }
+
+    /// Whether the pattern matches the whole path
+    pub fn matches(&self, path: &str) -> bool {
+        let path: Vec<&str> = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        match_segments(&self.segments, &path)
+    }
+}
+
+/// Whether any of the patterns matches the path or one of its parent directories
+pub fn matches_path_or_parent(patterns: &[Pattern], path: &str) -> bool {
+    let path = path.trim_matches('/');
+    if patterns.is_empty() || path.is_empty() {
+        return false;
+    }
+    let parents = path.match_indices('/').map(|(index, _)| &path[..index]);
+    parents
+        .chain(std::iter::once(path))
+        .any(|dir| patterns.iter().any(|pattern| pattern.matches(dir)))
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skipped| match_segments(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(segment, path)| {
+            match_segment(first, segment) && match_segments(rest, path)
+        }),
+    }
+}
+
+/// Match a single path segment against a pattern with `*` and `?` wildcards
+fn match_segment(pattern: &str, segment: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let segment: Vec<char> = segment.chars().collect();
+    let (mut p, mut s) = (0, 0);
+    // Position of the last `*` in the pattern and the segment position it was tried at
+    let mut backtrack = None;
+    while s < segment.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, s));
+                p += 1;
+            }
+            Some(&ch) if ch == '?' || ch == segment[s] => {
+                p += 1;
+                s += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character
+                Some((star, star_s)) => {
+                    backtrack = Some((star, star_s + 1));
+                    p = star + 1;
+                    s = star_s + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|ch| *ch == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_segment_wildcards() {
+        assert!(match_segment("legacy-*", "legacy-app"));
+        assert!(match_segment("legacy-*", "legacy-"));
+        assert!(!match_segment("legacy-*", "legacy"));
+        assert!(match_segment("*-app", "legacy-app"));
+        assert!(match_segment("l*y-*p", "legacy-app"));
+        assert!(match_segment("?x", "ax"));
+        assert!(!match_segment("?x", "x"));
+        assert!(match_segment("*", ""));
+        assert!(!match_segment("a", "ab"));
+    }
+
+    #[test]
+    fn match_paths() {
+        let legacy = Pattern::new("github.com/legacy-*/**");
+        assert!(legacy.matches("github.com/legacy-apps"));
+        assert!(legacy.matches("github.com/legacy-apps/repo"));
+        assert!(!legacy.matches("github.com/apps/legacy-repo"));
+        assert!(!legacy.matches("gitlab.com/legacy-apps"));
+
+        let archive = Pattern::new("**/archive/**");
+        assert!(archive.matches("archive"));
+        assert!(archive.matches("a.com/org/archive"));
+        assert!(archive.matches("a.com/archive/repo"));
+        assert!(!archive.matches("a.com/archived"));
+
+        let repo = Pattern::new("a.com/*/repo");
+        assert!(repo.matches("a.com/org/repo"));
+        assert!(!repo.matches("a.com/org/sub/repo"));
+        assert!(!repo.matches("a.com/org"));
+    }
+
+    #[test]
+    fn match_parents() {
+        let patterns = [Pattern::new("github.com/legacy-*")];
+        assert!(matches_path_or_parent(&patterns, "github.com/legacy-apps"));
+        assert!(matches_path_or_parent(
+            &patterns,
+            "github.com/legacy-apps/repo/src"
+        ));
+        assert!(!matches_path_or_parent(&patterns, "github.com"));
+        assert!(!matches_path_or_parent(&patterns, "github.com/apps/repo"));
+        assert!(!matches_path_or_parent(&[], "github.com/legacy-apps"));
+    }
+}
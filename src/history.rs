@@ -0,0 +1,186 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Result, bail};
+
+use crate::crypt;
+use crate::db::{flock, unix_secs};
+
+/// Size after which the log is compacted into the index
+pub const COMPACT_SIZE: u64 = 64 * 1024;
+
+/// Use of a project recorded in the history log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub used_at: SystemTime,
+    pub entry: String,
+    /// Query the entry was picked for in the interactive finder over better matches
+    pub query: Option<String>,
+}
+
+impl Record {
+    pub fn new(entry: &str, query: Option<&str>) -> Self {
+        Self {
+            used_at: SystemTime::now(),
+            entry: String::from(entry.trim()),
+            query: query.map(String::from),
+        }
+    }
+
+    /// Line for the record in the format `<unix time>\t<entry>[\t<query>]`
+    fn write_line(&self, out: &mut String) {
+        out.push_str(&unix_secs(self.used_at).to_string());
+        out.push('\t');
+        out.push_str(&self.entry);
+        if let Some(query) = &self.query {
+            out.push('\t');
+            out.push_str(&query.replace(['\t', '\n'], " "));
+        }
+        out.push('\n');
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let secs: u64 = fields.next()?.parse().ok()?;
+        let entry = fields.next().filter(|entry| !entry.is_empty())?;
+        Some(Self {
+            used_at: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            entry: String::from(entry),
+            query: fields.next().map(String::from),
+        })
+    }
+}
+
+/// Append-only log of project uses next to the index. Concurrent shells append to the log
+/// instead of rewriting the whole index for each use, and the log is compacted into the index
+/// whenever the index is saved. Appending and reading take a shared lock on the log,
+/// and compacting takes an exclusive lock, so that records are never lost or applied twice.
+pub struct History {
+    path: PathBuf,
+    /// None when the log doesn't exist
+    file: Option<File>,
+}
+
+impl History {
+    /// Open the log for reading with a shared lock
+    pub fn lock_shared(path: &Path) -> Result<Self> {
+        Self::lock(path, false)
+    }
+
+    /// Open the log for compacting with an exclusive lock
+    pub fn lock_exclusive(path: &Path) -> Result<Self> {
+        Self::lock(path, true)
+    }
+
+    fn lock(path: &Path, exclusive: bool) -> Result<Self> {
+        let file = match File::options().read(true).write(exclusive).open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    path: path.to_path_buf(),
+                    file: None,
+                });
+            }
+            Err(err) => bail!("Failed to open {}: {err}", path.to_string_lossy()),
+        };
+        let operation = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        flock(&file, path, operation)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+        })
+    }
+
+    /// Read the records after the given offset. Returns the records and the offset after them.
+    /// A line that is still being written is left for later reads.
+    pub fn read(&mut self, offset: u64) -> Result<(Vec<Record>, u64)> {
+        let Some(file) = &mut self.file else {
+            return Ok((Vec::new(), offset));
+        };
+        if file.metadata()?.len() <= offset {
+            return Ok((Vec::new(), offset));
+        }
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_to_string(&mut contents)?;
+        let mut records = Vec::new();
+        let mut len = 0;
+        for line in contents.split_inclusive('\n') {
+            let Some(line) = line.strip_suffix('\n') else {
+                break;
+            };
+            len += line.len() + 1;
+            let line = crypt::decrypt_line(&self.path, line)?;
+            match Record::parse(&line) {
+                Some(record) => records.push(record),
+                None => log::warn!("Ignoring invalid history record: {line:?}"),
+            }
+        }
+        Ok((records, offset + len as u64))
+    }
+
+    /// Remove all records after they have been compacted into the index
+    pub fn clear(&mut self) -> Result<()> {
+        if let Some(file) = &self.file {
+            file.set_len(0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Append records to the log. Returns the size of the log after appending.
+pub fn append(path: &Path, records: &[Record]) -> Result<u64> {
+    let mut lines = String::new();
+    let mut line = String::new();
+    for record in records {
+        line.clear();
+        record.write_line(&mut line);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        lines.push_str(&crypt::encrypt_line(line)?);
+        lines.push('\n');
+    }
+    let mut file = match File::options().create(true).append(true).open(path) {
+        Ok(file) => file,
+        Err(err) => bail!("Failed to open {}: {err}", path.to_string_lossy()),
+    };
+    flock(&file, path, libc::LOCK_SH)?;
+    // Appends of a single write don't interleave with the appends of other processes
+    file.write_all(lines.as_bytes())?;
+    Ok(file.metadata()?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_lines() {
+        let used_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1700000000);
+        let used = Record {
+            used_at,
+            entry: String::from("a.com/x"),
+            query: None,
+        };
+        let picked = Record {
+            used_at,
+            entry: String::from("a.com/y"),
+            query: Some(String::from("y\tz")),
+        };
+        let mut lines = String::new();
+        used.write_line(&mut lines);
+        picked.write_line(&mut lines);
+        assert_eq!(lines, "1700000000\ta.com/x\n1700000000\ta.com/y\ty z\n");
+
+        let records: Vec<Record> = lines.lines().filter_map(Record::parse).collect();
+        assert_eq!(records[0], used);
+        assert_eq!(records[1].query.as_deref(), Some("y z"));
+        assert_eq!(Record::parse("x\ta.com/x"), None);
+        assert_eq!(Record::parse("1700000000\t"), None);
+    }
+}
@@ -0,0 +1,548 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, mpsc};
+
+use termion::color::{self, Color};
+use termion::event::{Event, Key};
+use termion::raw::{IntoRawMode, RawTerminal};
+
+use crate::text;
+use crate::tui;
+
+/// Lines of output kept for each job
+const MAX_OUTPUT_LINES: usize = 10_000;
+const KEY_HELP: &str = "j/k select  tab focus  q quit";
+const TAB_WIDTH: usize = 4;
+/// Colors of the project tags in the output, cycled through in the order of the jobs
+const TAG_COLORS: [&dyn Color; 6] = [
+    &color::Cyan,
+    &color::Yellow,
+    &color::Green,
+    &color::Magenta,
+    &color::Blue,
+    &color::Red,
+];
+
+/// State of a command run in a project
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Ok,
+    /// Failed with the given reason, e.g. the exit code
+    Failed(String),
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Ok => "ok",
+            Self::Failed(_) => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Progress reported by the runner for the job at the given index
+pub enum Message {
+    Started(usize),
+    Output(usize, Stream, String),
+    Finished(usize, JobState),
+}
+
+/// Runs commands in projects and streams the output line by line.
+/// Running commands can be cancelled from another thread.
+pub struct Runner {
+    /// Process IDs of the running jobs
+    pids: Mutex<Vec<Option<u32>>>,
+    cancelled: AtomicBool,
+}
+
+impl Runner {
+    pub fn new(jobs: usize) -> Self {
+        Self {
+            pids: Mutex::new(vec![None; jobs]),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Run the commands one after another in the given directory and report the progress
+    /// as job `index`. The remaining commands are skipped when a command fails.
+    pub fn run(
+        &self,
+        index: usize,
+        dir: &Path,
+        commands: &[Vec<String>],
+        tx: &mpsc::Sender<Message>,
+    ) {
+        // Messages are dropped when nobody is listening anymore
+        let send = |message| {
+            let _ = tx.send(message);
+        };
+        if self.cancelled.load(Ordering::Relaxed) {
+            send(Message::Finished(index, JobState::Cancelled));
+            return;
+        }
+        send(Message::Started(index));
+        for command in commands {
+            let state = match self.run_command(index, dir, command, tx) {
+                JobState::Failed(reason) if commands.len() > 1 => {
+                    JobState::Failed(format!("{}: {reason}", command.join(" ")))
+                }
+                state => state,
+            };
+            if state != JobState::Ok {
+                send(Message::Finished(index, state));
+                return;
+            }
+            if self.cancelled.load(Ordering::Relaxed) {
+                send(Message::Finished(index, JobState::Cancelled));
+                return;
+            }
+        }
+        send(Message::Finished(index, JobState::Ok));
+    }
+
+    fn run_command(
+        &self,
+        index: usize,
+        dir: &Path,
+        command: &[String],
+        tx: &mpsc::Sender<Message>,
+    ) -> JobState {
+        // Each command gets its own process group, so that cancelling
+        // also terminates the processes started by the command
+        let res = Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(dir)
+            .process_group(0)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match res {
+            Ok(child) => child,
+            Err(err) => return JobState::Failed(err.to_string()),
+        };
+        self.set_pid(index, Some(child.id()));
+        if self.cancelled.load(Ordering::Relaxed) {
+            // Cancelled while starting
+            terminate_group(child.id());
+        }
+
+        let stdout = child.stdout.take().expect("Command stdout must be piped");
+        let stderr = child.stderr.take().expect("Command stderr must be piped");
+        std::thread::scope(|scope| {
+            scope.spawn(|| stream_lines(stderr, index, Stream::Stderr, tx));
+            stream_lines(stdout, index, Stream::Stdout, tx);
+        });
+        wait_for_exit(child.id());
+        self.set_pid(index, None);
+        let res = child.wait();
+
+        match res {
+            Ok(status) if status.success() => JobState::Ok,
+            Ok(_) if self.cancelled.load(Ordering::Relaxed) => JobState::Cancelled,
+            Ok(status) => JobState::Failed(status.to_string()),
+            Err(err) => JobState::Failed(err.to_string()),
+        }
+    }
+
+    /// Skip the queued jobs and terminate the running ones
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        let Ok(pids) = self.pids.lock() else {
+            return;
+        };
+        for pid in pids.iter().flatten() {
+            terminate_group(*pid);
+        }
+    }
+
+    fn set_pid(&self, index: usize, pid: Option<u32>) {
+        if let Ok(mut pids) = self.pids.lock() {
+            pids[index] = pid;
+        }
+    }
+}
+
+/// Terminate the process group led by the child process
+fn terminate_group(pid: u32) {
+    // SAFETY: the PID belongs to a child that has not been reaped yet, since the PID is cleared
+    // before the child is reaped, so the group ID can't have been reused either
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGTERM);
+    }
+}
+
+/// Wait for a child process to exit without reaping it, so that its PID can't be reused yet
+fn wait_for_exit(pid: u32) {
+    // SAFETY: siginfo_t is a plain C struct that waitid fills in
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    loop {
+        let flags = libc::WEXITED | libc::WNOWAIT;
+        let res = unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) };
+        if res == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            break;
+        }
+    }
+}
+
+fn stream_lines<R: Read>(reader: R, index: usize, stream: Stream, tx: &mpsc::Sender<Message>) {
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf);
+                let line = line.trim_end_matches(['\n', '\r']);
+                let _ = tx.send(Message::Output(index, stream, String::from(line)));
+            }
+        }
+    }
+}
+
+/// Remove terminal escape sequences and control characters from a line of output,
+/// so that it can't mess up the screen. Tabs are expanded to spaces.
+pub fn sanitize_line(line: &str) -> String {
+    let mut s = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\x1b' => {
+                // CSI sequences end with a byte in the range @ to ~, others after the next char
+                if chars.next_if_eq(&'[').is_some() {
+                    while chars.next_if(|c| !('@'..='~').contains(c)).is_some() {}
+                }
+                chars.next();
+            }
+            '\t' => s.push_str(&" ".repeat(TAB_WIDTH)),
+            ch if ch.is_control() => {}
+            ch => s.push(ch),
+        }
+    }
+    s
+}
+
+/// Tag for the output lines of a job, e.g. `[github.com/acme/api] `. Tags are padded to
+/// the given width, so that the output of the jobs lines up like in `docker compose`.
+pub fn line_tag(project: &str, index: usize, width: usize, colored: bool) -> String {
+    let tag = format!("[{project}]");
+    if !colored {
+        return format!("{tag:<width$} ");
+    }
+    format!(
+        "{}{tag:<width$}{} ",
+        color::Fg(TAG_COLORS[index % TAG_COLORS.len()]),
+        color::Fg(color::Reset)
+    )
+}
+
+/// Command run in a project
+pub struct Job {
+    pub project: String,
+    pub state: JobState,
+    pub output: Vec<String>,
+}
+
+impl Job {
+    pub fn new(project: String) -> Self {
+        Self {
+            project,
+            state: JobState::Queued,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn push_output(&mut self, line: &str) {
+        if self.output.len() >= MAX_OUTPUT_LINES {
+            self.output.remove(0);
+        }
+        self.output.push(sanitize_line(line));
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Focus {
+    Jobs,
+    Output,
+}
+
+/// What the user asked for in the jobs view
+pub enum ViewEvent {
+    Render,
+    Quit,
+}
+
+/// Full-screen view listing the jobs with their states above
+/// the output of the selected job
+pub struct JobsView<W: Write + AsFd> {
+    writer: RawTerminal<W>,
+    focus: Focus,
+    selected: usize,
+    /// Index of the first job shown in the list
+    offset: usize,
+    /// Lines scrolled up from the end of the output. Zero follows the output.
+    scroll: usize,
+    message: String,
+}
+
+impl<W: Write + AsFd> Drop for JobsView<W> {
+    fn drop(&mut self) {
+        if !tui::release_terminal() {
+            // Already restored by the panic hook
+            return;
+        }
+        if let Err(err) = self.quit() {
+            eprintln!("Failed to quit jobs view: {}", err);
+        }
+    }
+}
+
+impl<W: Write + AsFd> JobsView<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let options = tui::PromptOptions {
+            full_screen: true,
+            ..Default::default()
+        };
+        tui::guard_terminal(writer.as_fd().as_raw_fd(), options)?;
+        let mut writer = writer.into_raw_mode()?;
+        write!(
+            writer,
+            "{}{}",
+            termion::screen::ToAlternateScreen,
+            termion::cursor::Hide
+        )?;
+        Ok(Self {
+            writer,
+            focus: Focus::Jobs,
+            selected: 0,
+            offset: 0,
+            scroll: 0,
+            message: String::new(),
+        })
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    fn quit(&mut self) -> io::Result<()> {
+        write!(
+            self.writer,
+            "{}{}",
+            termion::screen::ToMainScreen,
+            termion::cursor::Show
+        )?;
+        self.writer.flush()
+    }
+
+    pub fn render(&mut self, jobs: &[Job]) -> io::Result<()> {
+        let (width, height) = termion::terminal_size().unwrap_or((80, 24));
+        let (width, height) = (width as usize, height as usize);
+        let (list_lines, output_lines) = pane_heights(height, jobs.len());
+        self.selected = self.selected.min(jobs.len().saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if list_lines > 0 && self.selected >= self.offset + list_lines {
+            self.offset = self.selected + 1 - list_lines;
+        }
+
+        write!(
+            self.writer,
+            "{}{}",
+            termion::clear::All,
+            termion::cursor::Goto(1, 1)
+        )?;
+
+        let project_width = jobs
+            .iter()
+            .map(|job| text::str_width(&job.project))
+            .max()
+            .unwrap_or_default();
+        for line in 0..list_lines {
+            let index = self.offset + line;
+            if let Some(job) = jobs.get(index) {
+                let padding = project_width - text::str_width(&job.project);
+                let row = format!(
+                    "{}{}  {}",
+                    job.project,
+                    " ".repeat(padding),
+                    job.state.as_str()
+                );
+                let row = text::truncate_to_width(&row, width);
+                if index == self.selected {
+                    write!(
+                        self.writer,
+                        "{}{row}{}",
+                        termion::style::Invert,
+                        termion::style::Reset
+                    )?;
+                } else {
+                    self.writer.write_all(row.as_bytes())?;
+                }
+            }
+            self.writer.write_all(b"\r\n")?;
+        }
+
+        // Output pane of the selected job
+        let job = jobs.get(self.selected);
+        let title = match job {
+            Some(Job {
+                project,
+                state: JobState::Failed(reason),
+                ..
+            }) => format!("── {project} ({reason}) "),
+            Some(job) => format!("── {} ({}) ", job.project, job.state.as_str()),
+            None => String::new(),
+        };
+        let title_width = text::str_width(&title);
+        let title = format!("{title}{}", "─".repeat(width.saturating_sub(title_width)));
+        let style = match self.focus {
+            Focus::Output => termion::style::Bold.to_string(),
+            Focus::Jobs => termion::style::Faint.to_string(),
+        };
+        write!(
+            self.writer,
+            "{style}{}{}\r\n",
+            text::truncate_to_width(&title, width),
+            termion::style::Reset
+        )?;
+
+        let output = job.map(|job| job.output.as_slice()).unwrap_or_default();
+        let max_scroll = output.len().saturating_sub(output_lines);
+        self.scroll = self.scroll.min(max_scroll);
+        let end = output.len() - self.scroll;
+        let start = end.saturating_sub(output_lines);
+        for line in 0..output_lines {
+            if let Some(s) = output.get(start + line).filter(|_| start + line < end) {
+                self.writer
+                    .write_all(text::truncate_to_width(s, width).as_bytes())?;
+            }
+            self.writer.write_all(b"\r\n")?;
+        }
+
+        let done = jobs.iter().filter(|job| job.state.is_done()).count();
+        let status_line = match self.message.is_empty() {
+            true => format!("{done}/{} done  {KEY_HELP}", jobs.len()),
+            false => format!("{done}/{} done  {}", jobs.len(), self.message),
+        };
+        write!(
+            self.writer,
+            "{}{}{}",
+            termion::style::Faint,
+            text::truncate_to_width(&status_line, width),
+            termion::style::Reset
+        )?;
+        self.writer.flush()
+    }
+
+    pub fn handle_event(&mut self, event: Event, jobs: usize) -> Option<ViewEvent> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        let page = termion::terminal_size()
+            .map(|(_, height)| pane_heights(height as usize, jobs).1)
+            .unwrap_or(1)
+            .max(1);
+        match (key, self.focus) {
+            (Key::Char('q') | Key::Ctrl('c'), _) => Some(ViewEvent::Quit),
+            (Key::Char('\t') | Key::BackTab, _) => {
+                self.focus = match self.focus {
+                    Focus::Jobs => Focus::Output,
+                    Focus::Output => Focus::Jobs,
+                };
+                Some(ViewEvent::Render)
+            }
+            (Key::Esc, Focus::Output) => {
+                self.focus = Focus::Jobs;
+                Some(ViewEvent::Render)
+            }
+            (Key::Up | Key::Char('k'), Focus::Jobs) => self.select(self.selected.saturating_sub(1)),
+            (Key::Down | Key::Char('j'), Focus::Jobs) => self.select(self.selected + 1),
+            (Key::Home | Key::Char('g'), Focus::Jobs) => self.select(0),
+            (Key::End | Key::Char('G'), Focus::Jobs) => self.select(jobs.saturating_sub(1)),
+            (Key::Up | Key::Char('k'), Focus::Output) => self.scroll_to(self.scroll + 1),
+            (Key::Down | Key::Char('j'), Focus::Output) => {
+                self.scroll_to(self.scroll.saturating_sub(1))
+            }
+            (Key::PageUp, Focus::Output) => self.scroll_to(self.scroll + page),
+            (Key::PageDown, Focus::Output) => self.scroll_to(self.scroll.saturating_sub(page)),
+            (Key::Home | Key::Char('g'), Focus::Output) => self.scroll_to(usize::MAX),
+            (Key::End | Key::Char('G'), Focus::Output) => self.scroll_to(0),
+            _ => None,
+        }
+    }
+
+    fn select(&mut self, selected: usize) -> Option<ViewEvent> {
+        // The selection is clamped to the jobs when rendering
+        if selected == self.selected {
+            return None;
+        }
+        self.selected = selected;
+        self.scroll = 0;
+        Some(ViewEvent::Render)
+    }
+
+    fn scroll_to(&mut self, scroll: usize) -> Option<ViewEvent> {
+        // The scroll is clamped to the output when rendering
+        self.scroll = scroll;
+        Some(ViewEvent::Render)
+    }
+}
+
+/// Heights of the job list and the output pane. The list takes up to a third
+/// of the screen, and the rest is left for the output title, output, and status line.
+fn pane_heights(height: usize, jobs: usize) -> (usize, usize) {
+    let list = jobs.min(height / 3).max(1);
+    (list, height.saturating_sub(list + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_output_lines() {
+        assert_eq!(sanitize_line("plain"), "plain");
+        assert_eq!(sanitize_line("\x1b[1;31merror\x1b[0m: oops"), "error: oops");
+        assert_eq!(sanitize_line("a\tb\r"), "a    b");
+        assert_eq!(sanitize_line("\x1b7done\x07"), "done");
+    }
+
+    #[test]
+    fn tag_output_lines() {
+        assert_eq!(line_tag("a.com/x", 0, 12, false), "[a.com/x]    ");
+        assert_eq!(line_tag("a.com/long", 1, 5, false), "[a.com/long] ");
+        assert_eq!(
+            line_tag("a.com/x", 7, 9, true),
+            "\x1b[38;5;3m[a.com/x]\x1b[39m "
+        );
+    }
+
+    #[test]
+    fn split_screen_between_panes() {
+        assert_eq!(pane_heights(30, 3), (3, 25));
+        assert_eq!(pane_heights(30, 50), (10, 18));
+        assert_eq!(pane_heights(2, 5), (1, 0));
+    }
+}
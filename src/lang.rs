@@ -0,0 +1,25 @@
+use std::path::Path;
+
+/// Marker files that identify the ecosystem of a project
+const MARKERS: [(&str, &str); 4] = [
+    ("Cargo.toml", "rust"),
+    ("go.mod", "go"),
+    ("package.json", "node"),
+    ("pyproject.toml", "python"),
+];
+
+/// Names of the ecosystems that can be detected
+pub const NAMES: [&str; 4] = ["go", "node", "python", "rust"];
+
+/// Detect the ecosystems of the project in the given directory from the marker files
+/// at the top of the project. Returns the names sorted alphabetically.
+pub fn detect(project_path: &Path) -> Vec<&'static str> {
+    let mut languages: Vec<&str> = MARKERS
+        .iter()
+        .filter(|(marker, _)| project_path.join(marker).is_file())
+        .map(|(_, name)| *name)
+        .collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
@@ -1,13 +1,37 @@
 mod app;
+mod auth;
+mod bench;
+mod build_info;
+mod checkouts;
 mod cli;
+mod completions;
 mod config;
+mod crypt;
+mod daemon;
+mod dashboard;
 mod db;
+mod disk_usage;
+mod forge;
 mod fuzzy;
 mod git_cmd;
 mod git_dir;
 mod git_url;
+mod glob;
+mod history;
+mod jobs;
+mod lang;
+mod opener;
+mod oplog;
+mod parallel;
+mod progress;
+mod project_config;
+mod status;
+mod template;
 mod text;
+mod tidy;
+mod trigram;
 mod tui;
+mod watch;
 
 use std::process::ExitCode;
 
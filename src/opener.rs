@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::text;
+
+/// Name of the opener that uses the editor from the `VISUAL` or `EDITOR` environment variables
+pub const EDITOR: &str = "editor";
+const DEFAULT_EDITOR: &str = "vi";
+const PATH_PLACEHOLDER: &str = "{path}";
+
+/// Choose the command template for opening a project.
+/// An opener chosen by name is used first, then the opener from the project configuration,
+/// then the opener of the first detected language that has one, and then the default opener.
+/// Returns None when the editor should be used.
+pub fn choose<'a>(
+    name: Option<&str>,
+    project_opener: Option<&'a str>,
+    languages: &[&str],
+    default: Option<&'a str>,
+    openers: &'a BTreeMap<String, String>,
+    lang_openers: &'a BTreeMap<String, String>,
+) -> Result<Option<&'a str>> {
+    if let Some(name) = name {
+        if name == EDITOR {
+            return Ok(None);
+        }
+        return match openers.get(name) {
+            Some(template) => Ok(Some(template)),
+            None => {
+                let names: Vec<&str> = std::iter::once(EDITOR)
+                    .chain(openers.keys().map(|k| k.as_str()))
+                    .collect();
+                bail!(
+                    "Opener not found: {name}. Available openers: {}",
+                    names.join(", ")
+                );
+            }
+        };
+    }
+    let lang_opener = languages
+        .iter()
+        .find_map(|lang| lang_openers.get(*lang))
+        .map(String::as_str);
+    Ok(project_opener.or(lang_opener).or(default))
+}
+
+/// Editor command from the `VISUAL` or `EDITOR` environment variables
+pub fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_EDITOR))
+}
+
+/// Build the command line for opening a project from a command template.
+/// `{path}` in the template is replaced with the project path.
+/// When the template doesn't have it, the path is added as the last argument.
+pub fn command_line(template: &str, path: &Path) -> Result<Vec<String>> {
+    let mut words = text::split_words(template)?;
+    if words.is_empty() {
+        bail!("Opener command is empty");
+    }
+    let path = path.to_string_lossy();
+    if words.iter().any(|word| word.contains(PATH_PLACEHOLDER)) {
+        for word in words.iter_mut() {
+            *word = word.replace(PATH_PLACEHOLDER, &path);
+        }
+    } else {
+        words.push(path.into_owned());
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choose_opener() {
+        let openers = BTreeMap::from([
+            (String::from("code"), String::from("code {path}")),
+            (String::from("idea"), String::from("idea")),
+        ]);
+        let lang_openers = BTreeMap::from([(String::from("go"), String::from("goland {path}"))]);
+        let choose = |name, languages: &[&str], default| {
+            choose(name, None, languages, default, &openers, &lang_openers).unwrap()
+        };
+        assert_eq!(choose(None, &[], None), None);
+        assert_eq!(choose(None, &["rust"], Some("hx")), Some("hx"));
+        assert_eq!(
+            choose(None, &["node", "go"], Some("hx")),
+            Some("goland {path}")
+        );
+        assert_eq!(choose(Some("code"), &["go"], None), Some("code {path}"));
+        assert_eq!(choose(Some(EDITOR), &["go"], Some("hx")), None);
+        let project_opener = super::choose(
+            None,
+            Some("clion"),
+            &["go"],
+            Some("hx"),
+            &openers,
+            &lang_openers,
+        );
+        assert_eq!(project_opener.unwrap(), Some("clion"));
+
+        let err = super::choose(Some("vim"), None, &[], None, &openers, &lang_openers).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Opener not found: vim. Available openers: editor, code, idea"
+        );
+    }
+
+    #[test]
+    fn build_command_line() {
+        let path = Path::new("/p/a.com/x");
+        assert_eq!(
+            command_line("code --goto {path}", path).unwrap(),
+            vec!["code", "--goto", "/p/a.com/x"]
+        );
+        assert_eq!(
+            command_line("'my ide' -w", path).unwrap(),
+            vec!["my ide", "-w", "/p/a.com/x"]
+        );
+        assert_eq!(
+            command_line("tmux new-window -c {path} -n x{path}", path).unwrap(),
+            vec![
+                "tmux",
+                "new-window",
+                "-c",
+                "/p/a.com/x",
+                "-n",
+                "x/p/a.com/x"
+            ]
+        );
+        assert!(command_line(" ", path).is_err());
+    }
+}
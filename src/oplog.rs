@@ -0,0 +1,294 @@
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::crypt;
+use crate::db::unix_secs;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Kind of operation done by gorg
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Project cloned from the remote URL in the details
+    Clone,
+    /// New local project initialized
+    Init,
+    /// Project moved to the archive path in the details
+    Archive,
+    /// Project moved to the entry in the details
+    Move,
+    /// Empty directory removed
+    Remove,
+    /// Project restored from the bundle in the details
+    Restore,
+    /// Index updated with the number of added and removed entries in the details
+    UpdateIndex,
+    /// Command in the details run in the projects matching the query in the target
+    Run,
+    /// Operation on the target undone. The details identify the undone operation.
+    Undo,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Clone => "clone",
+            Self::Init => "init",
+            Self::Archive => "archive",
+            Self::Move => "move",
+            Self::Remove => "remove",
+            Self::Restore => "restore",
+            Self::UpdateIndex => "update-index",
+            Self::Run => "run",
+            Self::Undo => "undo",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let action = match s {
+            "clone" => Self::Clone,
+            "init" => Self::Init,
+            "archive" => Self::Archive,
+            "move" => Self::Move,
+            "remove" => Self::Remove,
+            "restore" => Self::Restore,
+            "update-index" => Self::UpdateIndex,
+            "run" => Self::Run,
+            "undo" => Self::Undo,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Operation recorded in the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub time: SystemTime,
+    pub action: Action,
+    /// Project the operation was done on, or the query for operations on many projects
+    pub target: String,
+    /// Action specific details, such as the URL a project was cloned from
+    pub details: String,
+}
+
+impl Operation {
+    pub fn new(action: Action, target: &str, details: &str) -> Self {
+        Self {
+            time: SystemTime::now(),
+            action,
+            target: String::from(target),
+            details: String::from(details),
+        }
+    }
+
+    /// Details for the undo operation that undoes this operation
+    pub fn undo_details(&self) -> String {
+        format!("{} {}", self.action.as_str(), unix_secs(self.time))
+    }
+
+    fn is_undone_by(&self, undo: &Operation) -> bool {
+        undo.action == Action::Undo
+            && undo.target == self.target
+            && undo.details == self.undo_details()
+    }
+
+    fn parse_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let time = fields.next()?.parse().ok()?;
+        let action = Action::parse(fields.next()?)?;
+        let target = unescape(fields.next()?);
+        let details = unescape(fields.next()?);
+        Some(Self {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(time),
+            action,
+            target,
+            details,
+        })
+    }
+
+    fn format_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            unix_secs(self.time),
+            self.action.as_str(),
+            escape(&self.target),
+            escape(&self.details)
+        )
+    }
+}
+
+/// Append an operation to the log file.
+/// Each operation is written as a single line, so concurrent writers don't mix up the lines.
+pub fn append<P: AsRef<Path>>(path: P, operation: &Operation) -> Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let line = operation.format_line();
+    let line = crypt::encrypt_line(line.strip_suffix('\n').unwrap_or(&line))?;
+    file.write_all(format!("{line}\n").as_bytes())?;
+    Ok(())
+}
+
+/// Read all operations from the log file, oldest first.
+/// Lines that can't be parsed (e.g. from newer versions of gorg) are skipped.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Operation>> {
+    let path = path.as_ref();
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(err) => match err.kind() {
+            std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            _ => return Err(err.into()),
+        },
+    };
+    let mut operations = Vec::new();
+    for line in data.lines() {
+        if let Some(operation) = Operation::parse_line(&crypt::decrypt_line(path, line)?) {
+            operations.push(operation);
+        }
+    }
+    Ok(operations)
+}
+
+/// Find the most recent operation that archived or moved projects and hasn't been undone yet.
+/// Removed directories are skipped, as they are not kept anywhere.
+pub fn last_undoable(operations: &[Operation]) -> Option<&Operation> {
+    let mut undos = Vec::new();
+    for operation in operations.iter().rev() {
+        match operation.action {
+            Action::Undo => undos.push(operation),
+            Action::Archive | Action::Move
+                if !undos.iter().any(|undo| operation.is_undone_by(undo)) =>
+            {
+                return Some(operation);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Format a time as an ISO 8601 timestamp in UTC, e.g. `2025-01-31T12:00:00Z`
+pub fn format_time(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    let secs = secs % SECS_PER_DAY;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Convert days since the Unix epoch to a date in the proleptic Gregorian calendar
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Shift the epoch to 0000-03-01, so that leap days are at the end of the year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(ch) => out.push(ch),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operation_line_round_trip() {
+        let operation = Operation {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            action: Action::Run,
+            target: String::from("acme"),
+            details: String::from("sh -c 'printf \"a\\tb\\n\"'\tx"),
+        };
+        let line = operation.format_line();
+        assert_eq!(line.matches('\t').count(), 3);
+        assert_eq!(line.matches('\n').count(), 1);
+        assert_eq!(
+            Operation::parse_line(line.trim_end_matches('\n')),
+            Some(operation)
+        );
+        assert_eq!(Operation::parse_line("1\tunknown\tx\ty"), None);
+    }
+
+    #[test]
+    fn find_last_undoable_operation() {
+        let operation = |secs, action, target: &str, details: &str| Operation {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+            action,
+            target: String::from(target),
+            details: String::from(details),
+        };
+        let archive = operation(1, Action::Archive, "a.com/x", "/archive/a.com/x");
+        let moved = operation(2, Action::Move, "a.com/y", "a.com/z");
+        let mut operations = vec![
+            archive.clone(),
+            moved.clone(),
+            operation(3, Action::Clone, "a.com/w", "https://a.com/w.git"),
+        ];
+        assert_eq!(last_undoable(&operations), Some(&moved));
+        operations.push(operation(4, Action::Remove, "a.com/v", ""));
+        assert_eq!(last_undoable(&operations), Some(&moved));
+        operations.push(operation(5, Action::Undo, "a.com/y", &moved.undo_details()));
+        assert_eq!(last_undoable(&operations), Some(&archive));
+        operations.push(operation(
+            6,
+            Action::Undo,
+            "a.com/x",
+            &archive.undo_details(),
+        ));
+        assert_eq!(last_undoable(&operations), None);
+    }
+
+    #[test]
+    fn format_times() {
+        let time = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(format_time(time(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(time(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_time(time(1_700_000_000)), "2023-11-14T22:13:20Z");
+    }
+}
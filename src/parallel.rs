@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+/// Apply the given function to all items using a pool of threads.
+/// Items are picked up one at a time, so slow items don't hold up the rest of the work.
+/// Results are kept in the same order as the items.
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    map_in_threads(items, threads, f)
+}
+
+/// Like `map` but using the given number of threads
+pub fn map_in_threads<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let (next, f) = (&next, &f);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        results.push((index, f(item)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Worker thread must not panic"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Like `map` but with at most `group_limit` items of the same group processed at a time,
+/// e.g. to limit the number of concurrent requests to the same host.
+/// Items are picked up in order, skipping the ones whose group is at the limit.
+pub fn map_limited<T, R, F, G, K>(items: &[T], group: G, group_limit: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    G: Fn(&T) -> K,
+    K: Eq + Hash,
+{
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    map_limited_in_threads(items, threads, group, group_limit, f)
+}
+
+/// Like `map_limited` but using the given number of threads
+pub fn map_limited_in_threads<T, R, F, G, K>(
+    items: &[T],
+    threads: usize,
+    group: G,
+    group_limit: usize,
+    f: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    G: Fn(&T) -> K,
+    K: Eq + Hash,
+{
+    let group_limit = group_limit.max(1);
+    let mut group_ids = HashMap::new();
+    let groups: Vec<usize> = items
+        .iter()
+        .map(|item| {
+            let next_id = group_ids.len();
+            *group_ids.entry(group(item)).or_insert(next_id)
+        })
+        .collect();
+    let queue = Mutex::new(GroupQueue {
+        pending: (0..items.len()).collect(),
+        running: vec![0; group_ids.len()],
+    });
+    let finished = Condvar::new();
+    let (queue, finished, groups) = (&queue, &finished, &groups);
+
+    let indices: Vec<usize> = (0..items.len()).collect();
+    let mut results: Vec<(usize, R)> = map_in_threads(&indices, threads, |_| {
+        let index = {
+            let mut queue = queue.lock().expect("Queue lock must not be poisoned");
+            loop {
+                let next = queue
+                    .pending
+                    .iter()
+                    .position(|index| queue.running[groups[*index]] < group_limit);
+                if let Some(position) = next {
+                    let index = queue.pending.remove(position);
+                    queue.running[groups[index]] += 1;
+                    break index;
+                }
+                queue = finished
+                    .wait(queue)
+                    .expect("Queue lock must not be poisoned");
+            }
+        };
+        let result = f(&items[index]);
+        queue
+            .lock()
+            .expect("Queue lock must not be poisoned")
+            .running[groups[index]] -= 1;
+        finished.notify_all();
+        (index, result)
+    });
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Items waiting to be processed and the number of items being processed per group
+struct GroupQueue {
+    pending: Vec<usize>,
+    running: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_keeps_order() {
+        let items: Vec<u32> = (0..100).collect();
+        let results = map_in_threads(&items, 4, |n| n * 2);
+        let expected: Vec<u32> = (0..100).map(|n| n * 2).collect();
+        assert_eq!(results, expected);
+        assert!(map_in_threads(&[] as &[u32], 4, |n| *n).is_empty());
+    }
+
+    #[test]
+    fn map_limited_per_group() {
+        let items: Vec<u32> = (0..40).collect();
+        let running: Vec<AtomicUsize> = (0..2).map(|_| AtomicUsize::new(0)).collect();
+        let max_running: Vec<AtomicUsize> = (0..2).map(|_| AtomicUsize::new(0)).collect();
+        let results = map_limited_in_threads(
+            &items,
+            8,
+            |n| n % 2,
+            3,
+            |n| {
+                let group = (n % 2) as usize;
+                let now = running[group].fetch_add(1, Ordering::SeqCst) + 1;
+                max_running[group].fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(1));
+                running[group].fetch_sub(1, Ordering::SeqCst);
+                n * 2
+            },
+        );
+        let expected: Vec<u32> = (0..40).map(|n| n * 2).collect();
+        assert_eq!(results, expected);
+        for max in max_running.iter() {
+            assert!(max.load(Ordering::SeqCst) <= 3);
+        }
+    }
+}
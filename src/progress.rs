@@ -0,0 +1,67 @@
+use std::fmt::Arguments;
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress indicator rendered on a single line in stderr.
+/// Nothing is rendered when stderr is not a terminal.
+pub struct Progress {
+    enabled: bool,
+    label: &'static str,
+    frame: usize,
+    last_render: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(label: &'static str) -> Self {
+        Self {
+            enabled: std::io::stderr().is_terminal(),
+            label,
+            frame: 0,
+            last_render: None,
+        }
+    }
+
+    pub fn update(&mut self, message: Arguments) {
+        if !self.enabled {
+            return;
+        }
+        let now = Instant::now();
+        if self
+            .last_render
+            .is_some_and(|last_render| now.duration_since(last_render) < RENDER_INTERVAL)
+        {
+            return;
+        }
+        self.last_render = Some(now);
+        self.frame = (self.frame + 1) % SPINNER_FRAMES.len();
+
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{} {}: {message}{}",
+            SPINNER_FRAMES[self.frame],
+            self.label,
+            termion::clear::UntilNewline,
+        );
+        let _ = stderr.flush();
+    }
+
+    pub fn finish(&mut self) {
+        if !self.enabled || self.last_render.is_none() {
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r{}", termion::clear::CurrentLine);
+        let _ = stderr.flush();
+        self.last_render = None;
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Name of the configuration file inside a project
+pub const FILE_NAME: &str = ".gorg.toml";
+
+/// Project specific settings read from the `.gorg.toml` file committed in a repository
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Tags for finding the project with `--tag`. Tags can't contain commas or whitespace.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// Command template for opening the project, used over the language and default openers
+    #[serde(default)]
+    pub opener: Option<String>,
+
+    /// Command run by `gorg run` when no command is given
+    #[serde(default)]
+    pub task: Option<String>,
+
+    /// Whether to skip the project when running commands in all projects without a query
+    #[serde(default)]
+    pub exclude_from_runs: bool,
+}
+
+impl ProjectConfig {
+    /// Read the configuration of the project. Projects without the file use the defaults.
+    pub fn load(project_path: &Path) -> Result<ProjectConfig> {
+        let path = project_path.join(FILE_NAME);
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::from_str(&contents).with_context(|| {
+                format!("Invalid project configuration {}", path.to_string_lossy())
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Read the configuration of the project like `load`.
+    /// Invalid configuration files are reported, and the defaults are used instead.
+    pub fn load_or_default(project_path: &Path) -> ProjectConfig {
+        Self::load(project_path).unwrap_or_else(|err| {
+            log::error!("{err:#}");
+            Self::default()
+        })
+    }
+
+    fn from_str(s: &str) -> Result<ProjectConfig> {
+        let mut config: Self = toml::from_str(s)?;
+        // Tags are stored comma-separated in the index
+        config.tags.retain(|tag| {
+            let valid =
+                !tag.is_empty() && !tag.contains(|ch: char| ch == ',' || ch.is_whitespace());
+            if !valid {
+                log::error!("Ignoring invalid project tag: {tag:?}");
+            }
+            valid
+        });
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_project_config() {
+        let config = ProjectConfig::from_str(
+            "tags = [\"backend\", \"bad tag\", \"team-a\"]\ntask = \"make test\"\nexclude_from_runs = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ProjectConfig {
+                tags: vec![String::from("backend"), String::from("team-a")],
+                opener: None,
+                task: Some(String::from("make test")),
+                exclude_from_runs: true,
+            }
+        );
+        assert_eq!(
+            ProjectConfig::from_str("").unwrap(),
+            ProjectConfig::default()
+        );
+        assert!(ProjectConfig::from_str("tag = [\"x\"]\n").is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+
+use crate::crypt;
+use crate::db::{self, unix_secs};
+use crate::git_cmd::GitCmd;
+use crate::parallel;
+
+const FETCH_HEAD_PATH: &str = ".git/FETCH_HEAD";
+
+/// Git status of a project at the time it was last checked
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoStatus {
+    /// Checked out branch. None when HEAD is detached.
+    pub branch: Option<String>,
+
+    /// Whether there are uncommitted changes or untracked files
+    pub dirty: bool,
+
+    /// Number of commits ahead of and behind the upstream branch. None when there is no upstream.
+    pub ahead_behind: Option<(u32, u32)>,
+
+    /// Time of the last fetch
+    pub fetched_at: Option<SystemTime>,
+
+    /// Time when the status was read
+    pub checked_at: SystemTime,
+}
+
+impl RepoStatus {
+    /// Short summary of the status, e.g. `main* +1 -2`
+    pub fn summary(&self) -> String {
+        let mut s = match &self.branch {
+            Some(branch) => branch.clone(),
+            None => String::from("(detached)"),
+        };
+        if self.dirty {
+            s.push('*');
+        }
+        if let Some((ahead, behind)) = self.ahead_behind {
+            if ahead > 0 {
+                s.push_str(&format!(" +{ahead}"));
+            }
+            if behind > 0 {
+                s.push_str(&format!(" -{behind}"));
+            }
+        }
+        s
+    }
+
+    fn parse_line(line: &str) -> Option<(&str, Self)> {
+        let mut fields = line.split('\t');
+        let entry = fields.next()?;
+        let branch = fields.next()?;
+        let dirty = fields.next()?;
+        let ahead = fields.next()?;
+        let behind = fields.next()?;
+        let fetched_at = fields.next()?;
+        let checked_at = fields.next()?;
+        let ahead_behind = match (ahead.parse(), behind.parse()) {
+            (Ok(ahead), Ok(behind)) => Some((ahead, behind)),
+            _ => None,
+        };
+        let status = Self {
+            branch: (!branch.is_empty()).then(|| String::from(branch)),
+            dirty: dirty == "1",
+            ahead_behind,
+            fetched_at: parse_time(fetched_at),
+            checked_at: parse_time(checked_at)?,
+        };
+        Some((entry, status))
+    }
+
+    fn write_line(&self, entry: &str, out: &mut String) {
+        let (ahead, behind) = match self.ahead_behind {
+            Some((ahead, behind)) => (ahead.to_string(), behind.to_string()),
+            None => (String::from("-"), String::from("-")),
+        };
+        let fetched_at = match self.fetched_at {
+            Some(fetched_at) => unix_secs(fetched_at).to_string(),
+            None => String::from("-"),
+        };
+        let fields = [
+            entry,
+            self.branch.as_deref().unwrap_or_default(),
+            if self.dirty { "1" } else { "0" },
+            &ahead,
+            &behind,
+            &fetched_at,
+            &unix_secs(self.checked_at).to_string(),
+        ];
+        out.push_str(&fields.join("\t"));
+        out.push('\n');
+    }
+}
+
+/// States a project must be in to be included. Unset states are not checked.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatusFilter {
+    /// Uncommitted changes or untracked files
+    pub dirty: bool,
+    /// Commits not pushed to the upstream branch
+    pub ahead: bool,
+    /// Commits not pulled from the upstream branch
+    pub behind: bool,
+    /// HEAD is detached
+    pub detached: bool,
+    /// Checked out branch has no upstream branch
+    pub no_upstream: bool,
+}
+
+impl StatusFilter {
+    pub fn is_empty(&self) -> bool {
+        !(self.dirty || self.ahead || self.behind || self.detached || self.no_upstream)
+    }
+
+    /// Whether the status is in all of the states set in the filter
+    pub fn matches(&self, status: &RepoStatus) -> bool {
+        let (ahead, behind) = status.ahead_behind.unwrap_or_default();
+        (!self.dirty || status.dirty)
+            && (!self.ahead || ahead > 0)
+            && (!self.behind || behind > 0)
+            && (!self.detached || status.branch.is_none())
+            && (!self.no_upstream || (status.branch.is_some() && status.ahead_behind.is_none()))
+    }
+}
+
+/// Git statuses of projects stored next to the index, so that
+/// listing them doesn't require running Git for every project.
+#[derive(Default)]
+pub struct StatusCache {
+    /// Statuses sorted by the entry
+    entries: Vec<(String, RepoStatus)>,
+}
+
+impl StatusCache {
+    /// Load the cache from the given file. Returns an empty cache when the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match crypt::read_to_string(path.as_ref())? {
+            Some(data) => Ok(Self::parse(&data)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn parse(data: &str) -> Self {
+        let mut cache = Self::default();
+        for line in data.lines() {
+            match RepoStatus::parse_line(line) {
+                Some((entry, status)) => cache.set(entry, status),
+                None => log::debug!("Ignoring invalid status line: {line}"),
+            }
+        }
+        cache
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut contents = String::with_capacity(self.entries.len() * 100);
+        for (entry, status) in self.entries.iter() {
+            status.write_line(entry, &mut contents);
+        }
+        db::write_atomically(path.as_ref(), &crypt::encrypt(contents.into_bytes())?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, entry: &str) -> Option<&RepoStatus> {
+        self.entries
+            .binary_search_by(|(e, _)| e.as_str().cmp(entry))
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    pub fn set(&mut self, entry: &str, status: RepoStatus) {
+        match self
+            .entries
+            .binary_search_by(|(e, _)| e.as_str().cmp(entry))
+        {
+            Ok(index) => self.entries[index].1 = status,
+            Err(index) => self.entries.insert(index, (String::from(entry), status)),
+        }
+    }
+
+    /// Remove the statuses of entries that don't satisfy the given function
+    pub fn retain<F: Fn(&str) -> bool>(&mut self, keep: F) {
+        self.entries.retain(|(entry, _)| keep(entry));
+    }
+
+    /// Find the cached entry that contains the given path relative to the projects directory
+    pub fn entry_containing<'a>(&self, mut rel_path: &'a str) -> Option<&'a str> {
+        loop {
+            if self.get(rel_path).is_some() {
+                return Some(rel_path);
+            }
+            rel_path = &rel_path[..rel_path.rfind('/')?];
+        }
+    }
+}
+
+/// Read the Git status of the project in the given directory
+pub fn read_status(git_cmd: &GitCmd, dir: &Path) -> Result<RepoStatus> {
+    let checked_at = SystemTime::now();
+    let output = git_cmd.status(dir)?;
+    let (branch, dirty, ahead_behind) = parse_porcelain(&output);
+    // Failed fetches leave behind an empty FETCH_HEAD
+    let fetched_at = std::fs::metadata(dir.join(FETCH_HEAD_PATH))
+        .ok()
+        .filter(|metadata| metadata.len() > 0)
+        .and_then(|metadata| metadata.modified().ok());
+    Ok(RepoStatus {
+        branch,
+        dirty,
+        ahead_behind,
+        fetched_at,
+        checked_at,
+    })
+}
+
+/// Read the Git statuses of the given projects in parallel and store them in the cache
+pub fn refresh(git_cmd: &GitCmd, projects_path: &Path, entries: &[&str], cache: &mut StatusCache) {
+    let statuses = parallel::map(entries, |entry| {
+        read_status(git_cmd, &db::entry_path(projects_path, entry))
+    });
+    for (entry, res) in entries.iter().zip(statuses) {
+        match res {
+            Ok(status) => cache.set(entry, status),
+            Err(err) => log::error!("Failed to read status of {entry}: {err}"),
+        }
+    }
+}
+
+/// Parse the branch, dirty state, and upstream difference
+/// from the output of `git status --porcelain=v2 --branch`.
+fn parse_porcelain(output: &str) -> (Option<String>, bool, Option<(u32, u32)>) {
+    let mut branch = None;
+    let mut dirty = false;
+    let mut ahead_behind = None;
+    for line in output.lines() {
+        let Some(header) = line.strip_prefix("# ") else {
+            dirty |= !line.is_empty();
+            continue;
+        };
+        if let Some(head) = header.strip_prefix("branch.head ") {
+            branch = (head != "(detached)").then(|| String::from(head));
+        } else if let Some(ab) = header.strip_prefix("branch.ab ") {
+            let mut counts = ab
+                .split(' ')
+                .map(|count| count.trim_start_matches(['+', '-']).parse().ok());
+            if let (Some(Some(ahead)), Some(Some(behind))) = (counts.next(), counts.next()) {
+                ahead_behind = Some((ahead, behind));
+            }
+        }
+    }
+    (branch, dirty, ahead_behind)
+}
+
+/// Human-readable age of the given time, e.g. `3h`
+pub fn format_age(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn parse_time(s: &str) -> Option<SystemTime> {
+    let secs = s.parse().ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_porcelain_output() {
+        let output = "# branch.oid 1234\n# branch.head main\n# branch.upstream origin/main\n# branch.ab +1 -2\n? new.txt\n";
+        assert_eq!(
+            parse_porcelain(output),
+            (Some(String::from("main")), true, Some((1, 2)))
+        );
+
+        let output = "# branch.oid 1234\n# branch.head (detached)\n";
+        assert_eq!(parse_porcelain(output), (None, false, None));
+    }
+
+    #[test]
+    fn summary() {
+        let status = RepoStatus {
+            branch: Some(String::from("main")),
+            dirty: true,
+            ahead_behind: Some((0, 3)),
+            fetched_at: None,
+            checked_at: SystemTime::UNIX_EPOCH,
+        };
+        assert_eq!(status.summary(), "main* -3");
+        let status = RepoStatus {
+            branch: None,
+            dirty: false,
+            ahead_behind: None,
+            ..status
+        };
+        assert_eq!(status.summary(), "(detached)");
+    }
+
+    #[test]
+    fn filter_statuses() {
+        let status = RepoStatus {
+            branch: Some(String::from("main")),
+            dirty: true,
+            ahead_behind: Some((2, 0)),
+            fetched_at: None,
+            checked_at: SystemTime::UNIX_EPOCH,
+        };
+        let no_upstream = RepoStatus {
+            dirty: false,
+            ahead_behind: None,
+            ..status.clone()
+        };
+        let detached = RepoStatus {
+            branch: None,
+            ..no_upstream.clone()
+        };
+
+        assert!(StatusFilter::default().is_empty());
+        assert!(StatusFilter::default().matches(&detached));
+        let dirty_ahead = StatusFilter {
+            dirty: true,
+            ahead: true,
+            ..Default::default()
+        };
+        assert!(!dirty_ahead.is_empty());
+        assert!(dirty_ahead.matches(&status));
+        assert!(!dirty_ahead.matches(&no_upstream));
+        let behind = StatusFilter {
+            behind: true,
+            ..Default::default()
+        };
+        assert!(!behind.matches(&status));
+        let no_upstream_filter = StatusFilter {
+            no_upstream: true,
+            ..Default::default()
+        };
+        assert!(no_upstream_filter.matches(&no_upstream));
+        assert!(!no_upstream_filter.matches(&detached));
+        assert!(!no_upstream_filter.matches(&status));
+        let detached_filter = StatusFilter {
+            detached: true,
+            ..Default::default()
+        };
+        assert!(detached_filter.matches(&detached));
+        assert!(!detached_filter.matches(&status));
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let mut cache = StatusCache::default();
+        let status = RepoStatus {
+            branch: Some(String::from("main")),
+            dirty: true,
+            ahead_behind: Some((1, 0)),
+            fetched_at: Some(at(100)),
+            checked_at: at(200),
+        };
+        let detached = RepoStatus {
+            branch: None,
+            dirty: false,
+            ahead_behind: None,
+            fetched_at: None,
+            checked_at: at(300),
+        };
+        cache.set("a.com/y", detached.clone());
+        cache.set("a.com/x", status.clone());
+
+        let mut s = String::new();
+        for (entry, status) in cache.entries.iter() {
+            status.write_line(entry, &mut s);
+        }
+        assert_eq!(
+            s,
+            "a.com/x\tmain\t1\t1\t0\t100\t200\na.com/y\t\t0\t-\t-\t-\t300\n"
+        );
+
+        let parsed = StatusCache::parse(&s);
+        assert_eq!(parsed.get("a.com/x"), Some(&status));
+        assert_eq!(parsed.get("a.com/y"), Some(&detached));
+        assert_eq!(
+            parsed.entry_containing("a.com/x/src/main.rs"),
+            Some("a.com/x")
+        );
+        assert_eq!(parsed.entry_containing("a.com/z"), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Result, bail};
+
+use crate::config::Template;
+
+const GIT_DIR_NAME: &str = ".git";
+
+/// Apply a template to a newly created project directory
+pub fn apply(template: &Template, project_path: &Path) -> Result<()> {
+    match template {
+        Template::Dir(template_path) => {
+            if !template_path.is_dir() {
+                bail!(
+                    "Template directory does not exist: {}",
+                    template_path.to_string_lossy()
+                );
+            }
+            log::debug!(
+                "Copying template {} to {}",
+                template_path.to_string_lossy(),
+                project_path.to_string_lossy()
+            );
+            copy_dir(template_path, project_path)
+        }
+        Template::Command(command) => {
+            log::debug!(
+                "Running template command in {}: {command}",
+                project_path.to_string_lossy()
+            );
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .current_dir(project_path)
+                .spawn()?
+                .wait()?;
+            if !status.success() {
+                bail!("Template command failed: exit code = {:?}", status.code());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Recursively copy the contents of a directory. Git directories are not copied,
+/// and symbolic links are copied as links instead of the files they point to.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == GIT_DIR_NAME {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        let file_type = std::fs::symlink_metadata(entry.path())?.file_type();
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_template_dir() {
+        let work_dir =
+            std::env::temp_dir().join(format!("gorg-test-template-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&work_dir);
+        let from = work_dir.join("template");
+        let to = work_dir.join("project");
+        std::fs::create_dir_all(from.join(".git")).unwrap();
+        std::fs::create_dir_all(from.join("src")).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        std::fs::write(from.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(from.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::os::unix::fs::symlink("src/main.rs", from.join("link")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent", from.join("dangling")).unwrap();
+
+        copy_dir(&from, &to).unwrap();
+
+        assert!(!to.join(".git").exists());
+        assert_eq!(
+            std::fs::read_to_string(to.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            std::fs::read_link(to.join("link")).unwrap(),
+            Path::new("src/main.rs")
+        );
+        assert_eq!(
+            std::fs::read_link(to.join("dangling")).unwrap(),
+            Path::new("/nonexistent")
+        );
+        std::fs::remove_dir_all(&work_dir).unwrap();
+    }
+}
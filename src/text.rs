@@ -1,7 +1,289 @@
+use std::borrow::Cow;
+
+use anyhow::{Result, bail};
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_properties::{GeneralCategoryGroup, UnicodeGeneralCategory};
+use unicode_width::UnicodeWidthChar;
+
+/// Whether the character separates words in queries and project paths.
+/// ASCII symbols (e.g. `/`, `+`, `~`) count as separators in addition to
+/// Unicode whitespace, punctuation and separator characters.
 pub fn is_punctuation(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_whitespace() || ch.is_ascii_punctuation();
+    }
     ch.is_whitespace()
-        || (ch >= '!' && ch <= '/')
-        || (ch >= ':' && ch <= '@')
-        || (ch >= '[' && ch <= '`')
-        || (ch >= '{' && ch <= '~')
+        || matches!(
+            ch.general_category_group(),
+            GeneralCategoryGroup::Punctuation | GeneralCategoryGroup::Separator
+        )
+}
+
+/// Fold the string for matching by decomposing it (NFKD) and dropping the combining marks,
+/// so that e.g. `josé` and `jose` compare equal
+pub fn fold(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(s.nfkd().filter(|ch| !is_combining_mark(*ch)).collect())
+}
+
+/// Split a command line into words like a POSIX shell would, without any expansions.
+/// Words are separated by whitespace, and quotes and backslashes can be used for keeping
+/// whitespace in words. Backslashes are literal inside single quotes, and inside double quotes
+/// they only escape double quotes and backslashes.
+pub fn split_words(s: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => word.push(ch),
+                        None => bail!("Missing closing single quote in: {s}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(ch @ ('"' | '\\')) => word.push(ch),
+                            Some(ch) => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                            None => bail!("Missing closing double quote in: {s}"),
+                        },
+                        Some(ch) => word.push(ch),
+                        None => bail!("Missing closing double quote in: {s}"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(ch) => word.get_or_insert_default().push(ch),
+                None => bail!("Trailing backslash in: {s}"),
+            },
+            ch => word.get_or_insert_default().push(ch),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Number of terminal columns the given character occupies
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+/// Number of terminal columns the given string occupies
+pub fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// Longest prefix of the string that fits in the given number of terminal columns
+pub fn truncate_to_width(s: &str, max_width: usize) -> &str {
+    let mut width = 0;
+    for (index, ch) in s.char_indices() {
+        width += char_width(ch);
+        if width > max_width {
+            return &s[..index];
+        }
+    }
+    s
+}
+
+/// Number of single character insertions, deletions, and substitutions
+/// needed for turning one string into another
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[b.len()]
+}
+
+/// Replace the `{name}` placeholders in the template with their values.
+/// Fails on placeholders without a value, e.g. misspelled ones.
+pub fn fill_placeholders(template: &str, values: &[(&str, &str)]) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some((name, after)) = rest[start + 1..].split_once('}') else {
+            bail!("Unclosed placeholder in template: {template}");
+        };
+        match values.iter().find(|(placeholder, _)| *placeholder == name) {
+            Some((_, value)) => out.push_str(value),
+            None => {
+                let names: Vec<String> = values
+                    .iter()
+                    .map(|(name, _)| format!("{{{name}}}"))
+                    .collect();
+                bail!(
+                    "Unknown placeholder {{{name}}}. Use one of: {}",
+                    names.join(", ")
+                );
+            }
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Write the rows as a Markdown table with the given column headers
+pub fn write_markdown_table<W: std::io::Write>(
+    w: &mut W,
+    headers: &[&str],
+    rows: &[Vec<String>],
+) -> std::io::Result<()> {
+    writeln!(w, "| {} |", headers.join(" | "))?;
+    writeln!(w, "|{}", " --- |".repeat(headers.len()))?;
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+        writeln!(w, "| {} |", cells.join(" | "))?;
+    }
+    Ok(())
+}
+
+/// Escape the text for a Markdown table cell, which can't span lines or contain pipes
+fn markdown_cell(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_tables() {
+        let mut out = Vec::new();
+        let rows = vec![
+            vec![String::from("a.com/x"), String::from("main* +1")],
+            vec![String::from("a.com/y"), String::from("a|b\nc")],
+        ];
+        write_markdown_table(&mut out, &["Project", "Status"], &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "| Project | Status |\n\
+             | --- | --- |\n\
+             | a.com/x | main* +1 |\n\
+             | a.com/y | a\\|b c |\n"
+        );
+    }
+
+    #[test]
+    fn fill_template_placeholders() {
+        let values = [("host", "a.com"), ("org", "acme"), ("repo", "api")];
+        assert_eq!(
+            fill_placeholders("git@{host}:{org}/{repo}.git", &values).unwrap(),
+            "git@a.com:acme/api.git"
+        );
+        assert_eq!(fill_placeholders("plain", &values).unwrap(), "plain");
+        assert_eq!(
+            fill_placeholders("{hots}", &values)
+                .unwrap_err()
+                .to_string(),
+            "Unknown placeholder {hots}. Use one of: {host}, {org}, {repo}"
+        );
+        assert!(fill_placeholders("{host", &values).is_err());
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("project_path", "projects_path"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("mosue", "mouse"), 2);
+        assert_eq!(edit_distance("jösé", "jose"), 2);
+    }
+
+    #[test]
+    fn punctuation_ascii() {
+        for ch in ['/', '.', '-', '_', ' ', '~', '+', '@'] {
+            assert!(is_punctuation(ch), "{ch:?}");
+        }
+        for ch in ['a', 'Z', '0'] {
+            assert!(!is_punctuation(ch), "{ch:?}");
+        }
+    }
+
+    #[test]
+    fn punctuation_non_ascii() {
+        // Ideographic full stop and space, em dash, no-break space, guillemet
+        for ch in ['\u{3002}', '\u{3000}', '\u{2014}', '\u{a0}', '\u{ab}'] {
+            assert!(is_punctuation(ch), "{ch:?}");
+        }
+        // Letters, ideographs and combining marks are part of words
+        for ch in ['é', 'ß', '日', '\u{301}'] {
+            assert!(!is_punctuation(ch), "{ch:?}");
+        }
+    }
+
+    #[test]
+    fn fold_diacritics() {
+        assert!(matches!(fold("github.com/jose"), Cow::Borrowed(_)));
+        assert_eq!(fold("josé-utils"), "jose-utils");
+        // Precomposed and decomposed forms fold the same way
+        assert_eq!(fold("Ångström"), fold("A\u{30a}ngstro\u{308}m"));
+        assert_eq!(fold("ﬁle"), "file");
+    }
+
+    #[test]
+    fn split_command_words() {
+        let split = |s| split_words(s).unwrap();
+        assert_eq!(split("git fetch  --all"), ["git", "fetch", "--all"]);
+        assert_eq!(
+            split(r#"git commit -m 'fix: a "b"' --author="A B""#),
+            ["git", "commit", "-m", r#"fix: a "b""#, "--author=A B"]
+        );
+        assert_eq!(
+            split(r#"echo "a\"b\c" d\ e ''"#),
+            ["echo", r#"a"b\c"#, "d e", ""]
+        );
+        assert!(split("  ").is_empty());
+        assert!(split_words("echo 'a").is_err());
+        assert!(split_words("echo \"a").is_err());
+        assert!(split_words("echo a\\").is_err());
+    }
+
+    #[test]
+    fn truncate_ascii() {
+        assert_eq!(truncate_to_width("github.com", 6), "github");
+        assert_eq!(truncate_to_width("github.com", 20), "github.com");
+    }
+
+    #[test]
+    fn truncate_wide_chars() {
+        // Each of these characters takes two columns
+        assert_eq!(truncate_to_width("日本語", 4), "日本");
+        assert_eq!(truncate_to_width("日本語", 5), "日本");
+        assert_eq!(truncate_to_width("a日本", 2), "a");
+    }
+
+    #[test]
+    fn truncate_multi_byte_boundary() {
+        assert_eq!(truncate_to_width("héllo", 2), "hé");
+    }
 }
@@ -0,0 +1,375 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::db;
+use crate::git_url;
+use crate::glob;
+
+const GIT_DIR_NAME: &str = ".git";
+
+/// Clutter found in the projects directory
+#[derive(Debug, PartialEq)]
+pub enum Finding {
+    /// Directory that contains nothing but empty directories
+    Empty(String),
+
+    /// Directory that contains files but no Git repositories or pinned directories
+    NoRepository(String),
+
+    /// Repository that is not located where its remote URL would place it
+    Misplaced { entry: String, expected: String },
+}
+
+impl Finding {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Empty(_) => "empty",
+            Self::NoRepository(_) => "no-repository",
+            Self::Misplaced { .. } => "misplaced",
+        }
+    }
+
+    /// Path of the directory relative to the projects directory
+    pub fn entry(&self) -> &str {
+        match self {
+            Self::Empty(entry) | Self::NoRepository(entry) => entry,
+            Self::Misplaced { entry, .. } => entry,
+        }
+    }
+
+    /// Suggested action for cleaning up the clutter
+    pub fn suggestion(&self) -> String {
+        match self {
+            Self::Empty(_) => String::from("remove"),
+            Self::NoRepository(_) => String::from("pin or remove"),
+            Self::Misplaced { expected, .. } => format!("move to {expected}"),
+        }
+    }
+}
+
+/// What was found in a scanned directory
+struct DirContents {
+    /// Whether the directory is or contains a repository or a pinned directory
+    has_project: bool,
+
+    /// Whether the directory or its sub-directories contain anything other than directories
+    has_files: bool,
+}
+
+/// Scan the projects directory for clutter. Pinned directories are treated as projects,
+/// and the directories matching the exclude patterns are left alone.
+/// The remote URL of a repository is looked up using the given function,
+/// and the expected location is derived from it like in `git_url::to_path`.
+pub fn scan<F>(
+    projects_path: &Path,
+    pinned: &[String],
+    exclude: &[glob::Pattern],
+    lowercase_paths: bool,
+    remote_url: F,
+) -> Result<Vec<Finding>>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut scanner = Scanner {
+        projects_path,
+        pinned,
+        exclude,
+        lowercase_paths,
+        remote_url,
+        findings: Vec::new(),
+    };
+    scanner.scan_children(projects_path, "", true)?;
+    let mut findings = scanner.findings;
+    findings.sort_by(|a, b| a.entry().cmp(b.entry()));
+    Ok(findings)
+}
+
+/// Remove a directory that contains nothing but empty directories.
+/// Fails without removing any files when the directory is not empty.
+pub fn remove_empty_dir(dir: &Path) -> Result<()> {
+    for child in std::fs::read_dir(dir)? {
+        let child = child?;
+        if child.file_type()?.is_dir() {
+            remove_empty_dir(&child.path())?;
+        }
+    }
+    std::fs::remove_dir(dir)?;
+    Ok(())
+}
+
+/// Remove the empty parent directories of a path up to the given root directory
+pub fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+}
+
+/// Directories from the root directory down to the given path that don't exist yet,
+/// topmost first. The root directory itself is not included.
+pub fn missing_dirs(path: &Path, root: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|dir| *dir != root && dir.starts_with(root))
+        .take_while(|dir| !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    dirs.reverse();
+    dirs
+}
+
+/// Remove the directories created by a failed operation, deepest first.
+/// The deepest directory is removed with its contents, while its parents are only removed
+/// when they are empty.
+pub fn remove_new_dirs(dirs: &[PathBuf]) {
+    let Some((deepest, parents)) = dirs.split_last() else {
+        return;
+    };
+    if deepest.exists()
+        && let Err(err) = std::fs::remove_dir_all(deepest)
+    {
+        log::error!("Failed to remove {}: {err}", deepest.to_string_lossy());
+        return;
+    }
+    for dir in parents.iter().rev() {
+        if let Err(err) = std::fs::remove_dir(dir) {
+            log::debug!("Not removing {}: {err}", dir.to_string_lossy());
+            return;
+        }
+    }
+}
+
+struct Scanner<'a, F> {
+    projects_path: &'a Path,
+    pinned: &'a [String],
+    exclude: &'a [glob::Pattern],
+    lowercase_paths: bool,
+    remote_url: F,
+    findings: Vec<Finding>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Scanner<'_, F> {
+    fn scan_dir(&mut self, dir: &Path, entry: &str) -> Result<DirContents> {
+        let project = DirContents {
+            has_project: true,
+            has_files: true,
+        };
+        if self.pinned.iter().any(|p| p == entry)
+            || self.exclude.iter().any(|pattern| pattern.matches(entry))
+        {
+            return Ok(project);
+        }
+        if dir.join(GIT_DIR_NAME).exists() {
+            self.check_location(entry);
+            return Ok(project);
+        }
+        // Unreadable directories are left alone
+        match self.scan_children(dir, entry, false) {
+            Ok(contents) => Ok(contents),
+            Err(err) => {
+                log::error!("Failed to read {}: {err}", dir.to_string_lossy());
+                Ok(project)
+            }
+        }
+    }
+
+    /// Scan the sub-directories of a directory. Sub-directories without projects are reported
+    /// when the directory has projects, so that only the topmost cluttered directory is reported.
+    fn scan_children(&mut self, dir: &Path, entry: &str, is_root: bool) -> Result<DirContents> {
+        let mut children = Vec::new();
+        for child in std::fs::read_dir(dir)? {
+            children.push(child?);
+        }
+        children.sort_by_key(|child| child.file_name());
+
+        let mut has_files = false;
+        let mut has_project = false;
+        let mut cluttered = Vec::new();
+        for child in children {
+            if !child.file_type()?.is_dir() {
+                has_files = true;
+                continue;
+            }
+            let name = child.file_name();
+            let Some(name) = name.to_str() else {
+                log::error!(
+                    "Cannot read directory as a string: {}",
+                    child.path().to_string_lossy()
+                );
+                has_project = true;
+                continue;
+            };
+            let child_entry = match entry {
+                "" => String::from(name),
+                _ => format!("{entry}/{name}"),
+            };
+            let contents = self.scan_dir(&child.path(), &child_entry)?;
+            has_files |= contents.has_files;
+            if contents.has_project {
+                has_project = true;
+            } else if contents.has_files {
+                cluttered.push(Finding::NoRepository(child_entry));
+            } else {
+                cluttered.push(Finding::Empty(child_entry));
+            }
+        }
+
+        if has_project || is_root {
+            self.findings.extend(cluttered);
+        }
+        Ok(DirContents {
+            has_project,
+            has_files,
+        })
+    }
+
+    fn check_location(&mut self, entry: &str) {
+        let Some(url) = (self.remote_url)(entry) else {
+            return;
+        };
+        let expected = match git_url::to_path(&url, self.lowercase_paths) {
+            Ok(path) => path.join("/"),
+            Err(err) => {
+                log::debug!("Cannot derive a path for {entry} from {url}: {err}");
+                return;
+            }
+        };
+        // Additional checkouts of a repository are kept where they are
+        if expected != entry
+            && !db::entry_path(self.projects_path, &expected)
+                .join(GIT_DIR_NAME)
+                .exists()
+        {
+            self.findings.push(Finding::Misplaced {
+                entry: String::from(entry),
+                expected,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for the files of a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gorg-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn create_dirs(root: &Path, dirs: &[&str]) {
+        for dir in dirs {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+    }
+
+    fn create_files(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+    }
+
+    #[test]
+    fn scan_findings() {
+        let root = test_dir("tidy-scan");
+        create_dirs(
+            &root,
+            &[
+                "a.com/org/app/.git",
+                "a.com/org/moved/.git",
+                "a.com/org/empty/sub",
+                "e.com/x/y",
+            ],
+        );
+        create_files(
+            &root,
+            &[
+                "a.com/org/notes/x/readme.txt",
+                "b.com/pinned/file.txt",
+                "c.com/junk/file.txt",
+                "d.com/skip/file.txt",
+            ],
+        );
+        let pinned = vec![String::from("b.com/pinned")];
+        let exclude = vec![glob::Pattern::new("d.com/*")];
+
+        let findings = scan(&root, &pinned, &exclude, false, |entry| match entry {
+            "a.com/org/app" => Some(String::from("https://a.com/org/app.git")),
+            "a.com/org/moved" => Some(String::from("https://a.com/org/other.git")),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(
+            findings,
+            vec![
+                Finding::Empty(String::from("a.com/org/empty")),
+                Finding::Misplaced {
+                    entry: String::from("a.com/org/moved"),
+                    expected: String::from("a.com/org/other"),
+                },
+                Finding::NoRepository(String::from("a.com/org/notes")),
+                Finding::NoRepository(String::from("c.com")),
+                Finding::Empty(String::from("e.com")),
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn remove_only_empty_dirs() {
+        let root = test_dir("tidy-remove");
+        create_dirs(&root, &["empty/a/b", "empty/c"]);
+        create_files(&root, &["full/a/file.txt"]);
+
+        remove_empty_dir(&root.join("empty")).unwrap();
+        assert!(!root.join("empty").exists());
+
+        assert!(remove_empty_dir(&root.join("full")).is_err());
+        assert!(root.join("full/a/file.txt").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn remove_parents_up_to_root() {
+        let root = test_dir("tidy-parents");
+        create_dirs(&root, &["a/b/c"]);
+        create_files(&root, &["a/file.txt"]);
+
+        remove_empty_parents(&root.join("a/b/c/d"), &root);
+        assert!(!root.join("a/b").exists());
+        assert!(root.join("a/file.txt").exists());
+
+        std::fs::remove_file(root.join("a/file.txt")).unwrap();
+        remove_empty_parents(&root.join("a/b"), &root);
+        assert!(!root.join("a").exists());
+        assert!(root.exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn list_missing_dirs() {
+        let root = test_dir("tidy-missing");
+        create_dirs(&root, &["a"]);
+
+        assert_eq!(
+            missing_dirs(&root.join("a/b/c"), &root),
+            vec![root.join("a/b"), root.join("a/b/c")]
+        );
+        assert!(missing_dirs(&root.join("a"), &root).is_empty());
+        assert!(missing_dirs(&root, &root).is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::text;
+
+/// Index from byte trigrams to the lines that contain them.
+/// The lines and the queries are folded with `text::fold` like in fuzzy matching.
+///
+/// Used for narrowing down the lines to score for a fuzzy query:
+/// every part of the query must be found in a line for it to match,
+/// so the line must also contain all of the trigrams of each part.
+/// Parts may also match the initials of the line segments instead,
+/// so the lines are also indexed by the pairs such matches start with.
+pub struct TrigramIndex {
+    postings: HashMap<[u8; 3], Vec<u32>>,
+    initials: HashMap<[char; 2], Vec<u32>>,
+}
+
+impl TrigramIndex {
+    pub fn new(lines: &[&str]) -> Self {
+        let mut postings: HashMap<[u8; 3], Vec<u32>> = HashMap::new();
+        let mut initials: HashMap<[char; 2], Vec<u32>> = HashMap::new();
+        for (index, line) in lines.iter().enumerate() {
+            let index = index as u32;
+            let line = text::fold(line);
+            for trigram in line.as_bytes().windows(3) {
+                push_posting(&mut postings, [trigram[0], trigram[1], trigram[2]], index);
+            }
+            for pair in initial_pairs(&line) {
+                push_posting(&mut initials, pair, index);
+            }
+        }
+        Self { postings, initials }
+    }
+
+    /// Collect the indices of lines that may match the given fuzzy query.
+    /// Returns false when the query is too short to narrow down the lines.
+    pub fn candidates(&self, matcher: &str, out: &mut Vec<u32>) -> bool {
+        out.clear();
+        let matcher = text::fold(matcher);
+        let mut lists: Vec<Vec<u32>> = Vec::new();
+        for part in matcher.split(text::is_punctuation) {
+            if part.len() < 3 {
+                continue;
+            }
+            let mut trigram_lists = Vec::new();
+            for trigram in part.as_bytes().windows(3) {
+                match self.postings.get(trigram) {
+                    Some(list) => trigram_lists.push(list.as_slice()),
+                    None => {
+                        trigram_lists.clear();
+                        break;
+                    }
+                }
+            }
+            let mut list = intersect(trigram_lists);
+            let mut chars = part.chars();
+            if let (Some(first), Some(second)) = (chars.next(), chars.next())
+                && let Some(initials) = self.initials.get(&[first, second])
+            {
+                list = union(&list, initials);
+            }
+            lists.push(list);
+        }
+        if lists.is_empty() {
+            return false;
+        }
+        *out = intersect(lists.iter().map(|list| list.as_slice()).collect());
+        true
+    }
+}
+
+fn push_posting<K: std::hash::Hash + Eq>(postings: &mut HashMap<K, Vec<u32>>, key: K, index: u32) {
+    let list = postings.entry(key).or_default();
+    // Lines are visited in order, so checking the last item is enough for dedup.
+    if list.last() != Some(&index) {
+        list.push(index);
+    }
+}
+
+/// Pairs of characters that an initials match on the line can start with:
+/// the initial of a segment followed by a character later in the same segment
+/// or by the initial of a later segment. See `fuzzy::calc_initials_score`.
+fn initial_pairs(line: &str) -> Vec<[char; 2]> {
+    let segments: Vec<Vec<char>> = line
+        .split(text::is_punctuation)
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.chars().collect())
+        .collect();
+    let mut pairs = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let initial = segment[0];
+        pairs.extend(segment[1..].iter().map(|ch| [initial, *ch]));
+        pairs.extend(segments[i + 1..].iter().map(|later| [initial, later[0]]));
+    }
+    pairs
+}
+
+/// Intersect sorted lists, starting from the shortest one to keep the result short
+fn intersect(mut lists: Vec<&[u32]>) -> Vec<u32> {
+    lists.sort_unstable_by_key(|list| list.len());
+    let Some((first, rest)) = lists.split_first() else {
+        return Vec::new();
+    };
+    let mut out = first.to_vec();
+    for list in rest {
+        out.retain(|index| list.binary_search(index).is_ok());
+        if out.is_empty() {
+            break;
+        }
+    }
+    out
+}
+
+/// Merge sorted lists without duplicates
+fn union(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => {
+                out.push(a[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                out.push(b[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: [&str; 5] = [
+        "github.com/golang/go",
+        "github.com/jpallari/go",
+        "github.com/jpallari/gorg",
+        "github.com/jpallari/hugo",
+        "gitlab.com/acme/terraform-modules",
+    ];
+
+    #[test]
+    fn candidates_narrow_down() {
+        let index = TrigramIndex::new(&LINES);
+        let mut out = Vec::new();
+        assert!(index.candidates("jpa org", &mut out));
+        assert_eq!(out, [2]);
+        assert!(index.candidates("terraform", &mut out));
+        assert_eq!(out, [4]);
+    }
+
+    #[test]
+    fn candidates_include_initials() {
+        let index = TrigramIndex::new(&LINES);
+        let mut out = Vec::new();
+        assert!(index.candidates("tfm", &mut out));
+        assert_eq!(out, [4]);
+        // `go` starts a segment in the lines without the `gor` trigram too
+        assert!(index.candidates("jpa gor", &mut out));
+        assert_eq!(out, [1, 2]);
+    }
+
+    #[test]
+    fn candidates_fold_diacritics() {
+        let index = TrigramIndex::new(&["github.com/acme/josé-utils", "github.com/acme/svc"]);
+        let mut out = Vec::new();
+        assert!(index.candidates("jose", &mut out));
+        assert_eq!(out, [0]);
+        assert!(index.candidates("josé", &mut out));
+        assert_eq!(out, [0]);
+    }
+
+    #[test]
+    fn candidates_no_match() {
+        let index = TrigramIndex::new(&LINES);
+        let mut out = vec![1];
+        assert!(index.candidates("rust", &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn candidates_short_query() {
+        let index = TrigramIndex::new(&LINES);
+        let mut out = Vec::new();
+        assert!(!index.candidates("go jp", &mut out));
+    }
+}
@@ -1,18 +1,54 @@
+use crate::config::Layout;
 use crate::text;
 use std::{
-    io::{self, Write},
-    os::fd::AsFd,
+    io::{self, Read, Write},
+    os::fd::{AsFd, AsRawFd, RawFd},
+    sync::{Mutex, Once},
+    time::Duration,
 };
 
+use base64::Engine;
 use termion::raw::IntoRawMode;
 use termion::{
-    event::{Event, Key},
+    event::{Event, Key, MouseButton, MouseEvent},
     raw::RawTerminal,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 const QUERY_MAX_CHAR_LEN: u16 = 1000;
 const QUERY_MAX_BYTE_LEN: u16 = 4 * QUERY_MAX_CHAR_LEN;
+#[allow(clippy::redundant_static_lifetimes)]
 const PROMPT_STRING: &'static str = ">>> ";
+const NO_MATCHES_STRING: &str = "    (no matches)";
+const BRACKETED_PASTE_ENABLE: &str = "\x1b[?2004h";
+const BRACKETED_PASTE_DISABLE: &str = "\x1b[?2004l";
+const BRACKETED_PASTE_START: &[u8] = b"\x1b[200~";
+const BRACKETED_PASTE_END: &[u8] = b"\x1b[201~";
+const MOUSE_ENABLE: &str = "\x1b[?1000h\x1b[?1006h";
+const MOUSE_DISABLE: &str = "\x1b[?1006l\x1b[?1000l";
+const CURSOR_POSITION_REQUEST: &str = "\x1b[6n";
+
+/// Terminal settings from before the prompt enabled raw mode.
+/// Kept around for restoring the terminal when the prompt can't be cleaned up normally.
+static ORIGINAL_TERMINAL: Mutex<Option<SavedTerminal>> = Mutex::new(None);
+static PANIC_HOOK: Once = Once::new();
+
+struct SavedTerminal {
+    fd: RawFd,
+    termios: libc::termios,
+    options: PromptOptions,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct PromptOptions {
+    /// Render on the alternate screen using the full terminal height
+    pub full_screen: bool,
+    pub layout: Layout,
+    /// Number of lines reserved for matches in the reverse layout
+    pub max_items: usize,
+    /// Scroll and select matches using the mouse
+    pub mouse: bool,
+}
 
 pub enum PromptUIEvent {
     Exit,
@@ -20,16 +56,36 @@ pub enum PromptUIEvent {
     CursorUpdated,
     SelectionUpdated,
     SelectionDone,
+    CopySelection,
+    ToggleFullPath,
+    CycleHost,
+    CycleOrg,
+    ToggleArchived,
+    ToggleExplain,
 }
 
 pub struct PromptUI<W: Write + AsFd> {
     writer: RawTerminal<W>,
-    text_input: Vec<char>,
+    options: PromptOptions,
+    text_input: String,
+    /// Byte offset in the text input, always at a grapheme cluster boundary
     text_cursor: usize,
-    temp_buffer: String,
     selected_item: u16,
     max_items: u16,
     lines_printed: u16,
+    /// Number of matches and the number of projects searched
+    match_count: Option<(usize, usize)>,
+    /// Description of how the matches are narrowed down, e.g. the host or organization
+    scope: Option<String>,
+    /// Width of the dimmed host column. The host is shown as part of the item when not set.
+    host_column_width: Option<usize>,
+    /// Terminal row of the prompt line as reported by the terminal.
+    /// Only tracked when mouse support is enabled.
+    prompt_row: Option<u16>,
+    /// Whether we're between the start and the end of a bracketed paste
+    pasting: bool,
+    /// Whether the text input has changed during the current paste
+    pasted: bool,
 }
 
 #[derive(Copy, Clone)]
@@ -47,6 +103,10 @@ enum TextMovementAmount {
 
 impl<W: Write + AsFd> Drop for PromptUI<W> {
     fn drop(&mut self) {
+        if !release_terminal() {
+            // Already restored by the panic hook
+            return;
+        }
         if let Err(err) = self.quit() {
             eprintln!("Failed to quit prompt UI: {}", err);
         }
@@ -54,7 +114,7 @@ impl<W: Write + AsFd> Drop for PromptUI<W> {
 }
 
 impl<W: Write + AsFd> PromptUI<W> {
-    pub fn text_input(&self) -> &[char] {
+    pub fn text_input(&self) -> &str {
         &self.text_input
     }
 
@@ -62,60 +122,158 @@ impl<W: Write + AsFd> PromptUI<W> {
         self.selected_item
     }
 
-    pub fn new(writer: W, initial_text_input: &str) -> io::Result<PromptUI<W>> {
-        let mut text_input: Vec<char> = Vec::with_capacity(QUERY_MAX_CHAR_LEN.into());
+    pub fn new(
+        writer: W,
+        initial_text_input: &str,
+        options: PromptOptions,
+    ) -> io::Result<PromptUI<W>> {
+        let mut text_input = String::with_capacity(QUERY_MAX_BYTE_LEN.into());
         text_input.extend(initial_text_input.chars().take(QUERY_MAX_CHAR_LEN.into()));
         let cursor_pos = text_input.len();
-        let writer = writer.into_raw_mode()?;
+        guard_terminal(writer.as_fd().as_raw_fd(), options)?;
+        let mut writer = writer.into_raw_mode()?;
+        writer.write_all(BRACKETED_PASTE_ENABLE.as_bytes())?;
+        if options.full_screen {
+            write!(
+                writer,
+                "{}{}",
+                termion::screen::ToAlternateScreen,
+                termion::cursor::Goto(1, 1)
+            )?;
+        }
+        if options.mouse {
+            writer.write_all(MOUSE_ENABLE.as_bytes())?;
+        }
 
         Ok(PromptUI {
             writer,
+            options,
             text_input,
             lines_printed: 0,
             text_cursor: cursor_pos,
-            temp_buffer: String::with_capacity(QUERY_MAX_BYTE_LEN.into()),
             selected_item: 0,
             max_items: 0,
+            match_count: None,
+            scope: None,
+            host_column_width: None,
+            prompt_row: None,
+            pasting: false,
+            pasted: false,
         })
     }
 
     fn text(&mut self, line: &str) -> io::Result<()> {
-        self.writer.write(line.as_bytes())?;
+        self.writer.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn set_match_count(&mut self, matched: usize, total: usize) {
+        self.match_count = Some((matched, total));
+    }
+
+    /// Show how the matches are narrowed down next to the match count
+    pub fn set_scope(&mut self, scope: Option<String>) {
+        self.scope = scope;
+    }
+
+    /// Show the host of each item in a dimmed column of the given width
+    pub fn set_host_column(&mut self, width: Option<usize>) {
+        self.host_column_width = width;
+    }
+
+    fn prompt(&mut self, width: u16) -> io::Result<()> {
+        self.writer.write_all(PROMPT_STRING.as_bytes())?;
+        self.writer.write_all(self.text_input.as_bytes())?;
+        if let Some((matched, total)) = self.match_count {
+            // Match count is aligned to the right when there's room for it
+            let count = match &self.scope {
+                Some(scope) => format!("{scope} {matched}/{total}"),
+                None => format!("{matched}/{total}"),
+            };
+            let used = PROMPT_STRING.len() + text::str_width(&self.text_input);
+            let free = (width as usize).saturating_sub(used);
+            if free > count.len() + 1 {
+                let padding = (free - count.len()) as u16;
+                write!(self.writer, "{}{count}", termion::cursor::Right(padding))?;
+            }
+        }
         Ok(())
     }
 
-    fn prompt(&mut self) -> io::Result<()> {
-        self.writer.write(PROMPT_STRING.as_bytes())?;
-        self.temp_buffer.clear();
-        self.temp_buffer.extend(self.text_input.iter());
-        self.writer.write(&self.temp_buffer.as_bytes())?;
-        self.finish_line()?;
+    fn item(&mut self, index: usize, item: &str, width: u16) -> io::Result<()> {
+        self.max_items += 1;
+        let prefix = if index == self.selected_item as usize {
+            "  * "
+        } else {
+            "    "
+        };
+        self.text(prefix)?;
+        let mut item_width = (width as usize).max(10) - prefix.len();
+        let item = match (self.host_column_width, item.split_once('/')) {
+            (Some(host_width), Some((host, rest))) if item_width > host_width + 1 => {
+                let padding = host_width.saturating_sub(text::str_width(host));
+                write!(
+                    self.writer,
+                    "{}{host}{}{} ",
+                    termion::style::Faint,
+                    termion::style::NoFaint,
+                    " ".repeat(padding)
+                )?;
+                item_width -= host_width + 1;
+                rest
+            }
+            _ => item,
+        };
+        self.text(text::truncate_to_width(item, item_width))?;
         Ok(())
     }
 
     fn finish_line(&mut self) -> io::Result<()> {
-        self.writer.write("\r\n".as_bytes())?;
+        self.writer.write_all("\r\n".as_bytes())?;
         self.lines_printed += 1;
         Ok(())
     }
 
     fn done(&mut self) -> io::Result<()> {
-        let cursor_pos_bytes: u16 = self.text_input[..(self.text_cursor) as usize]
-            .iter()
-            .map(|c| c.len_utf8() as u16)
-            .sum();
-        write!(
-            self.writer,
-            "{}{}",
-            termion::cursor::Up(self.lines_printed),
-            termion::cursor::Right(PROMPT_STRING.len() as u16 + cursor_pos_bytes)
-        )?;
+        let cursor_pos_columns = text::str_width(&self.text_input[..(self.text_cursor)]);
+        let cursor_right =
+            termion::cursor::Right((PROMPT_STRING.len() + cursor_pos_columns) as u16);
+        match self.options.layout {
+            Layout::Default => write!(
+                self.writer,
+                "{}{}",
+                termion::cursor::Up(self.lines_printed),
+                cursor_right
+            )?,
+            // Prompt is on the last line, so the cursor is already on it
+            Layout::Reverse => write!(self.writer, "\r{}", cursor_right)?,
+        }
+        if self.options.mouse {
+            // The prompt may have moved due to scrolling, so its position is checked after every render
+            self.writer.write_all(CURSOR_POSITION_REQUEST.as_bytes())?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Copy the text to the system clipboard using the OSC 52 escape sequence.
+    /// The terminal handles the copying, so this also works over SSH.
+    pub fn copy_to_clipboard(&mut self, text: &str) -> io::Result<()> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+        write!(self.writer, "\x1b]52;c;{encoded}\x07")?;
         self.writer.flush()?;
         Ok(())
     }
 
     pub fn quit(&mut self) -> io::Result<()> {
         self.reset()?;
+        self.writer.write_all(BRACKETED_PASTE_DISABLE.as_bytes())?;
+        if self.options.mouse {
+            self.writer.write_all(MOUSE_DISABLE.as_bytes())?;
+        }
+        if self.options.full_screen {
+            write!(self.writer, "{}", termion::screen::ToMainScreen)?;
+        }
         self.writer.flush()?;
         Ok(())
     }
@@ -124,7 +282,18 @@ impl<W: Write + AsFd> PromptUI<W> {
         // Always clear the first line in case it contains input
         write!(self.writer, "\r{}", termion::clear::CurrentLine)?;
 
-        if self.lines_printed > 0 {
+        if self.options.layout == Layout::Reverse {
+            // Lines are above the prompt line
+            for _ in 0..self.lines_printed {
+                write!(
+                    self.writer,
+                    "{}\r{}",
+                    termion::cursor::Up(1),
+                    termion::clear::CurrentLine
+                )?;
+            }
+            self.lines_printed = 0;
+        } else if self.lines_printed > 0 {
             for _ in 0..self.lines_printed {
                 write!(
                     self.writer,
@@ -144,19 +313,41 @@ impl<W: Write + AsFd> PromptUI<W> {
 
         self.max_items = 0;
         self.reset()?;
-        self.prompt()?;
 
-        for (index, item) in items.enumerate().take(height as usize - 2) {
-            self.max_items += 1;
-            let prefix = if index == self.selected_item as usize {
-                "  * "
-            } else {
-                "    "
-            };
-            self.text(prefix)?;
-            let item_len = item.len().min((width as usize).max(10) - prefix.len());
-            self.text(&item[..item_len])?;
-            self.finish_line()?;
+        match self.options.layout {
+            Layout::Default => {
+                self.prompt(width)?;
+                self.finish_line()?;
+                for (index, item) in items.enumerate().take(height as usize - 2) {
+                    self.item(index, item, width)?;
+                    self.finish_line()?;
+                }
+                if self.max_items == 0 {
+                    self.text(NO_MATCHES_STRING)?;
+                    self.finish_line()?;
+                }
+            }
+            Layout::Reverse => {
+                // The number of lines stays the same between renders to keep the prompt in place
+                let lines = self
+                    .options
+                    .max_items
+                    .min((height as usize).saturating_sub(1));
+                let items: Vec<&str> = items.take(lines).collect();
+                let no_matches = items.is_empty() && lines > 0;
+                for _ in (items.len() + no_matches as usize)..lines {
+                    self.finish_line()?;
+                }
+                if no_matches {
+                    self.text(NO_MATCHES_STRING)?;
+                    self.finish_line()?;
+                }
+                for (index, item) in items.iter().enumerate().rev() {
+                    self.item(index, item, width)?;
+                    self.finish_line()?;
+                }
+                self.prompt(width)?;
+            }
         }
 
         self.done()?;
@@ -164,7 +355,46 @@ impl<W: Write + AsFd> PromptUI<W> {
     }
 
     pub fn handle_event(&mut self, event: Event) -> Option<PromptUIEvent> {
+        if self.pasting {
+            return self.handle_paste_event(event);
+        }
         match event {
+            Event::Unsupported(bytes) if bytes == BRACKETED_PASTE_START => {
+                self.pasting = true;
+                self.pasted = false;
+                None
+            }
+            Event::Unsupported(bytes) => {
+                if let Some((row, _)) = parse_cursor_position(&bytes) {
+                    self.prompt_row = Some(row);
+                }
+                None
+            }
+            Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, _, _)) => {
+                match self.options.layout {
+                    Layout::Default => self.select_previous(),
+                    Layout::Reverse => self.select_next(),
+                }
+            }
+            Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, _, _)) => {
+                match self.options.layout {
+                    Layout::Default => self.select_next(),
+                    Layout::Reverse => self.select_previous(),
+                }
+            }
+            Event::Mouse(MouseEvent::Press(MouseButton::Left, _, row)) => {
+                let index = self
+                    .prompt_row
+                    .and_then(|prompt_row| item_index_at(self.options.layout, prompt_row, row))
+                    .filter(|index| *index < self.max_items)?;
+                if index == self.selected_item {
+                    // Clicking the selected item picks it
+                    Some(PromptUIEvent::SelectionDone)
+                } else {
+                    self.selected_item = index;
+                    Some(PromptUIEvent::SelectionUpdated)
+                }
+            }
             Event::Key(Key::Char('\n')) => Some(PromptUIEvent::SelectionDone),
             Event::Key(Key::Backspace) => {
                 if self.delete_char() {
@@ -184,22 +414,14 @@ impl<W: Write + AsFd> PromptUI<W> {
                     None
                 }
             }
-            Event::Key(Key::Up) | Event::Key(Key::Ctrl('p')) => {
-                if self.selected_item > 0 {
-                    self.selected_item -= 1;
-                    Some(PromptUIEvent::SelectionUpdated)
-                } else {
-                    None
-                }
-            }
-            Event::Key(Key::Down) | Event::Key(Key::Ctrl('n')) => {
-                if self.selected_item + 1 < self.max_items {
-                    self.selected_item += 1;
-                    Some(PromptUIEvent::SelectionUpdated)
-                } else {
-                    None
-                }
-            }
+            Event::Key(Key::Up) | Event::Key(Key::Ctrl('p')) => match self.options.layout {
+                Layout::Default => self.select_previous(),
+                Layout::Reverse => self.select_next(),
+            },
+            Event::Key(Key::Down) | Event::Key(Key::Ctrl('n')) => match self.options.layout {
+                Layout::Default => self.select_next(),
+                Layout::Reverse => self.select_previous(),
+            },
             Event::Key(Key::Left) | Event::Key(Key::Ctrl('b')) => {
                 self.move_cursor(TextMovementDirection::Left, TextMovementAmount::Char);
                 Some(PromptUIEvent::CursorUpdated)
@@ -225,6 +447,21 @@ impl<W: Write + AsFd> PromptUI<W> {
                 Some(PromptUIEvent::CursorUpdated)
             }
             Event::Key(Key::Ctrl('c')) | Event::Key(Key::Ctrl('d')) => Some(PromptUIEvent::Exit),
+            Event::Key(Key::Ctrl('o')) => Some(PromptUIEvent::CopySelection),
+            Event::Key(Key::Ctrl('t')) => Some(PromptUIEvent::ToggleFullPath),
+            Event::Key(Key::Alt('e')) => Some(PromptUIEvent::ToggleExplain),
+            Event::Key(Key::Alt('h')) => {
+                self.selected_item = 0;
+                Some(PromptUIEvent::CycleHost)
+            }
+            Event::Key(Key::Alt('o')) => {
+                self.selected_item = 0;
+                Some(PromptUIEvent::CycleOrg)
+            }
+            Event::Key(Key::Alt('a')) => {
+                self.selected_item = 0;
+                Some(PromptUIEvent::ToggleArchived)
+            }
             Event::Key(Key::Char(ch)) => {
                 self.insert_char(ch);
                 self.selected_item = 0;
@@ -234,12 +471,59 @@ impl<W: Write + AsFd> PromptUI<W> {
         }
     }
 
+    fn select_previous(&mut self) -> Option<PromptUIEvent> {
+        if self.selected_item > 0 {
+            self.selected_item -= 1;
+            Some(PromptUIEvent::SelectionUpdated)
+        } else {
+            None
+        }
+    }
+
+    fn select_next(&mut self) -> Option<PromptUIEvent> {
+        if self.selected_item + 1 < self.max_items {
+            self.selected_item += 1;
+            Some(PromptUIEvent::SelectionUpdated)
+        } else {
+            None
+        }
+    }
+
+    /// Pasted text is inserted as is without triggering key bindings.
+    /// The prompt is updated once the paste is done.
+    fn handle_paste_event(&mut self, event: Event) -> Option<PromptUIEvent> {
+        match event {
+            Event::Unsupported(bytes) if bytes == BRACKETED_PASTE_END => {
+                self.pasting = false;
+                if self.pasted {
+                    self.selected_item = 0;
+                    Some(PromptUIEvent::PromptUpdated)
+                } else {
+                    None
+                }
+            }
+            Event::Key(Key::Char(ch)) => {
+                // New lines and tabs would end up as control characters in the prompt
+                let ch = if ch.is_control() { ' ' } else { ch };
+                self.pasted |= self.insert_char(ch);
+                None
+            }
+            _ => None,
+        }
+    }
+
     fn delete_char(&mut self) -> bool {
         if self.text_input.is_empty() || self.text_cursor == 0 {
             return false;
         }
-        self.text_cursor -= 1;
-        self.text_input.remove(self.text_cursor);
+        let next_cursor_pos = move_cursor(
+            &self.text_input,
+            self.text_cursor,
+            TextMovementDirection::Left,
+            TextMovementAmount::Char,
+        );
+        self.text_input.drain(next_cursor_pos..self.text_cursor);
+        self.text_cursor = next_cursor_pos;
         true
     }
 
@@ -263,19 +547,21 @@ impl<W: Write + AsFd> PromptUI<W> {
         true
     }
 
-    fn insert_char(&mut self, ch: char) {
-        if self.text_input.len() + 1 > self.text_input.capacity() {
+    fn insert_char(&mut self, ch: char) -> bool {
+        if self.text_input.len() + ch.len_utf8() > QUERY_MAX_BYTE_LEN.into() {
             // Max capacity reached for prompt
-            return;
+            return false;
         }
 
-        if self.text_cursor == self.text_input.len() {
-            self.text_input.push(ch);
-        } else {
-            self.text_input.insert(self.text_cursor, ch);
-        }
+        self.text_input.insert(self.text_cursor, ch);
+        self.text_cursor += ch.len_utf8();
 
-        self.text_cursor += 1;
+        // The inserted character may join the grapheme after the cursor
+        // (e.g. a base character before a combining mark).
+        if !is_grapheme_boundary(&self.text_input, self.text_cursor) {
+            self.move_cursor(TextMovementDirection::Right, TextMovementAmount::Char);
+        }
+        true
     }
 
     fn move_cursor(&mut self, direction: TextMovementDirection, amount: TextMovementAmount) {
@@ -283,8 +569,136 @@ impl<W: Write + AsFd> PromptUI<W> {
     }
 }
 
+/// Save the current terminal settings so that the terminal can be restored
+/// if the program panics while the terminal is in raw mode.
+/// The given options tell which terminal modes need to be disabled in the restore.
+pub fn guard_terminal(fd: RawFd, options: PromptOptions) -> io::Result<()> {
+    save_original_terminal(fd, options)?;
+    PANIC_HOOK.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+    });
+    Ok(())
+}
+
+/// Forget the saved terminal settings when the terminal is cleaned up normally.
+/// Returns false when the terminal was already restored by the panic hook.
+pub fn release_terminal() -> bool {
+    take_original_terminal().is_some()
+}
+
+/// Wait for terminal input on stdin until the timeout and parse the events from it.
+/// Reading only when asked keeps the terminal input free for interactive programs
+/// run while the dashboard is suspended.
+pub fn read_events(timeout: Duration) -> io::Result<Vec<Event>> {
+    let mut stdin = io::stdin();
+    let mut poll_fd = libc::pollfd {
+        fd: stdin.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: the pollfd struct is valid for the duration of the call
+    let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        return match err.kind() {
+            io::ErrorKind::Interrupted => Ok(Vec::new()),
+            _ => Err(err),
+        };
+    }
+    if ready == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut buf = [0; 1024];
+    let n = stdin.read(&mut buf)?;
+    if n == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    let mut bytes = buf[..n].iter().map(|b| Ok(*b));
+    let mut events = Vec::new();
+    while let Some(Ok(byte)) = bytes.next() {
+        events.push(termion::event::parse_event(byte, &mut bytes)?);
+    }
+    Ok(events)
+}
+
+fn save_original_terminal(fd: RawFd, options: PromptOptions) -> io::Result<()> {
+    // SAFETY: termios is a plain C struct that tcgetattr fills in
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if let Ok(mut original) = ORIGINAL_TERMINAL.lock() {
+        *original = Some(SavedTerminal {
+            fd,
+            termios,
+            options,
+        });
+    }
+    Ok(())
+}
+
+fn take_original_terminal() -> Option<SavedTerminal> {
+    ORIGINAL_TERMINAL
+        .lock()
+        .ok()
+        .and_then(|mut original| original.take())
+}
+
+/// Restore the terminal to the state it was in before the prompt was started.
+/// Does nothing when there's no prompt running.
+fn restore_terminal() {
+    let Some(saved) = take_original_terminal() else {
+        return;
+    };
+    let mut stderr = io::stderr();
+    let _ = write!(stderr, "\r\n{BRACKETED_PASTE_DISABLE}");
+    if saved.options.mouse {
+        let _ = write!(stderr, "{MOUSE_DISABLE}");
+    }
+    if saved.options.full_screen {
+        let _ = write!(stderr, "{}", termion::screen::ToMainScreen);
+    }
+    let _ = stderr.flush();
+    // SAFETY: the settings were read from the same file descriptor
+    unsafe { libc::tcsetattr(saved.fd, libc::TCSANOW, &saved.termios) };
+}
+
+/// Parse a cursor position report (`ESC [ row ; column R`) sent by the terminal
+fn parse_cursor_position(bytes: &[u8]) -> Option<(u16, u16)> {
+    let report = std::str::from_utf8(bytes).ok()?;
+    let report = report.strip_prefix("\x1b[")?.strip_suffix('R')?;
+    let (row, column) = report.split_once(';')?;
+    Some((row.parse().ok()?, column.parse().ok()?))
+}
+
+/// Index of the match rendered on the given terminal row
+fn item_index_at(layout: Layout, prompt_row: u16, row: u16) -> Option<u16> {
+    match layout {
+        Layout::Default => row.checked_sub(prompt_row)?.checked_sub(1),
+        Layout::Reverse => prompt_row.checked_sub(row)?.checked_sub(1),
+    }
+}
+
+fn is_grapheme_boundary(text: &str, index: usize) -> bool {
+    text.grapheme_indices(true)
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(text.len()))
+        .any(|offset| offset == index)
+}
+
+fn is_punctuation(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(text::is_punctuation)
+}
+
+/// Cursor position after the given movement.
+/// Both the given and the returned positions are byte offsets at grapheme cluster boundaries.
 fn move_cursor(
-    text: &[char],
+    text: &str,
     cursor: usize,
     direction: TextMovementDirection,
     amount: TextMovementAmount,
@@ -299,32 +713,28 @@ fn move_cursor(
     match (direction, amount) {
         (Left, End) => 0,
         (Right, End) => text.len(),
-        (Left, Char) => {
-            if cursor > 0 {
-                cursor - 1
-            } else {
-                0
-            }
-        }
-        (Right, Char) => {
-            if cursor < text.len() {
-                cursor + 1
-            } else {
-                cursor
-            }
-        }
+        (Left, Char) => text[..cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map(|(offset, _)| offset)
+            .unwrap_or(0),
+        (Right, Char) => text[cursor..]
+            .graphemes(true)
+            .next()
+            .map(|g| cursor + g.len())
+            .unwrap_or(cursor),
         (Left, Word) => {
             if cursor > 0 {
-                let mut iter = text[..cursor].iter().rev().enumerate();
-                let (_, first_char) = iter.next().expect("First char must be found");
-                let res = if text::is_punctuation(*first_char) {
-                    iter.skip_while(|(_, ch)| text::is_punctuation(**ch))
-                        .find(|(_, ch)| text::is_punctuation(**ch))
+                let mut iter = text[..cursor].grapheme_indices(true).rev();
+                let (_, first) = iter.next().expect("First grapheme must be found");
+                let res = if is_punctuation(first) {
+                    iter.skip_while(|(_, g)| is_punctuation(g))
+                        .find(|(_, g)| is_punctuation(g))
                 } else {
-                    iter.find(|(_, ch)| text::is_punctuation(**ch))
+                    iter.find(|(_, g)| is_punctuation(g))
                 };
                 match res {
-                    Some((offset, _)) => cursor - offset,
+                    Some((offset, g)) => offset + g.len(),
                     None => 0,
                 }
             } else {
@@ -333,13 +743,13 @@ fn move_cursor(
         }
         (Right, Word) => {
             if cursor < text.len() {
-                let mut iter = text[cursor..].iter().enumerate();
-                let (_, first_char) = iter.next().expect("First char must be found");
-                let res = if text::is_punctuation(*first_char) {
-                    iter.skip_while(|(_, ch)| text::is_punctuation(**ch))
-                        .find(|(_, ch)| text::is_punctuation(**ch))
+                let mut iter = text[cursor..].grapheme_indices(true);
+                let (_, first) = iter.next().expect("First grapheme must be found");
+                let res = if is_punctuation(first) {
+                    iter.skip_while(|(_, g)| is_punctuation(g))
+                        .find(|(_, g)| is_punctuation(g))
                 } else {
-                    iter.find(|(_, ch)| text::is_punctuation(**ch))
+                    iter.find(|(_, g)| is_punctuation(g))
                 };
                 match res {
                     Some((offset, _)) => cursor + offset,
@@ -360,53 +770,128 @@ mod tests {
     fn move_cursor_word_right_from_punctuation() {
         let dir = TextMovementDirection::Right;
         let amount = TextMovementAmount::Word;
-        let s: Vec<char> = Vec::from_iter("ground control to major tom".chars());
-        assert_eq!(14, move_cursor(&s, 6, dir, amount));
-        assert_eq!(17, move_cursor(&s, 14, dir, amount));
-        assert_eq!(23, move_cursor(&s, 17, dir, amount));
-        assert_eq!(27, move_cursor(&s, 23, dir, amount));
+        let s = "ground control to major tom";
+        assert_eq!(14, move_cursor(s, 6, dir, amount));
+        assert_eq!(17, move_cursor(s, 14, dir, amount));
+        assert_eq!(23, move_cursor(s, 17, dir, amount));
+        assert_eq!(27, move_cursor(s, 23, dir, amount));
     }
 
     #[test]
     fn move_cursor_word_right_from_char() {
         let dir = TextMovementDirection::Right;
         let amount = TextMovementAmount::Word;
-        let s: Vec<char> = Vec::from_iter("ground control to major tom".chars());
-        assert_eq!(6, move_cursor(&s, 0, dir, amount));
-        assert_eq!(6, move_cursor(&s, 3, dir, amount));
-        assert_eq!(14, move_cursor(&s, 7, dir, amount));
-        assert_eq!(14, move_cursor(&s, 13, dir, amount));
-        assert_eq!(23, move_cursor(&s, 18, dir, amount));
-        assert_eq!(23, move_cursor(&s, 20, dir, amount));
-        assert_eq!(27, move_cursor(&s, 24, dir, amount));
-        assert_eq!(27, move_cursor(&s, 25, dir, amount));
-        assert_eq!(27, move_cursor(&s, 27, dir, amount));
+        let s = "ground control to major tom";
+        assert_eq!(6, move_cursor(s, 0, dir, amount));
+        assert_eq!(6, move_cursor(s, 3, dir, amount));
+        assert_eq!(14, move_cursor(s, 7, dir, amount));
+        assert_eq!(14, move_cursor(s, 13, dir, amount));
+        assert_eq!(23, move_cursor(s, 18, dir, amount));
+        assert_eq!(23, move_cursor(s, 20, dir, amount));
+        assert_eq!(27, move_cursor(s, 24, dir, amount));
+        assert_eq!(27, move_cursor(s, 25, dir, amount));
+        assert_eq!(27, move_cursor(s, 27, dir, amount));
     }
 
     #[test]
     fn move_cursor_word_left_from_punctuation() {
         let dir = TextMovementDirection::Left;
         let amount = TextMovementAmount::Word;
-        let s: Vec<char> = Vec::from_iter("ground control to major tom".chars());
-        assert_eq!(0, move_cursor(&s, 6, dir, amount));
-        assert_eq!(7, move_cursor(&s, 14, dir, amount));
-        assert_eq!(15, move_cursor(&s, 17, dir, amount));
-        assert_eq!(18, move_cursor(&s, 23, dir, amount));
-        assert_eq!(24, move_cursor(&s, 27, dir, amount));
+        let s = "ground control to major tom";
+        assert_eq!(0, move_cursor(s, 6, dir, amount));
+        assert_eq!(7, move_cursor(s, 14, dir, amount));
+        assert_eq!(15, move_cursor(s, 17, dir, amount));
+        assert_eq!(18, move_cursor(s, 23, dir, amount));
+        assert_eq!(24, move_cursor(s, 27, dir, amount));
     }
 
     #[test]
     fn move_cursor_word_left_from_char() {
         let dir = TextMovementDirection::Left;
         let amount = TextMovementAmount::Word;
-        let s: Vec<char> = Vec::from_iter("ground control to major tom".chars());
-        assert_eq!(0, move_cursor(&s, 5, dir, amount));
-        assert_eq!(0, move_cursor(&s, 3, dir, amount));
-        assert_eq!(0, move_cursor(&s, 7, dir, amount));
-        assert_eq!(7, move_cursor(&s, 8, dir, amount));
-        assert_eq!(7, move_cursor(&s, 10, dir, amount));
-        assert_eq!(7, move_cursor(&s, 13, dir, amount));
-        assert_eq!(24, move_cursor(&s, 25, dir, amount));
-        assert_eq!(24, move_cursor(&s, 26, dir, amount));
+        let s = "ground control to major tom";
+        assert_eq!(0, move_cursor(s, 5, dir, amount));
+        assert_eq!(0, move_cursor(s, 3, dir, amount));
+        assert_eq!(0, move_cursor(s, 7, dir, amount));
+        assert_eq!(7, move_cursor(s, 8, dir, amount));
+        assert_eq!(7, move_cursor(s, 10, dir, amount));
+        assert_eq!(7, move_cursor(s, 13, dir, amount));
+        assert_eq!(24, move_cursor(s, 25, dir, amount));
+        assert_eq!(24, move_cursor(s, 26, dir, amount));
+    }
+
+    #[test]
+    fn move_cursor_char_over_combining_marks() {
+        // "e" followed by a combining acute accent
+        let s = "cafe\u{301} bar";
+        let amount = TextMovementAmount::Char;
+        assert_eq!(6, move_cursor(s, 3, TextMovementDirection::Right, amount));
+        assert_eq!(3, move_cursor(s, 6, TextMovementDirection::Left, amount));
+    }
+
+    #[test]
+    fn move_cursor_char_over_emoji_sequences() {
+        // Family emoji joined with zero-width joiners and a flag made of two regional indicators
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let s = format!("a{family}\u{1F1EB}\u{1F1EE}");
+        let after_family = 1 + family.len();
+        let dir = TextMovementDirection::Right;
+        let amount = TextMovementAmount::Char;
+        assert_eq!(after_family, move_cursor(&s, 1, dir, amount));
+        assert_eq!(s.len(), move_cursor(&s, after_family, dir, amount));
+        let dir = TextMovementDirection::Left;
+        assert_eq!(after_family, move_cursor(&s, s.len(), dir, amount));
+        assert_eq!(1, move_cursor(&s, after_family, dir, amount));
+    }
+
+    #[test]
+    fn move_cursor_word_with_composed_characters() {
+        let s = "cafe\u{301} to\u{308}y";
+        let dir = TextMovementDirection::Left;
+        let amount = TextMovementAmount::Word;
+        assert_eq!(7, move_cursor(s, s.len(), dir, amount));
+        assert_eq!(0, move_cursor(s, 6, dir, amount));
+        let dir = TextMovementDirection::Right;
+        assert_eq!(6, move_cursor(s, 0, dir, amount));
+        assert_eq!(s.len(), move_cursor(s, 7, dir, amount));
+    }
+
+    #[test]
+    fn move_cursor_word_non_ascii_punctuation() {
+        let s = "日本\u{3002}メモ";
+        let amount = TextMovementAmount::Word;
+        assert_eq!(6, move_cursor(s, 0, TextMovementDirection::Right, amount));
+        assert_eq!(
+            9,
+            move_cursor(s, s.len(), TextMovementDirection::Left, amount)
+        );
+    }
+
+    #[test]
+    fn cursor_position_report() {
+        assert_eq!(Some((12, 5)), parse_cursor_position(b"\x1b[12;5R"));
+        assert_eq!(None, parse_cursor_position(b"\x1b[200~"));
+        assert_eq!(None, parse_cursor_position(b"\x1b[12R"));
+    }
+
+    #[test]
+    fn item_index_at_row() {
+        assert_eq!(None, item_index_at(Layout::Default, 5, 5));
+        assert_eq!(Some(0), item_index_at(Layout::Default, 5, 6));
+        assert_eq!(Some(3), item_index_at(Layout::Default, 5, 9));
+        assert_eq!(None, item_index_at(Layout::Default, 5, 2));
+        assert_eq!(None, item_index_at(Layout::Reverse, 20, 20));
+        assert_eq!(Some(0), item_index_at(Layout::Reverse, 20, 19));
+        assert_eq!(Some(4), item_index_at(Layout::Reverse, 20, 15));
+        assert_eq!(None, item_index_at(Layout::Reverse, 20, 21));
+    }
+
+    #[test]
+    fn grapheme_boundaries() {
+        let s = "e\u{301}x";
+        assert!(is_grapheme_boundary(s, 0));
+        assert!(!is_grapheme_boundary(s, 1));
+        assert!(is_grapheme_boundary(s, 3));
+        assert!(is_grapheme_boundary(s, 4));
     }
 }
@@ -0,0 +1,238 @@
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::db::{self, DB, Transaction};
+use crate::git_cmd::GitCmd;
+use crate::git_dir;
+use crate::glob;
+use crate::status::{self, StatusCache};
+
+const GIT_DIR_NAME: &str = ".git";
+const EVENT_BATCH_WAIT: Duration = Duration::from_millis(200);
+/// Longest time to gather events for, so that a steady stream of them doesn't delay saving forever
+const EVENT_BATCH_MAX_WAIT: Duration = Duration::from_secs(2);
+
+pub fn watch(
+    projects_path: &Path,
+    index_file_path: &Path,
+    status_file_path: &Path,
+    git_cmd: &GitCmd,
+    follow_symlinks: bool,
+    exclude: &[glob::Pattern],
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(projects_path, RecursiveMode::Recursive)?;
+    log::info!("Watching {} for changes", projects_path.to_string_lossy());
+
+    while let Ok(res) = rx.recv() {
+        // File system changes tend to arrive in bursts (e.g. clones),
+        // so we gather them up before locking and writing the DB.
+        let mut events = vec![res];
+        let started = Instant::now();
+        while started.elapsed() < EVENT_BATCH_MAX_WAIT
+            && let Ok(res) = rx.recv_timeout(EVENT_BATCH_WAIT)
+        {
+            events.push(res);
+        }
+
+        let mut db = Transaction::begin(index_file_path)?;
+        let mut statuses = StatusCache::load(status_file_path)?;
+        let mut touched = BTreeSet::new();
+        let mut changed = false;
+        for res in events {
+            changed |= handle_event(
+                &mut db,
+                &statuses,
+                &mut touched,
+                projects_path,
+                follow_symlinks,
+                exclude,
+                res,
+            );
+        }
+
+        if changed {
+            log::debug!("Saving DB {}", index_file_path.to_string_lossy());
+            db.commit()?;
+        }
+
+        if !touched.is_empty() {
+            let entries: Vec<&str> = touched.iter().map(|s| s.as_str()).collect();
+            log::debug!("Refreshing status of {} project(s)", entries.len());
+            status::refresh(git_cmd, projects_path, &entries, &mut statuses);
+            statuses.save(status_file_path)?;
+        }
+    }
+    Ok(())
+}
+
+fn handle_event(
+    db: &mut DB,
+    statuses: &StatusCache,
+    touched: &mut BTreeSet<String>,
+    projects_path: &Path,
+    follow_symlinks: bool,
+    exclude: &[glob::Pattern],
+    res: notify::Result<Event>,
+) -> bool {
+    let event = match res {
+        Ok(event) => event,
+        Err(err) => {
+            log::error!("Failed to watch for changes: {err}");
+            return false;
+        }
+    };
+    // Statuses are only kept up to date for the projects already in the status cache.
+    // Git status reads files, so access events must be skipped to avoid refreshing in a loop.
+    if !matches!(event.kind, EventKind::Access(_)) {
+        for path in event.paths.iter() {
+            if let Some(rel_path) = path.strip_prefix(projects_path).ok().and_then(to_entry)
+                && let Some(entry) = statuses.entry_containing(&rel_path)
+            {
+                touched.insert(String::from(entry));
+            }
+        }
+    }
+    apply_event(db, projects_path, follow_symlinks, exclude, &event)
+}
+
+fn apply_event(
+    db: &mut DB,
+    projects_path: &Path,
+    follow_symlinks: bool,
+    exclude: &[glob::Pattern],
+    event: &Event,
+) -> bool {
+    // Only structural changes can add or remove projects.
+    // Access events are also triggered by our own directory scans.
+    match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {}
+        _ => return false,
+    }
+    let mut changed = false;
+    for path in event.paths.iter() {
+        changed |= apply_path(db, projects_path, follow_symlinks, exclude, path);
+    }
+    changed
+}
+
+fn apply_path(
+    db: &mut DB,
+    projects_path: &Path,
+    follow_symlinks: bool,
+    exclude: &[glob::Pattern],
+    path: &Path,
+) -> bool {
+    let Ok(rel_path) = path.strip_prefix(projects_path) else {
+        return false;
+    };
+    // Excluded directories are left out of the index like in `update-index`
+    if is_excluded(exclude, rel_path) {
+        return false;
+    }
+
+    // Changes inside Git directories don't affect which projects exist.
+    let mut parents = rel_path.components().rev().skip(1);
+    if parents.any(|c| c.as_os_str() == GIT_DIR_NAME) {
+        return false;
+    }
+
+    if path.file_name() == Some(OsStr::new(GIT_DIR_NAME)) {
+        let Some(project) = rel_path.parent().and_then(to_entry) else {
+            return false;
+        };
+        // Linked worktrees have a `.git` file instead of a directory
+        if path.exists() {
+            add_project(db, &project)
+        } else {
+            log::info!("Removing project {project}");
+            db.remove(&project)
+        }
+    } else if !path.exists() {
+        let Some(dir) = to_entry(rel_path) else {
+            return false;
+        };
+        let removed = db.remove_tree(&dir);
+        if removed > 0 {
+            log::info!("Removed {removed} project(s) under {dir}");
+        }
+        removed > 0
+    } else if path.is_dir() && (follow_symlinks || !path.is_symlink()) {
+        // Directory may have been moved into the projects directory
+        // with existing Git repositories inside.
+        // The directory may have been removed again by the time it's scanned
+        let iter = match git_dir::GitDirIterator::new(path) {
+            Ok(iter) => iter.with_follow_symlinks(follow_symlinks),
+            Err(err) => {
+                log::debug!("Cannot scan {}: {err}", path.to_string_lossy());
+                return false;
+            }
+        };
+        let mut changed = false;
+        for res in iter {
+            let dir = match res {
+                Ok(dir) => dir,
+                Err(err) => {
+                    log::error!("Failed to read file: {err}");
+                    continue;
+                }
+            };
+            let Ok(rel_path) = dir.strip_prefix(projects_path) else {
+                continue;
+            };
+            if is_excluded(exclude, rel_path) {
+                continue;
+            }
+            let Some(project) = to_entry(rel_path) else {
+                continue;
+            };
+            changed |= add_project(db, &project);
+        }
+        changed
+    } else {
+        false
+    }
+}
+
+/// Add the project to the DB unless it's already there. Returns whether the DB changed.
+fn add_project(db: &mut DB, project: &str) -> bool {
+    if db.entries().any(|entry| entry == project) {
+        return false;
+    }
+    log::info!("Adding project {project}");
+    if let Err(err) = db.add(project) {
+        log::error!("Failed to add project {project}: {err}");
+        return false;
+    }
+    true
+}
+
+fn is_excluded(exclude: &[glob::Pattern], rel_path: &Path) -> bool {
+    rel_path
+        .to_str()
+        .is_some_and(|rel_path| glob::matches_path_or_parent(exclude, rel_path))
+}
+
+fn to_entry(rel_path: &Path) -> Option<String> {
+    if rel_path.as_os_str().is_empty() {
+        return None;
+    }
+    match db::entry_from_path(rel_path) {
+        Some(entry) => Some(entry),
+        None => {
+            log::error!(
+                "Cannot use directory as a project: {}",
+                rel_path.to_string_lossy()
+            );
+            None
+        }
+    }
+}